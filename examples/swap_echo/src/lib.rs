@@ -0,0 +1,187 @@
+//! Example integrator of the Echo Program: a toy constant-product pool that deposits a receipt of every swap into an
+//! authorized buffer it owns, through `echo::cpi`.
+//!
+//! The pool only tracks virtual reserves, no tokens change hands. The authorized buffer is derived from the PDA
+//! `[AUTHORITY_SEED]` of this program, which signs the invocations of the Echo Program.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use echo::cpi;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+solana_program::declare_id!("9fhMw4GGY2MQqmHE6cNUUx7AAS3sgNqyyaa3xE3NAX9F");
+
+#[cfg(not(feature = "no-entrypoint"))]
+solana_program::entrypoint!(process_instruction);
+
+/// Seed of the PDA owning the receipts buffer.
+pub const AUTHORITY_SEED: &[u8] = b"echo_authority";
+
+/// Buffer seed of the receipts buffer.
+pub const RECEIPTS_BUFFER_SEED: u64 = 0;
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub enum SwapInstruction {
+    /// Creates the receipts buffer, paid by `authority`, which must be funded beforehand.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                  |
+    /// |-------|----------|--------|--------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | receipts: Authorized buffer of `authority`                   |
+    /// | 1     | ✅       | ❌     | authority: PDA of this program derived from `AUTHORITY_SEED` |
+    /// | 2     | ❌       | ❌     | system_program: Used to allocate the buffer                  |
+    /// | 3     | ❌       | ❌     | echo_program: The Echo Program                               |
+    InitializeReceipts,
+    /// Swaps `amount_in` of reserve A for reserve B and writes a `SwapReceipt` to the receipts buffer. Fails if fewer
+    /// than `minimum_amount_out` would come out.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                  |
+    /// |-------|----------|--------|--------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | pool: `Pool` owned by this program                           |
+    /// | 1     | ❌       | ✅     | user: Swapper                                                |
+    /// | 2     | ✅       | ❌     | receipts: Authorized buffer of `authority`                   |
+    /// | 3     | ❌       | ❌     | authority: PDA of this program derived from `AUTHORITY_SEED` |
+    /// | 4     | ❌       | ❌     | echo_program: The Echo Program                               |
+    Swap {
+        amount_in: u64,
+        minimum_amount_out: u64,
+    },
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct Pool {
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+}
+
+/// Echo data of the receipts buffer after a swap.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct SwapReceipt {
+    pub user: Pubkey,
+    pub amount_in: u64,
+    pub amount_out: u64,
+}
+
+impl SwapReceipt {
+    pub const LEN: usize = 32 + 8 + 8;
+}
+
+pub fn find_authority_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[AUTHORITY_SEED], program_id)
+}
+
+/// Amount of reserve B `amount_in` of reserve A buys from `pool`, keeping the product of the reserves constant.
+pub fn amount_out(pool: &Pool, amount_in: u64) -> Option<u64> {
+    let reserve_a = pool.reserve_a as u128;
+    let reserve_b = pool.reserve_b as u128;
+    let amount_out =
+        reserve_b.checked_mul(amount_in as u128)? / reserve_a.checked_add(amount_in as u128)?;
+    u64::try_from(amount_out).ok()
+}
+
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let instruction = SwapInstruction::try_from_slice(instruction_data)
+        .map_err(|_| ProgramError::InvalidInstructionData)?;
+    match instruction {
+        SwapInstruction::InitializeReceipts => {
+            msg!("Instruction: InitializeReceipts");
+
+            let accounts_iter = &mut accounts.iter();
+            let receipts = next_account_info(accounts_iter)?;
+            let authority = next_account_info(accounts_iter)?;
+            let system_program = next_account_info(accounts_iter)?;
+            let echo_program = next_account_info(accounts_iter)?;
+
+            let bump_seed = assert_authority(program_id, authority)?;
+            assert_echo_program(echo_program)?;
+
+            cpi::initialize_authorized_echo(
+                echo_program,
+                receipts,
+                authority,
+                system_program,
+                RECEIPTS_BUFFER_SEED,
+                echo::state::AuthorizedBufferHeader::header_len(&None) + SwapReceipt::LEN,
+                &[AUTHORITY_SEED, &[bump_seed]],
+            )
+        }
+        SwapInstruction::Swap {
+            amount_in,
+            minimum_amount_out,
+        } => {
+            msg!("Instruction: Swap");
+
+            let accounts_iter = &mut accounts.iter();
+            let pool_account = next_account_info(accounts_iter)?;
+            let user = next_account_info(accounts_iter)?;
+            let receipts = next_account_info(accounts_iter)?;
+            let authority = next_account_info(accounts_iter)?;
+            let echo_program = next_account_info(accounts_iter)?;
+
+            if pool_account.owner != program_id {
+                return Err(ProgramError::IncorrectProgramId);
+            }
+            if !user.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            let bump_seed = assert_authority(program_id, authority)?;
+            assert_echo_program(echo_program)?;
+
+            // swap
+            let mut pool = Pool::try_from_slice(&pool_account.data.borrow())?;
+            let amount_out = amount_out(&pool, amount_in).ok_or(ProgramError::InvalidArgument)?;
+            if amount_out < minimum_amount_out {
+                msg!("Slippage exceeded: {} < {}", amount_out, minimum_amount_out);
+                return Err(ProgramError::InvalidArgument);
+            }
+            pool.reserve_a = pool
+                .reserve_a
+                .checked_add(amount_in)
+                .ok_or(ProgramError::InvalidArgument)?;
+            pool.reserve_b -= amount_out;
+            pool.serialize(&mut *pool_account.data.borrow_mut())?;
+
+            // deposit the receipt
+            let receipt = SwapReceipt {
+                user: *user.key,
+                amount_in,
+                amount_out,
+            };
+            cpi::authorized_echo(
+                echo_program,
+                receipts,
+                authority,
+                None,
+                receipt.try_to_vec()?,
+                &[AUTHORITY_SEED, &[bump_seed]],
+            )
+        }
+    }
+}
+
+/// Checks `authority` is the PDA signing for this program and returns its bump seed.
+fn assert_authority(program_id: &Pubkey, authority: &AccountInfo) -> Result<u8, ProgramError> {
+    let (authority_key, bump_seed) = find_authority_address(program_id);
+    if *authority.key != authority_key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(bump_seed)
+}
+
+/// Checks `echo_program` is the Echo Program on the cluster the `echo` crate is built for.
+fn assert_echo_program(echo_program: &AccountInfo) -> ProgramResult {
+    if !echo::check_id(echo_program.key) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use echo::pda::find_authorized_buffer_address;
+use echo::processor::Processor;
+use echo::state::AuthorizedBufferHeader;
+use solana_program_test::{processor, tokio, ProgramTest};
+use solana_sdk::account::Account;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::system_program;
+use solana_sdk::transaction::Transaction;
+use swap_echo::{
+    amount_out, find_authority_address, Pool, SwapInstruction, SwapReceipt, RECEIPTS_BUFFER_SEED,
+};
+
+#[test]
+fn test_swap_echo() {
+    let program_id = swap_echo::id();
+    let pool = Pubkey::new_unique();
+    let user = Keypair::new();
+    let initial_pool = Pool {
+        reserve_a: 1_000,
+        reserve_b: 2_000,
+    };
+    let (authority, _) = find_authority_address(&program_id);
+    let (receipts, _) =
+        find_authorized_buffer_address(&echo::id(), &authority, RECEIPTS_BUFFER_SEED, None);
+
+    let mut program_test = ProgramTest::new(
+        "swap_echo",
+        program_id,
+        processor!(swap_echo::process_instruction),
+    );
+    program_test.add_program(
+        "echo",
+        echo::id(),
+        processor!(Processor::process_instruction),
+    );
+    program_test.add_account(
+        pool,
+        Account {
+            lamports: 1_000_000_000,
+            data: initial_pool.try_to_vec().unwrap(),
+            owner: program_id,
+            ..Account::default()
+        },
+    );
+    // Pays the rent of the receipts buffer
+    program_test.add_account(
+        authority,
+        Account {
+            lamports: 1_000_000_000,
+            owner: system_program::id(),
+            ..Account::default()
+        },
+    );
+
+    let swap_ix = |amount_in, minimum_amount_out| Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new_readonly(user.pubkey(), true),
+            AccountMeta::new(receipts, false),
+            AccountMeta::new_readonly(authority, false),
+            AccountMeta::new_readonly(echo::id(), false),
+        ],
+        data: SwapInstruction::Swap {
+            amount_in,
+            minimum_amount_out,
+        }
+        .try_to_vec()
+        .unwrap(),
+    };
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+        let expected_out = amount_out(&initial_pool, 100).unwrap();
+        let transaction = Transaction::new_signed_with_payer(
+            &[
+                Instruction {
+                    program_id,
+                    accounts: vec![
+                        AccountMeta::new(receipts, false),
+                        AccountMeta::new(authority, false),
+                        AccountMeta::new_readonly(system_program::id(), false),
+                        AccountMeta::new_readonly(echo::id(), false),
+                    ],
+                    data: SwapInstruction::InitializeReceipts.try_to_vec().unwrap(),
+                },
+                swap_ix(100, expected_out),
+            ],
+            Some(&payer.pubkey()),
+            &[&payer, &user],
+            recent_blockhash,
+        );
+        banks_client.process_transaction(transaction).await.unwrap();
+
+        let pool_account = banks_client.get_account(pool).await.unwrap().unwrap();
+        assert_eq!(
+            Pool::try_from_slice(&pool_account.data).unwrap(),
+            Pool {
+                reserve_a: 1_100,
+                reserve_b: 2_000 - expected_out,
+            }
+        );
+        let buffer = banks_client.get_account(receipts).await.unwrap().unwrap();
+        assert_eq!(buffer.owner, echo::id());
+        let header_len = AuthorizedBufferHeader::header_len(&None);
+        assert_eq!(
+            SwapReceipt::try_from_slice(&buffer.data[header_len..]).unwrap(),
+            SwapReceipt {
+                user: user.pubkey(),
+                amount_in: 100,
+                amount_out: expected_out,
+            }
+        );
+
+        // Slippage fails the whole swap, receipt included
+        let transaction = Transaction::new_signed_with_payer(
+            &[swap_ix(100, 2_000)],
+            Some(&payer.pubkey()),
+            &[&payer, &user],
+            recent_blockhash,
+        );
+        assert!(banks_client.process_transaction(transaction).await.is_err());
+        let buffer_after = banks_client.get_account(receipts).await.unwrap().unwrap();
+        assert_eq!(buffer_after.data, buffer.data);
+    });
+}
@@ -0,0 +1,5 @@
+fn main() {
+    // Generates the gRPC service of src/bin/grpc_server.rs
+    #[cfg(feature = "grpc-server")]
+    tonic_build::compile_protos("proto/echo.proto").unwrap();
+}
@@ -0,0 +1,289 @@
+//! gRPC flavour of the snapshot server, for internal services that should not depend on Solana crates. The
+//! interface is defined in proto/echo.proto.
+//!
+//! ```text
+//! cargo run --features grpc-server --bin grpc_server -- (--program-id <PROGRAM_ID> | --cluster <CLUSTER>) \
+//!     [--url http://localhost:8899] [--keypair ~/.config/solana/id.json] [--listen 127.0.0.1:50051] \
+//!     [--poll-interval-ms 1000]
+//! ```
+//!
+//! Snapshots are signed like those of the snapshot server. `WatchBuffer` polls the account every
+//! `--poll-interval-ms` and streams a snapshot whenever its data hash changes. `WriteBuffer` sends an
+//! `AuthorizedEcho` signed and paid for by the server keypair, so it only works for buffers whose authority is that
+//! keypair: run it in custodial setups only.
+
+// `tonic::Status` is the error type the generated service expects from every handler
+#![allow(clippy::result_large_err)]
+
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use borsh::BorshSerialize;
+use echo::instruction::EchoInstruction;
+use echo::program_ids::Cluster;
+use echo::state::AuthorizedBufferHeader;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::account::Account;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::hash;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+use tonic::{transport::Server, Request, Response, Status};
+
+use proto::echo_service_server::{EchoService, EchoServiceServer};
+use proto::{BufferSnapshot, ReadBufferRequest, WriteBufferRequest, WriteBufferResponse};
+
+pub mod proto {
+    tonic::include_proto!("echo");
+}
+
+struct Config {
+    url: String,
+    keypair: String,
+    program_id: Pubkey,
+    listen: String,
+    poll_interval: Duration,
+}
+
+#[derive(Clone)]
+struct EchoServer {
+    rpc_client: Arc<RpcClient>,
+    program_id: Pubkey,
+    keypair: Arc<Keypair>,
+    poll_interval: Duration,
+}
+
+fn parse_args() -> Result<Config, String> {
+    let mut config = Config {
+        url: "http://localhost:8899".to_string(),
+        keypair: format!(
+            "{}/.config/solana/id.json",
+            std::env::var("HOME").unwrap_or_default()
+        ),
+        program_id: Pubkey::default(),
+        listen: "127.0.0.1:50051".to_string(),
+        poll_interval: Duration::from_millis(1000),
+    };
+    let mut program_id = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or(format!("missing value for {}", flag))?;
+        match flag.as_str() {
+            "--url" => config.url = value,
+            "--keypair" => config.keypair = value,
+            "--listen" => config.listen = value,
+            "--program-id" => {
+                program_id = Some(Pubkey::from_str(&value).map_err(|e| e.to_string())?)
+            }
+            "--cluster" => program_id = Some(Cluster::from_str(&value)?.program_id()),
+            "--poll-interval-ms" => {
+                config.poll_interval =
+                    Duration::from_millis(value.parse().map_err(|_| "invalid --poll-interval-ms")?)
+            }
+            _ => return Err(format!("unknown flag {}", flag)),
+        }
+    }
+
+    config.program_id = program_id.ok_or("--program-id or --cluster is required")?;
+    Ok(config)
+}
+
+fn parse_pubkey(pubkey: &str) -> Result<Pubkey, Status> {
+    Pubkey::from_str(pubkey).map_err(|_| Status::invalid_argument("invalid pubkey"))
+}
+
+/// Header and echo data of `account` if it is an authorized buffer of `program_id`, legacy layout included.
+fn authorized_buffer_header(
+    program_id: &Pubkey,
+    account: &Account,
+) -> Option<AuthorizedBufferHeader> {
+    if account.owner != *program_id {
+        return None;
+    }
+    AuthorizedBufferHeader::unpack(&account.data).ok()
+}
+
+/// Runs the blocking RPC call `f` off the async runtime.
+async fn blocking<T, F>(f: F) -> Result<T, Status>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Status> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+}
+
+impl EchoServer {
+    fn snapshot(&self, pubkey: &Pubkey) -> Result<BufferSnapshot, Status> {
+        let response = self
+            .rpc_client
+            .get_account_with_commitment(pubkey, CommitmentConfig::confirmed())
+            .map_err(|e| Status::unavailable(e.to_string()))?;
+        let account = response
+            .value
+            .ok_or_else(|| Status::not_found("account not found"))?;
+
+        let slot = response.context.slot;
+        let data_hash = hash(&account.data);
+        let mut message = slot.to_le_bytes().to_vec();
+        message.extend_from_slice(data_hash.as_ref());
+        let echo_data =
+            authorized_buffer_header(&self.program_id, &account).map(|header| header.echo_data);
+        Ok(BufferSnapshot {
+            pubkey: pubkey.to_string(),
+            slot,
+            owner: account.owner.to_string(),
+            is_authorized_buffer: echo_data.is_some(),
+            echo_data: echo_data.unwrap_or_default(),
+            data: account.data,
+            hash: data_hash.as_ref().to_vec(),
+            signature: self.keypair.sign_message(&message).as_ref().to_vec(),
+            signer: self.keypair.pubkey().to_string(),
+        })
+    }
+
+    fn write(&self, pubkey: &Pubkey, data: Vec<u8>) -> Result<WriteBufferResponse, Status> {
+        let instruction = Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new(*pubkey, false),
+                AccountMeta::new_readonly(self.keypair.pubkey(), true),
+            ],
+            data: EchoInstruction::AuthorizedEcho { data }
+                .try_to_vec()
+                .map_err(|e| Status::internal(e.to_string()))?,
+        };
+        let blockhash = self
+            .rpc_client
+            .get_latest_blockhash()
+            .map_err(|e| Status::unavailable(e.to_string()))?;
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.keypair.pubkey()),
+            &[self.keypair.as_ref()],
+            blockhash,
+        );
+        let signature = self
+            .rpc_client
+            .send_and_confirm_transaction(&transaction)
+            .map_err(|e| match e.get_transaction_error() {
+                Some(transaction_error) => {
+                    Status::failed_precondition(transaction_error.to_string())
+                }
+                None => Status::unavailable(e.to_string()),
+            })?;
+        Ok(WriteBufferResponse {
+            signature: signature.to_string(),
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl EchoService for EchoServer {
+    type WatchBufferStream =
+        Pin<Box<dyn Stream<Item = Result<BufferSnapshot, Status>> + Send + 'static>>;
+
+    async fn read_buffer(
+        &self,
+        request: Request<ReadBufferRequest>,
+    ) -> Result<Response<BufferSnapshot>, Status> {
+        let pubkey = parse_pubkey(&request.into_inner().pubkey)?;
+        let server = self.clone();
+        let snapshot = blocking(move || server.snapshot(&pubkey)).await?;
+        Ok(Response::new(snapshot))
+    }
+
+    async fn watch_buffer(
+        &self,
+        request: Request<ReadBufferRequest>,
+    ) -> Result<Response<Self::WatchBufferStream>, Status> {
+        let pubkey = parse_pubkey(&request.into_inner().pubkey)?;
+        let server = self.clone();
+        let (sender, receiver) = mpsc::channel(4);
+        tokio::spawn(async move {
+            let mut last_hash = None;
+            loop {
+                let watcher = server.clone();
+                let snapshot = blocking(move || watcher.snapshot(&pubkey)).await;
+                let changed = match &snapshot {
+                    Ok(snapshot) => last_hash.as_ref() != Some(&snapshot.hash),
+                    Err(_) => true,
+                };
+                if changed {
+                    let failed = snapshot.is_err();
+                    if let Ok(snapshot) = &snapshot {
+                        last_hash = Some(snapshot.hash.clone());
+                    }
+                    // Stop once the client went away or the buffer cannot be read anymore
+                    if sender.send(snapshot).await.is_err() || failed {
+                        break;
+                    }
+                }
+                tokio::time::sleep(server.poll_interval).await;
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+
+    async fn write_buffer(
+        &self,
+        request: Request<WriteBufferRequest>,
+    ) -> Result<Response<WriteBufferResponse>, Status> {
+        let request = request.into_inner();
+        let pubkey = parse_pubkey(&request.pubkey)?;
+        let server = self.clone();
+        let response = blocking(move || server.write(&pubkey, request.data)).await?;
+        Ok(Response::new(response))
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let config = match parse_args() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            std::process::exit(2);
+        }
+    };
+    let keypair = read_keypair_file(&config.keypair).unwrap_or_else(|e| {
+        eprintln!("error: cannot read keypair {}: {}", config.keypair, e);
+        std::process::exit(2);
+    });
+    let listen = config.listen.parse().unwrap_or_else(|_| {
+        eprintln!("error: invalid --listen {}", config.listen);
+        std::process::exit(2);
+    });
+    let server = EchoServer {
+        rpc_client: Arc::new(RpcClient::new_with_commitment(
+            config.url.clone(),
+            CommitmentConfig::confirmed(),
+        )),
+        program_id: config.program_id,
+        keypair: Arc::new(keypair),
+        poll_interval: config.poll_interval,
+    };
+
+    println!(
+        "serving gRPC signed by {} on {}",
+        server.keypair.pubkey(),
+        config.listen
+    );
+    if let Err(error) = Server::builder()
+        .add_service(EchoServiceServer::new(server))
+        .serve(listen)
+        .await
+    {
+        eprintln!("error: {}", error);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,230 @@
+//! Replays a transaction against a native build of the Echo Program to debug failed writes.
+//!
+//! ```text
+//! cargo run --features replay --bin replay -- (--program-id <PROGRAM_ID> | --cluster <CLUSTER>) \
+//!     --signature <SIGNATURE> [--url http://localhost:8899]
+//! ```
+//!
+//! The transaction and every account it references are fetched over RPC, then the Echo Program instructions are
+//! run in order through `Processor::process_instruction` with the Clock of the slot the transaction landed in.
+//! All program logs are printed, so the assertion that failed shows up right before the returned error.
+//!
+//! RPC nodes only serve the latest state of an account. A failed transaction does not change its accounts, so
+//! the replay is exact as long as nothing else wrote to them since. Signer flags are taken from the original message
+//! and CPIs are logged but not executed.
+
+use std::str::FromStr;
+
+use borsh::BorshDeserialize;
+use echo::error::EchoError;
+use echo::instruction::EchoInstruction;
+use echo::processor::Processor;
+use echo::program_ids::Cluster;
+use num_traits::FromPrimitive;
+use solana_client::rpc_client::RpcClient;
+use solana_program::account_info::AccountInfo;
+use solana_program::clock::Clock;
+use solana_program::entrypoint::{ProgramResult, MAX_PERMITTED_DATA_INCREASE, SUCCESS};
+use solana_program::instruction::Instruction;
+use solana_program::program_error::ProgramError;
+use solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+use solana_program::rent::Rent;
+use solana_sdk::account::from_account;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::UiTransactionEncoding;
+
+struct Config {
+    url: String,
+    program_id: Pubkey,
+    signature: Signature,
+}
+
+struct ReplayStubs {
+    clock: Clock,
+    rent: Rent,
+}
+
+impl SyscallStubs for ReplayStubs {
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        _account_infos: &[AccountInfo],
+        _signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        println!("replay: CPI to {} not executed", instruction.program_id);
+        Ok(())
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Clock) = self.clock.clone() };
+        SUCCESS
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Rent) = self.rent };
+        SUCCESS
+    }
+}
+
+/// Account state with room in front of and after the data, as `AccountInfo::realloc` expects from the runtime.
+struct LoadedAccount {
+    key: Pubkey,
+    lamports: u64,
+    data_len: usize,
+    data: Vec<u8>,
+    owner: Pubkey,
+    executable: bool,
+    rent_epoch: u64,
+}
+
+fn parse_args() -> Result<Config, String> {
+    let mut url = "http://localhost:8899".to_string();
+    let mut program_id = None;
+    let mut signature = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or(format!("missing value for {}", flag))?;
+        match flag.as_str() {
+            "--url" => url = value,
+            "--program-id" => {
+                program_id = Some(Pubkey::from_str(&value).map_err(|e| e.to_string())?)
+            }
+            "--cluster" => program_id = Some(Cluster::from_str(&value)?.program_id()),
+            "--signature" => {
+                signature = Some(Signature::from_str(&value).map_err(|e| e.to_string())?)
+            }
+            _ => return Err(format!("unknown flag {}", flag)),
+        }
+    }
+
+    Ok(Config {
+        url,
+        program_id: program_id.ok_or("--program-id or --cluster is required")?,
+        signature: signature.ok_or("--signature is required")?,
+    })
+}
+
+fn describe(error: &ProgramError) -> String {
+    match error {
+        ProgramError::Custom(code) => match EchoError::from_u32(*code) {
+            Some(echo_error) => format!("{:?} ({})", echo_error, echo_error),
+            None => error.to_string(),
+        },
+        _ => error.to_string(),
+    }
+}
+
+fn replay(config: &Config) -> Result<(), String> {
+    let rpc_client =
+        RpcClient::new_with_commitment(config.url.clone(), CommitmentConfig::confirmed());
+    let confirmed = rpc_client
+        .get_transaction(&config.signature, UiTransactionEncoding::Base58)
+        .map_err(|e| e.to_string())?;
+    let transaction = confirmed
+        .transaction
+        .transaction
+        .decode()
+        .ok_or("cannot decode the transaction")?;
+    let message = &transaction.message;
+
+    println!("slot: {}", confirmed.slot);
+    match confirmed.transaction.meta.and_then(|meta| meta.err) {
+        Some(error) => println!("original error: {}", error),
+        None => println!("original error: none, the transaction succeeded"),
+    }
+
+    let mut loaded = Vec::with_capacity(message.account_keys.len());
+    for key in &message.account_keys {
+        let account = rpc_client
+            .get_account_with_commitment(key, CommitmentConfig::confirmed())
+            .map_err(|e| e.to_string())?
+            .value
+            .unwrap_or_default();
+        let data_len = account.data.len();
+        let mut data = vec![0; 8 + data_len + MAX_PERMITTED_DATA_INCREASE];
+        data[..8].copy_from_slice(&(data_len as u64).to_le_bytes());
+        data[8..8 + data_len].copy_from_slice(&account.data);
+        loaded.push(LoadedAccount {
+            key: *key,
+            lamports: account.lamports,
+            data_len,
+            data,
+            owner: account.owner,
+            executable: account.executable,
+            rent_epoch: account.rent_epoch,
+        });
+    }
+
+    set_syscall_stubs(Box::new(ReplayStubs {
+        clock: Clock {
+            slot: confirmed.slot,
+            unix_timestamp: confirmed.block_time.unwrap_or_default(),
+            ..Clock::default()
+        },
+        rent: rpc_client
+            .get_account(&solana_program::sysvar::rent::id())
+            .ok()
+            .and_then(|account| from_account(&account))
+            .unwrap_or_default(),
+    }));
+
+    let account_infos: Vec<AccountInfo> = loaded
+        .iter_mut()
+        .enumerate()
+        .map(|(i, account)| {
+            AccountInfo::new(
+                &account.key,
+                message.is_signer(i),
+                message.is_writable(i, true),
+                &mut account.lamports,
+                &mut account.data[8..8 + account.data_len],
+                &account.owner,
+                account.executable,
+                account.rent_epoch,
+            )
+        })
+        .collect();
+
+    for (index, instruction) in message.instructions.iter().enumerate() {
+        let program_id = message.account_keys[instruction.program_id_index as usize];
+        if program_id != config.program_id {
+            println!("instruction {}: skipped, program {}", index, program_id);
+            continue;
+        }
+        match EchoInstruction::try_from_slice(&instruction.data) {
+            Ok(echo_instruction) => println!("instruction {}: {:?}", index, echo_instruction),
+            Err(_) => println!("instruction {}: undecodable data", index),
+        }
+
+        let accounts: Vec<AccountInfo> = instruction
+            .accounts
+            .iter()
+            .map(|&account_index| account_infos[account_index as usize].clone())
+            .collect();
+        if let Err(error) =
+            Processor::process_instruction(&program_id, &accounts, &instruction.data)
+        {
+            println!("instruction {} failed: {}", index, describe(&error));
+            return Ok(());
+        }
+        println!("instruction {} succeeded", index);
+    }
+    Ok(())
+}
+
+fn main() {
+    let config = match parse_args() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            std::process::exit(2);
+        }
+    };
+    if let Err(error) = replay(&config) {
+        eprintln!("error: {}", error);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,267 @@
+//! Serves signed read snapshots of Echo Program buffers over plain HTTP, for web consumers that cannot speak
+//! Solana RPC.
+//!
+//! ```text
+//! cargo run --features snapshot-server --bin snapshot_server -- (--program-id <PROGRAM_ID> | --cluster <CLUSTER>) \
+//!     [--url http://localhost:8899] [--keypair ~/.config/solana/id.json] [--listen 127.0.0.1:8080]
+//! ```
+//!
+//! `GET /buffer/<pubkey>` answers with the account fetched at the `confirmed` commitment, decoded when it is an
+//! authorized buffer of the program, along with the slot it was read at, the sha256 `hash` of the account data and
+//! the `signature` of the server keypair over the 8 little-endian bytes of the slot followed by the 32 bytes of the
+//! hash. Clients verify the signature against `signer`, which they should pin.
+//!
+//! `GET /stats` answers the same way for the global stats account of the program, decoded into its counters of
+//! buffers created, writes, tokens burned and lamport fees, see `InitializeGlobalStats`.
+//!
+//! Requests are handled one at a time.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::str::FromStr;
+
+use borsh::BorshDeserialize;
+use echo::pda::find_global_stats_address;
+use echo::program_ids::Cluster;
+use echo::state::{AuthorizedBufferHeader, GlobalStats};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::account::Account;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+
+struct Config {
+    url: String,
+    keypair: String,
+    program_id: Pubkey,
+    listen: String,
+}
+
+fn parse_args() -> Result<Config, String> {
+    let mut url = "http://localhost:8899".to_string();
+    let mut keypair = format!(
+        "{}/.config/solana/id.json",
+        std::env::var("HOME").unwrap_or_default()
+    );
+    let mut listen = "127.0.0.1:8080".to_string();
+    let mut program_id = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or(format!("missing value for {}", flag))?;
+        match flag.as_str() {
+            "--url" => url = value,
+            "--keypair" => keypair = value,
+            "--listen" => listen = value,
+            "--program-id" => {
+                program_id = Some(Pubkey::from_str(&value).map_err(|e| e.to_string())?)
+            }
+            "--cluster" => program_id = Some(Cluster::from_str(&value)?.program_id()),
+            _ => return Err(format!("unknown flag {}", flag)),
+        }
+    }
+
+    Ok(Config {
+        url,
+        keypair,
+        program_id: program_id.ok_or("--program-id or --cluster is required")?,
+        listen,
+    })
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Header and echo data of `account` if it is an authorized buffer of `program_id`, legacy layout included.
+fn authorized_buffer_header(
+    program_id: &Pubkey,
+    account: &Account,
+) -> Option<AuthorizedBufferHeader> {
+    if account.owner != *program_id {
+        return None;
+    }
+    AuthorizedBufferHeader::unpack(&account.data).ok()
+}
+
+/// Counters of `account` if it is the global stats account of `program_id`.
+fn global_stats(program_id: &Pubkey, pubkey: &Pubkey, account: &Account) -> Option<GlobalStats> {
+    if account.owner != *program_id || *pubkey != find_global_stats_address(program_id).0 {
+        return None;
+    }
+    GlobalStats::try_from_slice(&account.data).ok()
+}
+
+/// JSON fields of `account`, decoded as the global stats or an authorized buffer when it is one.
+fn describe_account(program_id: &Pubkey, pubkey: &Pubkey, account: &Account) -> String {
+    if let Some(stats) = global_stats(program_id, pubkey, account) {
+        return format!(
+            concat!(
+                r#""kind":"global_stats","buffers_created":{},"writes":{},"tokens_burned":{},"#,
+                r#""lamport_fees":{}"#
+            ),
+            stats.buffers_created, stats.writes, stats.tokens_burned, stats.lamport_fees,
+        );
+    }
+    let header = authorized_buffer_header(program_id, account);
+    match header {
+        Some(header) => {
+            let namespace = match header.namespace {
+                Some(namespace) => format!("\"{}\"", to_hex(&namespace)),
+                None => "null".to_string(),
+            };
+            // PDA buffers derive their authority from their address
+            let authority = if header.is_keypair_buffer() {
+                format!("\"{}\"", header.authority)
+            } else {
+                "null".to_string()
+            };
+            format!(
+                concat!(
+                    r#""kind":"authorized_buffer","buffer_seed":{},"version":{},"namespace":{},"#,
+                    r#""write_deadline_slot":{},"close_after_slot":{},"reference_count":{},"authority":{},"#,
+                    r#""verify_after_write":{},"chunk_checksums":{},"write_offset":{},"features":{},"#,
+                    r#""echo_data":"{}""#
+                ),
+                header.buffer_seed,
+                header.version,
+                namespace,
+                header.write_deadline_slot,
+                header.close_after_slot,
+                header.reference_count,
+                authority,
+                header.verify_after_write,
+                header.chunk_checksums,
+                header.write_offset,
+                header.features,
+                to_hex(&header.echo_data),
+            )
+        }
+        None => format!(
+            r#""kind":"raw","owner":"{}","data":"{}""#,
+            account.owner,
+            to_hex(&account.data)
+        ),
+    }
+}
+
+/// Status line and JSON body answering `GET /buffer/<pubkey>` and `GET /stats`.
+fn snapshot(
+    rpc_client: &RpcClient,
+    program_id: &Pubkey,
+    keypair: &Keypair,
+    pubkey: &str,
+) -> (&'static str, String) {
+    let pubkey = match Pubkey::from_str(pubkey) {
+        Ok(pubkey) => pubkey,
+        Err(_) => {
+            return (
+                "400 Bad Request",
+                r#"{"error":"invalid pubkey"}"#.to_string(),
+            )
+        }
+    };
+    let response =
+        match rpc_client.get_account_with_commitment(&pubkey, CommitmentConfig::confirmed()) {
+            Ok(response) => response,
+            Err(e) => {
+                return (
+                    "502 Bad Gateway",
+                    format!(r#"{{"error":"{}"}}"#, e.to_string().replace('"', "'")),
+                )
+            }
+        };
+    let account = match response.value {
+        Some(account) => account,
+        None => {
+            return (
+                "404 Not Found",
+                r#"{"error":"account not found"}"#.to_string(),
+            )
+        }
+    };
+
+    let slot = response.context.slot;
+    let data_hash = hash(&account.data);
+    let mut message = slot.to_le_bytes().to_vec();
+    message.extend_from_slice(data_hash.as_ref());
+    let signature = keypair.sign_message(&message);
+    (
+        "200 OK",
+        format!(
+            r#"{{"pubkey":"{}","slot":{},{},"hash":"{}","signature":"{}","signer":"{}"}}"#,
+            pubkey,
+            slot,
+            describe_account(program_id, &pubkey, &account),
+            data_hash,
+            signature,
+            keypair.pubkey(),
+        ),
+    )
+}
+
+fn handle(
+    stream: TcpStream,
+    rpc_client: &RpcClient,
+    program_id: &Pubkey,
+    keypair: &Keypair,
+) -> std::io::Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next().unwrap_or_default()) {
+        (Some("GET"), "/stats") => {
+            let (global_stats, _) = find_global_stats_address(program_id);
+            snapshot(rpc_client, program_id, keypair, &global_stats.to_string())
+        }
+        (Some("GET"), path) => match path.strip_prefix("/buffer/") {
+            Some(pubkey) => snapshot(rpc_client, program_id, keypair, pubkey),
+            None => ("404 Not Found", r#"{"error":"not found"}"#.to_string()),
+        },
+        _ => (
+            "405 Method Not Allowed",
+            r#"{"error":"method not allowed"}"#.to_string(),
+        ),
+    };
+    write!(
+        &stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+fn main() {
+    let config = match parse_args() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            std::process::exit(2);
+        }
+    };
+    let keypair = read_keypair_file(&config.keypair).unwrap_or_else(|e| {
+        eprintln!("error: cannot read keypair {}: {}", config.keypair, e);
+        std::process::exit(2);
+    });
+    let listener = TcpListener::bind(&config.listen).unwrap_or_else(|e| {
+        eprintln!("error: cannot listen on {}: {}", config.listen, e);
+        std::process::exit(1);
+    });
+    let rpc_client =
+        RpcClient::new_with_commitment(config.url.clone(), CommitmentConfig::confirmed());
+
+    println!(
+        "serving snapshots signed by {} on {}",
+        keypair.pubkey(),
+        config.listen
+    );
+    for stream in listener.incoming() {
+        let result =
+            stream.and_then(|stream| handle(stream, &rpc_client, &config.program_id, &keypair));
+        if let Err(error) = result {
+            eprintln!("request failed: {}", error);
+        }
+    }
+}
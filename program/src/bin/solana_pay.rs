@@ -0,0 +1,352 @@
+//! Answers Solana Pay transaction requests buying a message on an Echo Program vending machine, so mobile wallets
+//! can write to a machine by scanning a QR code.
+//!
+//! ```text
+//! cargo run --features solana-pay --bin solana_pay -- (--program-id <PROGRAM_ID> | --cluster <CLUSTER>) \
+//!     [--url http://localhost:8899] [--listen 127.0.0.1:8081] [--label Echo] [--icon <URL>]
+//! ```
+//!
+//! The frontend renders a QR code of
+//!
+//! ```text
+//! solana:https://<host>/pay?machine=<MACHINE>&mint=<MINT>&message=<URL_ENCODED_MESSAGE>[&machine_seed=<SEED>]
+//! ```
+//!
+//! where `machine_seed` is only given for machines initialized with one.
+//!
+//! Wallets first `GET` that URL for the label and icon of the merchant, then `POST` `{"account":"<pubkey>"}` to it
+//! and receive an unsigned `VendingMachineEcho` transaction, paid for and signed by `account`, which burns the
+//! price of the machine from the associated token account of `account` and counts `account` in the unique writers
+//! of the machine. Receipts are minted to the associated token account of `account`, created if missing. Holding
+//! gates and Dutch auctions are handled, sessions are not. Requests are handled one at a time.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::str::FromStr;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use echo::instruction::EchoInstruction;
+use echo::pda::{
+    create_vending_machine_address, find_dutch_auction_address, find_holding_gate_address,
+    find_holding_registration_address, find_receipt_mint_address, find_writer_marker_address,
+};
+use echo::pricing::purchase_price;
+use echo::program_ids::Cluster;
+use echo::state::{DutchAuction, Trailing, VendingMachineBufferHeader, WriterMarker};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_program;
+use solana_sdk::transaction::Transaction;
+use spl_associated_token_account::{create_associated_token_account, get_associated_token_address};
+
+struct Config {
+    url: String,
+    program_id: Pubkey,
+    listen: String,
+    label: String,
+    icon: String,
+}
+
+struct Purchase {
+    machine: Pubkey,
+    mint: Pubkey,
+    message: Vec<u8>,
+    machine_seed: Option<u64>,
+}
+
+fn parse_args() -> Result<Config, String> {
+    let mut config = Config {
+        url: "http://localhost:8899".to_string(),
+        program_id: Pubkey::default(),
+        listen: "127.0.0.1:8081".to_string(),
+        label: "Echo".to_string(),
+        icon: String::new(),
+    };
+    let mut program_id = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or(format!("missing value for {}", flag))?;
+        match flag.as_str() {
+            "--url" => config.url = value,
+            "--listen" => config.listen = value,
+            "--label" => config.label = value,
+            "--icon" => config.icon = value,
+            "--program-id" => {
+                program_id = Some(Pubkey::from_str(&value).map_err(|e| e.to_string())?)
+            }
+            "--cluster" => program_id = Some(Cluster::from_str(&value)?.program_id()),
+            _ => return Err(format!("unknown flag {}", flag)),
+        }
+    }
+
+    config.program_id = program_id.ok_or("--program-id or --cluster is required")?;
+    Ok(config)
+}
+
+/// Decodes a `application/x-www-form-urlencoded` query value.
+fn url_decode(value: &str) -> Result<Vec<u8>, String> {
+    let mut decoded = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let hex = [
+                    bytes.next().ok_or("truncated escape")?,
+                    bytes.next().ok_or("truncated escape")?,
+                ];
+                let hex = std::str::from_utf8(&hex).map_err(|_| "invalid escape")?;
+                decoded.push(u8::from_str_radix(hex, 16).map_err(|_| "invalid escape")?);
+            }
+            byte => decoded.push(byte),
+        }
+    }
+    Ok(decoded)
+}
+
+fn parse_purchase(query: &str) -> Result<Purchase, String> {
+    let (mut machine, mut mint, mut message, mut machine_seed) = (None, None, None, None);
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        match key {
+            "machine" => machine = Some(Pubkey::from_str(value).map_err(|_| "invalid machine")?),
+            "mint" => mint = Some(Pubkey::from_str(value).map_err(|_| "invalid mint")?),
+            "message" => message = Some(url_decode(value)?),
+            "machine_seed" => {
+                machine_seed = Some(u64::from_str(value).map_err(|_| "invalid machine_seed")?)
+            }
+            _ => {}
+        }
+    }
+    Ok(Purchase {
+        machine: machine.ok_or("machine is required")?,
+        mint: mint.ok_or("mint is required")?,
+        message: message.ok_or("message is required")?,
+        machine_seed,
+    })
+}
+
+fn json_error(error: &str) -> String {
+    format!(r#"{{"error":"{}"}}"#, error.replace('"', "'"))
+}
+
+/// Status line and JSON body answering the `POST` of `account` for `purchase`.
+fn transaction_request(
+    rpc_client: &RpcClient,
+    program_id: &Pubkey,
+    purchase: &Purchase,
+    body: &[u8],
+) -> (&'static str, String) {
+    let account = match serde_json::from_slice::<serde_json::Value>(body)
+        .ok()
+        .and_then(|body| body["account"].as_str().map(str::to_string))
+        .and_then(|account| Pubkey::from_str(&account).ok())
+    {
+        Some(account) => account,
+        None => return ("400 Bad Request", json_error("invalid account")),
+    };
+
+    let machine_data = match rpc_client.get_account_data(&purchase.machine) {
+        Ok(data) => data,
+        Err(e) => return ("502 Bad Gateway", json_error(&e.to_string())),
+    };
+    let machine = match VendingMachineBufferHeader::try_from_slice(&machine_data) {
+        Ok(machine) => machine,
+        Err(_) => return ("400 Bad Request", json_error("not a vending machine")),
+    };
+    let is_machine_of_mint = create_vending_machine_address(
+        program_id,
+        &purchase.mint,
+        machine.price,
+        purchase.machine_seed,
+        machine.bump_seed,
+    ) == Ok(purchase.machine);
+    if !is_machine_of_mint {
+        return (
+            "400 Bad Request",
+            json_error("machine does not sell for mint"),
+        );
+    }
+
+    let user_token_account = get_associated_token_address(&account, &purchase.mint);
+    let mut accounts = vec![
+        AccountMeta::new(purchase.machine, false),
+        // Writable to pay for the writer marker
+        AccountMeta::new(account, true),
+        AccountMeta::new(user_token_account, false),
+        AccountMeta::new(purchase.mint, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        // No session
+        AccountMeta::new_readonly(*program_id, false),
+    ];
+    if machine.holding_gated {
+        let (holding_gate, _) = find_holding_gate_address(program_id, &purchase.machine);
+        let (holding_registration, _) =
+            find_holding_registration_address(program_id, &purchase.machine, &user_token_account);
+        accounts.push(AccountMeta::new_readonly(holding_gate, false));
+        accounts.push(AccountMeta::new_readonly(holding_registration, false));
+    }
+    let mut base_price = machine.price;
+    if machine.dutch_auction {
+        let (dutch_auction, _) = find_dutch_auction_address(program_id, &purchase.machine);
+        accounts.push(AccountMeta::new(dutch_auction, false));
+        // The price keeps decaying until the wallet sends the transaction, so this quote is an upper bound
+        let auction = match rpc_client
+            .get_account_data(&dutch_auction)
+            .map_err(|e| e.to_string())
+            .and_then(|data| DutchAuction::try_from_slice(&data).map_err(|e| e.to_string()))
+        {
+            Ok(auction) => auction,
+            Err(e) => return ("502 Bad Gateway", json_error(&e)),
+        };
+        base_price = match rpc_client.get_slot() {
+            Ok(slot) => auction.price_at(slot),
+            Err(e) => return ("502 Bad Gateway", json_error(&e.to_string())),
+        };
+    }
+    let mut instructions = vec![];
+    if machine.receipts {
+        let (receipt_mint, _) = find_receipt_mint_address(program_id, &purchase.machine);
+        let receipt_account = get_associated_token_address(&account, &receipt_mint);
+        if rpc_client.get_account_data(&receipt_account).is_err() {
+            instructions.push(create_associated_token_account(
+                &account,
+                &account,
+                &receipt_mint,
+            ));
+        }
+        accounts.push(AccountMeta::new(receipt_mint, false));
+        accounts.push(AccountMeta::new(receipt_account, false));
+    }
+    let (writer_marker, _) =
+        find_writer_marker_address(program_id, &purchase.machine, &user_token_account);
+    accounts.push(AccountMeta::new(writer_marker, false));
+    // Missing markers have no purchases yet, markers that predate the purchase count have at least one
+    let purchases = match rpc_client.get_account_data(&writer_marker) {
+        Ok(data) => WriterMarker::try_from_slice(&data).map_or(0, |marker| marker.purchases.max(1)),
+        Err(_) => 0,
+    };
+    let price = match purchase_price(base_price, machine.quadratic_pricing, purchases + 1) {
+        Some(price) => price,
+        None => return ("400 Bad Request", json_error("price overflows")),
+    };
+    accounts.push(AccountMeta::new_readonly(system_program::id(), false));
+    let data = EchoInstruction::VendingMachineEcho {
+        data: purchase.message.clone(),
+        machine_seed: Trailing(purchase.machine_seed),
+    }
+    .try_to_vec()
+    .expect("serializing to a Vec does not fail");
+    instructions.push(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    });
+
+    let blockhash = match rpc_client.get_latest_blockhash() {
+        Ok(blockhash) => blockhash,
+        Err(e) => return ("502 Bad Gateway", json_error(&e.to_string())),
+    };
+    let mut transaction = Transaction::new_with_payer(&instructions, Some(&account));
+    transaction.message.recent_blockhash = blockhash;
+    let transaction =
+        bincode::serialize(&transaction).expect("serializing a transaction does not fail");
+    (
+        "200 OK",
+        format!(
+            r#"{{"transaction":"{}","message":"Buy a message for {} tokens"}}"#,
+            base64::encode(transaction),
+            price,
+        ),
+    )
+}
+
+fn handle(stream: TcpStream, rpc_client: &RpcClient, config: &Config) -> std::io::Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next();
+    let (path, query) = parts
+        .next()
+        .map(|target| target.split_once('?').unwrap_or((target, "")))
+        .unwrap_or(("", ""));
+    let (status, body) = match (method, path) {
+        (Some("GET"), "/pay") => (
+            "200 OK",
+            format!(
+                r#"{{"label":"{}","icon":"{}"}}"#,
+                config.label.replace('"', "'"),
+                config.icon.replace('"', "'")
+            ),
+        ),
+        (Some("POST"), "/pay") => {
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body)?;
+            match parse_purchase(query) {
+                Ok(purchase) => {
+                    transaction_request(rpc_client, &config.program_id, &purchase, &body)
+                }
+                Err(error) => ("400 Bad Request", json_error(&error)),
+            }
+        }
+        (Some("OPTIONS"), "/pay") => ("204 No Content", String::new()),
+        (Some("GET"), _) | (Some("POST"), _) => ("404 Not Found", json_error("not found")),
+        _ => ("405 Method Not Allowed", json_error("method not allowed")),
+    };
+    // Wallets and frontends on other origins must be able to call the endpoint
+    write!(
+        &stream,
+        concat!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n",
+            "Access-Control-Allow-Origin: *\r\nAccess-Control-Allow-Methods: GET, POST, OPTIONS\r\n",
+            "Access-Control-Allow-Headers: Content-Type\r\nConnection: close\r\n\r\n{}"
+        ),
+        status,
+        body.len(),
+        body
+    )
+}
+
+fn main() {
+    let config = match parse_args() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            std::process::exit(2);
+        }
+    };
+    let listener = TcpListener::bind(&config.listen).unwrap_or_else(|e| {
+        eprintln!("error: cannot listen on {}: {}", config.listen, e);
+        std::process::exit(1);
+    });
+    let rpc_client =
+        RpcClient::new_with_commitment(config.url.clone(), CommitmentConfig::confirmed());
+
+    println!(
+        "serving Solana Pay transaction requests on {}",
+        config.listen
+    );
+    for stream in listener.incoming() {
+        let result = stream.and_then(|stream| handle(stream, &rpc_client, &config));
+        if let Err(error) = result {
+            eprintln!("request failed: {}", error);
+        }
+    }
+}
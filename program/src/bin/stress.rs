@@ -0,0 +1,353 @@
+//! Load generator for a localnet deployment of the Echo Program.
+//!
+//! ```text
+//! cargo run --features stress --bin stress -- (--program-id <PROGRAM_ID> | --cluster <CLUSTER>) \
+//!     [--url http://localhost:8899] [--keypair ~/.config/solana/id.json] \
+//!     [--concurrency 4] [--iterations 100] [--payload-sizes 32,256,1000] [--mix authorized=3,feed=1]
+//! ```
+//!
+//! Every worker funds its own authority, creates its own authorized buffer and feed, then sends `iterations`
+//! transactions picked from the instruction mix. The report lists the throughput, latencies, failures by error
+//! and the compute units consumed by the Echo Program.
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use borsh::BorshSerialize;
+use echo::instruction::EchoInstruction;
+use echo::pda::{find_authorized_buffer_address, find_feed_address};
+use echo::program_ids::Cluster;
+use echo::state::{AuthorizedBufferHeader, Trailing};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signature, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::system_program;
+use solana_sdk::transaction::Transaction;
+use solana_transaction_status::UiTransactionEncoding;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Authorized,
+    Feed,
+}
+
+struct Config {
+    url: String,
+    keypair: String,
+    program_id: Pubkey,
+    concurrency: usize,
+    iterations: usize,
+    payload_sizes: Vec<usize>,
+    // Each kind appears as many times as its weight
+    mix: Vec<Kind>,
+}
+
+struct Sample {
+    kind: Kind,
+    latency: Duration,
+    // Error of a failed transaction
+    error: Option<String>,
+    compute_units: Option<u64>,
+}
+
+fn parse_args() -> Result<Config, String> {
+    let mut config = Config {
+        url: "http://localhost:8899".to_string(),
+        keypair: format!(
+            "{}/.config/solana/id.json",
+            std::env::var("HOME").unwrap_or_default()
+        ),
+        program_id: Pubkey::default(),
+        concurrency: 4,
+        iterations: 100,
+        payload_sizes: vec![32, 256, 1000],
+        mix: vec![Kind::Authorized],
+    };
+    let mut program_id = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or(format!("missing value for {}", flag))?;
+        match flag.as_str() {
+            "--url" => config.url = value,
+            "--keypair" => config.keypair = value,
+            "--program-id" => {
+                program_id = Some(Pubkey::from_str(&value).map_err(|e| e.to_string())?)
+            }
+            "--cluster" => program_id = Some(Cluster::from_str(&value)?.program_id()),
+            "--concurrency" => {
+                config.concurrency = value.parse().map_err(|_| "invalid --concurrency")?
+            }
+            "--iterations" => {
+                config.iterations = value.parse().map_err(|_| "invalid --iterations")?
+            }
+            "--payload-sizes" => {
+                config.payload_sizes = value
+                    .split(',')
+                    .map(|size| size.parse())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| "invalid --payload-sizes")?
+            }
+            "--mix" => {
+                config.mix.clear();
+                for entry in value.split(',') {
+                    let (name, weight) = entry.split_once('=').ok_or("invalid --mix")?;
+                    let kind = match name {
+                        "authorized" => Kind::Authorized,
+                        "feed" => Kind::Feed,
+                        _ => return Err(format!("unknown instruction {} in --mix", name)),
+                    };
+                    let weight: usize = weight.parse().map_err(|_| "invalid --mix")?;
+                    config.mix.resize(config.mix.len() + weight, kind);
+                }
+            }
+            _ => return Err(format!("unknown flag {}", flag)),
+        }
+    }
+
+    config.program_id = program_id.ok_or("--program-id or --cluster is required")?;
+    if config.mix.is_empty() || config.payload_sizes.is_empty() || config.concurrency == 0 {
+        return Err("--mix, --payload-sizes and --concurrency must not be empty".to_string());
+    }
+    Ok(config)
+}
+
+fn send(
+    rpc_client: &RpcClient,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<Signature, String> {
+    let blockhash = rpc_client
+        .get_latest_blockhash()
+        .map_err(|e| e.to_string())?;
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&signers[0].pubkey()),
+        &signers.to_vec(),
+        blockhash,
+    );
+    rpc_client
+        .send_and_confirm_transaction(&transaction)
+        .map_err(|e| match e.get_transaction_error() {
+            Some(transaction_error) => transaction_error.to_string(),
+            None => e.to_string(),
+        })
+}
+
+/// Compute units the Echo Program reported in the logs of `signature`.
+fn compute_units(
+    rpc_client: &RpcClient,
+    program_id: &Pubkey,
+    signature: &Signature,
+) -> Option<u64> {
+    let transaction = rpc_client
+        .get_transaction(signature, UiTransactionEncoding::Json)
+        .ok()?;
+    let prefix = format!("Program {} consumed ", program_id);
+    transaction
+        .transaction
+        .meta?
+        .log_messages?
+        .iter()
+        .find_map(|log| log.strip_prefix(&prefix)?.split(' ').next()?.parse().ok())
+}
+
+fn run_worker(
+    config: &Config,
+    payer: &Keypair,
+    worker: usize,
+    samples: mpsc::Sender<Sample>,
+) -> Result<(), String> {
+    let rpc_client =
+        RpcClient::new_with_commitment(config.url.clone(), CommitmentConfig::confirmed());
+    let program_id = config.program_id;
+    let authority = Keypair::new();
+    let buffer_seed = worker as u64;
+    let (buffer, _) =
+        find_authorized_buffer_address(&program_id, &authority.pubkey(), buffer_seed, None);
+    let (feed, _) = find_feed_address(&program_id, &authority.pubkey(), buffer_seed);
+    let max_payload = config.payload_sizes.iter().copied().max().unwrap_or(0);
+
+    send(
+        &rpc_client,
+        &[
+            system_instruction::transfer(&payer.pubkey(), &authority.pubkey(), 1_000_000_000),
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(buffer, false),
+                    AccountMeta::new(authority.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::InitializeAuthorizedEcho {
+                    buffer_seed,
+                    buffer_size: AuthorizedBufferHeader::header_len(&None) + max_payload,
+                    namespace: Trailing(None),
+                    write_deadline_slot: Trailing(None),
+                }
+                .try_to_vec()
+                .map_err(|e| e.to_string())?,
+            },
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(feed, false),
+                    AccountMeta::new(authority.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::InitializeFeed {
+                    buffer_seed,
+                    exponent: 0,
+                    min_value: i128::MIN,
+                    max_value: i128::MAX,
+                }
+                .try_to_vec()
+                .map_err(|e| e.to_string())?,
+            },
+        ],
+        &[payer, &authority],
+    )?;
+
+    for i in 0..config.iterations {
+        let kind = config.mix[i % config.mix.len()];
+        let instruction = match kind {
+            Kind::Authorized => {
+                let payload_size = config.payload_sizes[i % config.payload_sizes.len()];
+                Instruction {
+                    program_id,
+                    accounts: vec![
+                        AccountMeta::new(buffer, false),
+                        AccountMeta::new_readonly(authority.pubkey(), true),
+                    ],
+                    data: EchoInstruction::AuthorizedEcho {
+                        data: vec![i as u8; payload_size],
+                    }
+                    .try_to_vec()
+                    .map_err(|e| e.to_string())?,
+                }
+            }
+            Kind::Feed => Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(feed, false),
+                    AccountMeta::new_readonly(authority.pubkey(), true),
+                ],
+                data: EchoInstruction::UpdateFeed { value: i as i128 }
+                    .try_to_vec()
+                    .map_err(|e| e.to_string())?,
+            },
+        };
+
+        let start = Instant::now();
+        let result = send(&rpc_client, &[instruction], &[payer, &authority]);
+        let latency = start.elapsed();
+        let sample = match result {
+            Ok(signature) => Sample {
+                kind,
+                latency,
+                error: None,
+                compute_units: compute_units(&rpc_client, &program_id, &signature),
+            },
+            Err(error) => Sample {
+                kind,
+                latency,
+                error: Some(error),
+                compute_units: None,
+            },
+        };
+        if samples.send(sample).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn percentile<T: Copy + Ord>(sorted: &[T], percent: usize) -> Option<T> {
+    if sorted.is_empty() {
+        return None;
+    }
+    Some(sorted[(sorted.len() - 1) * percent / 100])
+}
+
+fn report(samples: &[Sample], elapsed: Duration) {
+    let successes = samples
+        .iter()
+        .filter(|sample| sample.error.is_none())
+        .count();
+    println!("transactions: {}", samples.len());
+    println!("successes:    {}", successes);
+    println!(
+        "tps:          {:.1}",
+        successes as f64 / elapsed.as_secs_f64()
+    );
+
+    let mut latencies: Vec<Duration> = samples.iter().map(|sample| sample.latency).collect();
+    latencies.sort();
+    if let (Some(p50), Some(p99)) = (percentile(&latencies, 50), percentile(&latencies, 99)) {
+        println!("latency:      p50 {:?}, p99 {:?}", p50, p99);
+    }
+
+    let mut failures = BTreeMap::new();
+    for error in samples.iter().filter_map(|sample| sample.error.as_ref()) {
+        *failures.entry(error.as_str()).or_insert(0) += 1;
+    }
+    for (error, count) in failures {
+        println!("failure:      {} x {}", count, error);
+    }
+
+    for kind in [Kind::Authorized, Kind::Feed] {
+        let mut units: Vec<u64> = samples
+            .iter()
+            .filter(|sample| sample.kind == kind)
+            .filter_map(|sample| sample.compute_units)
+            .collect();
+        units.sort_unstable();
+        if let (Some(min), Some(p50), Some(p99), Some(max)) = (
+            units.first().copied(),
+            percentile(&units, 50),
+            percentile(&units, 99),
+            units.last().copied(),
+        ) {
+            println!(
+                "compute units {:?}: min {}, p50 {}, p99 {}, max {}",
+                kind, min, p50, p99, max
+            );
+        }
+    }
+}
+
+fn main() {
+    let config = match parse_args() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            std::process::exit(2);
+        }
+    };
+    let payer = read_keypair_file(&config.keypair).unwrap_or_else(|e| {
+        eprintln!("error: cannot read keypair {}: {}", config.keypair, e);
+        std::process::exit(2);
+    });
+
+    let (config, payer) = (Arc::new(config), Arc::new(payer));
+    let (sender, receiver) = mpsc::channel();
+    let start = Instant::now();
+    for worker in 0..config.concurrency {
+        let (config, payer, sender) = (config.clone(), payer.clone(), sender.clone());
+        thread::spawn(move || {
+            if let Err(error) = run_worker(&config, &payer, worker, sender) {
+                eprintln!("worker {} failed to set up: {}", worker, error);
+            }
+        });
+    }
+    drop(sender);
+    let samples: Vec<Sample> = receiver.iter().collect();
+
+    report(&samples, start.elapsed());
+}
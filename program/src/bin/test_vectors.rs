@@ -0,0 +1,498 @@
+//! Generates deterministic test vectors of the Echo Program, for auditors and alternative implementations to
+//! cross-check its behavior byte for byte.
+//!
+//! ```text
+//! cargo run --features test-vectors --bin test_vectors -- --seed <SEED> \
+//!     [--writes 8] [--out test-vectors-<SEED>.json]
+//! ```
+//!
+//! Every key, buffer size and payload of the scenario is derived from `--seed`, so the same seed always yields the
+//! same file. The scenario creates an authorized buffer and a keypair buffer, then writes to them `--writes` times
+//! each, every fourth write being signed by an intruder and expected to fail. The instructions are run in order
+//! through a native build of `Processor::process_instruction`, with the slot set to the step number and the default
+//! Rent. The System Program CPIs are emulated without checking signatures, and failed steps leave the accounts
+//! untouched, as the runtime would.
+//!
+//! Each step of the output lists the instruction, its result, the program logs and the lamports, owner and data of
+//! every account it references afterwards. Keypairs are listed with the 32 byte seed they are derived from, see
+//! `keypair_from_seed`, so the scenario can also be replayed against a cluster.
+
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use borsh::BorshSerialize;
+use echo::error::EchoError;
+use echo::instruction::EchoInstruction;
+use echo::pda::find_authorized_buffer_address;
+use echo::processor::Processor;
+use echo::state::{AuthorizedBufferHeader, Trailing};
+use num_traits::FromPrimitive;
+use serde_json::{json, Value};
+use solana_program::account_info::AccountInfo;
+use solana_program::clock::Clock;
+use solana_program::entrypoint::{ProgramResult, MAX_PERMITTED_DATA_INCREASE, SUCCESS};
+use solana_program::hash::hashv;
+use solana_program::instruction::{AccountMeta, Instruction};
+use solana_program::program_error::ProgramError;
+use solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+use solana_program::rent::Rent;
+use solana_program::system_instruction::SystemInstruction;
+use solana_program::system_program;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{keypair_from_seed, Keypair, Signer};
+
+struct Config {
+    seed: u64,
+    writes: usize,
+    out: Option<String>,
+}
+
+/// Clock, Rent, logs and System Program of the native runtime.
+struct VectorStubs {
+    slot: Arc<AtomicU64>,
+    logs: Arc<Mutex<Vec<String>>>,
+}
+
+impl SyscallStubs for VectorStubs {
+    fn sol_log(&self, message: &str) {
+        self.logs.lock().unwrap().push(message.to_string());
+    }
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        _signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        if instruction.program_id != system_program::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let find = |index: usize| -> Result<&AccountInfo, ProgramError> {
+            let key = instruction
+                .accounts
+                .get(index)
+                .ok_or(ProgramError::NotEnoughAccountKeys)?
+                .pubkey;
+            account_infos
+                .iter()
+                .find(|info| *info.key == key)
+                .ok_or(ProgramError::NotEnoughAccountKeys)
+        };
+        let move_lamports =
+            |from: &AccountInfo, to: &AccountInfo, lamports: u64| -> ProgramResult {
+                let from_lamports = from
+                    .lamports()
+                    .checked_sub(lamports)
+                    .ok_or(ProgramError::InsufficientFunds)?;
+                **from.try_borrow_mut_lamports()? = from_lamports;
+                **to.try_borrow_mut_lamports()? += lamports;
+                Ok(())
+            };
+
+        match bincode::deserialize(&instruction.data)
+            .map_err(|_| ProgramError::InvalidInstructionData)?
+        {
+            SystemInstruction::CreateAccount {
+                lamports,
+                space,
+                owner,
+            } => {
+                let (from, to) = (find(0)?, find(1)?);
+                if to.lamports() != 0 || to.data_len() != 0 || *to.owner != system_program::id() {
+                    return Err(ProgramError::AccountAlreadyInitialized);
+                }
+                move_lamports(from, to, lamports)?;
+                to.realloc(space as usize, true)?;
+                to.assign(&owner);
+                Ok(())
+            }
+            SystemInstruction::Transfer { lamports } => move_lamports(find(0)?, find(1)?, lamports),
+            _ => Err(ProgramError::InvalidInstructionData),
+        }
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        let clock = Clock {
+            slot: self.slot.load(Ordering::SeqCst),
+            ..Clock::default()
+        };
+        unsafe { *(var_addr as *mut Clock) = clock };
+        SUCCESS
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        SUCCESS
+    }
+}
+
+/// Account state with room in front of and after the data, as `AccountInfo::realloc` expects from the runtime.
+#[derive(Clone)]
+struct LoadedAccount {
+    key: Pubkey,
+    lamports: u64,
+    // Length prefix, data and room to grow, see `data`
+    buffer: Vec<u8>,
+    owner: Pubkey,
+    executable: bool,
+}
+
+impl LoadedAccount {
+    fn new(key: Pubkey, lamports: u64, owner: Pubkey, executable: bool) -> Self {
+        Self {
+            key,
+            lamports,
+            buffer: vec![0; 8 + MAX_PERMITTED_DATA_INCREASE],
+            owner,
+            executable,
+        }
+    }
+
+    fn data_len(&self) -> usize {
+        let mut length_prefix = [0; 8];
+        length_prefix.copy_from_slice(&self.buffer[..8]);
+        u64::from_le_bytes(length_prefix) as usize
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.buffer[8..8 + self.data_len()]
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "pubkey": self.key.to_string(),
+            "lamports": self.lamports,
+            "owner": self.owner.to_string(),
+            "executable": self.executable,
+            "data": hex(self.data()),
+        })
+    }
+}
+
+/// splitmix64, so that the scenario does not depend on the algorithm of an external crate.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform enough in `low..=high` for small ranges.
+    fn range(&mut self, low: usize, high: usize) -> usize {
+        low + (self.next_u64() % (high - low + 1) as u64) as usize
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next_u64() as u8).collect()
+    }
+}
+
+struct Scenario {
+    seed: u64,
+    accounts: Vec<LoadedAccount>,
+    slot: Arc<AtomicU64>,
+    logs: Arc<Mutex<Vec<String>>>,
+    steps: Vec<Value>,
+}
+
+impl Scenario {
+    fn account_mut(&mut self, key: &Pubkey) -> &mut LoadedAccount {
+        if let Some(index) = self.accounts.iter().position(|account| account.key == *key) {
+            return &mut self.accounts[index];
+        }
+        self.accounts
+            .push(LoadedAccount::new(*key, 0, system_program::id(), false));
+        self.accounts.last_mut().unwrap()
+    }
+
+    /// Runs `instruction` as step `self.steps.len() + 1` and records it.
+    fn run(&mut self, name: &str, program_id: &Pubkey, instruction: Instruction) {
+        for meta in &instruction.accounts {
+            self.account_mut(&meta.pubkey);
+        }
+        let slot = self.steps.len() as u64 + 1;
+        self.slot.store(slot, Ordering::SeqCst);
+        self.logs.lock().unwrap().clear();
+
+        let before = self.accounts.clone();
+        let result = {
+            let mut account_infos = Vec::with_capacity(instruction.accounts.len());
+            let mut keys = Vec::with_capacity(instruction.accounts.len());
+            for account in self.accounts.iter_mut() {
+                let meta = match instruction
+                    .accounts
+                    .iter()
+                    .find(|meta| meta.pubkey == account.key)
+                {
+                    Some(meta) => meta,
+                    None => continue,
+                };
+                let data_len = account.data_len();
+                keys.push(account.key);
+                account_infos.push(AccountInfo::new(
+                    &account.key,
+                    meta.is_signer,
+                    meta.is_writable,
+                    &mut account.lamports,
+                    &mut account.buffer[8..8 + data_len],
+                    &account.owner,
+                    account.executable,
+                    0,
+                ));
+            }
+            let accounts: Vec<AccountInfo> = instruction
+                .accounts
+                .iter()
+                .map(|meta| {
+                    account_infos[keys.iter().position(|key| *key == meta.pubkey).unwrap()].clone()
+                })
+                .collect();
+            Processor::process_instruction(program_id, &accounts, &instruction.data)
+        };
+        if result.is_err() {
+            self.accounts = before;
+        }
+
+        let accounts: Vec<Value> = instruction
+            .accounts
+            .iter()
+            .map(|meta| self.account_mut(&meta.pubkey).to_json())
+            .collect();
+        self.steps.push(json!({
+            "step": self.steps.len() + 1,
+            "name": name,
+            "slot": slot,
+            "instruction": {
+                "program_id": instruction.program_id.to_string(),
+                "accounts": instruction.accounts.iter().map(|meta| json!({
+                    "pubkey": meta.pubkey.to_string(),
+                    "is_signer": meta.is_signer,
+                    "is_writable": meta.is_writable,
+                })).collect::<Vec<Value>>(),
+                "data": hex(&instruction.data),
+            },
+            "result": match &result {
+                Ok(()) => "ok".to_string(),
+                Err(error) => describe(error),
+            },
+            "logs": self.logs.lock().unwrap().clone(),
+            "accounts": accounts,
+        }));
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn describe(error: &ProgramError) -> String {
+    match error {
+        ProgramError::Custom(code) => match EchoError::from_u32(*code) {
+            Some(echo_error) => format!("{:?}", echo_error),
+            None => error.to_string(),
+        },
+        _ => error.to_string(),
+    }
+}
+
+/// Seed of the keypair named `label`, derived from the scenario seed.
+fn derive_seed(seed: u64, label: &str) -> [u8; 32] {
+    hashv(&[&seed.to_le_bytes(), label.as_bytes()]).to_bytes()
+}
+
+fn derive_keypair(seed: u64, label: &str) -> Keypair {
+    keypair_from_seed(&derive_seed(seed, label)).expect("32 byte seeds are valid")
+}
+
+fn parse_args() -> Result<Config, String> {
+    let mut seed = None;
+    let mut config = Config {
+        seed: 0,
+        writes: 8,
+        out: None,
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or(format!("missing value for {}", flag))?;
+        match flag.as_str() {
+            "--seed" => seed = Some(u64::from_str(&value).map_err(|_| "invalid --seed")?),
+            "--writes" => config.writes = value.parse().map_err(|_| "invalid --writes")?,
+            "--out" => config.out = Some(value),
+            _ => return Err(format!("unknown flag {}", flag)),
+        }
+    }
+
+    config.seed = seed.ok_or("--seed is required")?;
+    Ok(config)
+}
+
+fn generate(config: &Config) -> Value {
+    let seed = config.seed;
+    let mut rng = Rng(seed);
+    let program_id = Pubkey::new_from_array(derive_seed(seed, "program_id"));
+    let authority = derive_keypair(seed, "authority");
+    let intruder = derive_keypair(seed, "intruder");
+    let keypair_buffer = derive_keypair(seed, "keypair_buffer");
+
+    let slot = Arc::new(AtomicU64::new(0));
+    let logs = Arc::new(Mutex::new(vec![]));
+    set_syscall_stubs(Box::new(VectorStubs {
+        slot: slot.clone(),
+        logs: logs.clone(),
+    }));
+
+    let mut scenario = Scenario {
+        seed,
+        accounts: vec![
+            LoadedAccount::new(
+                authority.pubkey(),
+                10_000_000_000,
+                system_program::id(),
+                false,
+            ),
+            LoadedAccount::new(
+                intruder.pubkey(),
+                10_000_000_000,
+                system_program::id(),
+                false,
+            ),
+            LoadedAccount::new(system_program::id(), 1, Pubkey::default(), true),
+        ],
+        slot,
+        logs,
+        steps: vec![],
+    };
+    let initial_accounts: Vec<Value> = scenario
+        .accounts
+        .iter()
+        .map(LoadedAccount::to_json)
+        .collect();
+
+    let buffer_seed = rng.next_u64();
+    let (authorized_buffer, _) =
+        find_authorized_buffer_address(&program_id, &authority.pubkey(), buffer_seed, None);
+    let capacity = rng.range(1, 64);
+    scenario.run(
+        "InitializeAuthorizedEcho",
+        &program_id,
+        Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(authorized_buffer, false),
+                AccountMeta::new(authority.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+            data: EchoInstruction::InitializeAuthorizedEcho {
+                buffer_seed,
+                buffer_size: AuthorizedBufferHeader::header_len(&None) + capacity,
+                namespace: Trailing(None),
+                write_deadline_slot: Trailing(None),
+            }
+            .try_to_vec()
+            .unwrap(),
+        },
+    );
+
+    let keypair_capacity = rng.range(1, 64);
+    scenario.run(
+        "InitializeKeypairAuthorizedEcho",
+        &program_id,
+        Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(keypair_buffer.pubkey(), true),
+                AccountMeta::new(authority.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+            data: EchoInstruction::InitializeKeypairAuthorizedEcho {
+                buffer_size: AuthorizedBufferHeader::header_len(&None) + keypair_capacity,
+                write_deadline_slot: None,
+            }
+            .try_to_vec()
+            .unwrap(),
+        },
+    );
+
+    for (buffer, capacity) in [
+        (authorized_buffer, capacity),
+        (keypair_buffer.pubkey(), keypair_capacity),
+    ] {
+        for write in 0..config.writes {
+            // Payloads may overflow the buffer, which truncates them
+            let len = rng.range(0, capacity + 8);
+            let data = rng.bytes(len);
+            let signer = if write % 4 == 3 {
+                &intruder
+            } else {
+                &authority
+            };
+            scenario.run(
+                "AuthorizedEcho",
+                &program_id,
+                Instruction {
+                    program_id,
+                    accounts: vec![
+                        AccountMeta::new(buffer, false),
+                        AccountMeta::new_readonly(signer.pubkey(), true),
+                    ],
+                    data: EchoInstruction::AuthorizedEcho { data }
+                        .try_to_vec()
+                        .unwrap(),
+                },
+            );
+        }
+    }
+
+    let keypairs: Vec<Value> = [
+        ("authority", &authority),
+        ("intruder", &intruder),
+        ("keypair_buffer", &keypair_buffer),
+    ]
+    .iter()
+    .map(|(label, keypair)| {
+        json!({
+            "label": label,
+            "pubkey": keypair.pubkey().to_string(),
+            "seed": hex(&derive_seed(seed, label)),
+        })
+    })
+    .collect();
+    json!({
+        "seed": scenario.seed,
+        "program_id": program_id.to_string(),
+        "keypairs": keypairs,
+        "initial_accounts": initial_accounts,
+        "steps": scenario.steps,
+    })
+}
+
+fn main() {
+    let config = match parse_args() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            std::process::exit(2);
+        }
+    };
+    let vectors = generate(&config);
+    let out = config
+        .out
+        .clone()
+        .unwrap_or_else(|| format!("test-vectors-{}.json", config.seed));
+    let json = serde_json::to_string_pretty(&vectors).expect("JSON values serialize");
+    if let Err(error) = std::fs::write(&out, json + "\n") {
+        eprintln!("error: cannot write {}: {}", out, error);
+        std::process::exit(1);
+    }
+    println!(
+        "wrote {} steps to {}",
+        vectors["steps"].as_array().map_or(0, Vec::len),
+        out
+    );
+}
@@ -0,0 +1,216 @@
+//! Watches Echo Program buffers and POSTs a signed JSON event to a webhook every time one of them changes.
+//!
+//! ```text
+//! ECHO_WEBHOOK_SECRET=<secret> cargo run --features webhooks --bin webhooks -- \
+//!     (--program-id <PROGRAM_ID> | --cluster <CLUSTER>) --webhook <BUFFER>=<URL> \
+//!     [--webhook <BUFFER>=<URL> ...] [--url http://localhost:8899] [--poll-interval-ms 1000] [--max-retries 5]
+//! ```
+//!
+//! Every buffer is polled at the `confirmed` commitment and compared by the sha256 hash of its data. Changes seen
+//! after startup are POSTed as
+//!
+//! ```text
+//! {"buffer":"<pubkey>","slot":<slot>,"previous_hash":"<hash>","hash":"<hash>","echo_data":"<hex>"}
+//! ```
+//!
+//! with `echo_data` the echo data of an authorized buffer, or the whole account data of any other account. The
+//! `X-Echo-Signature` header holds `sha256=` followed by the hex HMAC-SHA256 of the body keyed with
+//! `ECHO_WEBHOOK_SECRET`. Failed deliveries are retried with exponential backoff, starting at half a second.
+//! Registrations only live as long as the process.
+
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use echo::program_ids::Cluster;
+use echo::state::AuthorizedBufferHeader;
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::account::Account;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::{hash, Hash};
+use solana_sdk::pubkey::Pubkey;
+
+struct Config {
+    url: String,
+    program_id: Pubkey,
+    webhooks: Vec<Webhook>,
+    secret: String,
+    poll_interval: Duration,
+    max_retries: u32,
+}
+
+struct Webhook {
+    buffer: Pubkey,
+    url: String,
+    // Hash of the buffer data at the last poll, `None` until the first successful poll
+    last_hash: Option<Hash>,
+}
+
+fn parse_args() -> Result<Config, String> {
+    let mut config = Config {
+        url: "http://localhost:8899".to_string(),
+        program_id: Pubkey::default(),
+        webhooks: vec![],
+        secret: std::env::var("ECHO_WEBHOOK_SECRET")
+            .map_err(|_| "ECHO_WEBHOOK_SECRET must be set")?,
+        poll_interval: Duration::from_millis(1000),
+        max_retries: 5,
+    };
+    let mut program_id = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or(format!("missing value for {}", flag))?;
+        match flag.as_str() {
+            "--url" => config.url = value,
+            "--program-id" => {
+                program_id = Some(Pubkey::from_str(&value).map_err(|e| e.to_string())?)
+            }
+            "--cluster" => program_id = Some(Cluster::from_str(&value)?.program_id()),
+            "--webhook" => {
+                let (buffer, url) = value.split_once('=').ok_or("invalid --webhook")?;
+                config.webhooks.push(Webhook {
+                    buffer: Pubkey::from_str(buffer).map_err(|e| e.to_string())?,
+                    url: url.to_string(),
+                    last_hash: None,
+                });
+            }
+            "--poll-interval-ms" => {
+                config.poll_interval =
+                    Duration::from_millis(value.parse().map_err(|_| "invalid --poll-interval-ms")?)
+            }
+            "--max-retries" => {
+                config.max_retries = value.parse().map_err(|_| "invalid --max-retries")?
+            }
+            _ => return Err(format!("unknown flag {}", flag)),
+        }
+    }
+
+    config.program_id = program_id.ok_or("--program-id or --cluster is required")?;
+    if config.webhooks.is_empty() {
+        return Err("at least one --webhook is required".to_string());
+    }
+    Ok(config)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Echo data of `account` if it is an authorized buffer of `program_id`, its whole data otherwise.
+fn echo_data(program_id: &Pubkey, account: &Account) -> Vec<u8> {
+    if account.owner != *program_id {
+        return account.data.clone();
+    }
+    match AuthorizedBufferHeader::unpack(&account.data) {
+        Ok(header) => header.echo_data,
+        Err(_) => account.data.clone(),
+    }
+}
+
+fn sign(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body.as_bytes());
+    format!("sha256={}", to_hex(&mac.finalize().into_bytes()))
+}
+
+/// POSTs `body` to `url`, retrying up to `max_retries` times with exponential backoff.
+fn deliver(
+    http_client: &reqwest::blocking::Client,
+    config: &Config,
+    url: &str,
+    body: String,
+) -> Result<(), String> {
+    let signature = sign(&config.secret, &body);
+    let mut backoff = Duration::from_millis(500);
+    let mut attempt = 0;
+    loop {
+        let result = http_client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .header("X-Echo-Signature", &signature)
+            .body(body.clone())
+            .send()
+            .map_err(|e| e.to_string())
+            .and_then(|response| match response.status() {
+                status if status.is_success() => Ok(()),
+                status => Err(format!("status {}", status)),
+            });
+        match result {
+            Ok(()) => return Ok(()),
+            Err(error) if attempt >= config.max_retries => return Err(error),
+            Err(error) => {
+                eprintln!(
+                    "delivery to {} failed: {}, retrying in {:?}",
+                    url, error, backoff
+                );
+                thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+fn poll(
+    rpc_client: &RpcClient,
+    http_client: &reqwest::blocking::Client,
+    config: &Config,
+    webhook: &mut Webhook,
+) {
+    let response = match rpc_client
+        .get_account_with_commitment(&webhook.buffer, CommitmentConfig::confirmed())
+    {
+        Ok(response) => response,
+        Err(error) => {
+            eprintln!("cannot read {}: {}", webhook.buffer, error);
+            return;
+        }
+    };
+    // A closed buffer reads as empty data
+    let account = response.value.unwrap_or_default();
+    let data_hash = hash(&account.data);
+    let previous_hash = match webhook.last_hash.replace(data_hash) {
+        Some(previous_hash) if previous_hash != data_hash => previous_hash,
+        _ => return,
+    };
+
+    let body = format!(
+        r#"{{"buffer":"{}","slot":{},"previous_hash":"{}","hash":"{}","echo_data":"{}"}}"#,
+        webhook.buffer,
+        response.context.slot,
+        previous_hash,
+        data_hash,
+        to_hex(&echo_data(&config.program_id, &account)),
+    );
+    if let Err(error) = deliver(http_client, config, &webhook.url, body) {
+        eprintln!(
+            "dropping change of {} at slot {}: {}",
+            webhook.buffer, response.context.slot, error
+        );
+    }
+}
+
+fn main() {
+    let mut config = match parse_args() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            std::process::exit(2);
+        }
+    };
+    let rpc_client =
+        RpcClient::new_with_commitment(config.url.clone(), CommitmentConfig::confirmed());
+    let http_client = reqwest::blocking::Client::new();
+
+    let mut webhooks = std::mem::take(&mut config.webhooks);
+    loop {
+        for webhook in webhooks.iter_mut() {
+            poll(&rpc_client, &http_client, &config, webhook);
+        }
+        thread::sleep(config.poll_interval);
+    }
+}
@@ -0,0 +1,110 @@
+//! Wormhole messages attesting the content of authorized buffers, see `PostToBridge`.
+//!
+//! Payloads are big-endian, as is customary for Wormhole:
+//!
+//! | offset | length | content                      |
+//! |--------|--------|------------------------------|
+//! | 0      | 1      | `PAYLOAD_ID_ECHO`            |
+//! | 1      | 32     | buffer address               |
+//! | 33     | 8      | slot the buffer was read at  |
+//! | 41     | 32     | sha256 of the echo data      |
+//! | 73     | 4      | length of the echo data      |
+//! | 77     | ...    | echo data                    |
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    hash::hash,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program, sysvar,
+};
+
+/// First byte of every payload built by `build_payload`, so receiving contracts can tell Echo payloads apart.
+pub const PAYLOAD_ID_ECHO: u8 = 1;
+
+/// Index of `PostMessage` in the instruction enum of the Wormhole core bridge.
+const POST_MESSAGE: u8 = 1;
+
+/// Addresses of the Wormhole core bridge, the only program `PostToBridge` invokes, see
+/// `program_ids::Cluster::wormhole_program_id`.
+pub mod wormhole_program {
+    pub mod mainnet_beta {
+        solana_program::declare_id!("worm2ZoG2kUd4vFXhvjh93UUH596ayRfgQ2MgjNMTth");
+    }
+
+    pub mod devnet {
+        solana_program::declare_id!("3u8hJUVTA4jH1wYAyUur7FFZVQ8H635K3tSHHF4ssjQ5");
+    }
+
+    /// Address in the local devnet of the Wormhole repository
+    pub mod localnet {
+        solana_program::declare_id!("Bridge1p5gheXUvJ6jGWGeCsgPKgnE3YgdGKRVCMY9o");
+    }
+}
+
+/// Commitment the guardians wait for before signing a message.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ConsistencyLevel {
+    Confirmed,
+    Finalized,
+}
+
+#[derive(BorshSerialize)]
+struct PostMessageData<'a> {
+    nonce: u32,
+    payload: &'a [u8],
+    consistency_level: ConsistencyLevel,
+}
+
+/// Payload attesting that `buffer` held `echo_data` at `slot`.
+pub fn build_payload(buffer: &Pubkey, slot: u64, echo_data: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(77 + echo_data.len());
+    payload.push(PAYLOAD_ID_ECHO);
+    payload.extend_from_slice(buffer.as_ref());
+    payload.extend_from_slice(&slot.to_be_bytes());
+    payload.extend_from_slice(hash(echo_data).as_ref());
+    payload.extend_from_slice(&(echo_data.len() as u32).to_be_bytes());
+    payload.extend_from_slice(echo_data);
+    payload
+}
+
+/// `PostMessage` instruction of the Wormhole core bridge. `message` must sign, and so must `emitter`, through
+/// `invoke_signed` when it is a PDA. The bridge fee must already have been transferred to `fee_collector`.
+#[allow(clippy::too_many_arguments)]
+pub fn post_message(
+    wormhole_program_id: &Pubkey,
+    bridge_config: &Pubkey,
+    message: &Pubkey,
+    emitter: &Pubkey,
+    sequence: &Pubkey,
+    payer: &Pubkey,
+    fee_collector: &Pubkey,
+    nonce: u32,
+    payload: &[u8],
+    consistency_level: ConsistencyLevel,
+) -> Instruction {
+    let mut data = vec![POST_MESSAGE];
+    PostMessageData {
+        nonce,
+        payload,
+        consistency_level,
+    }
+    .serialize(&mut data)
+    .expect("serializing to a Vec does not fail");
+
+    Instruction {
+        program_id: *wormhole_program_id,
+        accounts: vec![
+            AccountMeta::new(*bridge_config, false),
+            AccountMeta::new(*message, true),
+            AccountMeta::new_readonly(*emitter, true),
+            AccountMeta::new(*sequence, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(*fee_collector, false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data,
+    }
+}
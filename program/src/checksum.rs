@@ -0,0 +1,65 @@
+//! CRC-32 of the fixed-size chunks of an authorized buffer's echo data, see `SetChunkChecksums`. Lets a client fetch
+//! part of the echo data with a data slice and check it without downloading the whole account.
+
+use std::ops::Range;
+
+/// Number of echo data bytes covered by one checksum. The last chunk covers the remaining bytes.
+pub const CHUNK_LEN: usize = 4096;
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// CRC-32 (IEEE 802.3, as used by zlib) of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Number of chunks, hence of checksums, of `echo_len` bytes of echo data.
+// `usize::div_ceil` is newer than the BPF toolchain of solana-program 1.9
+#[allow(clippy::manual_div_ceil)]
+pub fn chunk_count(echo_len: usize) -> usize {
+    (echo_len + CHUNK_LEN - 1) / CHUNK_LEN
+}
+
+/// Checksums of the chunks of `echo_data`, in order.
+pub fn chunk_checksums(echo_data: &[u8]) -> Vec<u32> {
+    echo_data.chunks(CHUNK_LEN).map(crc32).collect()
+}
+
+/// Chunk-aligned range of echo data to fetch to check the `range` of echo data of `echo_len` bytes, and the index
+/// of its first chunk.
+pub fn covering_range(range: Range<usize>, echo_len: usize) -> (usize, Range<usize>) {
+    let first_chunk = range.start / CHUNK_LEN;
+    let end = std::cmp::min(chunk_count(range.end) * CHUNK_LEN, echo_len);
+    (first_chunk, first_chunk * CHUNK_LEN..end)
+}
+
+/// Whether `bytes`, the echo data starting at chunk `first_chunk` as returned by `covering_range`, match
+/// `checksums`.
+pub fn verify_chunks(first_chunk: usize, bytes: &[u8], checksums: &[u32]) -> bool {
+    bytes
+        .chunks(CHUNK_LEN)
+        .enumerate()
+        .all(|(i, chunk)| checksums.get(first_chunk + i) == Some(&crc32(chunk)))
+}
@@ -0,0 +1,43 @@
+//! Instruction builders for clients of the Echo Program.
+
+use solana_program::{
+    instruction::Instruction, program_error::ProgramError, program_pack::Pack, pubkey::Pubkey,
+    system_instruction,
+};
+use spl_token::instruction::{close_account, initialize_account, sync_native};
+use spl_token::state::Account as TokenAccount;
+
+/// Wraps the `VendingMachineEcho` instruction `vending_machine_echo` of a machine priced in the native mint, so that
+/// `user` pays it with SOL: the returned instructions create the temporary wrapped SOL account `wrapped_sol_account`,
+/// which must sign, fund it with `price` lamports, pay with `vending_machine_echo` and close it, unwrapping the rest
+/// back to `user`.
+///
+/// `vending_machine_echo` must pass `wrapped_sol_account` as its `user_token_account`, and `rent_exempt_lamports` is
+/// the rent-exempt minimum of a token account.
+pub fn wrapped_sol_vending_machine_echo(
+    user: &Pubkey,
+    wrapped_sol_account: &Pubkey,
+    price: u64,
+    rent_exempt_lamports: u64,
+    vending_machine_echo: Instruction,
+) -> Result<Vec<Instruction>, ProgramError> {
+    Ok(vec![
+        system_instruction::create_account(
+            user,
+            wrapped_sol_account,
+            rent_exempt_lamports,
+            TokenAccount::LEN as u64,
+            &spl_token::id(),
+        ),
+        initialize_account(
+            &spl_token::id(),
+            wrapped_sol_account,
+            &spl_token::native_mint::id(),
+            user,
+        )?,
+        system_instruction::transfer(user, wrapped_sol_account, price),
+        sync_native(&spl_token::id(), wrapped_sol_account)?,
+        vending_machine_echo,
+        close_account(&spl_token::id(), wrapped_sol_account, user, user, &[])?,
+    ])
+}
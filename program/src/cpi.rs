@@ -0,0 +1,120 @@
+//! Cross-program invocations of the Echo Program, for programs that own an authorized buffer through a PDA
+//! authority. Depend on this crate with the `no-entrypoint` feature so that its entrypoint is not linked into yours,
+//! see `examples/swap_echo` for an end-to-end integrator.
+//!
+//! `authority_seeds` are the seeds, bump seed included, the calling program signs for `authority` with.
+
+use crate::instruction::EchoInstruction;
+use crate::state::Trailing;
+use borsh::BorshSerialize;
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::invoke_signed,
+};
+
+/// Invokes `InitializeAuthorizedEcho` without namespace nor write deadline. The rent of the buffer is paid by
+/// `authority`, which must hold enough lamports and be writable.
+pub fn initialize_authorized_echo<'a>(
+    echo_program: &AccountInfo<'a>,
+    authorized_buffer: &AccountInfo<'a>,
+    authority: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    buffer_seed: u64,
+    buffer_size: usize,
+    authority_seeds: &[&[u8]],
+) -> ProgramResult {
+    let instruction = EchoInstruction::InitializeAuthorizedEcho {
+        buffer_seed,
+        buffer_size,
+        namespace: Trailing(None),
+        write_deadline_slot: Trailing(None),
+    };
+    invoke_signed(
+        &Instruction {
+            program_id: *echo_program.key,
+            accounts: vec![
+                AccountMeta::new(*authorized_buffer.key, false),
+                AccountMeta::new(*authority.key, true),
+                AccountMeta::new_readonly(*system_program.key, false),
+            ],
+            data: instruction.try_to_vec()?,
+        },
+        &[
+            authorized_buffer.clone(),
+            authority.clone(),
+            system_program.clone(),
+            echo_program.clone(),
+        ],
+        &[authority_seeds],
+    )
+}
+
+/// Invokes `AuthorizedEcho`. `chunk_checksums` must be passed for buffers with chunk checksums.
+pub fn authorized_echo<'a>(
+    echo_program: &AccountInfo<'a>,
+    authorized_buffer: &AccountInfo<'a>,
+    authority: &AccountInfo<'a>,
+    chunk_checksums: Option<&AccountInfo<'a>>,
+    data: Vec<u8>,
+    authority_seeds: &[&[u8]],
+) -> ProgramResult {
+    write(
+        echo_program,
+        authorized_buffer,
+        authority,
+        chunk_checksums,
+        EchoInstruction::AuthorizedEcho { data },
+        authority_seeds,
+    )
+}
+
+/// Invokes `AppendEcho`, which requires `FEATURE_APPEND_ALLOWED` on the buffer. `chunk_checksums` must be passed for
+/// buffers with chunk checksums.
+pub fn append_echo<'a>(
+    echo_program: &AccountInfo<'a>,
+    authorized_buffer: &AccountInfo<'a>,
+    authority: &AccountInfo<'a>,
+    chunk_checksums: Option<&AccountInfo<'a>>,
+    data: Vec<u8>,
+    authority_seeds: &[&[u8]],
+) -> ProgramResult {
+    write(
+        echo_program,
+        authorized_buffer,
+        authority,
+        chunk_checksums,
+        EchoInstruction::AppendEcho { data },
+        authority_seeds,
+    )
+}
+
+fn write<'a>(
+    echo_program: &AccountInfo<'a>,
+    authorized_buffer: &AccountInfo<'a>,
+    authority: &AccountInfo<'a>,
+    chunk_checksums: Option<&AccountInfo<'a>>,
+    instruction: EchoInstruction,
+    authority_seeds: &[&[u8]],
+) -> ProgramResult {
+    let mut accounts = vec![
+        AccountMeta::new(*authorized_buffer.key, false),
+        AccountMeta::new_readonly(*authority.key, true),
+    ];
+    let mut account_infos = vec![authorized_buffer.clone(), authority.clone()];
+    if let Some(chunk_checksums) = chunk_checksums {
+        accounts.push(AccountMeta::new(*chunk_checksums.key, false));
+        account_infos.push(chunk_checksums.clone());
+    }
+    account_infos.push(echo_program.clone());
+    invoke_signed(
+        &Instruction {
+            program_id: *echo_program.key,
+            accounts,
+            data: instruction.try_to_vec()?,
+        },
+        &account_infos,
+        &[authority_seeds],
+    )
+}
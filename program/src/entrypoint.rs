@@ -1,22 +1,32 @@
-use crate::processor::Processor;
-use solana_program::{account_info::AccountInfo, entrypoint::ProgramResult, msg, pubkey::Pubkey};
+#[cfg(not(feature = "no-entrypoint"))]
+use crate::{processor::Processor, program_ids::Cluster};
+#[cfg(not(feature = "no-entrypoint"))]
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, program_error::ProgramError,
+    pubkey::Pubkey,
+};
 
 #[cfg(not(feature = "no-entrypoint"))]
 use solana_program::entrypoint;
 #[cfg(not(feature = "no-entrypoint"))]
 entrypoint!(process_instruction);
 
+#[cfg(not(feature = "no-entrypoint"))]
 fn process_instruction(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> ProgramResult {
-    msg!(
+    verbose_msg!(
         "process_instruction: {}: {} accounts, data={:?}",
         program_id,
         accounts.len(),
         instruction_data
     );
 
+    // A build loaded at another address would derive PDAs clients do not expect
+    if Cluster::of(program_id).is_none() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
     Processor::process_instruction(program_id, accounts, instruction_data)
 }
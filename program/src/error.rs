@@ -18,6 +18,131 @@ pub enum EchoError {
 
     #[error("Invalid Authorized_buffer_key")]
     InvalidAuthorizedBuffer,
+
+    #[error("Unsupported buffer version")]
+    InvalidBufferVersion,
+
+    #[error("Reserved header bytes must be zero")]
+    NonZeroReserved,
+
+    #[error("Invalid schema account")]
+    InvalidSchemaAccount,
+
+    #[error("Payload schema does not match the registered schema")]
+    SchemaMismatch,
+
+    #[error("Value length does not match the configured length")]
+    InvalidValueLength,
+
+    #[error("Clock timestamp is older than the last record")]
+    NonMonotonicTimestamp,
+
+    #[error("Feed value is outside of the configured bounds")]
+    FeedValueOutOfBounds,
+
+    #[error("Signer is not a whitelisted writer")]
+    WriterNotWhitelisted,
+
+    #[error("Not enough fresh submissions to compute the median")]
+    InsufficientSubmissions,
+
+    #[error("Buffer write deadline has passed")]
+    WriteDeadlinePassed,
+
+    #[error("Buffer write deadline has not passed yet")]
+    WriteDeadlineNotReached,
+
+    #[error("Session budget exceeded")]
+    SessionBudgetExceeded,
+
+    #[error(
+        "User is neither the owner nor an approved delegate of the token account for the price"
+    )]
+    InsufficientDelegation,
+
+    #[error("No close is scheduled for the buffer")]
+    CloseNotScheduled,
+
+    #[error("Scheduled close slot has not passed yet")]
+    CloseGracePeriodActive,
+
+    #[error("Buffer is still referenced")]
+    BufferReferenced,
+
+    #[error("Vending machine is outside of its sale window")]
+    SaleWindowClosed,
+
+    #[error("Token account has not held the required amount for long enough")]
+    HoldingRequirementNotMet,
+
+    #[error("Signer is not an admin of the org")]
+    NotAnOrgAdmin,
+
+    #[error("Signer is not a member of the project")]
+    NotAProjectMember,
+
+    #[error("Payload pointer CID is malformed or uses an unsupported hash function")]
+    InvalidContentIdentifier,
+
+    #[error("Purchase price does not fit in a u64")]
+    PriceOverflow,
+
+    #[error("Signer is not a guardian of the buffer")]
+    NotAGuardian,
+
+    #[error("No recovery is pending for the buffer")]
+    RecoveryNotPending,
+
+    #[error("Pending recovery lacks approvals or its timelock has not passed yet")]
+    RecoveryTimelockActive,
+
+    #[error("Dead-man switch content was already released")]
+    DeadManSwitchReleased,
+
+    #[error("Authority checked in recently enough")]
+    CheckInNotOverdue,
+
+    #[error("Vending machine buffer is not the PDA derived from its mint and price")]
+    InvalidVendingMachinePda,
+
+    #[error("Arithmetic overflow")]
+    ArithmeticOverflow,
+
+    #[error("Instruction data is shorter than the instruction tag")]
+    InstructionDataTooShort,
+
+    #[error("Instruction data is longer than MAX_INSTRUCTION_DATA_LEN")]
+    InstructionDataTooLong,
+
+    #[error("Buffer does not read back as written")]
+    WriteVerificationFailed,
+
+    #[error("Buffer already exists")]
+    BufferAlreadyExists,
+
+    #[error("Data does not fit in the space left after the write offset")]
+    BufferFull,
+
+    #[error("Buffer does not allow appends")]
+    AppendNotAllowed,
+
+    #[error("Buffer is immutable")]
+    BufferImmutable,
+
+    #[error("Buffer only accepts UTF-8 data")]
+    InvalidUtf8,
+
+    #[error("Buffer does not accept writes through CPI")]
+    CpiNotAllowed,
+
+    #[error("Data does not fit in the buffer at the given offset")]
+    OffsetOutOfBounds,
+
+    #[error("Another recovery is pending")]
+    RecoveryPending,
+
+    #[error("Token account does not hold the mint of the vending machine")]
+    InvalidTokenMint,
 }
 
 impl From<EchoError> for ProgramError {
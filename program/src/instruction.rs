@@ -1,4 +1,15 @@
 use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::pubkey::Pubkey;
+
+use crate::bridge::ConsistencyLevel;
+use crate::state::{BufferMetadata, PayloadPointer, Trailing, VendingMachineMetadata};
+
+/// Length of the Borsh tag selecting the `EchoInstruction` variant, the shortest instruction data accepted.
+pub const MIN_INSTRUCTION_DATA_LEN: usize = 1;
+
+/// Longest instruction data accepted. A transaction holds at most 1232 bytes, the rest is headroom for instructions
+/// issued through CPI.
+pub const MAX_INSTRUCTION_DATA_LEN: usize = 10 * 1024;
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
 pub enum EchoInstruction {
@@ -16,9 +27,29 @@ pub enum EchoInstruction {
     Echo { data: Vec<u8> },
     /// This instruction will allocate `buffer_size` bytes to the `authorized_buffer` account and assign it the Echo Program.
     ///
-    /// The first 9 bytes of authorized_buffer will be set with the following data:
+    /// The first 10 bytes of authorized_buffer will be set with the following data:
     ///     byte 0: bump_seed
     ///     bytes 1-8: buffer_seed
+    ///     byte 9: version
+    ///
+    /// If a `namespace` is provided it is appended to the PDA seeds (after `buffer_seed`) and stored in the
+    /// header right after `version`, so several applications sharing one `authority` can pick their
+    /// `buffer_seed` values independently.
+    ///
+    /// If a `write_deadline_slot` is provided, the buffer becomes permanently read-only once that slot has passed,
+    /// even if it was never written, and can then be closed by anyone with `CloseExpiredBuffer`.
+    ///
+    /// Both are trailing, so the payloads of clients predating them, which end after `buffer_size`, decode with
+    /// neither. A `write_deadline_slot` can only be sent after a `namespace`, be it `Some(None)`.
+    ///
+    /// The header ends with 2 reserved bytes that must stay zero.
+    ///
+    /// Fails with `BufferAlreadyExists` if a buffer was already created with the same seeds. The other instructions
+    /// creating buffers from a seed fail the same way.
+    ///
+    /// The rent is paid by `authority`, or by its `rent_vault` when passed. The rent vault is the system account
+    /// derived from `[b"rent_vault", authority]`, which anyone can top up with a plain transfer, so that scripts
+    /// creating buffers do not need the authority wallet to hold SOL. See `WithdrawRentVault`.
     ///
     /// Accounts:
     /// | index | writable | signer | description                                                              |
@@ -26,12 +57,27 @@ pub enum EchoInstruction {
     /// | 0     | ✅       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to |
     /// | 1     | ❌       | ✅     | authority: Pubkey with sole write access to `authorized_buffer`           |
     /// | 2     | ❌       | ❌     | system_program: Used to allocate the buffer                               |
+    /// | 3     | ✅       | ❌     | rent_vault: Optional, PDA of Echo Program derived from `authority`        |
     InitializeAuthorizedEcho {
         buffer_seed: u64,
         buffer_size: usize,
+        namespace: Trailing<Option<[u8; 8]>>,
+        write_deadline_slot: Trailing<Option<u64>>,
     },
     /// The contents of the data vector that is provided to the instruction will be copied into the `authorized_buffer` account
-    /// starting from index 9 (will NOT override the bump_seed and buffer_seed).
+    /// right after the header (will NOT override the bump_seed, buffer_seed, version, namespace, write_deadline_slot,
+    /// close_after_slot, reference_count, authority, verify_after_write, chunk_checksums, features and reserved bytes),
+    /// and moves the write offset of `AppendEcho` to the end of the bytes written.
+    ///
+    /// Fails if the header version is not current, its reserved bytes are non-zero or its write deadline has passed.
+    /// Buffers with `verify_after_write` set also fail with `WriteVerificationFailed` if they do not read back as
+    /// written, see `SetVerifyAfterWrite`.
+    ///
+    /// Buffers with `chunk_checksums` set require their `chunk_checksums` PDA after the other accounts, and the write
+    /// updates its checksums, see `SetChunkChecksums`. The same goes for the other instructions writing or resizing an
+    /// authorized buffer.
+    ///
+    /// Writes are also checked against the features of the buffer, see `SetFeatures`.
     ///
     /// If the remaining `authorized_buffer` account length ( N ) is smaller than the length of `data`, copy the first N bytes
     /// of data into `authorized_buffer`.
@@ -42,31 +88,48 @@ pub enum EchoInstruction {
     /// If any account besides the `authority` attempts to write to the `authorized_buffer`, the instruction will fail.
     ///
     /// Accounts:
-    /// | index | writable | signer | description                                                              |
-    /// |-------|----------|--------|--------------------------------------------------------------------------|
-    /// | 0     | ✅       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to |
-    /// | 1     | ❌       | ✅     | authority: Pubkey with sole write access to `authorized_buffer`           |
+    /// | index | writable | signer | description                                                                     |
+    /// |-------|----------|--------|---------------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to       |
+    /// | 1     | ❌       | ✅     | authority: Pubkey with sole write access to `authorized_buffer`                 |
+    /// | 2     | ✅       | ❌     | chunk_checksums: Optional, PDA of Echo Program derived from `authorized_buffer` |
     AuthorizedEcho { data: Vec<u8> },
     /// This instruction will allocate `buffer_size` bytes to the `vending_machine_buffer` account and assign it the Echo Program.
     ///
-    /// The first 9 bytes of `vending_machine_buffer` will be set with the following data:
+    /// The first 10 bytes of `vending_machine_buffer` will be set with the following data:
     ///     byte 0: bump_seed
     ///     bytes 1-8: price
+    ///     byte 9: version
+    ///
+    /// They are followed by the admin (`payer`), the sale window (open at all times until `SetSaleWindow` is called),
+    /// the holding gate flag, the count of unique writers and the quadratic pricing, Dutch auction and receipts flags.
+    ///
+    /// If `metadata` is provided, the account is grown by `VendingMachineMetadata::LEN` bytes to store it after the
+    /// echo data.
+    ///
+    /// If a `machine_seed` is provided it is appended to the PDA seeds (after `price`), so a project can run several
+    /// machines for the same mint and price. The seed is not stored in the machine: every instruction re-deriving the
+    /// machine address takes the same `machine_seed`. Omitting it keeps the original derivation.
     ///
     /// Accounts:
     /// | index | writable | signer | description                                                                                         |
     /// |-------|----------|--------|-----------------------------------------------------------------------------------------------------|
     /// | 0     | ✅       | ❌     | vending_machine_buffer: PDA of the Echo Program that only holders of a particular token can write to |
     /// | 1     | ❌       | ❌     | vending_machine_mint: Pubkey with sole write access to `authorized_buffer`                           |
-    /// | 2     | ❌       | ✅     | payer: Pubkey that allocates the `vending_machine_buffer`                                            |
+    /// | 2     | ❌       | ✅     | payer: Pubkey that allocates the `vending_machine_buffer` and becomes its admin                      |
     /// | 3     | ❌       | ❌     | system_program: Used to allocate the buffer                                                          |
     InitializeVendingMachineEcho {
         // Number of tokens required change the buffer
         price: u64,
         buffer_size: usize,
+        metadata: Option<VendingMachineMetadata>,
+        machine_seed: Trailing<u64>,
     },
-    /// The contents of the data vector that is provided to the instruction should be copied into the account starting from
-    /// index 9 (you do NOT want to override the bump_seed and price).
+    /// The contents of the data vector that is provided to the instruction should be copied into the account right after
+    /// the header (you do NOT want to override the bump_seed, price, version, admin, sale window and reserved bytes).
+    ///
+    /// Fails if the header version is not current, its reserved bytes are non-zero or the cluster clock is outside of
+    /// the sale window.
     ///
     /// If the remaining account length ( N ) is smaller than the length of data, copy the first N bytes of data into
     /// `vending_machine_buffer`.
@@ -78,9 +141,18 @@ pub enum EchoInstruction {
     /// This will require a cross program invocation to the Token Program. If this instruction succeed (verifies that the
     /// user in fact has sufficient tokens), then the copy can occur.
     ///
+    /// `user` can be the owner of `user_token_account` or a delegate approved for at least `price` tokens.
+    ///
+    /// Machines priced in the native mint (wrapped SOL) cannot burn, so `price` is instead transferred from
+    /// `user_token_account`, a wrapped SOL account, to the writable `proceeds` wrapped SOL account of the admin of the
+    /// machine, passed right after the receipt accounts. `vending_machine_mint` need not be writable then. Buyers
+    /// holding SOL only can wrap it into a temporary account and unwrap the rest in the same transaction, see
+    /// `wrapped_sol_vending_machine_echo`.
+    ///
     /// This instruction should fail in the case that the mint of the `vending_machine_buffer` does not match the mint
     /// used to seed the PDA.  You can verify this by comparing the output of `Pubkey::create_program_address` with the correct
-    /// seeds to the value of `vending_machine_buffer.key`.
+    /// seeds to the value of `vending_machine_buffer.key`. Machines created with a `machine_seed` need the same
+    /// `machine_seed` here.
     ///
     /// Accounts:
     /// | index | writable | signer | description                                                                                         |
@@ -89,6 +161,885 @@ pub enum EchoInstruction {
     /// | 1     | ❌       | ✅     | user: This is authority of the token account that is using the vending machine                       |
     /// | 2     | ✅       | ❌     | user_token_account: This is the token account that will pay for the use of the vending machine       |
     /// | 3     | ❌       | ❌     | vending_machine_mint: This is the token mint that is accepted by the `vending_machine_buffer`        |
-    /// | 3     | ❌       | ❌     | token_program: Used to burn the vending machine tokens                                               |
-    VendingMachineEcho { data: Vec<u8> },
+    /// | 4     | ❌       | ❌     | token_program: Used to burn the vending machine tokens                                               |
+    /// | 5     | ✅       | ❌     | session: Session opened with `OpenSession`, or the Echo Program ID to pay without one                |
+    ///
+    /// When `session` is passed, `user` is the session key of that session, and `price` is burned by the session as
+    /// delegate of `user_token_account` and drawn down from its budget. Buyers paying without a session can leave
+    /// the session slot out when no other accounts follow.
+    ///
+    /// Machines gated with `SetHoldingGate` take two more accounts after `session`, and fail unless
+    /// `user_token_account` was registered with `RegisterHolding` at least `min_slots` ago and still holds at least
+    /// `min_amount` tokens:
+    /// | index | writable | signer | description                                                                                         |
+    /// |-------|----------|--------|-----------------------------------------------------------------------------------------------------|
+    /// | 6     | ❌       | ❌     | holding_gate: PDA of Echo Program derived from `vending_machine_buffer`                              |
+    /// | 7     | ❌       | ❌     | holding_registration: PDA of Echo Program derived from the machine and `user_token_account`          |
+    ///
+    /// Writers are counted in `unique_writers` of the machine when they pass two more accounts after the holding gate,
+    /// Dutch auction and receipt ones. `user` must then be writable, as it pays for the `writer_marker` PDA
+    /// derived from `[b"writer", vending_machine_buffer, user_token_account]` on the first write of the token account:
+    /// | index | writable | signer | description                                                                                         |
+    /// |-------|----------|--------|-----------------------------------------------------------------------------------------------------|
+    /// | 6     | ✅       | ❌     | writer_marker: PDA of Echo Program derived from the machine and `user_token_account`                 |
+    /// | 7     | ❌       | ❌     | system_program: Used to allocate `writer_marker`                                                     |
+    ///
+    /// These accounts are required on machines with quadratic pricing, where the k-th purchase of
+    /// `user_token_account` costs `price * k^2`, see `SetQuadraticPricing`.
+    ///
+    /// Writer markers also add up the price of every purchase. Buyers passing them can pass the `leaderboard` PDA
+    /// derived from `[b"leaderboard", vending_machine_buffer]` right after them to enter their total on the top 10
+    /// burners of the machine, created on first use and paid by `user`:
+    /// | index | writable | signer | description                                                                                         |
+    /// |-------|----------|--------|-----------------------------------------------------------------------------------------------------|
+    /// | 8     | ✅       | ❌     | leaderboard: PDA of Echo Program derived from `vending_machine_buffer`                               |
+    ///
+    /// Machines sold by Dutch auction take their writable `dutch_auction` PDA, derived from
+    /// `[b"auction", vending_machine_buffer]`, right after the holding gate accounts. Their price is the current
+    /// auction price instead of `price`, see `SetDutchAuction`.
+    ///
+    /// Machines with receipts take two more accounts after the Dutch auction one, and mint one receipt token to
+    /// `receipt_account` on every purchase, see `InitializeReceiptMint`:
+    /// | index | writable | signer | description                                                                                         |
+    /// |-------|----------|--------|-----------------------------------------------------------------------------------------------------|
+    /// | 6     | ✅       | ❌     | receipt_mint: PDA of Echo Program derived from `[b"receipt", vending_machine_buffer]`                |
+    /// | 7     | ✅       | ❌     | receipt_account: Token account of `receipt_mint` receiving the receipt                               |
+    VendingMachineEcho {
+        data: Vec<u8>,
+        machine_seed: Trailing<u64>,
+    },
+    /// Rewrites an `authorized_buffer` created before headers carried a version and reserved bytes into the
+    /// current layout, growing the account and paying the extra rent from `authority`.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                              |
+    /// |-------|----------|--------|--------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to |
+    /// | 1     | ✅       | ✅     | authority: Pubkey with sole write access to `authorized_buffer`           |
+    /// | 2     | ❌       | ❌     | system_program: Used to pay the rent of the grown buffer                  |
+    MigrateAuthorizedBuffer,
+    /// Rewrites a `vending_machine_buffer` created before headers carried a version and reserved bytes, or in the
+    /// version 1 layout whose reserved bytes ran out, into the current layout, growing the account and paying the
+    /// extra rent from `payer`. Machines created with a `machine_seed` need the same `machine_seed` here.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                                                         |
+    /// |-------|----------|--------|-----------------------------------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | vending_machine_buffer: PDA of the Echo Program that only holders of a particular token can write to |
+    /// | 1     | ❌       | ❌     | vending_machine_mint: This is the token mint that is accepted by the `vending_machine_buffer`        |
+    /// | 2     | ✅       | ✅     | payer: Pubkey that pays the rent of the grown buffer                                                 |
+    /// | 3     | ❌       | ❌     | system_program: Used to pay the rent of the grown buffer                                             |
+    MigrateVendingMachineBuffer { machine_seed: Trailing<u64> },
+    /// Registers (or replaces) the hash of the Borsh schema that payloads of `authorized_buffer` follow. The hash
+    /// is stored in the `schema` PDA derived from `[b"schema", authorized_buffer]`, which is created on first use.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                              |
+    /// |-------|----------|--------|--------------------------------------------------------------------------|
+    /// | 0     | ❌       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to |
+    /// | 1     | ✅       | ✅     | authority: Pubkey with sole write access to `authorized_buffer`           |
+    /// | 2     | ✅       | ❌     | schema: PDA of Echo Program holding the schema hash of the buffer         |
+    /// | 3     | ❌       | ❌     | system_program: Used to allocate the schema account                       |
+    RegisterSchema { schema_hash: [u8; 32] },
+    /// Same as `AuthorizedEcho`, but fails unless `schema_hash` matches the hash registered for the buffer.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                                     |
+    /// |-------|----------|--------|---------------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to       |
+    /// | 1     | ❌       | ✅     | authority: Pubkey with sole write access to `authorized_buffer`                 |
+    /// | 2     | ❌       | ❌     | schema: PDA of Echo Program holding the schema hash of the buffer               |
+    /// | 3     | ✅       | ❌     | chunk_checksums: Optional, PDA of Echo Program derived from `authorized_buffer` |
+    AuthorizedEchoWithSchema {
+        schema_hash: [u8; 32],
+        data: Vec<u8>,
+    },
+    /// This instruction will allocate a `time_series` account holding `capacity` records of `value_len` bytes each
+    /// (plus an 8 byte timestamp per record) and assign it the Echo Program.
+    ///
+    /// `time_series` is the PDA derived from `[b"time_series", authority, buffer_seed]`.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                        |
+    /// |-------|----------|--------|--------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | time_series: PDA of Echo Program that only `authority` can append to |
+    /// | 1     | ✅       | ✅     | authority: Pubkey with sole write access to `time_series`            |
+    /// | 2     | ❌       | ❌     | system_program: Used to allocate the buffer                          |
+    InitializeTimeSeries {
+        buffer_seed: u64,
+        value_len: u32,
+        capacity: u32,
+    },
+    /// Appends `(Clock::unix_timestamp, value)` to `time_series`, overwriting the oldest record once the log is full.
+    ///
+    /// Fails if `value` is not exactly `value_len` bytes long, or if the cluster clock is older than the last
+    /// record's timestamp.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                        |
+    /// |-------|----------|--------|--------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | time_series: PDA of Echo Program that only `authority` can append to |
+    /// | 1     | ❌       | ✅     | authority: Pubkey with sole write access to `time_series`            |
+    AppendTimeSeries { value: Vec<u8> },
+    /// This instruction will allocate a `feed` account storing a single `value * 10^exponent` and assign it the
+    /// Echo Program. Every update must stay within `[min_value, max_value]`; the feed starts at `min_value`.
+    ///
+    /// `feed` is the PDA derived from `[b"feed", authority, buffer_seed]`.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                |
+    /// |-------|----------|--------|------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | feed: PDA of Echo Program that only `authority` can update   |
+    /// | 1     | ✅       | ✅     | authority: Pubkey with sole write access to `feed`           |
+    /// | 2     | ❌       | ❌     | system_program: Used to allocate the feed                    |
+    InitializeFeed {
+        buffer_seed: u64,
+        exponent: i32,
+        min_value: i128,
+        max_value: i128,
+    },
+    /// Sets the value of `feed`, failing if it is outside of the configured bounds.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                |
+    /// |-------|----------|--------|------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | feed: PDA of Echo Program that only `authority` can update   |
+    /// | 1     | ❌       | ✅     | authority: Pubkey with sole write access to `feed`           |
+    UpdateFeed { value: i128 },
+    /// This instruction will allocate an `aggregator` account with one submission slot per entry of `writers`
+    /// (at most 16) and assign it the Echo Program.
+    ///
+    /// `aggregator` is the PDA derived from `[b"aggregator", authority, buffer_seed]`.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                              |
+    /// |-------|----------|--------|----------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | aggregator: PDA of Echo Program collecting submissions     |
+    /// | 1     | ✅       | ✅     | authority: Pubkey that pays for and configures `aggregator` |
+    /// | 2     | ❌       | ❌     | system_program: Used to allocate the aggregator            |
+    InitializeAggregator {
+        buffer_seed: u64,
+        writers: Vec<Pubkey>,
+        // Submissions older than this many slots are ignored by `Crank`
+        max_staleness_slots: u64,
+        min_submissions: u8,
+    },
+    /// Stores `value` in the submission slot of `writer`, stamped with the current slot.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                          |
+    /// |-------|----------|--------|------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | aggregator: PDA of Echo Program collecting submissions |
+    /// | 1     | ❌       | ✅     | writer: One of the whitelisted writers                 |
+    SubmitValue { value: i128 },
+    /// Recomputes the median of the fresh submissions of `aggregator`. Anyone can crank.
+    ///
+    /// Fails if fewer than `min_submissions` writers submitted within `max_staleness_slots`.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                          |
+    /// |-------|----------|--------|------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | aggregator: PDA of Echo Program collecting submissions |
+    Crank,
+    /// Closes an `authorized_buffer` whose write deadline has passed and refunds its rent to `authority`, along with
+    /// its companions as in `CloseAuthorizedBuffer`. Anyone can call this instruction; `authority` does not need to
+    /// sign.
+    ///
+    /// Fails while the buffer is referenced.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                              |
+    /// |-------|----------|--------|--------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to |
+    /// | 1     | ✅       | ❌     | authority: Pubkey the buffer was derived from, receives the rent          |
+    /// | 2..10 | ✅       | ❌     | companions: PDAs of Echo Program derived from `authorized_buffer`         |
+    CloseExpiredBuffer,
+    /// Opens a session letting `session_key` pay for `VendingMachineEcho` calls on `vending_machine_buffer` with
+    /// tokens of `user_token_account`, up to `max_total_burn` tokens in total. The `session` PDA is approved as the
+    /// delegate of `user_token_account` for that amount.
+    ///
+    /// `session` is the PDA derived from `[b"session", vending_machine_buffer, user_token_account]`. Fails if the
+    /// header version of `vending_machine_buffer` is not current or its reserved bytes are non-zero.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                                    |
+    /// |-------|----------|--------|--------------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | session: PDA of Echo Program derived from the machine and `user_token_account` |
+    /// | 1     | ❌       | ❌     | vending_machine_buffer: The vending machine the session can pay for            |
+    /// | 2     | ✅       | ✅     | user: Owner of `user_token_account`, pays for the session account              |
+    /// | 3     | ✅       | ❌     | user_token_account: Token account the session burns from                       |
+    /// | 4     | ❌       | ❌     | session_key: Pubkey that signs `VendingMachineEcho` during the session         |
+    /// | 5     | ❌       | ❌     | token_program: Used to approve the session as delegate                         |
+    /// | 6     | ❌       | ❌     | system_program: Used to allocate the session                                   |
+    OpenSession { max_total_burn: u64 },
+    /// Revokes the delegation of `user_token_account`, closes `session` and refunds its rent to `user`.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                                    |
+    /// |-------|----------|--------|--------------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | session: PDA of Echo Program derived from the machine and `user_token_account` |
+    /// | 1     | ✅       | ✅     | user: Owner of `user_token_account` that opened the session                    |
+    /// | 2     | ✅       | ❌     | user_token_account: Token account the session burns from                       |
+    /// | 3     | ❌       | ❌     | token_program: Used to revoke the delegation                                   |
+    CloseSession,
+    /// Tops `buffer` up from `payer` to the rent-exempt minimum for its current size. Anyone can call this
+    /// instruction.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                  |
+    /// |-------|----------|--------|----------------------------------------------|
+    /// | 0     | ✅       | ❌     | buffer: Any account owned by the Echo Program |
+    /// | 1     | ✅       | ✅     | payer: Pays the missing lamports              |
+    /// | 2     | ❌       | ❌     | system_program: Used to transfer the lamports |
+    TopUpRent,
+    /// Sends the lamports of `authorized_buffer` above its rent-exempt minimum to `authority`, keeping the buffer
+    /// alive. Allowed past the write deadline.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                              |
+    /// |-------|----------|--------|--------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to |
+    /// | 1     | ✅       | ✅     | authority: Pubkey with sole write access to `authorized_buffer`           |
+    SweepExcessLamports,
+    /// Announces that `authorized_buffer` will be closed: `CloseAuthorizedBuffer` is allowed once `after_slot` has
+    /// passed, giving readers time to react. Scheduling again moves the close slot.
+    ///
+    /// Fails unless `after_slot` is in the future.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                              |
+    /// |-------|----------|--------|--------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to |
+    /// | 1     | ❌       | ✅     | authority: Pubkey with sole write access to `authorized_buffer`           |
+    ScheduleClose { after_slot: u64 },
+    /// Cancels the close scheduled with `ScheduleClose`.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                              |
+    /// |-------|----------|--------|--------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to |
+    /// | 1     | ❌       | ✅     | authority: Pubkey with sole write access to `authorized_buffer`           |
+    CancelClose,
+    /// Closes `authorized_buffer` and refunds its rent to `authority`.
+    ///
+    /// The companions of the buffer, see `pda::find_buffer_companion_addresses`, are closed along with it and their
+    /// lamports sent to `authority` too, so a buffer created again at the same address does not inherit them. All of
+    /// them must be passed, in order, including those that do not exist.
+    ///
+    /// Fails unless a close was scheduled with `ScheduleClose` and its slot has passed, or while the buffer is
+    /// referenced.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                              |
+    /// |-------|----------|--------|--------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to |
+    /// | 1     | ✅       | ✅     | authority: Pubkey with sole write access to `authorized_buffer`           |
+    /// | 2..10 | ✅       | ❌     | companions: PDAs of Echo Program derived from `authorized_buffer`         |
+    CloseAuthorizedBuffer,
+    /// Registers that `referrer` depends on `authorized_buffer`, typically called by another program through CPI
+    /// with one of its PDAs as `referrer`. The buffer cannot be closed until every reference is removed, so its
+    /// `authority` has to co-sign, and only `referrer` can remove the reference.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                          |
+    /// |-------|----------|--------|----------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | authorized_buffer: The referenced buffer                              |
+    /// | 1     | ❌       | ✅     | authority: Pubkey with sole write access to `authorized_buffer`       |
+    /// | 2     | ✅       | ❌     | reference: PDA of Echo Program derived from the buffer and `referrer` |
+    /// | 3     | ❌       | ✅     | referrer: Pubkey depending on the buffer                              |
+    /// | 4     | ✅       | ✅     | payer: Pays for the `reference` account                               |
+    /// | 5     | ❌       | ❌     | system_program: Used to allocate the reference                        |
+    AddReference,
+    /// Removes the reference of `referrer` on `authorized_buffer` and refunds its rent to `destination`.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                          |
+    /// |-------|----------|--------|----------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | authorized_buffer: The referenced buffer                              |
+    /// | 1     | ✅       | ❌     | reference: PDA of Echo Program derived from the buffer and `referrer` |
+    /// | 2     | ❌       | ✅     | referrer: Pubkey that added the reference                             |
+    /// | 3     | ✅       | ❌     | destination: Receives the rent of `reference`                         |
+    RemoveReference,
+    /// Same as `InitializeAuthorizedEcho`, but `authorized_buffer` is a keypair of the caller's choosing instead of a
+    /// PDA. `authority` is stored in the header and checked on every write in place of the PDA derivation; the
+    /// bump_seed and buffer_seed are zero and there is no namespace.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                          |
+    /// |-------|----------|--------|----------------------------------------------------------------------|
+    /// | 0     | ✅       | ✅     | authorized_buffer: Keypair account that only `authority` can write to |
+    /// | 1     | ✅       | ✅     | authority: Pubkey with sole write access, pays for the buffer         |
+    /// | 2     | ❌       | ❌     | system_program: Used to allocate the buffer                           |
+    InitializeKeypairAuthorizedEcho {
+        buffer_size: usize,
+        write_deadline_slot: Option<u64>,
+    },
+    /// Writes `data` once to `immutable_buffer`, the PDA derived from `sha256(data)`. No instruction modifies or closes
+    /// the buffer afterwards. Publishing data that already has a buffer does nothing.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                      |
+    /// |-------|----------|--------|------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | immutable_buffer: PDA of Echo Program derived from `sha256(data)` |
+    /// | 1     | ✅       | ✅     | payer: Pays for `immutable_buffer`                                |
+    /// | 2     | ❌       | ❌     | system_program: Used to allocate the buffer                       |
+    PublishImmutable { data: Vec<u8> },
+    /// Restricts `VendingMachineEcho` on `vending_machine_buffer` to the unix timestamps in `[opens_at, closes_at)`,
+    /// and, if `daily_window` is provided, to the `(opens_at, closes_at)` seconds since midnight UTC of every day.
+    /// A daily window that opens after it closes spans midnight. `None` removes the corresponding bound.
+    ///
+    /// Fails unless `admin` is the payer that initialized the machine.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                                                         |
+    /// |-------|----------|--------|-----------------------------------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | vending_machine_buffer: PDA of the Echo Program that only holders of a particular token can write to |
+    /// | 1     | ❌       | ✅     | admin: Payer that initialized the `vending_machine_buffer`                                           |
+    SetSaleWindow {
+        opens_at: Option<i64>,
+        closes_at: Option<i64>,
+        daily_window: Option<(u32, u32)>,
+    },
+    /// Requires buyers of `vending_machine_buffer` to hold at least `min_amount` tokens, registered with
+    /// `RegisterHolding` at least `min_slots` before buying, so tokens borrowed for a single transaction do not pass.
+    /// The requirement is stored in the `holding_gate` PDA derived from `[b"holding_gate", vending_machine_buffer]`,
+    /// which is created on first use. A `min_amount` of zero removes the gate.
+    ///
+    /// Fails unless `admin` is the payer that initialized the machine.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                                                         |
+    /// |-------|----------|--------|-----------------------------------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | vending_machine_buffer: PDA of the Echo Program that only holders of a particular token can write to |
+    /// | 1     | ✅       | ✅     | admin: Payer that initialized the `vending_machine_buffer`, pays for `holding_gate`                  |
+    /// | 2     | ✅       | ❌     | holding_gate: PDA of Echo Program derived from `vending_machine_buffer`                              |
+    /// | 3     | ❌       | ❌     | system_program: Used to allocate the holding gate                                                    |
+    SetHoldingGate { min_amount: u64, min_slots: u64 },
+    /// Records the current slot in the `holding_registration` PDA derived from
+    /// `[b"holding", vending_machine_buffer, user_token_account]`, starting the holding period required by the gate of
+    /// `vending_machine_buffer`.
+    ///
+    /// Fails unless `user` owns `user_token_account`, which holds at least `min_amount` tokens of the machine's mint.
+    /// Machines created with a `machine_seed` need the same `machine_seed` here.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                                                         |
+    /// |-------|----------|--------|-----------------------------------------------------------------------------------------------------|
+    /// | 0     | ❌       | ❌     | vending_machine_buffer: PDA of the Echo Program that only holders of a particular token can write to |
+    /// | 1     | ❌       | ❌     | vending_machine_mint: This is the token mint that is accepted by the `vending_machine_buffer`        |
+    /// | 2     | ❌       | ❌     | holding_gate: PDA of Echo Program derived from `vending_machine_buffer`                              |
+    /// | 3     | ✅       | ❌     | holding_registration: PDA of Echo Program derived from the machine and `user_token_account`          |
+    /// | 4     | ✅       | ✅     | user: Owner of `user_token_account`, pays for `holding_registration`                                 |
+    /// | 5     | ❌       | ❌     | user_token_account: Token account whose holding is registered                                        |
+    /// | 6     | ❌       | ❌     | system_program: Used to allocate the registration                                                    |
+    RegisterHolding { machine_seed: Trailing<u64> },
+    /// Sets the name, URI and description of `vending_machine_buffer`, growing the account and paying the extra rent
+    /// from `admin` if the machine had no metadata yet.
+    ///
+    /// Fails unless `admin` is the payer that initialized the machine and every field is valid UTF-8.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                                                         |
+    /// |-------|----------|--------|-----------------------------------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | vending_machine_buffer: PDA of the Echo Program that only holders of a particular token can write to |
+    /// | 1     | ✅       | ✅     | admin: Payer that initialized the `vending_machine_buffer`, pays for the grown account               |
+    /// | 2     | ❌       | ❌     | system_program: Used to pay the rent of the grown buffer                                             |
+    SetVendingMachineMetadata { metadata: VendingMachineMetadata },
+    /// Sets the title, external URL and tags of `authorized_buffer`, stored in the `buffer_metadata` PDA derived from
+    /// `[b"metadata", authorized_buffer]`, which is created on first use. Keeping them out of the buffer leaves its
+    /// data untouched.
+    ///
+    /// Fails unless every field is valid UTF-8.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                              |
+    /// |-------|----------|--------|--------------------------------------------------------------------------|
+    /// | 0     | ❌       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to |
+    /// | 1     | ✅       | ✅     | authority: Pubkey with sole write access to `authorized_buffer`           |
+    /// | 2     | ✅       | ❌     | buffer_metadata: PDA of Echo Program derived from `authorized_buffer`     |
+    /// | 3     | ❌       | ❌     | system_program: Used to allocate the metadata account                     |
+    SetBufferMetadata { metadata: BufferMetadata },
+    /// Closes `buffer_metadata` and refunds its rent to `creator`. Works after the buffer itself was closed.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                  |
+    /// |-------|----------|--------|--------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | buffer_metadata: PDA of Echo Program holding buffer metadata  |
+    /// | 1     | ✅       | ✅     | creator: Authority of the buffer that created the metadata    |
+    CloseBufferMetadata,
+    /// Creates the `org` PDA derived from `[b"org", creator, org_seed]` with `creator` as its only admin.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                   |
+    /// |-------|----------|--------|---------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | org: PDA of Echo Program derived from `creator` and `org_seed` |
+    /// | 1     | ✅       | ✅     | creator: First admin of the org, pays for `org`                |
+    /// | 2     | ❌       | ❌     | system_program: Used to allocate the org                       |
+    InitializeOrg { org_seed: u64 },
+    /// Adds `admin` to the admins of `org`, or removes it if `is_admin` is false. The account is resized to the
+    /// new number of admins, the extra rent being paid by `signer`.
+    ///
+    /// Fails unless `signer` is an admin of `org`, and if the org would exceed `MAX_ORG_ADMINS` admins or be left
+    /// without any.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                        |
+    /// |-------|----------|--------|----------------------------------------------------|
+    /// | 0     | ✅       | ❌     | org: PDA of Echo Program created by `InitializeOrg` |
+    /// | 1     | ✅       | ✅     | signer: Admin of `org`, pays for the grown org      |
+    /// | 2     | ❌       | ❌     | system_program: Used to pay the rent of the org     |
+    SetOrgAdmin { admin: Pubkey, is_admin: bool },
+    /// Creates the `project` PDA derived from `[b"project", org, project_seed]` without members.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                       |
+    /// |-------|----------|--------|-------------------------------------------------------------------|
+    /// | 0     | ❌       | ❌     | org: PDA of Echo Program created by `InitializeOrg`                |
+    /// | 1     | ✅       | ❌     | project: PDA of Echo Program derived from `org` and `project_seed` |
+    /// | 2     | ✅       | ✅     | admin: Admin of `org`, pays for `project`                          |
+    /// | 3     | ❌       | ❌     | system_program: Used to allocate the project                       |
+    InitializeProject { project_seed: u64 },
+    /// Adds `member` to the members of `project`, or removes it if `is_member` is false. The account is resized to
+    /// the new number of members, the extra rent being paid by `admin`.
+    ///
+    /// Fails unless `admin` is an admin of the org of `project`, and if the project would exceed
+    /// `MAX_PROJECT_MEMBERS` members.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                |
+    /// |-------|----------|--------|------------------------------------------------------------|
+    /// | 0     | ❌       | ❌     | org: PDA of Echo Program created by `InitializeOrg`         |
+    /// | 1     | ✅       | ❌     | project: PDA of Echo Program created by `InitializeProject` |
+    /// | 2     | ✅       | ✅     | admin: Admin of `org`, pays for the grown project           |
+    /// | 3     | ❌       | ❌     | system_program: Used to pay the rent of the project         |
+    SetProjectMember { member: Pubkey, is_member: bool },
+    /// Same as `InitializeAuthorizedEcho`, with the address of `project` as the authority seed, so that every
+    /// member of `project` can write to the buffer with `ProjectEcho`.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                  |
+    /// |-------|----------|--------|--------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | authorized_buffer: PDA of Echo Program derived from `project` |
+    /// | 1     | ❌       | ❌     | project: PDA of Echo Program created by `InitializeProject`   |
+    /// | 2     | ✅       | ✅     | member: Member of `project`, pays for `authorized_buffer`     |
+    /// | 3     | ❌       | ❌     | system_program: Used to allocate the buffer                   |
+    InitializeProjectBuffer {
+        buffer_seed: u64,
+        buffer_size: usize,
+    },
+    /// Same as `AuthorizedEcho` for a buffer initialized with `InitializeProjectBuffer`, signed by any member of
+    /// its project.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                                     |
+    /// |-------|----------|--------|---------------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | authorized_buffer: PDA of Echo Program derived from `project`                   |
+    /// | 1     | ❌       | ❌     | project: PDA of Echo Program created by `InitializeProject`                     |
+    /// | 2     | ❌       | ✅     | member: Member of `project`                                                     |
+    /// | 3     | ✅       | ❌     | chunk_checksums: Optional, PDA of Echo Program derived from `authorized_buffer` |
+    ProjectEcho { data: Vec<u8> },
+    /// Hands a keypair buffer over to `new` and appends the old key, `new`, the current slot and
+    /// `attestation_hash` to the `rotation_log` PDA derived from `[b"rotation_log", authorized_buffer]`, which is
+    /// created on first use. The log is never truncated, so it holds the full history of the buffer authority.
+    ///
+    /// Fails for PDA buffers, whose authority is part of their address.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                                |
+    /// |-------|----------|--------|----------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | authorized_buffer: Keypair account that only `authority` can write to       |
+    /// | 1     | ✅       | ✅     | authority: Current authority of `authorized_buffer`, pays for the grown log |
+    /// | 2     | ✅       | ❌     | rotation_log: PDA of Echo Program derived from `authorized_buffer`          |
+    /// | 3     | ❌       | ❌     | system_program: Used to allocate the log                                    |
+    RotateAuthorityWithAttestation {
+        new: Pubkey,
+        attestation_hash: [u8; 32],
+    },
+    /// Points `authorized_buffer` at a payload stored off-chain, for payloads larger than an account can hold. The
+    /// `payload_pointer` PDA derived from `[b"pointer", authorized_buffer]` is created on first use and records the
+    /// pointer with the current slot. Readers download the payload from the URI and check it with
+    /// `PayloadPointer::matches`.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                              |
+    /// |-------|----------|--------|--------------------------------------------------------------------------|
+    /// | 0     | ❌       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to |
+    /// | 1     | ✅       | ✅     | authority: Pubkey with sole write access to `authorized_buffer`           |
+    /// | 2     | ✅       | ❌     | payload_pointer: PDA of Echo Program derived from `authorized_buffer`     |
+    /// | 3     | ❌       | ❌     | system_program: Used to allocate the pointer account                      |
+    SetPayloadPointer { pointer: PayloadPointer },
+    /// Same as `SetPayloadPointer` for a payload pinned on IPFS, also storing its binary `cid` in the payload
+    /// pointer.
+    ///
+    /// Fails with `InvalidContentIdentifier` unless `cid` is a CIDv0, or a CIDv1 whose multihash is a 32 byte sha2-256
+    /// or blake2b-256 digest, see `is_valid_cid`.
+    ///
+    /// Accounts: same as `SetPayloadPointer`.
+    SetIpfsPayloadPointer {
+        pointer: PayloadPointer,
+        cid: Vec<u8>,
+    },
+    /// Posts the echo data of `authorized_buffer` to the Wormhole core bridge, as the payload built by
+    /// `bridge::build_payload` with the current slot. Messages are emitted by the PDA derived from `[b"emitter"]`.
+    /// The bridge fee must be transferred to `fee_collector` earlier in the same transaction.
+    ///
+    /// Fails with `InvalidInstructionData` unless the program is built with the `wormhole` feature.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                              |
+    /// |-------|----------|--------|--------------------------------------------------------------------------|
+    /// | 0     | ❌       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to |
+    /// | 1     | ❌       | ✅     | authority: Pubkey with sole write access to `authorized_buffer`           |
+    /// | 2     | ❌       | ❌     | wormhole_program: Wormhole core bridge of the cluster, see `program_ids`  |
+    /// | 3     | ✅       | ❌     | bridge_config: Config account of the core bridge                          |
+    /// | 4     | ✅       | ✅     | message: New account holding the posted message                           |
+    /// | 5     | ❌       | ❌     | emitter: PDA of Echo Program derived from `[b"emitter"]`                  |
+    /// | 6     | ✅       | ❌     | sequence: Sequence account of `emitter` in the core bridge                |
+    /// | 7     | ✅       | ✅     | payer: Pays for `message` and `sequence`                                  |
+    /// | 8     | ✅       | ❌     | fee_collector: Fee collector of the core bridge                           |
+    /// | 9     | ❌       | ❌     | clock: Clock sysvar                                                       |
+    /// | 10    | ❌       | ❌     | rent: Rent sysvar                                                         |
+    /// | 11    | ❌       | ❌     | system_program: Used by the core bridge to allocate `message`             |
+    PostToBridge {
+        nonce: u32,
+        consistency_level: ConsistencyLevel,
+    },
+    /// Turns quadratic pricing of `vending_machine_buffer` on or off. With quadratic pricing, the k-th
+    /// `VendingMachineEcho` of a token account costs `price * k^2`, counted in its writer marker, which buyers must
+    /// then pass.
+    ///
+    /// Fails unless `admin` is the payer that initialized the machine.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                                                         |
+    /// |-------|----------|--------|-----------------------------------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | vending_machine_buffer: PDA of the Echo Program that only holders of a particular token can write to |
+    /// | 1     | ❌       | ✅     | admin: Payer that initialized the `vending_machine_buffer`                                           |
+    SetQuadraticPricing { enabled: bool },
+    /// Sells `vending_machine_buffer` by Dutch auction: the price of a purchase decays linearly from `start_price`
+    /// to `floor_price` over the `decay_slots` slots following the previous purchase, or this instruction. The
+    /// auction is stored in the `dutch_auction` PDA derived from `[b"auction", vending_machine_buffer]`, which is
+    /// created on first use. A `decay_slots` of zero turns the auction off.
+    ///
+    /// Fails unless `admin` is the payer that initialized the machine and `floor_price <= start_price`.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                                                         |
+    /// |-------|----------|--------|-----------------------------------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | vending_machine_buffer: PDA of the Echo Program that only holders of a particular token can write to |
+    /// | 1     | ✅       | ✅     | admin: Payer that initialized the `vending_machine_buffer`, pays for `dutch_auction`                 |
+    /// | 2     | ✅       | ❌     | dutch_auction: PDA of Echo Program derived from `vending_machine_buffer`                             |
+    /// | 3     | ❌       | ❌     | system_program: Used to allocate the auction                                                         |
+    SetDutchAuction {
+        start_price: u64,
+        floor_price: u64,
+        decay_slots: u64,
+    },
+    /// Creates the `receipt_mint` PDA derived from `[b"receipt", vending_machine_buffer]`, an SPL mint with no
+    /// decimals and no freeze authority whose mint authority is `vending_machine_buffer`, and makes every later
+    /// `VendingMachineEcho` mint one receipt token to the buyer, for loyalty programs to build on.
+    ///
+    /// Fails unless `admin` is the payer that initialized the machine. The Token Program must be part of the
+    /// purchase transactions, which native-mint machines do not otherwise require.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                                                         |
+    /// |-------|----------|--------|-----------------------------------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | vending_machine_buffer: PDA of the Echo Program that only holders of a particular token can write to |
+    /// | 1     | ✅       | ✅     | admin: Payer that initialized the `vending_machine_buffer`, pays for `receipt_mint`                  |
+    /// | 2     | ✅       | ❌     | receipt_mint: PDA of Echo Program derived from `vending_machine_buffer`                              |
+    /// | 3     | ❌       | ❌     | system_program: Used to allocate the mint                                                            |
+    /// | 4     | ❌       | ❌     | token_program: Used to initialize the mint                                                           |
+    /// | 5     | ❌       | ❌     | rent: Rent sysvar, required by the Token Program                                                     |
+    InitializeReceiptMint,
+    /// Sets the guardians of the keypair buffer `authorized_buffer`, any `threshold` of which can hand it to a new
+    /// authority with `RecoverAuthority` once `timelock_slots` slots have passed, unless `authority` cancels first.
+    /// They are stored in the `recovery` PDA derived from `[b"recovery", authorized_buffer]`, which is created on
+    /// first use and resized to the number of guardians. An empty `guardians` with a `threshold` of zero turns
+    /// recovery off. Any pending recovery is cancelled.
+    ///
+    /// Fails unless `authorized_buffer` is a keypair buffer, the guardians are distinct and at most
+    /// `MAX_RECOVERY_GUARDIANS`, and `threshold` is between one and their number.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                      |
+    /// |-------|----------|--------|------------------------------------------------------------------|
+    /// | 0     | ❌       | ❌     | authorized_buffer: Keypair buffer of Echo Program                |
+    /// | 1     | ✅       | ✅     | authority: Authority of `authorized_buffer`, pays for `recovery` |
+    /// | 2     | ✅       | ❌     | recovery: PDA of Echo Program derived from `authorized_buffer`   |
+    /// | 3     | ❌       | ❌     | system_program: Used to allocate the recovery account            |
+    SetRecoveryGuardians {
+        guardians: Vec<Pubkey>,
+        threshold: u8,
+        timelock_slots: u64,
+    },
+    /// Approves handing `authorized_buffer` to `new`. The timelock starts once `threshold` guardians approved it.
+    ///
+    /// Fails with `NotAGuardian` unless `guardian` is one of the guardians set by `SetRecoveryGuardians`, and with
+    /// `RecoveryPending` while a recovery proposing another authority is pending, until the authority cancels it
+    /// with `CancelRecovery` or it is finalized.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                    |
+    /// |-------|----------|--------|----------------------------------------------------------------|
+    /// | 0     | ❌       | ❌     | authorized_buffer: Keypair buffer of Echo Program              |
+    /// | 1     | ✅       | ❌     | recovery: PDA of Echo Program derived from `authorized_buffer` |
+    /// | 2     | ❌       | ✅     | guardian: Guardian of `authorized_buffer`                      |
+    RecoverAuthority { new: Pubkey },
+    /// Cancels the pending recovery of `authorized_buffer`.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                    |
+    /// |-------|----------|--------|----------------------------------------------------------------|
+    /// | 0     | ❌       | ❌     | authorized_buffer: Keypair buffer of Echo Program              |
+    /// | 1     | ❌       | ✅     | authority: Authority of `authorized_buffer`                    |
+    /// | 2     | ✅       | ❌     | recovery: PDA of Echo Program derived from `authorized_buffer` |
+    CancelRecovery,
+    /// Hands `authorized_buffer` to the authority of its pending recovery once the timelock has passed, and
+    /// records the rotation in its rotation log, as `RotateAuthorityWithAttestation` would with a zero
+    /// `attestation_hash`. Anyone can finalize a recovery.
+    ///
+    /// Fails with `RecoveryTimelockActive` before `threshold` guardians approved the recovery and `timelock_slots`
+    /// slots passed since.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                        |
+    /// |-------|----------|--------|--------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | authorized_buffer: Keypair buffer of Echo Program                  |
+    /// | 1     | ✅       | ❌     | recovery: PDA of Echo Program derived from `authorized_buffer`     |
+    /// | 2     | ✅       | ❌     | rotation_log: PDA of Echo Program derived from `authorized_buffer` |
+    /// | 3     | ✅       | ✅     | payer: Pays for the grown `rotation_log`                           |
+    /// | 4     | ❌       | ❌     | system_program: Used to allocate the log                           |
+    FinalizeRecovery,
+    /// Stages `data` in the `dead_man_switch` PDA derived from `[b"dead_man", authorized_buffer]`, created on first
+    /// use and resized to `data`, and checks in. Readers ignore the staged data until `ReleaseDeadManSwitch`
+    /// releases it, which anyone can do once `authority` went more than `check_in_epochs` epochs without checking
+    /// in. The data is not encrypted by the program, see `DeadManSwitchHeader`.
+    ///
+    /// Fails with `DeadManSwitchReleased` once the data was released, and if `check_in_epochs` is zero.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                               |
+    /// |-------|----------|--------|---------------------------------------------------------------------------|
+    /// | 0     | ❌       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to |
+    /// | 1     | ✅       | ✅     | authority: Authority of `authorized_buffer`, pays for the switch          |
+    /// | 2     | ✅       | ❌     | dead_man_switch: PDA of Echo Program derived from `authorized_buffer`     |
+    /// | 3     | ❌       | ❌     | system_program: Used to allocate the switch                               |
+    StageDeadManSwitch { data: Vec<u8>, check_in_epochs: u64 },
+    /// Records that `authority` is still around, postponing the release of the dead-man switch of
+    /// `authorized_buffer` by `check_in_epochs` epochs from the current one.
+    ///
+    /// Fails with `DeadManSwitchReleased` once the data was released.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                               |
+    /// |-------|----------|--------|---------------------------------------------------------------------------|
+    /// | 0     | ❌       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to |
+    /// | 1     | ❌       | ✅     | authority: Pubkey with sole write access to `authorized_buffer`           |
+    /// | 2     | ✅       | ❌     | dead_man_switch: PDA of Echo Program derived from `authorized_buffer`     |
+    CheckIn,
+    /// Releases the data staged in `dead_man_switch`. Anyone can crank the release.
+    ///
+    /// Fails with `CheckInNotOverdue` unless more than `check_in_epochs` epochs passed since the last check-in, and
+    /// with `DeadManSwitchReleased` if the data was already released.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                          |
+    /// |-------|----------|--------|----------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | dead_man_switch: PDA of Echo Program created by `StageDeadManSwitch` |
+    ReleaseDeadManSwitch,
+    /// Transfers `lamports` from the rent vault of `authority` back to it.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                          |
+    /// |-------|----------|--------|----------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | rent_vault: System account derived from `[b"rent_vault", authority]` |
+    /// | 1     | ✅       | ✅     | authority: Owner of the rent vault                                   |
+    /// | 2     | ❌       | ❌     | system_program: Used to transfer the lamports                        |
+    WithdrawRentVault { lamports: u64 },
+    /// Sets whether writes to `authorized_buffer` read the account back once written and compare the hash of the
+    /// decoded header and echo data with the hash of what was meant to be written, failing the write with
+    /// `WriteVerificationFailed` on a mismatch. Costs compute units on every write, for buffers where a silently
+    /// corrupted write is worse than a failed one.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                               |
+    /// |-------|----------|--------|---------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to |
+    /// | 1     | ❌       | ✅     | authority: Pubkey with sole write access to `authorized_buffer`           |
+    SetVerifyAfterWrite { enabled: bool },
+    /// Same as `AuthorizedEcho`, but succeeds without writing if `idempotency_key` is the key of the last write made
+    /// with this instruction, so clients can resubmit a write whose outcome they did not observe without applying it
+    /// twice. The last key is kept in the `idempotency_record` PDA derived from `[b"idempotency", authorized_buffer]`,
+    /// created on the first use.
+    ///
+    /// Only the last key is remembered: resubmitting a write after another one went through applies it again.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                                                    |
+    /// |-------|----------|--------|------------------------------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to                      |
+    /// | 1     | ✅       | ✅     | authority: Pubkey with sole write access to `authorized_buffer`, pays for `idempotency_record` |
+    /// | 2     | ✅       | ❌     | idempotency_record: PDA of Echo Program derived from `authorized_buffer`                       |
+    /// | 3     | ❌       | ❌     | system_program: Used to allocate the record                                                    |
+    /// | 4     | ✅       | ❌     | chunk_checksums: Optional, PDA of Echo Program derived from `authorized_buffer`                |
+    AuthorizedEchoIdempotent {
+        data: Vec<u8>,
+        idempotency_key: [u8; 16],
+    },
+    /// Creates the singleton `global_stats` PDA derived from `[b"global_stats"]`, counting buffers created, writes,
+    /// tokens burned and lamports paid to native vending machines across the program.
+    ///
+    /// Instructions are counted when `global_stats` is appended, writable, after their own accounts: the initialize
+    /// instructions count a created buffer, the echo instructions a write, and `VendingMachineEcho` also counts the
+    /// tokens burned or lamports paid. Instructions sent without it are not counted, so the counters are a lower bound
+    /// kept up to date by the clients that opt in.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                        |
+    /// |-------|----------|--------|--------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | global_stats: PDA of Echo Program derived from `[b"global_stats"]` |
+    /// | 1     | ✅       | ✅     | payer: Pays for `global_stats`                                     |
+    /// | 2     | ❌       | ❌     | system_program: Used to allocate the stats                         |
+    InitializeGlobalStats,
+    /// Grows or shrinks `authorized_buffer` to `buffer_size` bytes, header included, keeping the echo data up to the
+    /// new length and zeroing any added bytes. `authority` pays the rent of the added bytes, and receives the lamports
+    /// above the rent-exempt minimum of a shrunk buffer.
+    ///
+    /// The runtime grows an account by at most 10 KiB per instruction.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                                                |
+    /// |-------|----------|--------|--------------------------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to                  |
+    /// | 1     | ✅       | ✅     | authority: Pubkey with sole write access to `authorized_buffer`, pays or receives the rent |
+    /// | 2     | ❌       | ❌     | system_program: Used to transfer the rent                                                  |
+    /// | 3     | ✅       | ❌     | chunk_checksums: Optional, PDA of Echo Program derived from `authorized_buffer`            |
+    ResizeAuthorizedBuffer { buffer_size: usize },
+    /// Sets whether writes to `authorized_buffer` keep a CRC-32 of each 4 KiB chunk of its echo data in the
+    /// `chunk_checksums` PDA derived from `[b"chunk_checksums", authorized_buffer]`, so clients can fetch a byte range
+    /// with a data slice and check it against the checksums of the chunks covering it, see `checksum`. Enabling
+    /// creates the PDA, paid by `authority`, with the checksums of the current echo data; disabling closes it and
+    /// refunds `authority`.
+    ///
+    /// Costs compute units on every write, proportional to the size of the echo data.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                                                |
+    /// |-------|----------|--------|--------------------------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to                  |
+    /// | 1     | ✅       | ✅     | authority: Pubkey with sole write access to `authorized_buffer`, pays or receives the rent |
+    /// | 2     | ✅       | ❌     | chunk_checksums: PDA of Echo Program derived from `authorized_buffer`                      |
+    /// | 3     | ❌       | ❌     | system_program: Used to allocate the checksums                                             |
+    SetChunkChecksums { enabled: bool },
+    /// Closes `vending_machine_buffer` and sends all of its lamports, rent and native sale proceeds included, to
+    /// `admin`. The account is shrunk to zero bytes and handed back to the System Program, so lamports sent to it
+    /// later in the same transaction leave an empty system account rather than reviving the machine.
+    ///
+    /// The holding gate, Dutch auction and leaderboard PDAs of the machine must follow `admin`, and those that exist
+    /// are closed the same way, their lamports going to `admin` too. The writer markers, holding registrations and
+    /// sessions of the machine can be passed after them, each followed by the token account it is derived from, and
+    /// are closed as well. Sessions are also followed by the user that opened them, who gets their rent back. Those
+    /// left out stay in place and apply again if a machine is later initialized at the same address, so clients
+    /// should pass all of them.
+    ///
+    /// Fails unless `admin` is the payer that initialized the machine, so migrated machines cannot be closed, and for
+    /// machines issuing receipts, as the Token Program cannot close their receipt mint.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                                                         |
+    /// |-------|----------|--------|-----------------------------------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | vending_machine_buffer: PDA of the Echo Program that only holders of a particular token can write to |
+    /// | 1     | ✅       | ✅     | admin: Payer that initialized the `vending_machine_buffer`, receives its lamports                    |
+    /// | 2     | ✅       | ❌     | holding_gate: PDA of Echo Program derived from `vending_machine_buffer`                              |
+    /// | 3     | ✅       | ❌     | dutch_auction: PDA of Echo Program derived from `vending_machine_buffer`                             |
+    /// | 4     | ✅       | ❌     | leaderboard: PDA of Echo Program derived from `vending_machine_buffer`                               |
+    /// | 5..   | ✅       | ❌     | companions: Optional, per token account PDAs of `vending_machine_buffer` to close, see above         |
+    CloseVendingMachine,
+    /// Writes `data[i]` to `authorized_buffer_i` as `AuthorizedEcho` would, for buffers of possibly different
+    /// authorities that all sign the transaction. The accounts come in one pair per entry of `data`, in the same
+    /// order, followed by the `chunk_checksums` PDAs of the buffers with `chunk_checksums` set, in the same order.
+    ///
+    /// Either every buffer is written or none is: any failing write fails the whole instruction.
+    ///
+    /// Accounts:
+    /// | index     | writable | signer | description                                                                  |
+    /// |-----------|----------|--------|------------------------------------------------------------------------------|
+    /// | 2 * i     | ✅       | ❌     | authorized_buffer_i: PDA of Echo Program that only `authority_i` can write to |
+    /// | 2 * i + 1 | ❌       | ✅     | authority_i: Pubkey with sole write access to `authorized_buffer_i`           |
+    CoSignedBatchEcho { data: Vec<Vec<u8>> },
+    /// Copies `data` into the echo data of `authorized_buffer` at its `write_offset` and moves the offset to the end
+    /// of `data`, leaving the bytes before it untouched, so the buffer can be extended like a log. The instructions
+    /// overwriting the echo data move the offset to the end of the bytes they wrote, and `ResizeAuthorizedBuffer`
+    /// pulls it back to the end of a shrunk buffer.
+    ///
+    /// Fails with `AppendNotAllowed` unless the buffer has `FEATURE_APPEND_ALLOWED`, and with `BufferFull` unless all
+    /// of `data` fits after the offset; nothing is written then. Checked like `AuthorizedEcho` otherwise.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                                     |
+    /// |-------|----------|--------|---------------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to       |
+    /// | 1     | ❌       | ✅     | authority: Pubkey with sole write access to `authorized_buffer`                 |
+    /// | 2     | ✅       | ❌     | chunk_checksums: Optional, PDA of Echo Program derived from `authorized_buffer` |
+    AppendEcho { data: Vec<u8> },
+    /// Replaces the `features` bitfield of `authorized_buffer` with `features`, a combination of the `FEATURE_*` bits
+    /// of `state`. The instructions writing authorized buffers check them in one place before writing:
+    ///
+    /// - `FEATURE_APPEND_ALLOWED`: `AppendEcho` is allowed.
+    /// - `FEATURE_IMMUTABLE`: writes, `ResizeAuthorizedBuffer` and `SetFeatures` fail with `BufferImmutable`, so the
+    ///   echo data and the features are frozen for good.
+    /// - `FEATURE_UTF8`: writes fail with `InvalidUtf8` unless the bytes they store, along with the chars of the echo
+    ///   data they land in, are valid UTF-8. A patch or an append may end in a cut char for the next write to
+    ///   complete, as long as it ends at or past the write offset with only zero padding after it.
+    /// - `FEATURE_CPI_GUARD`: writes fail with `CpiNotAllowed` when issued through CPI. They need the instructions
+    ///   sysvar, passed after their other accounts.
+    /// - `FEATURE_LOG_WRITES`: writes log the buffer, offset and length of the bytes they store.
+    ///
+    /// Fails with `InvalidInstructionData` if `features` has unknown bits, and with `InvalidUtf8` when turning on
+    /// `FEATURE_UTF8` while the echo data is not valid UTF-8.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                               |
+    /// |-------|----------|--------|---------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to |
+    /// | 1     | ❌       | ✅     | authority: Pubkey with sole write access to `authorized_buffer`           |
+    SetFeatures { features: u32 },
+    /// Copies `data` into the echo data of `authorized_buffer` starting `offset` bytes in, leaving the other bytes
+    /// untouched, so a buffer larger than what fits in one transaction can be filled across several. Does not move
+    /// the write offset of `AppendEcho`.
+    ///
+    /// Fails with `OffsetOutOfBounds` unless all of `data` fits in the echo data from `offset`; nothing is written
+    /// then. Checked like `AuthorizedEcho` otherwise.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                                     |
+    /// |-------|----------|--------|---------------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | authorized_buffer: PDA of Echo Program that only `authority` can write to       |
+    /// | 1     | ❌       | ✅     | authority: Pubkey with sole write access to `authorized_buffer`                 |
+    /// | 2     | ✅       | ❌     | chunk_checksums: Optional, PDA of Echo Program derived from `authorized_buffer` |
+    EchoAtOffset { offset: u32, data: Vec<u8> },
+    /// Writes `data[i]` to `authorized_buffer_i` as `AuthorizedEcho` would, for buffers of the same `authority`, so
+    /// that publishing to several buffers takes a single instruction. The buffers follow `authority` in the same order
+    /// as `data`, followed by the `chunk_checksums` PDAs of the buffers with `chunk_checksums` set, in the same order.
+    ///
+    /// Either every buffer is written or none is: any failing write fails the whole instruction.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                                 |
+    /// |-------|----------|--------|-----------------------------------------------------------------------------|
+    /// | 0     | ❌       | ✅     | authority: Pubkey with sole write access to the buffers                     |
+    /// | 1 + i | ✅       | ❌     | authorized_buffer_i: PDA of Echo Program that only `authority` can write to |
+    MultiEcho { data: Vec<Vec<u8>> },
+    /// Sends the lamports of `vending_machine_buffer` above its rent-exempt minimum to `admin`, keeping the machine
+    /// open.
+    ///
+    /// Fails unless `admin` is the payer that initialized the machine.
+    ///
+    /// Accounts:
+    /// | index | writable | signer | description                                                                                         |
+    /// |-------|----------|--------|-----------------------------------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | vending_machine_buffer: PDA of the Echo Program that only holders of a particular token can write to |
+    /// | 1     | ✅       | ✅     | admin: Payer that initialized the `vending_machine_buffer`, receives the excess lamports             |
+    SweepVendingMachineLamports,
 }
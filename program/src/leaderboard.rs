@@ -0,0 +1,45 @@
+//! Bounded board of the token accounts that burned the most on a vending machine, see `VendingMachineEcho`.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::pubkey::Pubkey;
+
+/// Number of entries kept on a board.
+pub const LEADERBOARD_LEN: usize = 10;
+
+/// Cumulative burn of one token account. Unused entries have a zero `burned`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct LeaderboardEntry {
+    pub buyer: Pubkey,
+    pub burned: u64,
+}
+
+impl LeaderboardEntry {
+    pub const LEN: usize = 32 + 8;
+}
+
+/// Records that `buyer` has burned `burned` in total, keeping `entries` sorted by decreasing burn. A buyer already on
+/// the board moves up to its new total, a new buyer takes the place of the smallest entry if it burned strictly more,
+/// and ties keep the buyer that reached the total first in front. Returns whether the board changed.
+pub fn record_burn(
+    entries: &mut [LeaderboardEntry; LEADERBOARD_LEN],
+    buyer: Pubkey,
+    burned: u64,
+) -> bool {
+    let current = entries
+        .iter()
+        .position(|entry| entry.burned > 0 && entry.buyer == buyer);
+    // Evicts the buyer itself when it is on the board, the smallest entry otherwise
+    let evicted = match current {
+        Some(index) if entries[index].burned >= burned => return false,
+        Some(index) => index,
+        None if entries[LEADERBOARD_LEN - 1].burned >= burned => return false,
+        None => LEADERBOARD_LEN - 1,
+    };
+    let index = entries
+        .iter()
+        .position(|entry| entry.burned < burned)
+        .unwrap_or(evicted);
+    entries[index..=evicted].rotate_right(1);
+    entries[index] = LeaderboardEntry { buyer, burned };
+    true
+}
@@ -1,5 +1,26 @@
+#[macro_use]
+mod log;
+
+pub mod bridge;
+pub mod checksum;
+pub mod client;
+pub mod cpi;
 pub mod entrypoint;
 pub mod error;
 pub mod instruction;
+pub mod leaderboard;
+#[cfg(feature = "mock-token")]
+pub mod mock_token;
+pub mod pda;
+pub mod pricing;
 pub mod processor;
+pub mod program_ids;
 pub mod state;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+
+// The program ID of the cluster the crate is built for, see `program_ids::Cluster::BUILD`
+#[cfg(feature = "devnet")]
+pub use program_ids::devnet::{check_id, id, ID};
+#[cfg(not(feature = "devnet"))]
+pub use program_ids::mainnet_beta::{check_id, id, ID};
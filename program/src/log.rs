@@ -0,0 +1,15 @@
+//! Logging that the `quiet` feature strips from release builds to save compute units.
+
+/// `msg!` for verbose, informational logs. With the `quiet` feature it compiles to nothing, except in debug builds.
+/// Errors are not logged through it: their reasons stay visible as `EchoError` codes.
+#[cfg(any(not(feature = "quiet"), debug_assertions))]
+macro_rules! verbose_msg {
+    ($($arg:tt)*) => {
+        solana_program::msg!($($arg)*)
+    };
+}
+
+#[cfg(all(feature = "quiet", not(debug_assertions)))]
+macro_rules! verbose_msg {
+    ($($arg:tt)*) => {};
+}
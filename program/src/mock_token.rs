@@ -0,0 +1,112 @@
+//! Minimal stand-in for the SPL Token Program, implementing only the instructions the Echo Program invokes.
+//!
+//! Register it with `ProgramTest::add_program("spl_token", spl_token::id(), processor!(process_instruction))` and add
+//! packed `Mint` and `Account` states directly, so vending machine logic can be tested without a real token setup.
+
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+use spl_token::{
+    error::TokenError,
+    instruction::TokenInstruction,
+    state::{Account, AccountState, Mint},
+};
+
+pub fn process_instruction(
+    _program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    match TokenInstruction::unpack(instruction_data)? {
+        TokenInstruction::Burn { amount } => burn(accounts, amount),
+        TokenInstruction::Approve { amount } => approve(accounts, amount),
+        TokenInstruction::Revoke => revoke(accounts),
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+fn burn(accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let source = next_account_info(accounts_iter)?;
+    let mint = next_account_info(accounts_iter)?;
+    let authority = next_account_info(accounts_iter)?;
+
+    let mut source_account = Account::unpack(&source.data.borrow())?;
+    let mut mint_data = Mint::unpack(&mint.data.borrow())?;
+    if source_account.mint != *mint.key {
+        return Err(TokenError::MintMismatch.into());
+    }
+    if source_account.state == AccountState::Frozen {
+        return Err(TokenError::AccountFrozen.into());
+    }
+    if !authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if source_account.owner != *authority.key {
+        if source_account.delegate != COption::Some(*authority.key) {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        source_account.delegated_amount = source_account
+            .delegated_amount
+            .checked_sub(amount)
+            .ok_or(TokenError::InsufficientFunds)?;
+        if source_account.delegated_amount == 0 {
+            source_account.delegate = COption::None;
+        }
+    }
+
+    source_account.amount = source_account
+        .amount
+        .checked_sub(amount)
+        .ok_or(TokenError::InsufficientFunds)?;
+    mint_data.supply = mint_data
+        .supply
+        .checked_sub(amount)
+        .ok_or(TokenError::Overflow)?;
+
+    Account::pack(source_account, &mut source.data.borrow_mut())?;
+    Mint::pack(mint_data, &mut mint.data.borrow_mut())
+}
+
+fn approve(accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let source = next_account_info(accounts_iter)?;
+    let delegate = next_account_info(accounts_iter)?;
+    let owner = next_account_info(accounts_iter)?;
+
+    let mut source_account = Account::unpack(&source.data.borrow())?;
+    if source_account.owner != *owner.key {
+        return Err(TokenError::OwnerMismatch.into());
+    }
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    source_account.delegate = COption::Some(*delegate.key);
+    source_account.delegated_amount = amount;
+    Account::pack(source_account, &mut source.data.borrow_mut())
+}
+
+fn revoke(accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let source = next_account_info(accounts_iter)?;
+    let owner = next_account_info(accounts_iter)?;
+
+    let mut source_account = Account::unpack(&source.data.borrow())?;
+    if source_account.owner != *owner.key {
+        return Err(TokenError::OwnerMismatch.into());
+    }
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    source_account.delegate = COption::None;
+    source_account.delegated_amount = 0;
+    Account::pack(source_account, &mut source.data.borrow_mut())
+}
@@ -0,0 +1,435 @@
+use solana_program::pubkey::{Pubkey, PubkeyError};
+
+pub const AUTHORITY_SEED: &[u8] = b"authority";
+pub const VENDING_MACHINE_SEED: &[u8] = b"vending_machine";
+pub const SCHEMA_SEED: &[u8] = b"schema";
+pub const TIME_SERIES_SEED: &[u8] = b"time_series";
+pub const FEED_SEED: &[u8] = b"feed";
+pub const AGGREGATOR_SEED: &[u8] = b"aggregator";
+pub const SESSION_SEED: &[u8] = b"session";
+pub const REFERENCE_SEED: &[u8] = b"reference";
+pub const IMMUTABLE_SEED: &[u8] = b"immutable";
+pub const HOLDING_GATE_SEED: &[u8] = b"holding_gate";
+pub const HOLDING_SEED: &[u8] = b"holding";
+pub const METADATA_SEED: &[u8] = b"metadata";
+pub const ORG_SEED: &[u8] = b"org";
+pub const PROJECT_SEED: &[u8] = b"project";
+pub const ROTATION_LOG_SEED: &[u8] = b"rotation_log";
+pub const POINTER_SEED: &[u8] = b"pointer";
+pub const EMITTER_SEED: &[u8] = b"emitter";
+pub const WRITER_SEED: &[u8] = b"writer";
+pub const AUCTION_SEED: &[u8] = b"auction";
+pub const RECEIPT_SEED: &[u8] = b"receipt";
+pub const RECOVERY_SEED: &[u8] = b"recovery";
+pub const DEAD_MAN_SEED: &[u8] = b"dead_man";
+pub const RENT_VAULT_SEED: &[u8] = b"rent_vault";
+pub const IDEMPOTENCY_SEED: &[u8] = b"idempotency";
+pub const GLOBAL_STATS_SEED: &[u8] = b"global_stats";
+pub const LEADERBOARD_SEED: &[u8] = b"leaderboard";
+pub const CHUNK_CHECKSUMS_SEED: &[u8] = b"chunk_checksums";
+
+/// Seeds of an `authorized_buffer` PDA, without the bump seed.
+///
+/// The `namespace` seed is only appended when present, so buffers created without one keep
+/// their original address.
+pub fn authorized_buffer_seeds<'a>(
+    authority: &'a Pubkey,
+    buffer_seed: &'a [u8; 8],
+    namespace: &'a Option<[u8; 8]>,
+) -> Vec<&'a [u8]> {
+    let mut seeds = vec![AUTHORITY_SEED, authority.as_ref(), buffer_seed.as_ref()];
+    if let Some(namespace) = namespace {
+        seeds.push(namespace.as_ref());
+    }
+    seeds
+}
+
+pub fn find_authorized_buffer_address(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    buffer_seed: u64,
+    namespace: Option<[u8; 8]>,
+) -> (Pubkey, u8) {
+    let buffer_seed = buffer_seed.to_le_bytes();
+    Pubkey::find_program_address(
+        &authorized_buffer_seeds(authority, &buffer_seed, &namespace),
+        program_id,
+    )
+}
+
+pub fn create_authorized_buffer_address(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    buffer_seed: u64,
+    namespace: Option<[u8; 8]>,
+    bump_seed: u8,
+) -> Result<Pubkey, PubkeyError> {
+    let buffer_seed = buffer_seed.to_le_bytes();
+    let bump_seed = [bump_seed];
+    let mut seeds = authorized_buffer_seeds(authority, &buffer_seed, &namespace);
+    seeds.push(&bump_seed);
+    Pubkey::create_program_address(&seeds, program_id)
+}
+
+/// Seeds of a `vending_machine_buffer` PDA, without the bump seed.
+///
+/// The `machine_seed` is only appended when present, so machines created without one keep
+/// their original address.
+pub fn vending_machine_seeds<'a>(
+    vending_machine_mint: &'a Pubkey,
+    price: &'a [u8; 8],
+    machine_seed: &'a Option<[u8; 8]>,
+) -> Vec<&'a [u8]> {
+    let mut seeds = vec![
+        VENDING_MACHINE_SEED,
+        vending_machine_mint.as_ref(),
+        price.as_ref(),
+    ];
+    if let Some(machine_seed) = machine_seed {
+        seeds.push(machine_seed.as_ref());
+    }
+    seeds
+}
+
+pub fn find_vending_machine_address(
+    program_id: &Pubkey,
+    vending_machine_mint: &Pubkey,
+    price: u64,
+    machine_seed: Option<u64>,
+) -> (Pubkey, u8) {
+    let price = price.to_le_bytes();
+    let machine_seed = machine_seed.map(u64::to_le_bytes);
+    Pubkey::find_program_address(
+        &vending_machine_seeds(vending_machine_mint, &price, &machine_seed),
+        program_id,
+    )
+}
+
+pub fn create_vending_machine_address(
+    program_id: &Pubkey,
+    vending_machine_mint: &Pubkey,
+    price: u64,
+    machine_seed: Option<u64>,
+    bump_seed: u8,
+) -> Result<Pubkey, PubkeyError> {
+    let price = price.to_le_bytes();
+    let machine_seed = machine_seed.map(u64::to_le_bytes);
+    let bump_seed = [bump_seed];
+    let mut seeds = vending_machine_seeds(vending_machine_mint, &price, &machine_seed);
+    seeds.push(&bump_seed);
+    Pubkey::create_program_address(&seeds, program_id)
+}
+
+pub fn find_schema_address(program_id: &Pubkey, buffer: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[SCHEMA_SEED, buffer.as_ref()], program_id)
+}
+
+pub fn find_time_series_address(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    buffer_seed: u64,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            TIME_SERIES_SEED,
+            authority.as_ref(),
+            &buffer_seed.to_le_bytes(),
+        ],
+        program_id,
+    )
+}
+
+pub fn create_time_series_address(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    buffer_seed: u64,
+    bump_seed: u8,
+) -> Result<Pubkey, PubkeyError> {
+    Pubkey::create_program_address(
+        &[
+            TIME_SERIES_SEED,
+            authority.as_ref(),
+            &buffer_seed.to_le_bytes(),
+            &[bump_seed],
+        ],
+        program_id,
+    )
+}
+
+pub fn find_feed_address(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    buffer_seed: u64,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[FEED_SEED, authority.as_ref(), &buffer_seed.to_le_bytes()],
+        program_id,
+    )
+}
+
+pub fn create_feed_address(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    buffer_seed: u64,
+    bump_seed: u8,
+) -> Result<Pubkey, PubkeyError> {
+    Pubkey::create_program_address(
+        &[
+            FEED_SEED,
+            authority.as_ref(),
+            &buffer_seed.to_le_bytes(),
+            &[bump_seed],
+        ],
+        program_id,
+    )
+}
+
+pub fn find_aggregator_address(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    buffer_seed: u64,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            AGGREGATOR_SEED,
+            authority.as_ref(),
+            &buffer_seed.to_le_bytes(),
+        ],
+        program_id,
+    )
+}
+
+pub fn find_session_address(
+    program_id: &Pubkey,
+    vending_machine_buffer: &Pubkey,
+    user_token_account: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            SESSION_SEED,
+            vending_machine_buffer.as_ref(),
+            user_token_account.as_ref(),
+        ],
+        program_id,
+    )
+}
+
+pub fn create_session_address(
+    program_id: &Pubkey,
+    vending_machine_buffer: &Pubkey,
+    user_token_account: &Pubkey,
+    bump_seed: u8,
+) -> Result<Pubkey, PubkeyError> {
+    Pubkey::create_program_address(
+        &[
+            SESSION_SEED,
+            vending_machine_buffer.as_ref(),
+            user_token_account.as_ref(),
+            &[bump_seed],
+        ],
+        program_id,
+    )
+}
+
+pub fn find_reference_address(
+    program_id: &Pubkey,
+    buffer: &Pubkey,
+    referrer: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[REFERENCE_SEED, buffer.as_ref(), referrer.as_ref()],
+        program_id,
+    )
+}
+
+/// Address of the immutable buffer holding data whose sha256 hash is `content_hash`.
+pub fn find_immutable_address(program_id: &Pubkey, content_hash: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[IMMUTABLE_SEED, content_hash], program_id)
+}
+
+pub fn find_holding_gate_address(
+    program_id: &Pubkey,
+    vending_machine_buffer: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[HOLDING_GATE_SEED, vending_machine_buffer.as_ref()],
+        program_id,
+    )
+}
+
+pub fn find_holding_registration_address(
+    program_id: &Pubkey,
+    vending_machine_buffer: &Pubkey,
+    user_token_account: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            HOLDING_SEED,
+            vending_machine_buffer.as_ref(),
+            user_token_account.as_ref(),
+        ],
+        program_id,
+    )
+}
+
+pub fn find_buffer_metadata_address(program_id: &Pubkey, buffer: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[METADATA_SEED, buffer.as_ref()], program_id)
+}
+
+pub fn create_buffer_metadata_address(
+    program_id: &Pubkey,
+    buffer: &Pubkey,
+    bump_seed: u8,
+) -> Result<Pubkey, PubkeyError> {
+    Pubkey::create_program_address(&[METADATA_SEED, buffer.as_ref(), &[bump_seed]], program_id)
+}
+
+pub fn find_org_address(program_id: &Pubkey, creator: &Pubkey, org_seed: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[ORG_SEED, creator.as_ref(), &org_seed.to_le_bytes()],
+        program_id,
+    )
+}
+
+pub fn create_org_address(
+    program_id: &Pubkey,
+    creator: &Pubkey,
+    org_seed: u64,
+    bump_seed: u8,
+) -> Result<Pubkey, PubkeyError> {
+    Pubkey::create_program_address(
+        &[
+            ORG_SEED,
+            creator.as_ref(),
+            &org_seed.to_le_bytes(),
+            &[bump_seed],
+        ],
+        program_id,
+    )
+}
+
+pub fn find_project_address(program_id: &Pubkey, org: &Pubkey, project_seed: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[PROJECT_SEED, org.as_ref(), &project_seed.to_le_bytes()],
+        program_id,
+    )
+}
+
+pub fn create_project_address(
+    program_id: &Pubkey,
+    org: &Pubkey,
+    project_seed: u64,
+    bump_seed: u8,
+) -> Result<Pubkey, PubkeyError> {
+    Pubkey::create_program_address(
+        &[
+            PROJECT_SEED,
+            org.as_ref(),
+            &project_seed.to_le_bytes(),
+            &[bump_seed],
+        ],
+        program_id,
+    )
+}
+
+pub fn find_rotation_log_address(program_id: &Pubkey, buffer: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[ROTATION_LOG_SEED, buffer.as_ref()], program_id)
+}
+
+pub fn find_payload_pointer_address(program_id: &Pubkey, buffer: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[POINTER_SEED, buffer.as_ref()], program_id)
+}
+
+/// Wormhole emitter of the program, shared by all buffers since payloads name their buffer.
+pub fn find_emitter_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[EMITTER_SEED], program_id)
+}
+
+pub fn find_writer_marker_address(
+    program_id: &Pubkey,
+    vending_machine_buffer: &Pubkey,
+    user_token_account: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            WRITER_SEED,
+            vending_machine_buffer.as_ref(),
+            user_token_account.as_ref(),
+        ],
+        program_id,
+    )
+}
+
+pub fn find_dutch_auction_address(
+    program_id: &Pubkey,
+    vending_machine_buffer: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[AUCTION_SEED, vending_machine_buffer.as_ref()], program_id)
+}
+
+pub fn find_receipt_mint_address(
+    program_id: &Pubkey,
+    vending_machine_buffer: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[RECEIPT_SEED, vending_machine_buffer.as_ref()], program_id)
+}
+
+pub fn find_recovery_address(program_id: &Pubkey, buffer: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[RECOVERY_SEED, buffer.as_ref()], program_id)
+}
+
+pub fn find_dead_man_switch_address(program_id: &Pubkey, buffer: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[DEAD_MAN_SEED, buffer.as_ref()], program_id)
+}
+
+/// System account holding the lamports prepaid by or for `authority`, see `InitializeAuthorizedEcho`.
+pub fn find_rent_vault_address(program_id: &Pubkey, authority: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[RENT_VAULT_SEED, authority.as_ref()], program_id)
+}
+
+pub fn find_idempotency_record_address(program_id: &Pubkey, buffer: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[IDEMPOTENCY_SEED, buffer.as_ref()], program_id)
+}
+
+/// Singleton counting buffers, writes, burns and fees across the program, see `InitializeGlobalStats`.
+pub fn find_global_stats_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[GLOBAL_STATS_SEED], program_id)
+}
+
+pub fn find_leaderboard_address(program_id: &Pubkey, vending_machine: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[LEADERBOARD_SEED, vending_machine.as_ref()], program_id)
+}
+
+/// Checksums of the echo data chunks of an authorized buffer, see `SetChunkChecksums`.
+pub fn find_chunk_checksums_address(program_id: &Pubkey, buffer: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CHUNK_CHECKSUMS_SEED, buffer.as_ref()], program_id)
+}
+
+/// PDAs holding state about an authorized buffer, in the order `CloseAuthorizedBuffer` and `CloseExpiredBuffer` take
+/// them: schema, metadata, idempotency record, chunk checksums, recovery, dead-man switch, payload pointer and
+/// rotation log.
+pub fn find_buffer_companion_addresses(program_id: &Pubkey, buffer: &Pubkey) -> [Pubkey; 8] {
+    [
+        find_schema_address(program_id, buffer).0,
+        find_buffer_metadata_address(program_id, buffer).0,
+        find_idempotency_record_address(program_id, buffer).0,
+        find_chunk_checksums_address(program_id, buffer).0,
+        find_recovery_address(program_id, buffer).0,
+        find_dead_man_switch_address(program_id, buffer).0,
+        find_payload_pointer_address(program_id, buffer).0,
+        find_rotation_log_address(program_id, buffer).0,
+    ]
+}
+
+/// PDAs configuring a vending machine, in the order `CloseVendingMachine` takes them: holding gate, Dutch auction
+/// and leaderboard.
+pub fn find_vending_machine_companion_addresses(
+    program_id: &Pubkey,
+    vending_machine: &Pubkey,
+) -> [Pubkey; 3] {
+    [
+        find_holding_gate_address(program_id, vending_machine).0,
+        find_dutch_auction_address(program_id, vending_machine).0,
+        find_leaderboard_address(program_id, vending_machine).0,
+    ]
+}
@@ -0,0 +1,62 @@
+//! Prices of `VendingMachineEcho` purchases.
+
+/// Price of the `k`-th purchase, counting from 1, by the same token account on a machine selling at `price`:
+/// `price * k^2` with quadratic pricing, so repeat buyers cannot cheaply keep the board to themselves, and `price`
+/// otherwise. `None` if the price does not fit in a `u64`.
+pub fn purchase_price(price: u64, quadratic: bool, k: u64) -> Option<u64> {
+    if !quadratic {
+        return Some(price);
+    }
+    k.checked_mul(k)
+        .and_then(|k_squared| price.checked_mul(k_squared))
+}
+
+/// Price of a Dutch auction `elapsed_slots` after the last purchase: decays linearly from `start_price` to
+/// `floor_price` over `decay_slots`, then stays at `floor_price`. Expects `floor_price <= start_price`.
+pub fn dutch_auction_price(
+    start_price: u64,
+    floor_price: u64,
+    decay_slots: u64,
+    elapsed_slots: u64,
+) -> u64 {
+    if elapsed_slots >= decay_slots {
+        return floor_price;
+    }
+    // Cannot overflow: the product is below 2^128 and the quotient below `start_price - floor_price`
+    let decayed = (start_price - floor_price) as u128 * elapsed_slots as u128 / decay_slots as u128;
+    start_price - decayed as u64
+}
+
+/// Pricing of the purchases of one token account on a vending machine.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PricingCurve {
+    /// Price of the machine, or the current price of its Dutch auction, which decays independently of the buyer
+    pub price: u64,
+    /// Whether the machine has quadratic pricing, see `SetQuadraticPricing`
+    pub quadratic: bool,
+}
+
+/// Prices of successive purchases of one token account, with the running total burned after each of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Simulation {
+    pub prices: Vec<u64>,
+    pub cumulative_burn: Vec<u64>,
+}
+
+/// Off-chain model of the first `n_purchases` purchases of a token account counted by its writer marker on a machine
+/// priced by `curve`, priced as by `VendingMachineEcho`. `None` if a price or the total burn does not fit in a `u64`,
+/// where `VendingMachineEcho` fails too.
+pub fn simulate(curve: &PricingCurve, n_purchases: u64) -> Option<Simulation> {
+    let mut simulation = Simulation {
+        prices: vec![],
+        cumulative_burn: vec![],
+    };
+    let mut burned = 0u64;
+    for k in 1..=n_purchases {
+        let price = purchase_price(curve.price, curve.quadratic, k)?;
+        burned = burned.checked_add(price)?;
+        simulation.prices.push(price);
+        simulation.cumulative_burn.push(burned);
+    }
+    Some(simulation)
+}
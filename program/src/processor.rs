@@ -1,20 +1,58 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
-    account_info::{next_account_info, AccountInfo}, 
-    entrypoint::ProgramResult, msg, 
-    program_error::ProgramError,
-    pubkey::Pubkey,
-    system_instruction,
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    hash::hash,
+    msg,
     program::{invoke, invoke_signed},
-    sysvar::{rent::Rent, Sysvar},
+    program_error::ProgramError,
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::{Pubkey, PubkeyError},
+    system_instruction, system_program,
+    sysvar::{self, clock::Clock, rent::Rent, Sysvar},
 };
 // use solana_sdk::account::WritableAccount;
 
-use spl_token::instruction::burn;
+use spl_token::instruction::{approve, burn, initialize_mint, mint_to, revoke, transfer};
+use spl_token::state::Account as TokenAccount;
 
+use crate::bridge::ConsistencyLevel;
+use crate::checksum;
 use crate::error::EchoError;
-use crate::instruction::EchoInstruction;
-use crate::state::{AuthorizedBufferHeader, VendingMachineBufferHeader};
+use crate::instruction::{EchoInstruction, MAX_INSTRUCTION_DATA_LEN, MIN_INSTRUCTION_DATA_LEN};
+use crate::leaderboard::record_burn;
+use crate::pda::{
+    authorized_buffer_seeds, create_authorized_buffer_address, create_buffer_metadata_address,
+    create_feed_address, create_org_address, create_project_address, create_session_address,
+    create_time_series_address, create_vending_machine_address, find_aggregator_address,
+    find_authorized_buffer_address, find_buffer_companion_addresses, find_buffer_metadata_address,
+    find_chunk_checksums_address, find_dead_man_switch_address, find_dutch_auction_address,
+    find_feed_address, find_global_stats_address, find_holding_gate_address,
+    find_holding_registration_address, find_idempotency_record_address, find_immutable_address,
+    find_leaderboard_address, find_org_address, find_payload_pointer_address, find_project_address,
+    find_receipt_mint_address, find_recovery_address, find_reference_address,
+    find_rent_vault_address, find_rotation_log_address, find_schema_address, find_session_address,
+    find_time_series_address, find_vending_machine_address,
+    find_vending_machine_companion_addresses, find_writer_marker_address, vending_machine_seeds,
+    AGGREGATOR_SEED, AUCTION_SEED, CHUNK_CHECKSUMS_SEED, DEAD_MAN_SEED, FEED_SEED,
+    GLOBAL_STATS_SEED, HOLDING_GATE_SEED, HOLDING_SEED, IDEMPOTENCY_SEED, IMMUTABLE_SEED,
+    LEADERBOARD_SEED, METADATA_SEED, ORG_SEED, POINTER_SEED, PROJECT_SEED, RECEIPT_SEED,
+    RECOVERY_SEED, REFERENCE_SEED, RENT_VAULT_SEED, ROTATION_LOG_SEED, SCHEMA_SEED, SESSION_SEED,
+    TIME_SERIES_SEED, WRITER_SEED,
+};
+use crate::pricing::purchase_price;
+use crate::state::{
+    checked_account_len, is_valid_cid, AggregatorHeader, AuthorityRotation, AuthorityRotationLog,
+    AuthorizedBufferHeader, BufferMetadataHeader, BufferReference, BufferSchema, ChunkChecksums,
+    DeadManSwitchHeader, DutchAuction, FeedHeader, GlobalStats, HoldingGate, HoldingRegistration,
+    IdempotencyRecord, ImmutableBufferHeader, Leaderboard, LegacyAuthorizedBufferHeader, OrgHeader,
+    PayloadPointer, PayloadPointerHeader, ProjectHeader, RecoveryHeader, Reserved, SessionHeader,
+    TimeSeriesHeader, Trailing, VendingMachineBufferHeader, VendingMachineMetadata, WriterMarker,
+    WriterSlot, CURRENT_VERSION, FEATURE_APPEND_ALLOWED, FEATURE_CPI_GUARD, FEATURE_IMMUTABLE,
+    FEATURE_LOG_WRITES, FEATURE_UTF8, KNOWN_FEATURES, MAX_AGGREGATOR_WRITERS, MAX_ORG_ADMINS,
+    MAX_PROJECT_MEMBERS, MAX_RECOVERY_GUARDIANS, SECONDS_PER_DAY, VENDING_MACHINE_VERSION,
+};
 
 pub struct Processor {}
 
@@ -27,7 +65,6 @@ pub fn assert_with_msg(statement: bool, err: ProgramError, msg: &str) -> Program
     }
 }
 
-
 pub fn assert_is_writable(account_info: &AccountInfo) -> ProgramResult {
     assert_with_msg(
         account_info.is_writable,
@@ -36,18 +73,1113 @@ pub fn assert_is_writable(account_info: &AccountInfo) -> ProgramResult {
     )
 }
 
+pub fn assert_valid_header<const N: usize>(version: u8, reserved: &Reserved<N>) -> ProgramResult {
+    if version != CURRENT_VERSION {
+        return Err(EchoError::InvalidBufferVersion.into());
+    }
+    if !reserved.is_zero() {
+        return Err(EchoError::NonZeroReserved.into());
+    }
+    Ok(())
+}
+
+/// `assert_valid_header` for vending machine buffers, which are versioned apart from the other accounts.
+pub fn assert_valid_vending_header(vending_buffer: &VendingMachineBufferHeader) -> ProgramResult {
+    if vending_buffer.version != VENDING_MACHINE_VERSION {
+        return Err(EchoError::InvalidBufferVersion.into());
+    }
+    if !vending_buffer._reserved.is_zero() {
+        return Err(EchoError::NonZeroReserved.into());
+    }
+    Ok(())
+}
+
+/// Checks that `account` is the PDA `expected`, a `find_*_address` key or the result of a `create_*_address`, whose
+/// seeds may not yield a PDA at all when a stored bump seed was tampered with. Fails with `error` in both cases,
+/// logging the expected and actual keys, rather than with an opaque `InvalidSeeds`.
+fn assert_pda(
+    expected: Result<Pubkey, PubkeyError>,
+    account: &AccountInfo,
+    error: EchoError,
+) -> ProgramResult {
+    match expected {
+        Ok(expected) if expected == *account.key => Ok(()),
+        Ok(expected) => {
+            msg!("Expected PDA {}, got {}", expected, account.key);
+            Err(error.into())
+        }
+        Err(_) => {
+            msg!("Seeds of {} do not yield a PDA", account.key);
+            Err(error.into())
+        }
+    }
+}
+
+/// Checks that `buffer` was not created yet, so reusing a seed fails with `BufferAlreadyExists` rather than with the
+/// opaque error of the system program refusing to create an account in use.
+fn assert_buffer_not_created(program_id: &Pubkey, buffer: &AccountInfo) -> ProgramResult {
+    if buffer.owner == program_id {
+        msg!("Buffer {} already exists", buffer.key);
+        return Err(EchoError::BufferAlreadyExists.into());
+    }
+    Ok(())
+}
+
+/// Checks that `authority` is the authority of `authorized_buffer`: the key stored in the header of a keypair
+/// buffer, or the key a PDA buffer was derived from.
+fn assert_buffer_authority(
+    program_id: &Pubkey,
+    authorized_buffer: &AccountInfo,
+    buffer_data: &AuthorizedBufferHeader,
+    authority: &Pubkey,
+) -> ProgramResult {
+    if buffer_data.is_keypair_buffer() {
+        if authorized_buffer.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        if buffer_data.authority != *authority {
+            return Err(EchoError::InvalidAuthority.into());
+        }
+        return Ok(());
+    }
+
+    let authorized_buffer_key = create_authorized_buffer_address(
+        program_id,
+        authority,
+        buffer_data.buffer_seed,
+        buffer_data.namespace,
+        buffer_data.bump_seed,
+    );
+    assert_pda(
+        authorized_buffer_key,
+        authorized_buffer,
+        EchoError::InvalidAuthority,
+    )?;
+    Ok(())
+}
+
+/// Checks that `authority` signed and is the authority of `authorized_buffer`, and returns the decoded header,
+/// without its echo data.
+fn load_authorized_buffer(
+    program_id: &Pubkey,
+    authorized_buffer: &AccountInfo,
+    authority: &AccountInfo,
+) -> Result<AuthorizedBufferHeader, ProgramError> {
+    // check signer
+    if !authority.is_signer {
+        return Err(EchoError::AuthorityNotSigner.into());
+    }
+
+    let buffer_data = AuthorizedBufferHeader::unpack_header(&authorized_buffer.data.borrow())?;
+    assert_valid_header(buffer_data.version, &buffer_data._reserved)?;
+    assert_buffer_authority(program_id, authorized_buffer, &buffer_data, authority.key)?;
+
+    if buffer_data.is_expired(Clock::get()?.slot) {
+        return Err(EchoError::WriteDeadlinePassed.into());
+    }
+    Ok(buffer_data)
+}
+
+/// Checks that `holding_gate` is the holding gate of `vending_machine_buffer` and returns it.
+fn load_holding_gate(
+    program_id: &Pubkey,
+    vending_machine_buffer: &AccountInfo,
+    holding_gate: &AccountInfo,
+) -> Result<HoldingGate, ProgramError> {
+    let (holding_gate_key, _) = find_holding_gate_address(program_id, vending_machine_buffer.key);
+    assert_pda(
+        Ok(holding_gate_key),
+        holding_gate,
+        EchoError::InvalidAuthority,
+    )?;
+    if holding_gate.owner != program_id {
+        return Err(EchoError::InvalidAuthority.into());
+    }
+    let gate = HoldingGate::try_from_slice(&holding_gate.data.borrow())?;
+    assert_valid_header(gate.version, &gate._reserved)?;
+    Ok(gate)
+}
+
+/// Checks that `user_token_account` was registered long enough ago and still holds enough tokens to pass the
+/// holding gate of `vending_machine_buffer`.
+fn assert_holding(
+    program_id: &Pubkey,
+    vending_machine_buffer: &AccountInfo,
+    holding_gate: &AccountInfo,
+    holding_registration: &AccountInfo,
+    user_token_account: &AccountInfo,
+) -> ProgramResult {
+    let gate = load_holding_gate(program_id, vending_machine_buffer, holding_gate)?;
+
+    let (registration_key, _) = find_holding_registration_address(
+        program_id,
+        vending_machine_buffer.key,
+        user_token_account.key,
+    );
+    assert_pda(
+        Ok(registration_key),
+        holding_registration,
+        EchoError::InvalidAuthority,
+    )?;
+    // Not registered yet
+    if holding_registration.owner != program_id {
+        return Err(EchoError::HoldingRequirementNotMet.into());
+    }
+    let registration = HoldingRegistration::try_from_slice(&holding_registration.data.borrow())?;
+    assert_valid_header(registration.version, &registration._reserved)?;
+
+    if user_token_account.owner != &spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let token_account = TokenAccount::unpack(&user_token_account.data.borrow())?;
+    if Clock::get()?.slot < registration.slot.saturating_add(gate.min_slots)
+        || token_account.amount < gate.min_amount
+    {
+        return Err(EchoError::HoldingRequirementNotMet.into());
+    }
+    Ok(())
+}
+
+/// Checks that `org` is an org of the Echo Program and returns it.
+fn load_org(program_id: &Pubkey, org: &AccountInfo) -> Result<OrgHeader, ProgramError> {
+    if org.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let org_data = OrgHeader::try_from_slice(&org.data.borrow())?;
+    assert_valid_header(org_data.version, &org_data._reserved)?;
+    let org_key = create_org_address(
+        program_id,
+        &org_data.creator,
+        org_data.org_seed,
+        org_data.bump_seed,
+    );
+    assert_pda(org_key, org, EchoError::InvalidAuthority)?;
+    Ok(org_data)
+}
+
+/// Checks that `admin` signed and is an admin of `org`, and returns the decoded org.
+fn load_org_as_admin(
+    program_id: &Pubkey,
+    org: &AccountInfo,
+    admin: &AccountInfo,
+) -> Result<OrgHeader, ProgramError> {
+    if !admin.is_signer {
+        return Err(EchoError::AuthorityNotSigner.into());
+    }
+    let org_data = load_org(program_id, org)?;
+    if !org_data.is_admin(admin.key) {
+        return Err(EchoError::NotAnOrgAdmin.into());
+    }
+    Ok(org_data)
+}
+
+/// Checks that `project` is a project of the Echo Program and returns it.
+fn load_project(program_id: &Pubkey, project: &AccountInfo) -> Result<ProjectHeader, ProgramError> {
+    if project.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let project_data = ProjectHeader::try_from_slice(&project.data.borrow())?;
+    assert_valid_header(project_data.version, &project_data._reserved)?;
+    let project_key = create_project_address(
+        program_id,
+        &project_data.org,
+        project_data.project_seed,
+        project_data.bump_seed,
+    );
+    assert_pda(project_key, project, EchoError::InvalidAuthority)?;
+    Ok(project_data)
+}
+
+/// Checks that `member` signed and is a member of `project`, and returns the decoded project.
+fn load_project_as_member(
+    program_id: &Pubkey,
+    project: &AccountInfo,
+    member: &AccountInfo,
+) -> Result<ProjectHeader, ProgramError> {
+    if !member.is_signer {
+        return Err(EchoError::AuthorityNotSigner.into());
+    }
+    let project_data = load_project(program_id, project)?;
+    if !project_data.is_member(member.key) {
+        return Err(EchoError::NotAProjectMember.into());
+    }
+    Ok(project_data)
+}
+
+/// Adds `key` to `keys` or removes it, doing nothing if it already is or is not there. Fails if `keys` would grow
+/// past `max`.
+fn set_membership(
+    keys: &mut Vec<Pubkey>,
+    key: Pubkey,
+    is_member: bool,
+    max: usize,
+) -> ProgramResult {
+    match (keys.iter().position(|k| *k == key), is_member) {
+        (None, true) => {
+            assert_with_msg(
+                keys.len() < max,
+                ProgramError::InvalidInstructionData,
+                "Too many members",
+            )?;
+            keys.push(key);
+        }
+        (Some(index), false) => {
+            keys.remove(index);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Writes `pointer` and `cid` to the payload pointer of an authorized buffer, creating it on first use. Shared by
+/// `SetPayloadPointer` and `SetIpfsPayloadPointer`, which have the same accounts.
+fn write_payload_pointer(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    pointer: PayloadPointer,
+    cid: &[u8],
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let authorized_buffer = next_account_info(accounts_iter)?;
+    let authority = next_account_info(accounts_iter)?;
+    let payload_pointer = next_account_info(accounts_iter)?;
+
+    load_authorized_buffer(program_id, authorized_buffer, authority)?;
+    assert_is_writable(payload_pointer)?;
+
+    let (payload_pointer_key, bump_seed) =
+        find_payload_pointer_address(program_id, authorized_buffer.key);
+    assert_pda(
+        Ok(payload_pointer_key),
+        payload_pointer,
+        EchoError::InvalidAuthorizedBuffer,
+    )?;
+
+    if payload_pointer.data_is_empty() {
+        create_program_account(
+            authority,
+            payload_pointer,
+            PayloadPointerHeader::LEN,
+            program_id,
+            &[POINTER_SEED, authorized_buffer.key.as_ref(), &[bump_seed]],
+        )?;
+    }
+
+    let mut pointer_data = PayloadPointerHeader {
+        bump_seed,
+        version: CURRENT_VERSION,
+        buffer: *authorized_buffer.key,
+        pointer,
+        slot: Clock::get()?.slot,
+        cid_prefix_len: 0,
+        cid_prefix: [0; 8],
+        cid_digest: [0; 32],
+        _reserved: Reserved::default(),
+    };
+    pointer_data.set_cid(cid);
+    pointer_data.serialize(&mut *payload_pointer.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// CPIs `PostMessage` of the Wormhole core bridge with the echo data of the buffer, signed by the emitter PDA.
+#[cfg(feature = "wormhole")]
+fn post_to_bridge(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    nonce: u32,
+    consistency_level: ConsistencyLevel,
+) -> ProgramResult {
+    use crate::bridge::{build_payload, post_message};
+    use crate::pda::{find_emitter_address, EMITTER_SEED};
+    use crate::program_ids::Cluster;
+
+    let accounts_iter = &mut accounts.iter();
+    let authorized_buffer = next_account_info(accounts_iter)?;
+    let authority = next_account_info(accounts_iter)?;
+    let wormhole = next_account_info(accounts_iter)?;
+    let bridge_config = next_account_info(accounts_iter)?;
+    let message = next_account_info(accounts_iter)?;
+    let emitter = next_account_info(accounts_iter)?;
+    let sequence = next_account_info(accounts_iter)?;
+    let payer = next_account_info(accounts_iter)?;
+    let fee_collector = next_account_info(accounts_iter)?;
+    let clock = next_account_info(accounts_iter)?;
+    let rent = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+
+    // Posting only reads the buffer, so it is allowed past the write deadline
+    if !authority.is_signer {
+        return Err(EchoError::AuthorityNotSigner.into());
+    }
+    let buffer_data = AuthorizedBufferHeader::unpack_header(&authorized_buffer.data.borrow())?;
+    assert_valid_header(buffer_data.version, &buffer_data._reserved)?;
+    assert_buffer_authority(program_id, authorized_buffer, &buffer_data, authority.key)?;
+
+    // The program ID tells the cluster, hence the core bridge to post to
+    let wormhole_program_id = Cluster::of(program_id).map(|cluster| cluster.wormhole_program_id());
+    assert_with_msg(
+        wormhole_program_id == Some(*wormhole.key),
+        ProgramError::IncorrectProgramId,
+        "wormhole_program is not the Wormhole core bridge",
+    )?;
+    let (emitter_key, bump_seed) = find_emitter_address(program_id);
+    assert_pda(Ok(emitter_key), emitter, EchoError::InvalidAuthority)?;
+
+    let payload = {
+        let data = authorized_buffer.data.borrow();
+        build_payload(
+            authorized_buffer.key,
+            Clock::get()?.slot,
+            &data[AuthorizedBufferHeader::header_len(&buffer_data.namespace)..],
+        )
+    };
+    invoke_signed(
+        &post_message(
+            wormhole.key,
+            bridge_config.key,
+            message.key,
+            emitter.key,
+            sequence.key,
+            payer.key,
+            fee_collector.key,
+            nonce,
+            &payload,
+            consistency_level,
+        ),
+        &[
+            bridge_config.clone(),
+            message.clone(),
+            emitter.clone(),
+            sequence.clone(),
+            payer.clone(),
+            fee_collector.clone(),
+            clock.clone(),
+            rent.clone(),
+            system_program.clone(),
+            wormhole.clone(),
+        ],
+        &[&[EMITTER_SEED, &[bump_seed]]],
+    )
+}
+
+#[cfg(not(feature = "wormhole"))]
+fn post_to_bridge(_: &Pubkey, _: &[AccountInfo], _: u32, _: ConsistencyLevel) -> ProgramResult {
+    msg!("PostToBridge requires the wormhole feature");
+    Err(ProgramError::InvalidInstructionData)
+}
+
+/// Checks that `dutch_auction` is the auction of `vending_machine_buffer`, records a purchase at the current slot and
+/// returns its price.
+fn charge_dutch_auction(
+    program_id: &Pubkey,
+    vending_machine_buffer: &AccountInfo,
+    dutch_auction: &AccountInfo,
+) -> Result<u64, ProgramError> {
+    assert_is_writable(dutch_auction)?;
+    let (dutch_auction_key, _) = find_dutch_auction_address(program_id, vending_machine_buffer.key);
+    assert_pda(
+        Ok(dutch_auction_key),
+        dutch_auction,
+        EchoError::InvalidAuthority,
+    )?;
+    if dutch_auction.owner != program_id {
+        return Err(EchoError::InvalidAuthority.into());
+    }
+    let mut auction = DutchAuction::try_from_slice(&dutch_auction.data.borrow())?;
+    assert_valid_header(auction.version, &auction._reserved)?;
+
+    let slot = Clock::get()?.slot;
+    let price = auction.price_at(slot);
+    auction.last_purchase_slot = slot;
+    auction.serialize(&mut *dutch_auction.data.borrow_mut())?;
+    Ok(price)
+}
+
+/// Checks that `proceeds` is a wrapped SOL token account of the admin of the vending machine, which collects the
+/// payments of machines priced in the native mint. Migrated machines have no admin to collect them.
+fn assert_native_proceeds(
+    proceeds: &AccountInfo,
+    vending_buffer: &VendingMachineBufferHeader,
+) -> ProgramResult {
+    assert_is_writable(proceeds)?;
+    if proceeds.owner != &spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let proceeds_account = TokenAccount::unpack(&proceeds.data.borrow())?;
+    if proceeds_account.mint != spl_token::native_mint::id() {
+        return Err(EchoError::InvalidTokenMint.into());
+    }
+    if vending_buffer.admin == Pubkey::default() || proceeds_account.owner != vending_buffer.admin {
+        msg!("Proceeds account is not owned by the vending machine admin");
+        return Err(EchoError::InvalidAuthority.into());
+    }
+    Ok(())
+}
+
+/// Takes `price` tokens from `user_token_account` with `authority`, its owner or delegate. Tokens are burned, except
+/// wrapped SOL, which the Token Program cannot burn and which is transferred to `proceeds` instead.
+fn charge<'a>(
+    user_token_account: &AccountInfo<'a>,
+    vending_machine_mint: &AccountInfo<'a>,
+    proceeds: Option<&AccountInfo<'a>>,
+    authority: &AccountInfo<'a>,
+    price: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let (instruction, destination) = match proceeds {
+        Some(proceeds) => (
+            transfer(
+                &spl_token::id(),
+                user_token_account.key,
+                proceeds.key,
+                authority.key,
+                &[],
+                price,
+            )?,
+            proceeds,
+        ),
+        None => (
+            burn(
+                &spl_token::id(),
+                user_token_account.key,
+                vending_machine_mint.key,
+                authority.key,
+                &[],
+                price,
+            )?,
+            vending_machine_mint,
+        ),
+    };
+    invoke_signed(
+        &instruction,
+        &[
+            user_token_account.clone(),
+            destination.clone(),
+            authority.clone(),
+        ],
+        signer_seeds,
+    )
+}
+
+/// Mints one receipt token to `receipt_account`, signed by `vending_machine_buffer` as mint authority.
+fn mint_receipt<'a>(
+    program_id: &Pubkey,
+    vending_machine_buffer: &AccountInfo<'a>,
+    vending_machine_mint: &AccountInfo<'a>,
+    vending_buffer: &VendingMachineBufferHeader,
+    machine_seed: Option<u64>,
+    receipt_mint: &AccountInfo<'a>,
+    receipt_account: &AccountInfo<'a>,
+) -> ProgramResult {
+    let (receipt_mint_key, _) = find_receipt_mint_address(program_id, vending_machine_buffer.key);
+    assert_pda(
+        Ok(receipt_mint_key),
+        receipt_mint,
+        EchoError::InvalidAuthority,
+    )?;
+    let price_bytes = vending_buffer.price.to_le_bytes();
+    let machine_seed_bytes = machine_seed.map(u64::to_le_bytes);
+    let bump_seed_bytes = [vending_buffer.bump_seed];
+    let mut signer_seeds =
+        vending_machine_seeds(vending_machine_mint.key, &price_bytes, &machine_seed_bytes);
+    signer_seeds.push(&bump_seed_bytes);
+    invoke_signed(
+        &mint_to(
+            &spl_token::id(),
+            receipt_mint.key,
+            receipt_account.key,
+            vending_machine_buffer.key,
+            &[],
+            1,
+        )?,
+        &[
+            receipt_mint.clone(),
+            receipt_account.clone(),
+            vending_machine_buffer.clone(),
+        ],
+        &[&signer_seeds],
+    )
+}
+
+/// Counts a purchase of `user_token_account` on `vending_machine_buffer` in its writer marker and returns the
+/// marker, for the caller to add the price once known and write it back. The marker is created on the first purchase,
+/// paid by `user`, which also counts the token account in `unique_writers`.
+fn record_purchase<'a>(
+    program_id: &Pubkey,
+    vending_machine_buffer: &AccountInfo<'a>,
+    user_token_account: &AccountInfo<'a>,
+    user: &AccountInfo<'a>,
+    writer_marker: &AccountInfo<'a>,
+    vending_buffer: &mut VendingMachineBufferHeader,
+) -> Result<WriterMarker, ProgramError> {
+    let (writer_marker_key, bump_seed) = find_writer_marker_address(
+        program_id,
+        vending_machine_buffer.key,
+        user_token_account.key,
+    );
+    assert_pda(
+        Ok(writer_marker_key),
+        writer_marker,
+        EchoError::InvalidAuthority,
+    )?;
+
+    let marker = if writer_marker.data_is_empty() {
+        create_program_account(
+            user,
+            writer_marker,
+            WriterMarker::LEN,
+            program_id,
+            &[
+                WRITER_SEED,
+                vending_machine_buffer.key.as_ref(),
+                user_token_account.key.as_ref(),
+                &[bump_seed],
+            ],
+        )?;
+        vending_buffer.unique_writers = vending_buffer
+            .unique_writers
+            .checked_add(1)
+            .ok_or(EchoError::ArithmeticOverflow)?;
+        WriterMarker {
+            bump_seed,
+            version: CURRENT_VERSION,
+            slot: Clock::get()?.slot,
+            purchases: 1,
+            burned: 0,
+            _reserved: Reserved::default(),
+        }
+    } else {
+        let mut marker = WriterMarker::try_from_slice(&writer_marker.data.borrow())?;
+        assert_valid_header(marker.version, &marker._reserved)?;
+        // Markers created before purchases were counted hold zero after their first purchase
+        marker.purchases = marker
+            .purchases
+            .max(1)
+            .checked_add(1)
+            .ok_or(EchoError::ArithmeticOverflow)?;
+        marker
+    };
+    Ok(marker)
+}
+
+/// Moves `user_token_account` to its total `burned` on the leaderboard of `vending_machine_buffer`, creating the board
+/// on first use, paid by `user`.
+fn update_leaderboard<'a>(
+    program_id: &Pubkey,
+    vending_machine_buffer: &AccountInfo<'a>,
+    user_token_account: &AccountInfo<'a>,
+    user: &AccountInfo<'a>,
+    leaderboard: &AccountInfo<'a>,
+    bump_seed: u8,
+    burned: u64,
+) -> ProgramResult {
+    let (mut board, created) = if leaderboard.data_is_empty() {
+        create_program_account(
+            user,
+            leaderboard,
+            Leaderboard::LEN,
+            program_id,
+            &[
+                LEADERBOARD_SEED,
+                vending_machine_buffer.key.as_ref(),
+                &[bump_seed],
+            ],
+        )?;
+        let board = Leaderboard {
+            bump_seed,
+            version: CURRENT_VERSION,
+            _reserved: Reserved::default(),
+            entries: Default::default(),
+        };
+        (board, true)
+    } else {
+        if leaderboard.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let board = Leaderboard::try_from_slice(&leaderboard.data.borrow())?;
+        assert_valid_header(board.version, &board._reserved)?;
+        (board, false)
+    };
+    if record_burn(&mut board.entries, *user_token_account.key, burned) || created {
+        board.serialize(&mut *leaderboard.data.borrow_mut())?;
+    }
+    Ok(())
+}
+
+/// Zeroes `echo_data` and copies in as much of `data` as fits.
+fn write_echo_data(echo_data: &mut [u8], data: &[u8]) {
+    echo_data.fill(0);
+    let min_of_len = std::cmp::min(echo_data.len(), data.len());
+    echo_data[..min_of_len].copy_from_slice(&data[..min_of_len]);
+}
+
+/// Checks that `chunk_checksums` is the checksums PDA of `authorized_buffer` and returns it.
+fn load_chunk_checksums(
+    program_id: &Pubkey,
+    authorized_buffer: &AccountInfo,
+    chunk_checksums: &AccountInfo,
+) -> Result<ChunkChecksums, ProgramError> {
+    let (chunk_checksums_key, _) = find_chunk_checksums_address(program_id, authorized_buffer.key);
+    assert_pda(
+        Ok(chunk_checksums_key),
+        chunk_checksums,
+        EchoError::InvalidAuthority,
+    )?;
+    if chunk_checksums.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let checksums_data = ChunkChecksums::try_from_slice(&chunk_checksums.data.borrow())?;
+    assert_valid_header(checksums_data.version, &checksums_data._reserved)?;
+    Ok(checksums_data)
+}
+
+/// Change made to the echo data of an authorized buffer, see `write_authorized_buffer`.
+enum EchoWrite<'d> {
+    /// Zeroes the echo data and copies in as much of the data as fits, see `write_echo_data`
+    Overwrite(&'d [u8]),
+    /// Copies the data at `offset`, which the caller checked it fits after
+    Append { offset: usize, data: &'d [u8] },
+    /// Same as `Append`, for `EchoAtOffset`
+    Patch { offset: usize, data: &'d [u8] },
+}
+
+impl EchoWrite<'_> {
+    fn apply(&self, echo_data: &mut [u8]) {
+        match *self {
+            EchoWrite::Overwrite(data) => write_echo_data(echo_data, data),
+            EchoWrite::Append { offset, data } | EchoWrite::Patch { offset, data } => {
+                echo_data[offset..offset + data.len()].copy_from_slice(data)
+            }
+        }
+    }
+
+    /// Offset and bytes of data stored in `echo_len` bytes of echo data, leaving out the zero padding.
+    fn written(&self, echo_len: usize) -> (usize, &[u8]) {
+        match *self {
+            EchoWrite::Overwrite(data) => (0, &data[..std::cmp::min(data.len(), echo_len)]),
+            EchoWrite::Append { offset, data } | EchoWrite::Patch { offset, data } => {
+                (offset, data)
+            }
+        }
+    }
+}
+
+/// Checks that `write` leaves `echo_data` UTF-8 from the char boundary before the written bytes to the one after them,
+/// the bytes around them being left as they are by the write. A patch or an append may end in a cut char only when it
+/// ends at or past `write_offset` with nothing but zero padding after it, so that the next write can complete the
+/// char.
+fn assert_utf8_write(echo_data: &[u8], write: &EchoWrite, write_offset: usize) -> ProgramResult {
+    let (offset, written) = write.written(echo_data.len());
+    let end = offset + written.len();
+    let is_continuation = |byte: &u8| byte & 0xC0 == 0x80;
+    // Chars are at most 4 bytes long, so a char cut by the write starts at most 3 bytes before it
+    let start = (offset.saturating_sub(3)..offset)
+        .find(|&i| !is_continuation(&echo_data[i]))
+        .unwrap_or(offset);
+    let mut result = echo_data[start..offset].to_vec();
+    result.extend_from_slice(written);
+    // An overwrite zeroes the rest of the echo data
+    if !matches!(write, EchoWrite::Overwrite(_)) {
+        result.extend(
+            echo_data[end..]
+                .iter()
+                .take(3)
+                .take_while(|byte| is_continuation(byte)),
+        );
+    }
+    let at_end = !matches!(write, EchoWrite::Overwrite(_))
+        && end >= write_offset
+        && echo_data[end..].iter().all(|&byte| byte == 0);
+    match std::str::from_utf8(&result) {
+        Ok(_) => Ok(()),
+        // With the continuation bytes after the write taken, a char still cut stays cut until the next write
+        Err(e) if e.error_len().is_none() && at_end => Ok(()),
+        Err(_) => Err(EchoError::InvalidUtf8.into()),
+    }
+}
+
+/// Checks `write` against the features of `buffer_data`, see `SetFeatures`, and logs it if the buffer asks for it.
+/// `accounts` are searched for the instructions sysvar that `FEATURE_CPI_GUARD` needs.
+fn assert_write_features(
+    program_id: &Pubkey,
+    authorized_buffer: &AccountInfo,
+    buffer_data: &AuthorizedBufferHeader,
+    write: &EchoWrite,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if buffer_data.has_feature(FEATURE_IMMUTABLE) {
+        return Err(EchoError::BufferImmutable.into());
+    }
+    if matches!(write, EchoWrite::Append { .. }) && !buffer_data.has_feature(FEATURE_APPEND_ALLOWED)
+    {
+        return Err(EchoError::AppendNotAllowed.into());
+    }
+    let header_len = AuthorizedBufferHeader::header_len(&buffer_data.namespace);
+    let echo_len = authorized_buffer.data_len() - header_len;
+    let (offset, written) = write.written(echo_len);
+    if buffer_data.has_feature(FEATURE_UTF8) {
+        assert_utf8_write(
+            &authorized_buffer.data.borrow()[header_len..],
+            write,
+            buffer_data.write_offset as usize,
+        )?;
+    }
+    if buffer_data.has_feature(FEATURE_CPI_GUARD) {
+        let instructions = accounts
+            .iter()
+            .find(|account| sysvar::instructions::check_id(account.key))
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        // The top-level instruction belongs to another program when the write comes through CPI
+        if sysvar::instructions::get_instruction_relative(0, instructions)?.program_id
+            != *program_id
+        {
+            return Err(EchoError::CpiNotAllowed.into());
+        }
+    }
+    if buffer_data.has_feature(FEATURE_LOG_WRITES) {
+        msg!(
+            "Write to {}: {} bytes at offset {}",
+            authorized_buffer.key,
+            written.len(),
+            offset
+        );
+    }
+    Ok(())
+}
+
+/// Applies `write` to the echo data of `authorized_buffer` and stores `buffer_data` as its header, once checked
+/// against its features. With `chunk_checksums` set, updates the checksums PDA, which must then be passed. With
+/// `verify_after_write` set, applies `write` to a copy of the echo data as well, decodes the account again and fails
+/// unless the hashes of its header and echo data match the expected ones.
+fn write_authorized_buffer(
+    program_id: &Pubkey,
+    authorized_buffer: &AccountInfo,
+    buffer_data: &AuthorizedBufferHeader,
+    write: EchoWrite,
+    chunk_checksums: Option<&AccountInfo>,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    assert_write_features(program_id, authorized_buffer, buffer_data, &write, accounts)?;
+    let header_len = AuthorizedBufferHeader::header_len(&buffer_data.namespace);
+    let expected_echo_data = if buffer_data.verify_after_write {
+        let mut echo_data = authorized_buffer.data.borrow()[header_len..].to_vec();
+        write.apply(&mut echo_data);
+        Some(echo_data)
+    } else {
+        None
+    };
+    {
+        let mut account_data = authorized_buffer.data.borrow_mut();
+        buffer_data.pack_header(&mut account_data)?;
+        write.apply(&mut account_data[header_len..]);
+    }
+    if buffer_data.chunk_checksums {
+        let chunk_checksums = chunk_checksums.ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let mut checksums_data =
+            load_chunk_checksums(program_id, authorized_buffer, chunk_checksums)?;
+        checksums_data.checksums =
+            checksum::chunk_checksums(&authorized_buffer.data.borrow()[header_len..]);
+        checksums_data.serialize(&mut *chunk_checksums.data.borrow_mut())?;
+    }
+    let expected_echo_data = match expected_echo_data {
+        Some(expected_echo_data) => expected_echo_data,
+        None => return Ok(()),
+    };
+
+    let account_data = authorized_buffer.data.borrow();
+    let written = AuthorizedBufferHeader::unpack_header_checked(&account_data)?;
+    assert_with_msg(
+        hash(&written.try_to_vec()?) == hash(&buffer_data.try_to_vec()?)
+            && hash(&account_data[header_len..]) == hash(&expected_echo_data),
+        EchoError::WriteVerificationFailed.into(),
+        "Buffer does not read back as written",
+    )
+}
+
+/// Overwrites the echo data of `authorized_buffer` with `data`, see `write_echo_data`, and moves its write offset to
+/// the end of the bytes written.
+fn write_authorized_echo(
+    program_id: &Pubkey,
+    authorized_buffer: &AccountInfo,
+    buffer_data: &AuthorizedBufferHeader,
+    data: &[u8],
+    chunk_checksums: Option<&AccountInfo>,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let echo_len =
+        authorized_buffer.data_len() - AuthorizedBufferHeader::header_len(&buffer_data.namespace);
+    let buffer_data = AuthorizedBufferHeader {
+        write_offset: std::cmp::min(data.len(), echo_len) as u32,
+        ..buffer_data.clone()
+    };
+    write_authorized_buffer(
+        program_id,
+        authorized_buffer,
+        &buffer_data,
+        EchoWrite::Overwrite(data),
+        chunk_checksums,
+        accounts,
+    )
+}
+
+/// Moves all lamports of `account` to `destination` and zeroes its data, so the runtime deletes it.
+fn close_account(account: &AccountInfo, destination: &AccountInfo) -> ProgramResult {
+    let lamports = account.lamports();
+    **destination.try_borrow_mut_lamports()? = destination
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(EchoError::ArithmeticOverflow)?;
+    **account.try_borrow_mut_lamports()? = 0;
+    account.try_borrow_mut_data()?.fill(0);
+    Ok(())
+}
+
+/// Same as `close_account`, but also shrinks `account` to zero bytes and assigns it to the System Program, so an
+/// instruction sending lamports back to it later in the same transaction cannot revive it as an Echo Program account.
+fn close_account_permanently(account: &AccountInfo, destination: &AccountInfo) -> ProgramResult {
+    close_account(account, destination)?;
+    account.realloc(0, false)?;
+    account.assign(&system_program::id());
+    Ok(())
+}
+
+/// Closes `authorized_buffer` along with its companions, see `find_buffer_companion_addresses`, sending their lamports
+/// to `authority`, so a buffer created again at the same address starts from a clean slate. `companions` must hold
+/// every companion address in order; those the program does not own hold no state and are left alone.
+fn close_authorized_buffer(
+    program_id: &Pubkey,
+    authorized_buffer: &AccountInfo,
+    buffer_data: &AuthorizedBufferHeader,
+    authority: &AccountInfo,
+    companions: &[AccountInfo],
+) -> ProgramResult {
+    if buffer_data.reference_count > 0 {
+        return Err(EchoError::BufferReferenced.into());
+    }
+    let companion_keys = find_buffer_companion_addresses(program_id, authorized_buffer.key);
+    if companions.len() < companion_keys.len() {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    for (companion, companion_key) in companions.iter().zip(companion_keys) {
+        assert_pda(Ok(companion_key), companion, EchoError::InvalidAuthority)?;
+        if companion.owner == program_id {
+            assert_is_writable(companion)?;
+            close_account_permanently(companion, authority)?;
+        }
+    }
+    close_account_permanently(authorized_buffer, authority)
+}
+
+/// Allocates `size` rent-exempt bytes to the PDA `account`, paid by `payer`, and assigns it to the Echo Program.
+fn create_program_account<'a>(
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    size: usize,
+    program_id: &Pubkey,
+    signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            account.key,
+            Rent::get()?.minimum_balance(size),
+            size as u64,
+            program_id,
+        ),
+        &[payer.clone(), account.clone()],
+        &[signer_seeds],
+    )
+}
+
+/// Splits the global stats account off the end of `accounts` when the caller appended it, see
+/// `InitializeGlobalStats`.
+fn split_global_stats<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'b [AccountInfo<'a>],
+) -> (&'b [AccountInfo<'a>], Option<&'b AccountInfo<'a>>) {
+    match accounts.split_last() {
+        // The owner and length are checked first to skip the PDA derivation for any other account
+        Some((last, rest))
+            if last.owner == program_id
+                && last.data_len() == GlobalStats::LEN
+                && *last.key == find_global_stats_address(program_id).0 =>
+        {
+            (rest, Some(last))
+        }
+        _ => (accounts, None),
+    }
+}
+
+/// Applies `update` to the global stats account if the instruction was passed one.
+fn record_stats(
+    global_stats: Option<&AccountInfo>,
+    update: impl FnOnce(&mut GlobalStats),
+) -> ProgramResult {
+    if let Some(global_stats) = global_stats {
+        assert_is_writable(global_stats)?;
+        let mut stats = GlobalStats::try_from_slice(&global_stats.data.borrow())?;
+        assert_valid_header(stats.version, &stats._reserved)?;
+        update(&mut stats);
+        stats.serialize(&mut *global_stats.data.borrow_mut())?;
+    }
+    Ok(())
+}
+
+/// Transfers from `payer` whatever `buffer` lacks to be rent-exempt at `len` bytes.
+fn top_up_rent<'a>(buffer: &AccountInfo<'a>, payer: &AccountInfo<'a>, len: usize) -> ProgramResult {
+    let required_lamports = Rent::get()?
+        .minimum_balance(len)
+        .saturating_sub(buffer.lamports());
+    if required_lamports > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, buffer.key, required_lamports),
+            &[payer.clone(), buffer.clone()],
+        )?;
+    }
+    Ok(())
+}
+
+/// Moves the lamports of `buffer` above its rent-exempt minimum to `destination`.
+fn release_excess_rent(buffer: &AccountInfo, destination: &AccountInfo) -> ProgramResult {
+    let excess_lamports = buffer
+        .lamports()
+        .saturating_sub(Rent::get()?.minimum_balance(buffer.data_len()));
+    **buffer.try_borrow_mut_lamports()? = buffer
+        .lamports()
+        .checked_sub(excess_lamports)
+        .ok_or(EchoError::ArithmeticOverflow)?;
+    **destination.try_borrow_mut_lamports()? = destination
+        .lamports()
+        .checked_add(excess_lamports)
+        .ok_or(EchoError::ArithmeticOverflow)?;
+    Ok(())
+}
+
+/// Grows `buffer` to `new_len` bytes, topping it up to rent exemption from `payer` first.
+fn realloc_with_rent<'a>(
+    buffer: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    new_len: usize,
+) -> ProgramResult {
+    top_up_rent(buffer, payer, new_len)?;
+    buffer.realloc(new_len, false)
+}
+
+/// Appends `rotation` to the rotation log of the keypair buffer `authorized_buffer`, creating the log on first use.
+/// Shared by `RotateAuthorityWithAttestation` and `FinalizeRecovery`.
+fn append_rotation<'a>(
+    program_id: &Pubkey,
+    authorized_buffer: &AccountInfo<'a>,
+    rotation_log: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    rotation: AuthorityRotation,
+) -> ProgramResult {
+    let (rotation_log_key, bump_seed) =
+        find_rotation_log_address(program_id, authorized_buffer.key);
+    assert_pda(
+        Ok(rotation_log_key),
+        rotation_log,
+        EchoError::InvalidAuthorizedBuffer,
+    )?;
+    let mut log_data = if rotation_log.data_is_empty() {
+        create_program_account(
+            payer,
+            rotation_log,
+            AuthorityRotationLog::HEADER_LEN,
+            program_id,
+            &[
+                ROTATION_LOG_SEED,
+                authorized_buffer.key.as_ref(),
+                &[bump_seed],
+            ],
+        )?;
+        AuthorityRotationLog {
+            bump_seed,
+            version: CURRENT_VERSION,
+            buffer: *authorized_buffer.key,
+            _reserved: Reserved::default(),
+            rotations: vec![],
+        }
+    } else {
+        let log_data = AuthorityRotationLog::try_from_slice(&rotation_log.data.borrow())?;
+        assert_valid_header(log_data.version, &log_data._reserved)?;
+        log_data
+    };
+
+    log_data.rotations.push(rotation);
+    let new_len = checked_account_len(
+        AuthorityRotationLog::HEADER_LEN,
+        log_data.rotations.len(),
+        AuthorityRotation::LEN,
+    )
+    .ok_or(EchoError::ArithmeticOverflow)?;
+    realloc_with_rent(rotation_log, payer, new_len)?;
+    log_data.serialize(&mut *rotation_log.data.borrow_mut())?;
+    Ok(())
+}
+
+/// Checks that `recovery` is the recovery account of `authorized_buffer` and returns it.
+fn load_recovery(
+    program_id: &Pubkey,
+    authorized_buffer: &AccountInfo,
+    recovery: &AccountInfo,
+) -> Result<RecoveryHeader, ProgramError> {
+    let (recovery_key, _) = find_recovery_address(program_id, authorized_buffer.key);
+    assert_pda(Ok(recovery_key), recovery, EchoError::InvalidAuthority)?;
+    if recovery.owner != program_id {
+        return Err(EchoError::InvalidAuthority.into());
+    }
+    let recovery_data = RecoveryHeader::try_from_slice(&recovery.data.borrow())?;
+    assert_valid_header(recovery_data.version, &recovery_data._reserved)?;
+    Ok(recovery_data)
+}
+
+/// Checks that `dead_man_switch` is the dead-man switch of `authorized_buffer`, that it was not released yet, and
+/// returns it.
+fn load_armed_dead_man_switch(
+    program_id: &Pubkey,
+    authorized_buffer: &AccountInfo,
+    dead_man_switch: &AccountInfo,
+) -> Result<DeadManSwitchHeader, ProgramError> {
+    let (dead_man_switch_key, _) = find_dead_man_switch_address(program_id, authorized_buffer.key);
+    assert_pda(
+        Ok(dead_man_switch_key),
+        dead_man_switch,
+        EchoError::InvalidAuthority,
+    )?;
+    if dead_man_switch.owner != program_id {
+        return Err(EchoError::InvalidAuthority.into());
+    }
+    let switch_data = DeadManSwitchHeader::try_from_slice(&dead_man_switch.data.borrow())?;
+    assert_valid_header(switch_data.version, &switch_data._reserved)?;
+    if switch_data.released {
+        return Err(EchoError::DeadManSwitchReleased.into());
+    }
+    Ok(switch_data)
+}
+
 impl Processor {
     pub fn process_instruction(
         program_id: &Pubkey,
         accounts: &[AccountInfo],
         instruction_data: &[u8],
     ) -> ProgramResult {
+        // Rejected before decoding, so oversized data never reaches the Borsh allocations
+        if instruction_data.len() < MIN_INSTRUCTION_DATA_LEN {
+            return Err(EchoError::InstructionDataTooShort.into());
+        }
+        if instruction_data.len() > MAX_INSTRUCTION_DATA_LEN {
+            return Err(EchoError::InstructionDataTooLong.into());
+        }
         let instruction = EchoInstruction::try_from_slice(instruction_data)
             .map_err(|_| ProgramError::InvalidInstructionData)?;
+        // Split off before any instruction reads its optional trailing accounts
+        let (accounts, global_stats) = split_global_stats(program_id, accounts);
 
         match instruction {
             EchoInstruction::Echo { data } => {
-                msg!("Instruction: Echo");
+                verbose_msg!("Instruction: Echo");
                 let accounts_iter = &mut accounts.iter();
                 let echo_buffer = next_account_info(accounts_iter)?;
 
@@ -55,185 +1187,278 @@ impl Processor {
                     return Err(EchoError::NonZeroData.into());
                 }
 
-                let mut echo_data = echo_buffer.try_borrow_mut_data()?; 
+                let mut echo_data = echo_buffer.try_borrow_mut_data()?;
                 for &mut dat in echo_data.into_iter() {
                     if dat != 0u8 {
                         return Err(EchoError::NonZeroData.into());
                     }
                 }
-                    
+
                 if echo_data.len() > data.len() {
                     echo_data.copy_from_slice(&data);
                 } else {
                     let echo_len = echo_data.len();
                     echo_data.copy_from_slice(&data[..echo_len]);
                 }
+                record_stats(global_stats, GlobalStats::record_write)?;
                 Ok(())
             }
 
-
             EchoInstruction::InitializeAuthorizedEcho {
                 buffer_seed,
                 buffer_size,
+                namespace,
+                write_deadline_slot,
             } => {
-                msg!("Instruction: InitializeAuthorizedEcho");
-                
+                verbose_msg!("Instruction: InitializeAuthorizedEcho");
+                let namespace = namespace.0.flatten();
+                let write_deadline_slot = write_deadline_slot.0.flatten();
+
                 // accounts
                 let accounts_iter = &mut accounts.iter();
                 let authorized_buffer = next_account_info(accounts_iter)?;
                 let authority = next_account_info(accounts_iter)?;
                 // let system_program = next_account_info(accounts_iter)?;
 
-                
-                // check signer 
+                // check signer
                 if !authority.is_signer {
-                    return Err(EchoError::AuthorityNotSigner.into())
+                    return Err(EchoError::AuthorityNotSigner.into());
                 }
 
                 // allocate buffer_size bytes to the authorized_buffer account and assign it the Echo Program.
-                let (authorized_buffer_key, bump_seed) = Pubkey::find_program_address(
-                    &[
-                        b"authority",
-                        authority.key.as_ref(),
-                        &buffer_seed.to_le_bytes()
-                ],
+                let (authorized_buffer_key, bump_seed) = find_authorized_buffer_address(
                     program_id,
+                    authority.key,
+                    buffer_seed,
+                    namespace,
                 );
 
                 // check authorized_buffer_key is same as authorized_buffer
-                if authorized_buffer_key != *authorized_buffer.key {
-                    return Err(EchoError::InvalidAuthorizedBuffer.into())
-                }
+                assert_pda(
+                    Ok(authorized_buffer_key),
+                    authorized_buffer,
+                    EchoError::InvalidAuthorizedBuffer,
+                )?;
+                assert_buffer_not_created(program_id, authorized_buffer)?;
 
                 // CPI to the system program
-                invoke_signed(
-                    &system_instruction::create_account(
-                        authority.key,
-                        authorized_buffer.key,
-                        Rent::get()?.minimum_balance(buffer_size) as u64,
-                        buffer_size as u64,
-                        program_id,
-                    ),
-                    &[authority.clone(), authorized_buffer.clone()],
-                    &[&[b"authority", authority.key.as_ref(), &buffer_seed.to_le_bytes(), &[bump_seed]]],
-                )?;
+                let buffer_seed_bytes = buffer_seed.to_le_bytes();
+                let bump_seed_bytes = [bump_seed];
+                let mut signer_seeds =
+                    authorized_buffer_seeds(authority.key, &buffer_seed_bytes, &namespace);
+                signer_seeds.push(&bump_seed_bytes);
+                let echo_len = buffer_size
+                    .checked_sub(AuthorizedBufferHeader::header_len(&namespace))
+                    .ok_or(EchoError::ArithmeticOverflow)?;
+                let lamports = Rent::get()?.minimum_balance(buffer_size);
+                match accounts.get(3) {
+                    // The rent is drawn from the rent vault of the authority
+                    Some(rent_vault) => {
+                        let (rent_vault_key, vault_bump_seed) =
+                            find_rent_vault_address(program_id, authority.key);
+                        assert_pda(Ok(rent_vault_key), rent_vault, EchoError::InvalidAuthority)?;
+                        invoke_signed(
+                            &system_instruction::create_account(
+                                rent_vault.key,
+                                authorized_buffer.key,
+                                lamports,
+                                buffer_size as u64,
+                                program_id,
+                            ),
+                            &[rent_vault.clone(), authorized_buffer.clone()],
+                            &[
+                                &signer_seeds,
+                                &[RENT_VAULT_SEED, authority.key.as_ref(), &[vault_bump_seed]],
+                            ],
+                        )?;
+                    }
+                    None => {
+                        invoke_signed(
+                            &system_instruction::create_account(
+                                authority.key,
+                                authorized_buffer.key,
+                                lamports,
+                                buffer_size as u64,
+                                program_id,
+                            ),
+                            &[authority.clone(), authorized_buffer.clone()],
+                            &[&signer_seeds],
+                        )?;
+                    }
+                }
 
                 // Setting up authorized buffer
                 // byte 0: bump_seed
                 // bytes 1-8: buffer_seed
-                let echo_data = vec![0; buffer_size - 9 - 4];
-                let buffer_data = AuthorizedBufferHeader { bump_seed, buffer_seed, echo_data };
+                // byte 9: version
+                // bytes 10..: namespace, reserved
+                let echo_data = vec![0; echo_len];
+                let buffer_data = AuthorizedBufferHeader {
+                    bump_seed,
+                    buffer_seed,
+                    version: CURRENT_VERSION,
+                    namespace,
+                    write_deadline_slot: write_deadline_slot.unwrap_or(0),
+                    close_after_slot: 0,
+                    reference_count: 0,
+                    authority: Pubkey::default(),
+                    verify_after_write: false,
+                    chunk_checksums: false,
+                    write_offset: 0,
+                    features: 0,
+                    _reserved: Reserved::default(),
+                    echo_data,
+                };
                 let mut authorized_buffer_data = authorized_buffer.try_borrow_mut_data()?;
                 buffer_data.serialize(&mut *authorized_buffer_data)?;
-                
+                record_stats(global_stats, GlobalStats::record_buffer_created)?;
+
                 Ok(())
             }
 
-
-            EchoInstruction::AuthorizedEcho { data} => {
-                msg!("Instruction: AuthorizedEcho");
+            EchoInstruction::AuthorizedEcho { data } => {
+                verbose_msg!("Instruction: AuthorizedEcho");
                 let accounts_iter = &mut accounts.iter();
                 let authorized_buffer = next_account_info(accounts_iter)?;
                 let authority = next_account_info(accounts_iter)?;
 
-                // check signer 
-                if !authority.is_signer {
-                    return Err(EchoError::AuthorityNotSigner.into())
-                }
-
-                let mut buffer_data = AuthorizedBufferHeader::try_from_slice(&authorized_buffer.data.borrow())?; 
-
-                let authority_seeds = &[b"authority", authority.key.as_ref(), &buffer_data.buffer_seed.to_le_bytes(), &[buffer_data.bump_seed]];
-                let authorized_buffer_key = Pubkey::create_program_address(authority_seeds, program_id)?;
-
-                // Invalid Authority Error
-                if authorized_buffer_key != *authorized_buffer.key {
-                    return Err(EchoError::InvalidAuthority.into())
-                }
-                
-                // Zero out all the data
-                buffer_data.echo_data.fill(0);
+                let buffer_data = load_authorized_buffer(program_id, authorized_buffer, authority)?;
 
-                // Copy data in to authorized_buffer
-                let min_of_len = std::cmp::min(buffer_data.echo_data.len(), data.len());
-                buffer_data.echo_data.copy_from_slice(&data[..min_of_len]);
-                buffer_data.serialize(&mut *authorized_buffer.data.borrow_mut())?;
-                msg!("end");
+                // Zero out all the data and copy data in to authorized_buffer, in place
+                write_authorized_echo(
+                    program_id,
+                    authorized_buffer,
+                    &buffer_data,
+                    &data,
+                    accounts.get(2),
+                    accounts,
+                )?;
+                verbose_msg!("end");
+                record_stats(global_stats, GlobalStats::record_write)?;
 
                 Ok(())
             }
-            
+
             EchoInstruction::InitializeVendingMachineEcho {
                 price,
                 buffer_size,
+                metadata,
+                machine_seed,
             } => {
-                msg!("Instruction: InitializeVendingMachineEcho");
+                verbose_msg!("Instruction: InitializeVendingMachineEcho");
                 let accounts_iter = &mut accounts.iter();
                 let vending_machine_buffer = next_account_info(accounts_iter)?;
                 let vending_machine_mint = next_account_info(accounts_iter)?;
                 let payer = next_account_info(accounts_iter)?;
                 // let system_program = next_account_info(accounts_iter)?;
 
-                // check signer 
+                // check signer
                 if !payer.is_signer {
-                    return Err(EchoError::AuthorityNotSigner.into())
+                    return Err(EchoError::AuthorityNotSigner.into());
                 }
 
                 assert_is_writable(vending_machine_buffer)?;
 
                 // msg!("Before");
 
-                let (authorithed_buffer_key, bump_seed) = Pubkey::find_program_address(
-                    &[
-                        b"vending_machine",
-                        vending_machine_mint.key.as_ref(),
-                        &price.to_le_bytes(),
-                    ],
+                let (authorithed_buffer_key, bump_seed) = find_vending_machine_address(
                     program_id,
+                    vending_machine_mint.key,
+                    price,
+                    machine_seed.0,
                 );
 
                 // msg!("AfterPDA");
 
                 // Check Authority
-                if authorithed_buffer_key != *vending_machine_buffer.key {
-                    return Err(EchoError::InvalidAuthorizedBuffer.into())
+                assert_pda(
+                    Ok(authorithed_buffer_key),
+                    vending_machine_buffer,
+                    EchoError::InvalidVendingMachinePda,
+                )?;
+                assert_buffer_not_created(program_id, vending_machine_buffer)?;
+
+                if let Some(metadata) = &metadata {
+                    assert_with_msg(
+                        metadata.is_valid(),
+                        ProgramError::InvalidInstructionData,
+                        "Vending machine metadata is not valid UTF-8",
+                    )?;
                 }
+                let echo_len = buffer_size
+                    .checked_sub(VendingMachineBufferHeader::HEADER_LEN)
+                    .ok_or(EchoError::ArithmeticOverflow)?;
+                let account_size = buffer_size
+                    .checked_add(metadata.as_ref().map_or(0, |_| VendingMachineMetadata::LEN))
+                    .ok_or(EchoError::ArithmeticOverflow)?;
+
+                let price_bytes = price.to_le_bytes();
+                let machine_seed_bytes = machine_seed.0.map(u64::to_le_bytes);
+                let bump_seed_bytes = [bump_seed];
+                let mut signer_seeds = vending_machine_seeds(
+                    vending_machine_mint.key,
+                    &price_bytes,
+                    &machine_seed_bytes,
+                );
+                signer_seeds.push(&bump_seed_bytes);
 
                 // CPI to the system program
                 invoke_signed(
                     &system_instruction::create_account(
                         payer.key,
                         vending_machine_buffer.key,
-                        Rent::get()?.minimum_balance(buffer_size),
-                        buffer_size as u64,
+                        Rent::get()?.minimum_balance(account_size),
+                        account_size as u64,
                         program_id,
                     ),
                     &[payer.clone(), vending_machine_buffer.clone()],
-                    &[&[b"vending_machine", vending_machine_mint.key.as_ref(), &price.to_le_bytes(), &[bump_seed]]],
+                    &[&signer_seeds],
                 )?;
-                
+
                 // msg!("AfterCPI");
 
                 // Setting up authorized buffer
-                let echo_data = vec![0; buffer_size - 1 - 8 - 4];
-                let buffer_data = VendingMachineBufferHeader { bump_seed, price,echo_data };
+                let echo_data = vec![0; echo_len];
+                let buffer_data = VendingMachineBufferHeader {
+                    bump_seed,
+                    price,
+                    version: VENDING_MACHINE_VERSION,
+                    admin: *payer.key,
+                    opens_at: 0,
+                    closes_at: 0,
+                    daily_opens_at: 0,
+                    daily_closes_at: 0,
+                    holding_gated: false,
+                    unique_writers: 0,
+                    quadratic_pricing: false,
+                    dutch_auction: false,
+                    receipts: false,
+                    _reserved: Reserved::default(),
+                    echo_data,
+                    metadata: Trailing(metadata),
+                };
                 let mut vending_buffer_data = vending_machine_buffer.try_borrow_mut_data()?;
                 buffer_data.serialize(&mut *vending_buffer_data)?;
 
-                msg!("Instruction: InitializeVendingMachineEcho END & SUCCESS");
+                record_stats(global_stats, GlobalStats::record_buffer_created)?;
+
+                verbose_msg!("Instruction: InitializeVendingMachineEcho END & SUCCESS");
                 Ok(())
             }
 
-
-            EchoInstruction::VendingMachineEcho { data} => {
-                msg!("Instruction: VendingMachineEcho");
+            EchoInstruction::VendingMachineEcho { data, machine_seed } => {
+                verbose_msg!("Instruction: VendingMachineEcho");
                 let accounts_iter = &mut accounts.iter();
                 let vending_machine_buffer = next_account_info(accounts_iter)?;
                 let user = next_account_info(accounts_iter)?;
                 let user_token_account = next_account_info(accounts_iter)?;
                 let vending_machine_mint = next_account_info(accounts_iter)?;
-                // let token_program = next_account_info(accounts_iter)?;
-                
+                let _token_program = next_account_info(accounts_iter)?;
+                // Buyers paying without a session pass the Echo Program ID in the session slot, or leave it out
+                // when no other accounts follow
+                let session = next_account_info(accounts_iter)
+                    .ok()
+                    .filter(|session| session.key != program_id);
 
                 if !user.is_signer {
                     return Err(EchoError::AuthorityNotSigner.into());
@@ -241,49 +1466,2786 @@ impl Processor {
 
                 assert_is_writable(vending_machine_buffer)?;
                 assert_is_writable(user_token_account)?;
-                assert_is_writable(vending_machine_mint)?;
-
-                msg!("AfterCheck");
-
-                let mut vending_buffer = VendingMachineBufferHeader::try_from_slice(&vending_machine_buffer.data.borrow())?;
-
-                let vending_seeds = &[b"vending_machine",vending_machine_mint.key.as_ref(), &vending_buffer.price.to_le_bytes(), &[vending_buffer.bump_seed]];                
-                let vending_buffer_key = Pubkey::create_program_address(vending_seeds, program_id)?;
-
-                if vending_buffer_key != *vending_machine_buffer.key {
-                    return Err(EchoError::InvalidAuthority.into());
+                // Native machines are paid by transfer, which leaves the supply of the mint alone
+                let is_native = *vending_machine_mint.key == spl_token::native_mint::id();
+                if !is_native {
+                    assert_is_writable(vending_machine_mint)?;
                 }
 
-                msg!("BeforeCPI");
+                verbose_msg!("AfterCheck");
 
-                // Burn price amount of tokens from user_token_account
-                invoke(
-                    &burn(
-                        &spl_token::id(),
-                        user_token_account.key,
-                        vending_machine_mint.key,
-                        user.key,
-                        &[user.key],
-                        vending_buffer.price
-                    )?,
-                    &[user_token_account.clone(), vending_machine_mint.clone(), user.clone()],
+                let mut vending_buffer = VendingMachineBufferHeader::try_from_slice(
+                    &vending_machine_buffer.data.borrow(),
                 )?;
+                assert_valid_vending_header(&vending_buffer)?;
 
-                msg!("AfterCPI");
-
+                let vending_buffer_key = create_vending_machine_address(
+                    program_id,
+                    vending_machine_mint.key,
+                    vending_buffer.price,
+                    machine_seed.0,
+                    vending_buffer.bump_seed,
+                );
 
-                vending_buffer.echo_data.fill(0);
-                let min_of_len = std::cmp::min(vending_buffer.echo_data.len(), data.len());
-                vending_buffer.echo_data.copy_from_slice(&data[..min_of_len]);
-                // vending_buffer.echo_data.copy_from_slice(&data);
-                vending_buffer.serialize(&mut *vending_machine_buffer.data.borrow_mut())?;
+                assert_pda(
+                    vending_buffer_key,
+                    vending_machine_buffer,
+                    EchoError::InvalidVendingMachinePda,
+                )?;
 
-                msg!("{:?}", data);
-                msg!("{:?}", &vending_buffer.echo_data);
+                if !vending_buffer.is_on_sale(Clock::get()?.unix_timestamp) {
+                    return Err(EchoError::SaleWindowClosed.into());
+                }
 
-                msg!("Instruction: VendingMachineEcho END & SUCCESS");
-                
-                Ok(())
+                if vending_buffer.holding_gated {
+                    let holding_gate = next_account_info(accounts_iter)?;
+                    let holding_registration = next_account_info(accounts_iter)?;
+                    assert_holding(
+                        program_id,
+                        vending_machine_buffer,
+                        holding_gate,
+                        holding_registration,
+                        user_token_account,
+                    )?;
+                }
+                let base_price = if vending_buffer.dutch_auction {
+                    let dutch_auction = next_account_info(accounts_iter)?;
+                    charge_dutch_auction(program_id, vending_machine_buffer, dutch_auction)?
+                } else {
+                    vending_buffer.price
+                };
+                let receipt_accounts = if vending_buffer.receipts {
+                    Some((
+                        next_account_info(accounts_iter)?,
+                        next_account_info(accounts_iter)?,
+                    ))
+                } else {
+                    None
+                };
+                let proceeds = if is_native {
+                    let proceeds = next_account_info(accounts_iter)?;
+                    assert_native_proceeds(proceeds, &vending_buffer)?;
+                    Some(proceeds)
+                } else {
+                    None
+                };
+                // The writer marker is always followed by the System Program
+                let remaining_accounts = accounts_iter.as_slice();
+                let has_writer_marker = remaining_accounts.len() >= 2
+                    && system_program::check_id(remaining_accounts[1].key);
+                let writer_marker = if has_writer_marker {
+                    let writer_marker = next_account_info(accounts_iter)?;
+                    let _system_program = next_account_info(accounts_iter)?;
+                    let marker = record_purchase(
+                        program_id,
+                        vending_machine_buffer,
+                        user_token_account,
+                        user,
+                        writer_marker,
+                        &mut vending_buffer,
+                    )?;
+                    Some((writer_marker, marker))
+                } else {
+                    None
+                };
+                if vending_buffer.quadratic_pricing && writer_marker.is_none() {
+                    msg!("Quadratic pricing requires the writer marker");
+                    return Err(ProgramError::NotEnoughAccountKeys);
+                }
+                // The leaderboard ranks the totals kept in writer markers, so it is only looked for after one
+                let leaderboard = match accounts_iter.as_slice().first() {
+                    Some(account) if writer_marker.is_some() => {
+                        let (leaderboard_key, bump_seed) =
+                            find_leaderboard_address(program_id, vending_machine_buffer.key);
+                        if *account.key == leaderboard_key {
+                            next_account_info(accounts_iter)?;
+                            Some((account, bump_seed))
+                        } else {
+                            None
+                        }
+                    }
+                    _ => None,
+                };
+                let purchases = writer_marker
+                    .as_ref()
+                    .map_or(1, |(_, marker)| marker.purchases);
+                let price = purchase_price(base_price, vending_buffer.quadratic_pricing, purchases)
+                    .ok_or(EchoError::PriceOverflow)?;
+
+                verbose_msg!("BeforeCPI");
+
+                if user_token_account.owner != &spl_token::id() {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+                let token_account = TokenAccount::unpack(&user_token_account.data.borrow())?;
+                if token_account.mint != *vending_machine_mint.key {
+                    return Err(EchoError::InvalidTokenMint.into());
+                }
+
+                if let Some(session) = session {
+                    assert_is_writable(session)?;
+                    if session.owner != program_id {
+                        return Err(ProgramError::IncorrectProgramId);
+                    }
+
+                    let mut session_data = SessionHeader::try_from_slice(&session.data.borrow())?;
+                    assert_valid_header(session_data.version, &session_data._reserved)?;
+
+                    let session_key = create_session_address(
+                        program_id,
+                        vending_machine_buffer.key,
+                        user_token_account.key,
+                        session_data.bump_seed,
+                    );
+                    assert_pda(session_key, session, EchoError::InvalidAuthority)?;
+                    if session_data.vending_machine_buffer != *vending_machine_buffer.key
+                        || session_data.session_key != *user.key
+                    {
+                        return Err(EchoError::InvalidAuthority.into());
+                    }
+
+                    session_data.total_burned = session_data
+                        .total_burned
+                        .checked_add(price)
+                        .filter(|total_burned| *total_burned <= session_data.max_total_burn)
+                        .ok_or(EchoError::SessionBudgetExceeded)?;
+
+                    // Pay price amount of tokens from user_token_account, as its delegate
+                    charge(
+                        user_token_account,
+                        vending_machine_mint,
+                        proceeds,
+                        session,
+                        price,
+                        &[&[
+                            SESSION_SEED,
+                            vending_machine_buffer.key.as_ref(),
+                            user_token_account.key.as_ref(),
+                            &[session_data.bump_seed],
+                        ]],
+                    )?;
+
+                    session_data.serialize(&mut *session.data.borrow_mut())?;
+                } else {
+                    // user pays either as owner or as delegate of user_token_account
+                    let is_delegate = token_account.delegate == COption::Some(*user.key)
+                        && token_account.delegated_amount >= price;
+                    if token_account.owner != *user.key && !is_delegate {
+                        return Err(EchoError::InsufficientDelegation.into());
+                    }
+
+                    // Pay price amount of tokens from user_token_account
+                    charge(
+                        user_token_account,
+                        vending_machine_mint,
+                        proceeds,
+                        user,
+                        price,
+                        &[],
+                    )?;
+                }
+
+                if let Some((writer_marker, mut marker)) = writer_marker {
+                    marker.burned = marker
+                        .burned
+                        .checked_add(price)
+                        .ok_or(EchoError::ArithmeticOverflow)?;
+                    marker.serialize(&mut *writer_marker.data.borrow_mut())?;
+                    if let Some((leaderboard, bump_seed)) = leaderboard {
+                        update_leaderboard(
+                            program_id,
+                            vending_machine_buffer,
+                            user_token_account,
+                            user,
+                            leaderboard,
+                            bump_seed,
+                            marker.burned,
+                        )?;
+                    }
+                }
+
+                if let Some((receipt_mint, receipt_account)) = receipt_accounts {
+                    mint_receipt(
+                        program_id,
+                        vending_machine_buffer,
+                        vending_machine_mint,
+                        &vending_buffer,
+                        machine_seed.0,
+                        receipt_mint,
+                        receipt_account,
+                    )?;
+                }
+
+                verbose_msg!("AfterCPI");
+
+                write_echo_data(&mut vending_buffer.echo_data, &data);
+                vending_buffer.serialize(&mut *vending_machine_buffer.data.borrow_mut())?;
+
+                verbose_msg!("{:?}", data);
+                verbose_msg!("{:?}", &vending_buffer.echo_data);
+
+                verbose_msg!("Instruction: VendingMachineEcho END & SUCCESS");
+
+                record_stats(global_stats, |stats| {
+                    stats.record_write();
+                    if is_native {
+                        stats.record_fee(price);
+                    } else {
+                        stats.record_burn(price);
+                    }
+                })?;
+                Ok(())
+            }
+
+            EchoInstruction::MigrateAuthorizedBuffer => {
+                verbose_msg!("Instruction: MigrateAuthorizedBuffer");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+
+                if !authority.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                assert_is_writable(authorized_buffer)?;
+
+                let legacy =
+                    LegacyAuthorizedBufferHeader::try_from_slice(&authorized_buffer.data.borrow())?;
+                let authorized_buffer_key = create_authorized_buffer_address(
+                    program_id,
+                    authority.key,
+                    legacy.buffer_seed,
+                    None,
+                    legacy.bump_seed,
+                );
+                assert_pda(
+                    authorized_buffer_key,
+                    authorized_buffer,
+                    EchoError::InvalidAuthority,
+                )?;
+
+                let buffer_data = AuthorizedBufferHeader {
+                    version: CURRENT_VERSION,
+                    ..AuthorizedBufferHeader::from(legacy)
+                };
+                let new_len = AuthorizedBufferHeader::header_len(&buffer_data.namespace)
+                    .checked_add(buffer_data.echo_data.len())
+                    .ok_or(EchoError::ArithmeticOverflow)?;
+                realloc_with_rent(authorized_buffer, authority, new_len)?;
+                buffer_data.serialize(&mut *authorized_buffer.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::MigrateVendingMachineBuffer { machine_seed } => {
+                verbose_msg!("Instruction: MigrateVendingMachineBuffer");
+                let accounts_iter = &mut accounts.iter();
+                let vending_machine_buffer = next_account_info(accounts_iter)?;
+                let vending_machine_mint = next_account_info(accounts_iter)?;
+                let payer = next_account_info(accounts_iter)?;
+
+                if !payer.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                assert_is_writable(vending_machine_buffer)?;
+
+                let previous =
+                    VendingMachineBufferHeader::unpack(&vending_machine_buffer.data.borrow())?;
+                if previous.version == VENDING_MACHINE_VERSION {
+                    return Err(EchoError::InvalidBufferVersion.into());
+                }
+                let vending_buffer_key = create_vending_machine_address(
+                    program_id,
+                    vending_machine_mint.key,
+                    previous.price,
+                    machine_seed.0,
+                    previous.bump_seed,
+                );
+                assert_pda(
+                    vending_buffer_key,
+                    vending_machine_buffer,
+                    EchoError::InvalidVendingMachinePda,
+                )?;
+
+                let buffer_data = VendingMachineBufferHeader {
+                    version: VENDING_MACHINE_VERSION,
+                    ..previous
+                };
+                let metadata_len = buffer_data
+                    .metadata
+                    .0
+                    .as_ref()
+                    .map_or(0, |_| VendingMachineMetadata::LEN);
+                let new_len = VendingMachineBufferHeader::HEADER_LEN
+                    .checked_add(buffer_data.echo_data.len())
+                    .and_then(|len| len.checked_add(metadata_len))
+                    .ok_or(EchoError::ArithmeticOverflow)?;
+                realloc_with_rent(vending_machine_buffer, payer, new_len)?;
+                buffer_data.serialize(&mut *vending_machine_buffer.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::RegisterSchema { schema_hash } => {
+                verbose_msg!("Instruction: RegisterSchema");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+                let schema = next_account_info(accounts_iter)?;
+
+                load_authorized_buffer(program_id, authorized_buffer, authority)?;
+                assert_is_writable(schema)?;
+
+                let (schema_key, bump_seed) =
+                    find_schema_address(program_id, authorized_buffer.key);
+                assert_pda(Ok(schema_key), schema, EchoError::InvalidSchemaAccount)?;
+
+                if schema.data_is_empty() {
+                    create_program_account(
+                        authority,
+                        schema,
+                        BufferSchema::LEN,
+                        program_id,
+                        &[SCHEMA_SEED, authorized_buffer.key.as_ref(), &[bump_seed]],
+                    )?;
+                }
+
+                let schema_data = BufferSchema {
+                    bump_seed,
+                    schema_hash,
+                };
+                schema_data.serialize(&mut *schema.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::AuthorizedEchoWithSchema { schema_hash, data } => {
+                verbose_msg!("Instruction: AuthorizedEchoWithSchema");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+                let schema = next_account_info(accounts_iter)?;
+
+                let buffer_data = load_authorized_buffer(program_id, authorized_buffer, authority)?;
+
+                let (schema_key, _) = find_schema_address(program_id, authorized_buffer.key);
+                assert_pda(Ok(schema_key), schema, EchoError::InvalidSchemaAccount)?;
+                if schema.owner != program_id {
+                    return Err(EchoError::InvalidSchemaAccount.into());
+                }
+                let schema_data = BufferSchema::try_from_slice(&schema.data.borrow())?;
+                if schema_data.schema_hash != schema_hash {
+                    return Err(EchoError::SchemaMismatch.into());
+                }
+
+                write_authorized_echo(
+                    program_id,
+                    authorized_buffer,
+                    &buffer_data,
+                    &data,
+                    accounts.get(3),
+                    accounts,
+                )?;
+                record_stats(global_stats, GlobalStats::record_write)?;
+
+                Ok(())
+            }
+
+            EchoInstruction::InitializeTimeSeries {
+                buffer_seed,
+                value_len,
+                capacity,
+            } => {
+                verbose_msg!("Instruction: InitializeTimeSeries");
+                let accounts_iter = &mut accounts.iter();
+                let time_series = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+
+                if !authority.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                if capacity == 0 {
+                    return Err(ProgramError::InvalidInstructionData);
+                }
+
+                let (time_series_key, bump_seed) =
+                    find_time_series_address(program_id, authority.key, buffer_seed);
+                assert_pda(
+                    Ok(time_series_key),
+                    time_series,
+                    EchoError::InvalidAuthorizedBuffer,
+                )?;
+                assert_buffer_not_created(program_id, time_series)?;
+
+                let records_len = TimeSeriesHeader::record_len(value_len)
+                    .checked_mul(capacity as usize)
+                    .ok_or(EchoError::ArithmeticOverflow)?;
+                let buffer_size = TimeSeriesHeader::HEADER_LEN
+                    .checked_add(records_len)
+                    .ok_or(EchoError::ArithmeticOverflow)?;
+
+                create_program_account(
+                    authority,
+                    time_series,
+                    buffer_size,
+                    program_id,
+                    &[
+                        TIME_SERIES_SEED,
+                        authority.key.as_ref(),
+                        &buffer_seed.to_le_bytes(),
+                        &[bump_seed],
+                    ],
+                )?;
+
+                let buffer_data = TimeSeriesHeader {
+                    bump_seed,
+                    buffer_seed,
+                    version: CURRENT_VERSION,
+                    value_len,
+                    capacity,
+                    len: 0,
+                    head: 0,
+                    last_timestamp: i64::MIN,
+                    _reserved: Reserved::default(),
+                    records: vec![0; records_len],
+                };
+                buffer_data.serialize(&mut *time_series.data.borrow_mut())?;
+                record_stats(global_stats, GlobalStats::record_buffer_created)?;
+
+                Ok(())
+            }
+
+            EchoInstruction::AppendTimeSeries { value } => {
+                verbose_msg!("Instruction: AppendTimeSeries");
+                let accounts_iter = &mut accounts.iter();
+                let time_series = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+
+                if !authority.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+
+                let mut buffer_data = TimeSeriesHeader::try_from_slice(&time_series.data.borrow())?;
+                assert_valid_header(buffer_data.version, &buffer_data._reserved)?;
+
+                let time_series_key = create_time_series_address(
+                    program_id,
+                    authority.key,
+                    buffer_data.buffer_seed,
+                    buffer_data.bump_seed,
+                );
+                assert_pda(time_series_key, time_series, EchoError::InvalidAuthority)?;
+
+                if value.len() != buffer_data.value_len as usize {
+                    return Err(EchoError::InvalidValueLength.into());
+                }
+
+                let timestamp = Clock::get()?.unix_timestamp;
+                if timestamp < buffer_data.last_timestamp {
+                    return Err(EchoError::NonMonotonicTimestamp.into());
+                }
+
+                let record_len = TimeSeriesHeader::record_len(buffer_data.value_len);
+                let offset = (buffer_data.head as usize)
+                    .checked_mul(record_len)
+                    .ok_or(EchoError::ArithmeticOverflow)?;
+                let end = offset
+                    .checked_add(record_len)
+                    .ok_or(EchoError::ArithmeticOverflow)?;
+                let record = &mut buffer_data.records[offset..end];
+                record[..8].copy_from_slice(&timestamp.to_le_bytes());
+                record[8..].copy_from_slice(&value);
+
+                let next = buffer_data
+                    .head
+                    .checked_add(1)
+                    .ok_or(EchoError::ArithmeticOverflow)?;
+                buffer_data.head = next % buffer_data.capacity;
+                buffer_data.len = buffer_data.len.saturating_add(1).min(buffer_data.capacity);
+                buffer_data.last_timestamp = timestamp;
+                buffer_data.serialize(&mut *time_series.data.borrow_mut())?;
+                record_stats(global_stats, GlobalStats::record_write)?;
+
+                Ok(())
+            }
+
+            EchoInstruction::InitializeFeed {
+                buffer_seed,
+                exponent,
+                min_value,
+                max_value,
+            } => {
+                verbose_msg!("Instruction: InitializeFeed");
+                let accounts_iter = &mut accounts.iter();
+                let feed = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+
+                if !authority.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                if min_value > max_value {
+                    return Err(ProgramError::InvalidInstructionData);
+                }
+
+                let (feed_key, bump_seed) =
+                    find_feed_address(program_id, authority.key, buffer_seed);
+                assert_pda(Ok(feed_key), feed, EchoError::InvalidAuthorizedBuffer)?;
+                assert_buffer_not_created(program_id, feed)?;
+
+                create_program_account(
+                    authority,
+                    feed,
+                    FeedHeader::LEN,
+                    program_id,
+                    &[
+                        FEED_SEED,
+                        authority.key.as_ref(),
+                        &buffer_seed.to_le_bytes(),
+                        &[bump_seed],
+                    ],
+                )?;
+
+                let feed_data = FeedHeader {
+                    bump_seed,
+                    buffer_seed,
+                    version: CURRENT_VERSION,
+                    exponent,
+                    min_value,
+                    max_value,
+                    value: min_value,
+                    last_update_slot: Clock::get()?.slot,
+                    _reserved: Reserved::default(),
+                };
+                feed_data.serialize(&mut *feed.data.borrow_mut())?;
+                record_stats(global_stats, GlobalStats::record_buffer_created)?;
+
+                Ok(())
+            }
+
+            EchoInstruction::UpdateFeed { value } => {
+                verbose_msg!("Instruction: UpdateFeed");
+                let accounts_iter = &mut accounts.iter();
+                let feed = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+
+                if !authority.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+
+                let mut feed_data = FeedHeader::try_from_slice(&feed.data.borrow())?;
+                assert_valid_header(feed_data.version, &feed_data._reserved)?;
+
+                let feed_key = create_feed_address(
+                    program_id,
+                    authority.key,
+                    feed_data.buffer_seed,
+                    feed_data.bump_seed,
+                );
+                assert_pda(feed_key, feed, EchoError::InvalidAuthority)?;
+
+                if value < feed_data.min_value || value > feed_data.max_value {
+                    return Err(EchoError::FeedValueOutOfBounds.into());
+                }
+
+                feed_data.value = value;
+                feed_data.last_update_slot = Clock::get()?.slot;
+                feed_data.serialize(&mut *feed.data.borrow_mut())?;
+                record_stats(global_stats, GlobalStats::record_write)?;
+
+                Ok(())
+            }
+
+            EchoInstruction::InitializeAggregator {
+                buffer_seed,
+                writers,
+                max_staleness_slots,
+                min_submissions,
+            } => {
+                verbose_msg!("Instruction: InitializeAggregator");
+                let accounts_iter = &mut accounts.iter();
+                let aggregator = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+
+                if !authority.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                if writers.is_empty()
+                    || writers.len() > MAX_AGGREGATOR_WRITERS
+                    || min_submissions as usize > writers.len()
+                {
+                    return Err(ProgramError::InvalidInstructionData);
+                }
+
+                let (aggregator_key, bump_seed) =
+                    find_aggregator_address(program_id, authority.key, buffer_seed);
+                assert_pda(
+                    Ok(aggregator_key),
+                    aggregator,
+                    EchoError::InvalidAuthorizedBuffer,
+                )?;
+                assert_buffer_not_created(program_id, aggregator)?;
+
+                let buffer_size = checked_account_len(
+                    AggregatorHeader::HEADER_LEN,
+                    writers.len(),
+                    WriterSlot::LEN,
+                )
+                .ok_or(EchoError::ArithmeticOverflow)?;
+                create_program_account(
+                    authority,
+                    aggregator,
+                    buffer_size,
+                    program_id,
+                    &[
+                        AGGREGATOR_SEED,
+                        authority.key.as_ref(),
+                        &buffer_seed.to_le_bytes(),
+                        &[bump_seed],
+                    ],
+                )?;
+
+                let aggregator_data = AggregatorHeader {
+                    bump_seed,
+                    buffer_seed,
+                    version: CURRENT_VERSION,
+                    max_staleness_slots,
+                    min_submissions,
+                    median: 0,
+                    median_slot: 0,
+                    _reserved: Reserved::default(),
+                    writers: writers
+                        .into_iter()
+                        .map(|writer| WriterSlot {
+                            writer,
+                            value: 0,
+                            slot: 0,
+                        })
+                        .collect(),
+                };
+                aggregator_data.serialize(&mut *aggregator.data.borrow_mut())?;
+                record_stats(global_stats, GlobalStats::record_buffer_created)?;
+
+                Ok(())
+            }
+
+            EchoInstruction::SubmitValue { value } => {
+                verbose_msg!("Instruction: SubmitValue");
+                let accounts_iter = &mut accounts.iter();
+                let aggregator = next_account_info(accounts_iter)?;
+                let writer = next_account_info(accounts_iter)?;
+
+                if !writer.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                if aggregator.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+
+                let mut aggregator_data =
+                    AggregatorHeader::try_from_slice(&aggregator.data.borrow())?;
+                assert_valid_header(aggregator_data.version, &aggregator_data._reserved)?;
+
+                let slot = Clock::get()?.slot;
+                let writer_slot = aggregator_data
+                    .writers
+                    .iter_mut()
+                    .find(|w| w.writer == *writer.key)
+                    .ok_or(EchoError::WriterNotWhitelisted)?;
+                writer_slot.value = value;
+                writer_slot.slot = slot;
+                aggregator_data.serialize(&mut *aggregator.data.borrow_mut())?;
+                record_stats(global_stats, GlobalStats::record_write)?;
+
+                Ok(())
+            }
+
+            EchoInstruction::Crank => {
+                verbose_msg!("Instruction: Crank");
+                let accounts_iter = &mut accounts.iter();
+                let aggregator = next_account_info(accounts_iter)?;
+
+                if aggregator.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+
+                let mut aggregator_data =
+                    AggregatorHeader::try_from_slice(&aggregator.data.borrow())?;
+                assert_valid_header(aggregator_data.version, &aggregator_data._reserved)?;
+
+                let slot = Clock::get()?.slot;
+                aggregator_data.median = aggregator_data
+                    .fresh_median(slot)
+                    .ok_or(EchoError::InsufficientSubmissions)?;
+                aggregator_data.median_slot = slot;
+                aggregator_data.serialize(&mut *aggregator.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::CloseExpiredBuffer => {
+                verbose_msg!("Instruction: CloseExpiredBuffer");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+
+                assert_is_writable(authorized_buffer)?;
+                assert_is_writable(authority)?;
+                if authorized_buffer.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+
+                let buffer_data =
+                    AuthorizedBufferHeader::unpack_header(&authorized_buffer.data.borrow())?;
+                // Rent can only be refunded to the authority of the buffer
+                assert_buffer_authority(
+                    program_id,
+                    authorized_buffer,
+                    &buffer_data,
+                    authority.key,
+                )?;
+
+                if !buffer_data.is_expired(Clock::get()?.slot) {
+                    return Err(EchoError::WriteDeadlineNotReached.into());
+                }
+
+                close_authorized_buffer(
+                    program_id,
+                    authorized_buffer,
+                    &buffer_data,
+                    authority,
+                    accounts_iter.as_slice(),
+                )
+            }
+
+            EchoInstruction::OpenSession { max_total_burn } => {
+                verbose_msg!("Instruction: OpenSession");
+                let accounts_iter = &mut accounts.iter();
+                let session = next_account_info(accounts_iter)?;
+                let vending_machine_buffer = next_account_info(accounts_iter)?;
+                let user = next_account_info(accounts_iter)?;
+                let user_token_account = next_account_info(accounts_iter)?;
+                let session_key = next_account_info(accounts_iter)?;
+
+                if !user.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                assert_is_writable(user_token_account)?;
+                if vending_machine_buffer.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+                let vending_buffer = VendingMachineBufferHeader::try_from_slice(
+                    &vending_machine_buffer.data.borrow(),
+                )?;
+                assert_valid_vending_header(&vending_buffer)?;
+
+                let (session_address, bump_seed) = find_session_address(
+                    program_id,
+                    vending_machine_buffer.key,
+                    user_token_account.key,
+                );
+                assert_pda(
+                    Ok(session_address),
+                    session,
+                    EchoError::InvalidAuthorizedBuffer,
+                )?;
+
+                let signer_seeds: &[&[u8]] = &[
+                    SESSION_SEED,
+                    vending_machine_buffer.key.as_ref(),
+                    user_token_account.key.as_ref(),
+                    &[bump_seed],
+                ];
+                create_program_account(
+                    user,
+                    session,
+                    SessionHeader::LEN,
+                    program_id,
+                    signer_seeds,
+                )?;
+
+                invoke(
+                    &approve(
+                        &spl_token::id(),
+                        user_token_account.key,
+                        session.key,
+                        user.key,
+                        &[user.key],
+                        max_total_burn,
+                    )?,
+                    &[user_token_account.clone(), session.clone(), user.clone()],
+                )?;
+
+                let session_data = SessionHeader {
+                    bump_seed,
+                    version: CURRENT_VERSION,
+                    user: *user.key,
+                    vending_machine_buffer: *vending_machine_buffer.key,
+                    session_key: *session_key.key,
+                    max_total_burn,
+                    total_burned: 0,
+                    _reserved: Reserved::default(),
+                };
+                session_data.serialize(&mut *session.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::CloseSession => {
+                verbose_msg!("Instruction: CloseSession");
+                let accounts_iter = &mut accounts.iter();
+                let session = next_account_info(accounts_iter)?;
+                let user = next_account_info(accounts_iter)?;
+                let user_token_account = next_account_info(accounts_iter)?;
+
+                if !user.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                assert_is_writable(session)?;
+                assert_is_writable(user)?;
+                if session.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+
+                let session_data = SessionHeader::try_from_slice(&session.data.borrow())?;
+                let session_address = create_session_address(
+                    program_id,
+                    &session_data.vending_machine_buffer,
+                    user_token_account.key,
+                    session_data.bump_seed,
+                );
+                assert_pda(session_address, session, EchoError::InvalidAuthority)?;
+                if session_data.user != *user.key {
+                    return Err(EchoError::InvalidAuthority.into());
+                }
+
+                invoke(
+                    &revoke(
+                        &spl_token::id(),
+                        user_token_account.key,
+                        user.key,
+                        &[user.key],
+                    )?,
+                    &[user_token_account.clone(), user.clone()],
+                )?;
+
+                close_account(session, user)
+            }
+
+            EchoInstruction::TopUpRent => {
+                verbose_msg!("Instruction: TopUpRent");
+                let accounts_iter = &mut accounts.iter();
+                let buffer = next_account_info(accounts_iter)?;
+                let payer = next_account_info(accounts_iter)?;
+
+                if !payer.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                assert_is_writable(buffer)?;
+                if buffer.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+
+                top_up_rent(buffer, payer, buffer.data_len())?;
+                if !Rent::get()?.is_exempt(buffer.lamports(), buffer.data_len()) {
+                    return Err(ProgramError::AccountNotRentExempt);
+                }
+
+                Ok(())
+            }
+
+            EchoInstruction::SweepExcessLamports => {
+                verbose_msg!("Instruction: SweepExcessLamports");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+
+                assert_is_writable(authorized_buffer)?;
+                assert_is_writable(authority)?;
+                if authorized_buffer.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+                // Sweeping leaves the echo data alone, so it is allowed past the write deadline
+                if !authority.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                let buffer_data =
+                    AuthorizedBufferHeader::unpack_header(&authorized_buffer.data.borrow())?;
+                assert_valid_header(buffer_data.version, &buffer_data._reserved)?;
+                assert_buffer_authority(
+                    program_id,
+                    authorized_buffer,
+                    &buffer_data,
+                    authority.key,
+                )?;
+
+                release_excess_rent(authorized_buffer, authority)
+            }
+
+            EchoInstruction::ScheduleClose { after_slot } => {
+                verbose_msg!("Instruction: ScheduleClose");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+
+                assert_is_writable(authorized_buffer)?;
+                let mut buffer_data =
+                    load_authorized_buffer(program_id, authorized_buffer, authority)?;
+                if after_slot <= Clock::get()?.slot {
+                    return Err(ProgramError::InvalidInstructionData);
+                }
+
+                buffer_data.close_after_slot = after_slot;
+                buffer_data.pack_header(&mut authorized_buffer.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::CancelClose => {
+                verbose_msg!("Instruction: CancelClose");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+
+                assert_is_writable(authorized_buffer)?;
+                let mut buffer_data =
+                    load_authorized_buffer(program_id, authorized_buffer, authority)?;
+                if buffer_data.close_after_slot == 0 {
+                    return Err(EchoError::CloseNotScheduled.into());
+                }
+
+                buffer_data.close_after_slot = 0;
+                buffer_data.pack_header(&mut authorized_buffer.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::CloseAuthorizedBuffer => {
+                verbose_msg!("Instruction: CloseAuthorizedBuffer");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+
+                assert_is_writable(authorized_buffer)?;
+                assert_is_writable(authority)?;
+                let buffer_data = load_authorized_buffer(program_id, authorized_buffer, authority)?;
+                if buffer_data.close_after_slot == 0 {
+                    return Err(EchoError::CloseNotScheduled.into());
+                }
+                if Clock::get()?.slot <= buffer_data.close_after_slot {
+                    return Err(EchoError::CloseGracePeriodActive.into());
+                }
+
+                close_authorized_buffer(
+                    program_id,
+                    authorized_buffer,
+                    &buffer_data,
+                    authority,
+                    accounts_iter.as_slice(),
+                )
+            }
+
+            EchoInstruction::AddReference => {
+                verbose_msg!("Instruction: AddReference");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+                let reference = next_account_info(accounts_iter)?;
+                let referrer = next_account_info(accounts_iter)?;
+                let payer = next_account_info(accounts_iter)?;
+
+                // The authority consents, as a reference keeps it from closing its buffer
+                if !authority.is_signer || !referrer.is_signer || !payer.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                assert_is_writable(authorized_buffer)?;
+                if authorized_buffer.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+
+                let mut buffer_data = AuthorizedBufferHeader::unpack_header_checked(
+                    &authorized_buffer.data.borrow(),
+                )?;
+                assert_valid_header(buffer_data.version, &buffer_data._reserved)?;
+                assert_buffer_authority(
+                    program_id,
+                    authorized_buffer,
+                    &buffer_data,
+                    authority.key,
+                )?;
+
+                let (reference_key, bump_seed) =
+                    find_reference_address(program_id, authorized_buffer.key, referrer.key);
+                assert_pda(
+                    Ok(reference_key),
+                    reference,
+                    EchoError::InvalidAuthorizedBuffer,
+                )?;
+
+                create_program_account(
+                    payer,
+                    reference,
+                    BufferReference::LEN,
+                    program_id,
+                    &[
+                        REFERENCE_SEED,
+                        authorized_buffer.key.as_ref(),
+                        referrer.key.as_ref(),
+                        &[bump_seed],
+                    ],
+                )?;
+                let reference_data = BufferReference {
+                    bump_seed,
+                    buffer: *authorized_buffer.key,
+                    referrer: *referrer.key,
+                };
+                reference_data.serialize(&mut *reference.data.borrow_mut())?;
+
+                buffer_data.reference_count = buffer_data
+                    .reference_count
+                    .checked_add(1)
+                    .ok_or(EchoError::ArithmeticOverflow)?;
+                buffer_data.pack_header(&mut authorized_buffer.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::RemoveReference => {
+                verbose_msg!("Instruction: RemoveReference");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let reference = next_account_info(accounts_iter)?;
+                let referrer = next_account_info(accounts_iter)?;
+                let destination = next_account_info(accounts_iter)?;
+
+                if !referrer.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                assert_is_writable(authorized_buffer)?;
+                assert_is_writable(reference)?;
+                assert_is_writable(destination)?;
+                if authorized_buffer.owner != program_id || reference.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+
+                let reference_data = BufferReference::try_from_slice(&reference.data.borrow())?;
+                if reference_data.buffer != *authorized_buffer.key
+                    || reference_data.referrer != *referrer.key
+                {
+                    return Err(EchoError::InvalidAuthority.into());
+                }
+
+                let mut buffer_data = AuthorizedBufferHeader::unpack_header_checked(
+                    &authorized_buffer.data.borrow(),
+                )?;
+                assert_valid_header(buffer_data.version, &buffer_data._reserved)?;
+                buffer_data.reference_count = buffer_data
+                    .reference_count
+                    .checked_sub(1)
+                    .ok_or(EchoError::ArithmeticOverflow)?;
+                buffer_data.pack_header(&mut authorized_buffer.data.borrow_mut())?;
+
+                close_account(reference, destination)
+            }
+
+            EchoInstruction::PublishImmutable { data } => {
+                verbose_msg!("Instruction: PublishImmutable");
+                let accounts_iter = &mut accounts.iter();
+                let immutable_buffer = next_account_info(accounts_iter)?;
+                let payer = next_account_info(accounts_iter)?;
+
+                if !payer.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+
+                let content_hash = hash(&data).to_bytes();
+                let (immutable_key, bump_seed) = find_immutable_address(program_id, &content_hash);
+                assert_pda(
+                    Ok(immutable_key),
+                    immutable_buffer,
+                    EchoError::InvalidAuthorizedBuffer,
+                )?;
+                // The address commits to the content, so an existing buffer already holds `data`
+                if immutable_buffer.owner == program_id {
+                    return Ok(());
+                }
+
+                create_program_account(
+                    payer,
+                    immutable_buffer,
+                    ImmutableBufferHeader::HEADER_LEN
+                        .checked_add(data.len())
+                        .ok_or(EchoError::ArithmeticOverflow)?,
+                    program_id,
+                    &[IMMUTABLE_SEED, &content_hash, &[bump_seed]],
+                )?;
+                let buffer_data = ImmutableBufferHeader {
+                    bump_seed,
+                    version: CURRENT_VERSION,
+                    content_hash,
+                    data,
+                };
+                buffer_data.serialize(&mut *immutable_buffer.data.borrow_mut())?;
+                record_stats(global_stats, GlobalStats::record_buffer_created)?;
+
+                Ok(())
+            }
+
+            EchoInstruction::InitializeKeypairAuthorizedEcho {
+                buffer_size,
+                write_deadline_slot,
+            } => {
+                verbose_msg!("Instruction: InitializeKeypairAuthorizedEcho");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+
+                if !authorized_buffer.is_signer || !authority.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                assert_buffer_not_created(program_id, authorized_buffer)?;
+
+                let echo_len = buffer_size
+                    .checked_sub(AuthorizedBufferHeader::header_len(&None))
+                    .ok_or(EchoError::ArithmeticOverflow)?;
+                invoke(
+                    &system_instruction::create_account(
+                        authority.key,
+                        authorized_buffer.key,
+                        Rent::get()?.minimum_balance(buffer_size),
+                        buffer_size as u64,
+                        program_id,
+                    ),
+                    &[authority.clone(), authorized_buffer.clone()],
+                )?;
+
+                let buffer_data = AuthorizedBufferHeader {
+                    bump_seed: 0,
+                    buffer_seed: 0,
+                    version: CURRENT_VERSION,
+                    namespace: None,
+                    write_deadline_slot: write_deadline_slot.unwrap_or(0),
+                    close_after_slot: 0,
+                    reference_count: 0,
+                    authority: *authority.key,
+                    verify_after_write: false,
+                    chunk_checksums: false,
+                    write_offset: 0,
+                    features: 0,
+                    _reserved: Reserved::default(),
+                    echo_data: vec![0; echo_len],
+                };
+                buffer_data.serialize(&mut *authorized_buffer.data.borrow_mut())?;
+                record_stats(global_stats, GlobalStats::record_buffer_created)?;
+
+                Ok(())
+            }
+
+            EchoInstruction::SetSaleWindow {
+                opens_at,
+                closes_at,
+                daily_window,
+            } => {
+                verbose_msg!("Instruction: SetSaleWindow");
+                let accounts_iter = &mut accounts.iter();
+                let vending_machine_buffer = next_account_info(accounts_iter)?;
+                let admin = next_account_info(accounts_iter)?;
+
+                if !admin.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                assert_is_writable(vending_machine_buffer)?;
+                if vending_machine_buffer.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+
+                let mut vending_buffer = VendingMachineBufferHeader::try_from_slice(
+                    &vending_machine_buffer.data.borrow(),
+                )?;
+                assert_valid_vending_header(&vending_buffer)?;
+                // Migrated machines have the default pubkey as admin, which cannot sign
+                if vending_buffer.admin != *admin.key {
+                    return Err(EchoError::InvalidAuthority.into());
+                }
+
+                let (opens_at, closes_at) = (opens_at.unwrap_or(0), closes_at.unwrap_or(0));
+                let (daily_opens_at, daily_closes_at) = daily_window.unwrap_or((0, 0));
+                assert_with_msg(
+                    opens_at == 0 || closes_at == 0 || opens_at < closes_at,
+                    ProgramError::InvalidInstructionData,
+                    "Sale window closes before it opens",
+                )?;
+                assert_with_msg(
+                    daily_opens_at < SECONDS_PER_DAY as u32
+                        && daily_closes_at < SECONDS_PER_DAY as u32,
+                    ProgramError::InvalidInstructionData,
+                    "Daily sale window is not within a day",
+                )?;
+
+                vending_buffer.opens_at = opens_at;
+                vending_buffer.closes_at = closes_at;
+                vending_buffer.daily_opens_at = daily_opens_at;
+                vending_buffer.daily_closes_at = daily_closes_at;
+                vending_buffer.serialize(&mut *vending_machine_buffer.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::SetHoldingGate {
+                min_amount,
+                min_slots,
+            } => {
+                verbose_msg!("Instruction: SetHoldingGate");
+                let accounts_iter = &mut accounts.iter();
+                let vending_machine_buffer = next_account_info(accounts_iter)?;
+                let admin = next_account_info(accounts_iter)?;
+                let holding_gate = next_account_info(accounts_iter)?;
+
+                if !admin.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                assert_is_writable(vending_machine_buffer)?;
+                assert_is_writable(holding_gate)?;
+                if vending_machine_buffer.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+
+                let mut vending_buffer = VendingMachineBufferHeader::try_from_slice(
+                    &vending_machine_buffer.data.borrow(),
+                )?;
+                assert_valid_vending_header(&vending_buffer)?;
+                if vending_buffer.admin != *admin.key {
+                    return Err(EchoError::InvalidAuthority.into());
+                }
+
+                let (holding_gate_key, bump_seed) =
+                    find_holding_gate_address(program_id, vending_machine_buffer.key);
+                assert_pda(
+                    Ok(holding_gate_key),
+                    holding_gate,
+                    EchoError::InvalidAuthority,
+                )?;
+                if holding_gate.data_is_empty() {
+                    create_program_account(
+                        admin,
+                        holding_gate,
+                        HoldingGate::LEN,
+                        program_id,
+                        &[
+                            HOLDING_GATE_SEED,
+                            vending_machine_buffer.key.as_ref(),
+                            &[bump_seed],
+                        ],
+                    )?;
+                }
+
+                let gate = HoldingGate {
+                    bump_seed,
+                    version: CURRENT_VERSION,
+                    min_amount,
+                    min_slots,
+                    _reserved: Reserved::default(),
+                };
+                gate.serialize(&mut *holding_gate.data.borrow_mut())?;
+
+                vending_buffer.holding_gated = min_amount != 0;
+                vending_buffer.serialize(&mut *vending_machine_buffer.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::RegisterHolding { machine_seed } => {
+                verbose_msg!("Instruction: RegisterHolding");
+                let accounts_iter = &mut accounts.iter();
+                let vending_machine_buffer = next_account_info(accounts_iter)?;
+                let vending_machine_mint = next_account_info(accounts_iter)?;
+                let holding_gate = next_account_info(accounts_iter)?;
+                let holding_registration = next_account_info(accounts_iter)?;
+                let user = next_account_info(accounts_iter)?;
+                let user_token_account = next_account_info(accounts_iter)?;
+
+                if !user.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                assert_is_writable(holding_registration)?;
+                if vending_machine_buffer.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+
+                let vending_buffer = VendingMachineBufferHeader::try_from_slice(
+                    &vending_machine_buffer.data.borrow(),
+                )?;
+                assert_valid_vending_header(&vending_buffer)?;
+                let vending_buffer_key = create_vending_machine_address(
+                    program_id,
+                    vending_machine_mint.key,
+                    vending_buffer.price,
+                    machine_seed.0,
+                    vending_buffer.bump_seed,
+                );
+                assert_pda(
+                    vending_buffer_key,
+                    vending_machine_buffer,
+                    EchoError::InvalidVendingMachinePda,
+                )?;
+                let gate = load_holding_gate(program_id, vending_machine_buffer, holding_gate)?;
+
+                if user_token_account.owner != &spl_token::id() {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+                let token_account = TokenAccount::unpack(&user_token_account.data.borrow())?;
+                if token_account.owner != *user.key
+                    || token_account.mint != *vending_machine_mint.key
+                {
+                    return Err(EchoError::InvalidAuthority.into());
+                }
+                if token_account.amount < gate.min_amount {
+                    return Err(EchoError::HoldingRequirementNotMet.into());
+                }
+
+                let (registration_key, bump_seed) = find_holding_registration_address(
+                    program_id,
+                    vending_machine_buffer.key,
+                    user_token_account.key,
+                );
+                assert_pda(
+                    Ok(registration_key),
+                    holding_registration,
+                    EchoError::InvalidAuthority,
+                )?;
+                create_program_account(
+                    user,
+                    holding_registration,
+                    HoldingRegistration::LEN,
+                    program_id,
+                    &[
+                        HOLDING_SEED,
+                        vending_machine_buffer.key.as_ref(),
+                        user_token_account.key.as_ref(),
+                        &[bump_seed],
+                    ],
+                )?;
+
+                let registration = HoldingRegistration {
+                    bump_seed,
+                    version: CURRENT_VERSION,
+                    amount: token_account.amount,
+                    slot: Clock::get()?.slot,
+                    _reserved: Reserved::default(),
+                };
+                registration.serialize(&mut *holding_registration.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::SetVendingMachineMetadata { metadata } => {
+                verbose_msg!("Instruction: SetVendingMachineMetadata");
+                let accounts_iter = &mut accounts.iter();
+                let vending_machine_buffer = next_account_info(accounts_iter)?;
+                let admin = next_account_info(accounts_iter)?;
+
+                if !admin.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                assert_is_writable(vending_machine_buffer)?;
+                if vending_machine_buffer.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+                assert_with_msg(
+                    metadata.is_valid(),
+                    ProgramError::InvalidInstructionData,
+                    "Vending machine metadata is not valid UTF-8",
+                )?;
+
+                let mut vending_buffer = VendingMachineBufferHeader::try_from_slice(
+                    &vending_machine_buffer.data.borrow(),
+                )?;
+                assert_valid_vending_header(&vending_buffer)?;
+                if vending_buffer.admin != *admin.key {
+                    return Err(EchoError::InvalidAuthority.into());
+                }
+
+                if vending_buffer.metadata.0.is_none() {
+                    let new_len = vending_machine_buffer
+                        .data_len()
+                        .checked_add(VendingMachineMetadata::LEN)
+                        .ok_or(EchoError::ArithmeticOverflow)?;
+                    realloc_with_rent(vending_machine_buffer, admin, new_len)?;
+                }
+                vending_buffer.metadata = Trailing(Some(metadata));
+                vending_buffer.serialize(&mut *vending_machine_buffer.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::SetBufferMetadata { metadata } => {
+                verbose_msg!("Instruction: SetBufferMetadata");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+                let buffer_metadata = next_account_info(accounts_iter)?;
+
+                load_authorized_buffer(program_id, authorized_buffer, authority)?;
+                assert_is_writable(buffer_metadata)?;
+                assert_with_msg(
+                    metadata.is_valid(),
+                    ProgramError::InvalidInstructionData,
+                    "Buffer metadata is not valid UTF-8",
+                )?;
+
+                let (buffer_metadata_key, bump_seed) =
+                    find_buffer_metadata_address(program_id, authorized_buffer.key);
+                assert_pda(
+                    Ok(buffer_metadata_key),
+                    buffer_metadata,
+                    EchoError::InvalidAuthorizedBuffer,
+                )?;
+
+                if buffer_metadata.data_is_empty() {
+                    create_program_account(
+                        authority,
+                        buffer_metadata,
+                        BufferMetadataHeader::LEN,
+                        program_id,
+                        &[METADATA_SEED, authorized_buffer.key.as_ref(), &[bump_seed]],
+                    )?;
+                }
+
+                let metadata_data = BufferMetadataHeader {
+                    bump_seed,
+                    version: CURRENT_VERSION,
+                    buffer: *authorized_buffer.key,
+                    creator: *authority.key,
+                    metadata,
+                    _reserved: Reserved::default(),
+                };
+                metadata_data.serialize(&mut *buffer_metadata.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::CloseBufferMetadata => {
+                verbose_msg!("Instruction: CloseBufferMetadata");
+                let accounts_iter = &mut accounts.iter();
+                let buffer_metadata = next_account_info(accounts_iter)?;
+                let creator = next_account_info(accounts_iter)?;
+
+                if !creator.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                assert_is_writable(buffer_metadata)?;
+                if buffer_metadata.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+
+                let metadata_data =
+                    BufferMetadataHeader::try_from_slice(&buffer_metadata.data.borrow())?;
+                assert_valid_header(metadata_data.version, &metadata_data._reserved)?;
+                let buffer_metadata_key = create_buffer_metadata_address(
+                    program_id,
+                    &metadata_data.buffer,
+                    metadata_data.bump_seed,
+                );
+                assert_pda(
+                    buffer_metadata_key,
+                    buffer_metadata,
+                    EchoError::InvalidAuthority,
+                )?;
+                if metadata_data.creator != *creator.key {
+                    return Err(EchoError::InvalidAuthority.into());
+                }
+
+                close_account(buffer_metadata, creator)
+            }
+            EchoInstruction::InitializeOrg { org_seed } => {
+                verbose_msg!("Instruction: InitializeOrg");
+                let accounts_iter = &mut accounts.iter();
+                let org = next_account_info(accounts_iter)?;
+                let creator = next_account_info(accounts_iter)?;
+
+                if !creator.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+
+                let (org_key, bump_seed) = find_org_address(program_id, creator.key, org_seed);
+                assert_pda(Ok(org_key), org, EchoError::InvalidAuthorizedBuffer)?;
+                create_program_account(
+                    creator,
+                    org,
+                    OrgHeader::HEADER_LEN + 32,
+                    program_id,
+                    &[
+                        ORG_SEED,
+                        creator.key.as_ref(),
+                        &org_seed.to_le_bytes(),
+                        &[bump_seed],
+                    ],
+                )?;
+
+                let org_data = OrgHeader {
+                    bump_seed,
+                    version: CURRENT_VERSION,
+                    creator: *creator.key,
+                    org_seed,
+                    _reserved: Reserved::default(),
+                    admins: vec![*creator.key],
+                };
+                org_data.serialize(&mut *org.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::SetOrgAdmin { admin, is_admin } => {
+                verbose_msg!("Instruction: SetOrgAdmin");
+                let accounts_iter = &mut accounts.iter();
+                let org = next_account_info(accounts_iter)?;
+                let signer = next_account_info(accounts_iter)?;
+
+                assert_is_writable(org)?;
+                let mut org_data = load_org_as_admin(program_id, org, signer)?;
+                set_membership(&mut org_data.admins, admin, is_admin, MAX_ORG_ADMINS)?;
+                assert_with_msg(
+                    !org_data.admins.is_empty(),
+                    ProgramError::InvalidInstructionData,
+                    "Cannot remove the last admin of an org",
+                )?;
+
+                let new_len = checked_account_len(OrgHeader::HEADER_LEN, org_data.admins.len(), 32)
+                    .ok_or(EchoError::ArithmeticOverflow)?;
+                realloc_with_rent(org, signer, new_len)?;
+                org_data.serialize(&mut *org.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::InitializeProject { project_seed } => {
+                verbose_msg!("Instruction: InitializeProject");
+                let accounts_iter = &mut accounts.iter();
+                let org = next_account_info(accounts_iter)?;
+                let project = next_account_info(accounts_iter)?;
+                let admin = next_account_info(accounts_iter)?;
+
+                load_org_as_admin(program_id, org, admin)?;
+
+                let (project_key, bump_seed) =
+                    find_project_address(program_id, org.key, project_seed);
+                assert_pda(Ok(project_key), project, EchoError::InvalidAuthorizedBuffer)?;
+                create_program_account(
+                    admin,
+                    project,
+                    ProjectHeader::HEADER_LEN,
+                    program_id,
+                    &[
+                        PROJECT_SEED,
+                        org.key.as_ref(),
+                        &project_seed.to_le_bytes(),
+                        &[bump_seed],
+                    ],
+                )?;
+
+                let project_data = ProjectHeader {
+                    bump_seed,
+                    version: CURRENT_VERSION,
+                    org: *org.key,
+                    project_seed,
+                    _reserved: Reserved::default(),
+                    members: vec![],
+                };
+                project_data.serialize(&mut *project.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::SetProjectMember { member, is_member } => {
+                verbose_msg!("Instruction: SetProjectMember");
+                let accounts_iter = &mut accounts.iter();
+                let org = next_account_info(accounts_iter)?;
+                let project = next_account_info(accounts_iter)?;
+                let admin = next_account_info(accounts_iter)?;
+
+                assert_is_writable(project)?;
+                load_org_as_admin(program_id, org, admin)?;
+                let mut project_data = load_project(program_id, project)?;
+                if project_data.org != *org.key {
+                    return Err(EchoError::InvalidAuthority.into());
+                }
+                set_membership(
+                    &mut project_data.members,
+                    member,
+                    is_member,
+                    MAX_PROJECT_MEMBERS,
+                )?;
+
+                let new_len =
+                    checked_account_len(ProjectHeader::HEADER_LEN, project_data.members.len(), 32)
+                        .ok_or(EchoError::ArithmeticOverflow)?;
+                realloc_with_rent(project, admin, new_len)?;
+                project_data.serialize(&mut *project.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::InitializeProjectBuffer {
+                buffer_seed,
+                buffer_size,
+            } => {
+                verbose_msg!("Instruction: InitializeProjectBuffer");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let project = next_account_info(accounts_iter)?;
+                let member = next_account_info(accounts_iter)?;
+
+                load_project_as_member(program_id, project, member)?;
+                let header_len = AuthorizedBufferHeader::header_len(&None);
+                assert_with_msg(
+                    buffer_size >= header_len,
+                    ProgramError::InvalidInstructionData,
+                    "Buffer size is smaller than the header",
+                )?;
+
+                let (authorized_buffer_key, bump_seed) =
+                    find_authorized_buffer_address(program_id, project.key, buffer_seed, None);
+                assert_pda(
+                    Ok(authorized_buffer_key),
+                    authorized_buffer,
+                    EchoError::InvalidAuthorizedBuffer,
+                )?;
+                assert_buffer_not_created(program_id, authorized_buffer)?;
+                let buffer_seed_bytes = buffer_seed.to_le_bytes();
+                let bump_seed_bytes = [bump_seed];
+                let mut signer_seeds =
+                    authorized_buffer_seeds(project.key, &buffer_seed_bytes, &None);
+                signer_seeds.push(&bump_seed_bytes);
+                create_program_account(
+                    member,
+                    authorized_buffer,
+                    buffer_size,
+                    program_id,
+                    &signer_seeds,
+                )?;
+
+                let buffer_data = AuthorizedBufferHeader {
+                    bump_seed,
+                    buffer_seed,
+                    version: CURRENT_VERSION,
+                    namespace: None,
+                    write_deadline_slot: 0,
+                    close_after_slot: 0,
+                    reference_count: 0,
+                    authority: Pubkey::default(),
+                    verify_after_write: false,
+                    chunk_checksums: false,
+                    write_offset: 0,
+                    features: 0,
+                    _reserved: Reserved::default(),
+                    echo_data: vec![0; buffer_size - header_len],
+                };
+                buffer_data.serialize(&mut *authorized_buffer.data.borrow_mut())?;
+                record_stats(global_stats, GlobalStats::record_buffer_created)?;
+
+                Ok(())
+            }
+
+            EchoInstruction::ProjectEcho { data } => {
+                verbose_msg!("Instruction: ProjectEcho");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let project = next_account_info(accounts_iter)?;
+                let member = next_account_info(accounts_iter)?;
+
+                load_project_as_member(program_id, project, member)?;
+                if authorized_buffer.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+                let buffer_data =
+                    AuthorizedBufferHeader::unpack_header(&authorized_buffer.data.borrow())?;
+                assert_valid_header(buffer_data.version, &buffer_data._reserved)?;
+                assert_buffer_authority(program_id, authorized_buffer, &buffer_data, project.key)?;
+                if buffer_data.is_expired(Clock::get()?.slot) {
+                    return Err(EchoError::WriteDeadlinePassed.into());
+                }
+
+                write_authorized_echo(
+                    program_id,
+                    authorized_buffer,
+                    &buffer_data,
+                    &data,
+                    accounts.get(3),
+                    accounts,
+                )?;
+                record_stats(global_stats, GlobalStats::record_write)?;
+
+                Ok(())
+            }
+
+            EchoInstruction::RotateAuthorityWithAttestation {
+                new,
+                attestation_hash,
+            } => {
+                verbose_msg!("Instruction: RotateAuthorityWithAttestation");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+                let rotation_log = next_account_info(accounts_iter)?;
+
+                assert_is_writable(authorized_buffer)?;
+                assert_is_writable(rotation_log)?;
+                let mut buffer_data =
+                    load_authorized_buffer(program_id, authorized_buffer, authority)?;
+                assert_with_msg(
+                    buffer_data.is_keypair_buffer(),
+                    ProgramError::InvalidArgument,
+                    "Only keypair buffers can rotate their authority",
+                )?;
+                assert_with_msg(
+                    new != Pubkey::default(),
+                    ProgramError::InvalidInstructionData,
+                    "New authority must not be the default pubkey",
+                )?;
+
+                append_rotation(
+                    program_id,
+                    authorized_buffer,
+                    rotation_log,
+                    authority,
+                    AuthorityRotation {
+                        old_authority: *authority.key,
+                        new_authority: new,
+                        slot: Clock::get()?.slot,
+                        attestation_hash,
+                    },
+                )?;
+
+                buffer_data.authority = new;
+                buffer_data.pack_header(&mut authorized_buffer.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::SetPayloadPointer { pointer } => {
+                verbose_msg!("Instruction: SetPayloadPointer");
+                write_payload_pointer(program_id, accounts, pointer, &[])
+            }
+
+            EchoInstruction::SetIpfsPayloadPointer { pointer, cid } => {
+                verbose_msg!("Instruction: SetIpfsPayloadPointer");
+                if !is_valid_cid(&cid) {
+                    return Err(EchoError::InvalidContentIdentifier.into());
+                }
+                write_payload_pointer(program_id, accounts, pointer, &cid)
+            }
+
+            EchoInstruction::PostToBridge {
+                nonce,
+                consistency_level,
+            } => {
+                verbose_msg!("Instruction: PostToBridge");
+                post_to_bridge(program_id, accounts, nonce, consistency_level)
+            }
+
+            EchoInstruction::SetDutchAuction {
+                start_price,
+                floor_price,
+                decay_slots,
+            } => {
+                verbose_msg!("Instruction: SetDutchAuction");
+                let accounts_iter = &mut accounts.iter();
+                let vending_machine_buffer = next_account_info(accounts_iter)?;
+                let admin = next_account_info(accounts_iter)?;
+                let dutch_auction = next_account_info(accounts_iter)?;
+
+                if !admin.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                assert_is_writable(vending_machine_buffer)?;
+                assert_is_writable(dutch_auction)?;
+                if vending_machine_buffer.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+
+                let mut vending_buffer = VendingMachineBufferHeader::try_from_slice(
+                    &vending_machine_buffer.data.borrow(),
+                )?;
+                assert_valid_vending_header(&vending_buffer)?;
+                if vending_buffer.admin != *admin.key {
+                    return Err(EchoError::InvalidAuthority.into());
+                }
+                assert_with_msg(
+                    floor_price <= start_price,
+                    ProgramError::InvalidInstructionData,
+                    "Floor price is above the start price",
+                )?;
+
+                let (dutch_auction_key, bump_seed) =
+                    find_dutch_auction_address(program_id, vending_machine_buffer.key);
+                assert_pda(
+                    Ok(dutch_auction_key),
+                    dutch_auction,
+                    EchoError::InvalidAuthority,
+                )?;
+                if dutch_auction.data_is_empty() {
+                    create_program_account(
+                        admin,
+                        dutch_auction,
+                        DutchAuction::LEN,
+                        program_id,
+                        &[
+                            AUCTION_SEED,
+                            vending_machine_buffer.key.as_ref(),
+                            &[bump_seed],
+                        ],
+                    )?;
+                }
+
+                let auction = DutchAuction {
+                    bump_seed,
+                    version: CURRENT_VERSION,
+                    start_price,
+                    floor_price,
+                    decay_slots,
+                    last_purchase_slot: Clock::get()?.slot,
+                    _reserved: Reserved::default(),
+                };
+                auction.serialize(&mut *dutch_auction.data.borrow_mut())?;
+
+                vending_buffer.dutch_auction = decay_slots != 0;
+                vending_buffer.serialize(&mut *vending_machine_buffer.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::InitializeReceiptMint => {
+                verbose_msg!("Instruction: InitializeReceiptMint");
+                let accounts_iter = &mut accounts.iter();
+                let vending_machine_buffer = next_account_info(accounts_iter)?;
+                let admin = next_account_info(accounts_iter)?;
+                let receipt_mint = next_account_info(accounts_iter)?;
+                let _system_program = next_account_info(accounts_iter)?;
+                let token_program = next_account_info(accounts_iter)?;
+                let rent = next_account_info(accounts_iter)?;
+
+                if !admin.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                assert_is_writable(vending_machine_buffer)?;
+                assert_is_writable(receipt_mint)?;
+                if vending_machine_buffer.owner != program_id
+                    || *token_program.key != spl_token::id()
+                {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+
+                let mut vending_buffer = VendingMachineBufferHeader::try_from_slice(
+                    &vending_machine_buffer.data.borrow(),
+                )?;
+                assert_valid_vending_header(&vending_buffer)?;
+                if vending_buffer.admin != *admin.key {
+                    return Err(EchoError::InvalidAuthority.into());
+                }
+
+                let (receipt_mint_key, bump_seed) =
+                    find_receipt_mint_address(program_id, vending_machine_buffer.key);
+                assert_pda(
+                    Ok(receipt_mint_key),
+                    receipt_mint,
+                    EchoError::InvalidAuthority,
+                )?;
+                // The mint is owned by the Token Program, so it is allocated for it rather than for this program
+                create_program_account(
+                    admin,
+                    receipt_mint,
+                    spl_token::state::Mint::LEN,
+                    &spl_token::id(),
+                    &[
+                        RECEIPT_SEED,
+                        vending_machine_buffer.key.as_ref(),
+                        &[bump_seed],
+                    ],
+                )?;
+                invoke(
+                    &initialize_mint(
+                        &spl_token::id(),
+                        receipt_mint.key,
+                        vending_machine_buffer.key,
+                        None,
+                        0,
+                    )?,
+                    &[receipt_mint.clone(), rent.clone()],
+                )?;
+
+                vending_buffer.receipts = true;
+                vending_buffer.serialize(&mut *vending_machine_buffer.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::SetQuadraticPricing { enabled } => {
+                verbose_msg!("Instruction: SetQuadraticPricing");
+                let accounts_iter = &mut accounts.iter();
+                let vending_machine_buffer = next_account_info(accounts_iter)?;
+                let admin = next_account_info(accounts_iter)?;
+
+                if !admin.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                assert_is_writable(vending_machine_buffer)?;
+                if vending_machine_buffer.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+
+                let mut vending_buffer = VendingMachineBufferHeader::try_from_slice(
+                    &vending_machine_buffer.data.borrow(),
+                )?;
+                assert_valid_vending_header(&vending_buffer)?;
+                if vending_buffer.admin != *admin.key {
+                    return Err(EchoError::InvalidAuthority.into());
+                }
+
+                vending_buffer.quadratic_pricing = enabled;
+                vending_buffer.serialize(&mut *vending_machine_buffer.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::SetRecoveryGuardians {
+                guardians,
+                threshold,
+                timelock_slots,
+            } => {
+                verbose_msg!("Instruction: SetRecoveryGuardians");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+                let recovery = next_account_info(accounts_iter)?;
+
+                assert_is_writable(recovery)?;
+                let buffer_data = load_authorized_buffer(program_id, authorized_buffer, authority)?;
+                assert_with_msg(
+                    buffer_data.is_keypair_buffer(),
+                    ProgramError::InvalidArgument,
+                    "Only keypair buffers can have guardians",
+                )?;
+                assert_with_msg(
+                    guardians.len() <= MAX_RECOVERY_GUARDIANS,
+                    ProgramError::InvalidInstructionData,
+                    "Too many guardians",
+                )?;
+                assert_with_msg(
+                    guardians
+                        .iter()
+                        .enumerate()
+                        .all(|(i, guardian)| !guardians[..i].contains(guardian)),
+                    ProgramError::InvalidInstructionData,
+                    "Guardians must be distinct",
+                )?;
+                let valid_threshold = if guardians.is_empty() {
+                    threshold == 0
+                } else {
+                    threshold >= 1 && threshold as usize <= guardians.len()
+                };
+                assert_with_msg(
+                    valid_threshold,
+                    ProgramError::InvalidInstructionData,
+                    "Threshold must be between one and the number of guardians",
+                )?;
+
+                let (recovery_key, bump_seed) =
+                    find_recovery_address(program_id, authorized_buffer.key);
+                assert_pda(
+                    Ok(recovery_key),
+                    recovery,
+                    EchoError::InvalidAuthorizedBuffer,
+                )?;
+                let mut recovery_data = if recovery.data_is_empty() {
+                    create_program_account(
+                        authority,
+                        recovery,
+                        RecoveryHeader::HEADER_LEN,
+                        program_id,
+                        &[RECOVERY_SEED, authorized_buffer.key.as_ref(), &[bump_seed]],
+                    )?;
+                    RecoveryHeader {
+                        bump_seed,
+                        version: CURRENT_VERSION,
+                        buffer: *authorized_buffer.key,
+                        threshold: 0,
+                        timelock_slots: 0,
+                        pending_authority: Pubkey::default(),
+                        approvals: 0,
+                        unlock_slot: 0,
+                        _reserved: Reserved::default(),
+                        guardians: vec![],
+                    }
+                } else {
+                    load_recovery(program_id, authorized_buffer, recovery)?
+                };
+
+                recovery_data.threshold = threshold;
+                recovery_data.timelock_slots = timelock_slots;
+                recovery_data.clear_pending();
+                recovery_data.guardians = guardians;
+                let new_len = checked_account_len(
+                    RecoveryHeader::HEADER_LEN,
+                    recovery_data.guardians.len(),
+                    32,
+                )
+                .ok_or(EchoError::ArithmeticOverflow)?;
+                realloc_with_rent(recovery, authority, new_len)?;
+                recovery_data.serialize(&mut *recovery.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::RecoverAuthority { new } => {
+                verbose_msg!("Instruction: RecoverAuthority");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let recovery = next_account_info(accounts_iter)?;
+                let guardian = next_account_info(accounts_iter)?;
+
+                if !guardian.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                assert_is_writable(recovery)?;
+                assert_with_msg(
+                    new != Pubkey::default(),
+                    ProgramError::InvalidInstructionData,
+                    "New authority must not be the default pubkey",
+                )?;
+
+                let mut recovery_data = load_recovery(program_id, authorized_buffer, recovery)?;
+                let index = recovery_data
+                    .guardians
+                    .iter()
+                    .position(|key| key == guardian.key)
+                    .ok_or(EchoError::NotAGuardian)?;
+                recovery_data.approve(index, new, Clock::get()?.slot)?;
+                recovery_data.serialize(&mut *recovery.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::CancelRecovery => {
+                verbose_msg!("Instruction: CancelRecovery");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+                let recovery = next_account_info(accounts_iter)?;
+
+                if !authority.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                assert_is_writable(recovery)?;
+                // Unlike load_authorized_buffer, expired buffers can still cancel a recovery
+                let buffer_data =
+                    AuthorizedBufferHeader::unpack_header(&authorized_buffer.data.borrow())?;
+                assert_valid_header(buffer_data.version, &buffer_data._reserved)?;
+                assert_buffer_authority(
+                    program_id,
+                    authorized_buffer,
+                    &buffer_data,
+                    authority.key,
+                )?;
+
+                let mut recovery_data = load_recovery(program_id, authorized_buffer, recovery)?;
+                if !recovery_data.is_pending() {
+                    return Err(EchoError::RecoveryNotPending.into());
+                }
+                recovery_data.clear_pending();
+                recovery_data.serialize(&mut *recovery.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::FinalizeRecovery => {
+                verbose_msg!("Instruction: FinalizeRecovery");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let recovery = next_account_info(accounts_iter)?;
+                let rotation_log = next_account_info(accounts_iter)?;
+                let payer = next_account_info(accounts_iter)?;
+
+                assert_is_writable(authorized_buffer)?;
+                assert_is_writable(recovery)?;
+                assert_is_writable(rotation_log)?;
+                if authorized_buffer.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+                let mut buffer_data =
+                    AuthorizedBufferHeader::unpack_header(&authorized_buffer.data.borrow())?;
+                assert_valid_header(buffer_data.version, &buffer_data._reserved)?;
+                assert_with_msg(
+                    buffer_data.is_keypair_buffer(),
+                    ProgramError::InvalidArgument,
+                    "Only keypair buffers can be recovered",
+                )?;
+
+                let mut recovery_data = load_recovery(program_id, authorized_buffer, recovery)?;
+                if !recovery_data.is_pending() {
+                    return Err(EchoError::RecoveryNotPending.into());
+                }
+                let slot = Clock::get()?.slot;
+                if !recovery_data.is_unlocked(slot) {
+                    return Err(EchoError::RecoveryTimelockActive.into());
+                }
+
+                append_rotation(
+                    program_id,
+                    authorized_buffer,
+                    rotation_log,
+                    payer,
+                    AuthorityRotation {
+                        old_authority: buffer_data.authority,
+                        new_authority: recovery_data.pending_authority,
+                        slot,
+                        attestation_hash: [0; 32],
+                    },
+                )?;
+
+                buffer_data.authority = recovery_data.pending_authority;
+                buffer_data.pack_header(&mut authorized_buffer.data.borrow_mut())?;
+                recovery_data.clear_pending();
+                recovery_data.serialize(&mut *recovery.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::StageDeadManSwitch {
+                data,
+                check_in_epochs,
+            } => {
+                verbose_msg!("Instruction: StageDeadManSwitch");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+                let dead_man_switch = next_account_info(accounts_iter)?;
+
+                assert_is_writable(dead_man_switch)?;
+                load_authorized_buffer(program_id, authorized_buffer, authority)?;
+                assert_with_msg(
+                    check_in_epochs > 0,
+                    ProgramError::InvalidInstructionData,
+                    "Check-in interval must be at least one epoch",
+                )?;
+
+                let (dead_man_switch_key, bump_seed) =
+                    find_dead_man_switch_address(program_id, authorized_buffer.key);
+                assert_pda(
+                    Ok(dead_man_switch_key),
+                    dead_man_switch,
+                    EchoError::InvalidAuthorizedBuffer,
+                )?;
+                let mut switch_data = if dead_man_switch.data_is_empty() {
+                    create_program_account(
+                        authority,
+                        dead_man_switch,
+                        DeadManSwitchHeader::HEADER_LEN,
+                        program_id,
+                        &[DEAD_MAN_SEED, authorized_buffer.key.as_ref(), &[bump_seed]],
+                    )?;
+                    DeadManSwitchHeader {
+                        bump_seed,
+                        version: CURRENT_VERSION,
+                        buffer: *authorized_buffer.key,
+                        check_in_epochs: 0,
+                        last_check_in_epoch: 0,
+                        released: false,
+                        _reserved: Reserved::default(),
+                        data: vec![],
+                    }
+                } else {
+                    load_armed_dead_man_switch(program_id, authorized_buffer, dead_man_switch)?
+                };
+
+                switch_data.check_in_epochs = check_in_epochs;
+                switch_data.last_check_in_epoch = Clock::get()?.epoch;
+                switch_data.data = data;
+                let new_len = DeadManSwitchHeader::HEADER_LEN
+                    .checked_add(switch_data.data.len())
+                    .ok_or(EchoError::ArithmeticOverflow)?;
+                realloc_with_rent(dead_man_switch, authority, new_len)?;
+                switch_data.serialize(&mut *dead_man_switch.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::CheckIn => {
+                verbose_msg!("Instruction: CheckIn");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+                let dead_man_switch = next_account_info(accounts_iter)?;
+
+                assert_is_writable(dead_man_switch)?;
+                load_authorized_buffer(program_id, authorized_buffer, authority)?;
+                let mut switch_data =
+                    load_armed_dead_man_switch(program_id, authorized_buffer, dead_man_switch)?;
+                switch_data.last_check_in_epoch = Clock::get()?.epoch;
+                switch_data.serialize(&mut *dead_man_switch.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::ReleaseDeadManSwitch => {
+                verbose_msg!("Instruction: ReleaseDeadManSwitch");
+                let accounts_iter = &mut accounts.iter();
+                let dead_man_switch = next_account_info(accounts_iter)?;
+
+                assert_is_writable(dead_man_switch)?;
+                if dead_man_switch.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+                let mut switch_data =
+                    DeadManSwitchHeader::try_from_slice(&dead_man_switch.data.borrow())?;
+                assert_valid_header(switch_data.version, &switch_data._reserved)?;
+                let (dead_man_switch_key, _) =
+                    find_dead_man_switch_address(program_id, &switch_data.buffer);
+                assert_pda(
+                    Ok(dead_man_switch_key),
+                    dead_man_switch,
+                    EchoError::InvalidAuthority,
+                )?;
+                if switch_data.released {
+                    return Err(EchoError::DeadManSwitchReleased.into());
+                }
+                if !switch_data.is_overdue(Clock::get()?.epoch) {
+                    return Err(EchoError::CheckInNotOverdue.into());
+                }
+
+                switch_data.released = true;
+                switch_data.serialize(&mut *dead_man_switch.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::WithdrawRentVault { lamports } => {
+                verbose_msg!("Instruction: WithdrawRentVault");
+                let accounts_iter = &mut accounts.iter();
+                let rent_vault = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+
+                if !authority.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                let (rent_vault_key, bump_seed) =
+                    find_rent_vault_address(program_id, authority.key);
+                assert_pda(Ok(rent_vault_key), rent_vault, EchoError::InvalidAuthority)?;
+                invoke_signed(
+                    &system_instruction::transfer(rent_vault.key, authority.key, lamports),
+                    &[rent_vault.clone(), authority.clone()],
+                    &[&[RENT_VAULT_SEED, authority.key.as_ref(), &[bump_seed]]],
+                )?;
+
+                Ok(())
+            }
+
+            EchoInstruction::SetVerifyAfterWrite { enabled } => {
+                verbose_msg!("Instruction: SetVerifyAfterWrite");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+
+                assert_is_writable(authorized_buffer)?;
+                let mut buffer_data =
+                    load_authorized_buffer(program_id, authorized_buffer, authority)?;
+
+                buffer_data.verify_after_write = enabled;
+                buffer_data.pack_header(&mut authorized_buffer.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::AuthorizedEchoIdempotent {
+                data,
+                idempotency_key,
+            } => {
+                verbose_msg!("Instruction: AuthorizedEchoIdempotent");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+                let idempotency_record = next_account_info(accounts_iter)?;
+
+                let buffer_data = load_authorized_buffer(program_id, authorized_buffer, authority)?;
+
+                let (record_key, bump_seed) =
+                    find_idempotency_record_address(program_id, authorized_buffer.key);
+                assert_pda(
+                    Ok(record_key),
+                    idempotency_record,
+                    EchoError::InvalidAuthority,
+                )?;
+                let mut record = if idempotency_record.data_is_empty() {
+                    create_program_account(
+                        authority,
+                        idempotency_record,
+                        IdempotencyRecord::LEN,
+                        program_id,
+                        &[
+                            IDEMPOTENCY_SEED,
+                            authorized_buffer.key.as_ref(),
+                            &[bump_seed],
+                        ],
+                    )?;
+                    IdempotencyRecord {
+                        bump_seed,
+                        version: CURRENT_VERSION,
+                        last_key: [0; 16],
+                        _reserved: Reserved::default(),
+                    }
+                } else {
+                    if idempotency_record.owner != program_id {
+                        return Err(ProgramError::IncorrectProgramId);
+                    }
+                    let record =
+                        IdempotencyRecord::try_from_slice(&idempotency_record.data.borrow())?;
+                    assert_valid_header(record.version, &record._reserved)?;
+                    if record.last_key == idempotency_key {
+                        msg!("Idempotency key already used, skipping the write");
+                        return Ok(());
+                    }
+                    record
+                };
+
+                write_authorized_echo(
+                    program_id,
+                    authorized_buffer,
+                    &buffer_data,
+                    &data,
+                    accounts.get(4),
+                    accounts,
+                )?;
+                record.last_key = idempotency_key;
+                record.serialize(&mut *idempotency_record.data.borrow_mut())?;
+                record_stats(global_stats, GlobalStats::record_write)?;
+
+                Ok(())
+            }
+
+            EchoInstruction::InitializeGlobalStats => {
+                verbose_msg!("Instruction: InitializeGlobalStats");
+                let accounts_iter = &mut accounts.iter();
+                let global_stats = next_account_info(accounts_iter)?;
+                let payer = next_account_info(accounts_iter)?;
+
+                if !payer.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                let (global_stats_key, bump_seed) = find_global_stats_address(program_id);
+                assert_pda(
+                    Ok(global_stats_key),
+                    global_stats,
+                    EchoError::InvalidAuthority,
+                )?;
+                assert_buffer_not_created(program_id, global_stats)?;
+
+                create_program_account(
+                    payer,
+                    global_stats,
+                    GlobalStats::LEN,
+                    program_id,
+                    &[GLOBAL_STATS_SEED, &[bump_seed]],
+                )?;
+                let stats = GlobalStats {
+                    bump_seed,
+                    version: CURRENT_VERSION,
+                    buffers_created: 0,
+                    writes: 0,
+                    tokens_burned: 0,
+                    lamport_fees: 0,
+                    _reserved: Reserved::default(),
+                };
+                stats.serialize(&mut *global_stats.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::ResizeAuthorizedBuffer { buffer_size } => {
+                verbose_msg!("Instruction: ResizeAuthorizedBuffer");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+
+                assert_is_writable(authorized_buffer)?;
+                assert_is_writable(authority)?;
+                if authorized_buffer.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+                let mut buffer_data =
+                    load_authorized_buffer(program_id, authorized_buffer, authority)?;
+                if buffer_data.has_feature(FEATURE_IMMUTABLE) {
+                    return Err(EchoError::BufferImmutable.into());
+                }
+
+                let header_len = AuthorizedBufferHeader::header_len(&buffer_data.namespace);
+                let echo_len = buffer_size
+                    .checked_sub(header_len)
+                    .and_then(|echo_len| u32::try_from(echo_len).ok())
+                    .ok_or(EchoError::ArithmeticOverflow)?;
+                let old_len = authorized_buffer.data_len();
+                if buffer_size > old_len {
+                    realloc_with_rent(authorized_buffer, authority, buffer_size)?;
+                    authorized_buffer.data.borrow_mut()[old_len..].fill(0);
+                } else {
+                    authorized_buffer.realloc(buffer_size, false)?;
+                    release_excess_rent(authorized_buffer, authority)?;
+                }
+                authorized_buffer.data.borrow_mut()[header_len - 4..header_len]
+                    .copy_from_slice(&echo_len.to_le_bytes());
+                if buffer_data.write_offset > echo_len {
+                    buffer_data.write_offset = echo_len;
+                    buffer_data.pack_header(&mut authorized_buffer.data.borrow_mut())?;
+                }
+
+                if buffer_data.chunk_checksums {
+                    let chunk_checksums =
+                        accounts.get(3).ok_or(ProgramError::NotEnoughAccountKeys)?;
+                    let mut checksums_data =
+                        load_chunk_checksums(program_id, authorized_buffer, chunk_checksums)?;
+                    let checksums_len = ChunkChecksums::len(echo_len as usize);
+                    if checksums_len > chunk_checksums.data_len() {
+                        realloc_with_rent(chunk_checksums, authority, checksums_len)?;
+                    } else {
+                        chunk_checksums.realloc(checksums_len, false)?;
+                        release_excess_rent(chunk_checksums, authority)?;
+                    }
+                    checksums_data.checksums =
+                        checksum::chunk_checksums(&authorized_buffer.data.borrow()[header_len..]);
+                    checksums_data.serialize(&mut *chunk_checksums.data.borrow_mut())?;
+                }
+
+                Ok(())
+            }
+
+            EchoInstruction::SetChunkChecksums { enabled } => {
+                verbose_msg!("Instruction: SetChunkChecksums");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+                let chunk_checksums = next_account_info(accounts_iter)?;
+
+                assert_is_writable(authorized_buffer)?;
+                let mut buffer_data =
+                    load_authorized_buffer(program_id, authorized_buffer, authority)?;
+                let (chunk_checksums_key, bump_seed) =
+                    find_chunk_checksums_address(program_id, authorized_buffer.key);
+                assert_pda(
+                    Ok(chunk_checksums_key),
+                    chunk_checksums,
+                    EchoError::InvalidAuthority,
+                )?;
+
+                if enabled && !buffer_data.chunk_checksums {
+                    // A checksums account left by an earlier buffer at the same address must be closed first
+                    assert_buffer_not_created(program_id, chunk_checksums)?;
+                    let header_len = AuthorizedBufferHeader::header_len(&buffer_data.namespace);
+                    let echo_len = authorized_buffer.data_len() - header_len;
+                    create_program_account(
+                        authority,
+                        chunk_checksums,
+                        ChunkChecksums::len(echo_len),
+                        program_id,
+                        &[
+                            CHUNK_CHECKSUMS_SEED,
+                            authorized_buffer.key.as_ref(),
+                            &[bump_seed],
+                        ],
+                    )?;
+                    let checksums_data = ChunkChecksums {
+                        bump_seed,
+                        version: CURRENT_VERSION,
+                        _reserved: Reserved::default(),
+                        checksums: checksum::chunk_checksums(
+                            &authorized_buffer.data.borrow()[header_len..],
+                        ),
+                    };
+                    checksums_data.serialize(&mut *chunk_checksums.data.borrow_mut())?;
+                } else if !enabled && chunk_checksums.owner == program_id {
+                    close_account(chunk_checksums, authority)?;
+                }
+                buffer_data.chunk_checksums = enabled;
+                buffer_data.pack_header(&mut authorized_buffer.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::CloseVendingMachine => {
+                verbose_msg!("Instruction: CloseVendingMachine");
+                let accounts_iter = &mut accounts.iter();
+                let vending_machine_buffer = next_account_info(accounts_iter)?;
+                let admin = next_account_info(accounts_iter)?;
+
+                if !admin.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                assert_is_writable(vending_machine_buffer)?;
+                assert_is_writable(admin)?;
+                if vending_machine_buffer.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+
+                let vending_buffer = VendingMachineBufferHeader::try_from_slice(
+                    &vending_machine_buffer.data.borrow(),
+                )?;
+                assert_valid_vending_header(&vending_buffer)?;
+                // Migrated machines have the default pubkey as admin, which cannot sign
+                if vending_buffer.admin != *admin.key {
+                    return Err(EchoError::InvalidAuthority.into());
+                }
+                // The Token Program cannot close mints, so the receipt mint would outlive the machine
+                assert_with_msg(
+                    !vending_buffer.receipts,
+                    ProgramError::InvalidArgument,
+                    "Vending machines issuing receipts cannot be closed",
+                )?;
+
+                let companion_keys = find_vending_machine_companion_addresses(
+                    program_id,
+                    vending_machine_buffer.key,
+                );
+                for companion_key in companion_keys {
+                    let companion = next_account_info(accounts_iter)?;
+                    assert_pda(Ok(companion_key), companion, EchoError::InvalidAuthority)?;
+                    if companion.owner == program_id {
+                        assert_is_writable(companion)?;
+                        close_account_permanently(companion, admin)?;
+                    }
+                }
+                while let Ok(companion) = next_account_info(accounts_iter) {
+                    assert_is_writable(companion)?;
+                    if companion.owner != program_id {
+                        return Err(ProgramError::IncorrectProgramId);
+                    }
+                    // Writer markers, holding registrations and sessions are derived from a token account as well
+                    let user_token_account = next_account_info(accounts_iter)?;
+                    let (writer_marker_key, _) = find_writer_marker_address(
+                        program_id,
+                        vending_machine_buffer.key,
+                        user_token_account.key,
+                    );
+                    let (registration_key, _) = find_holding_registration_address(
+                        program_id,
+                        vending_machine_buffer.key,
+                        user_token_account.key,
+                    );
+                    let (session_key, _) = find_session_address(
+                        program_id,
+                        vending_machine_buffer.key,
+                        user_token_account.key,
+                    );
+                    if *companion.key == session_key {
+                        // Sessions were paid for by the user that opened them, who gets their rent back
+                        let user = next_account_info(accounts_iter)?;
+                        let session_data = SessionHeader::try_from_slice(&companion.data.borrow())?;
+                        if session_data.user != *user.key {
+                            return Err(EchoError::InvalidAuthority.into());
+                        }
+                        assert_is_writable(user)?;
+                        close_account_permanently(companion, user)?;
+                        continue;
+                    }
+                    if *companion.key != writer_marker_key && *companion.key != registration_key {
+                        msg!(
+                            "Account {} is not a companion of the vending machine",
+                            companion.key
+                        );
+                        return Err(EchoError::InvalidAuthority.into());
+                    }
+                    close_account_permanently(companion, admin)?;
+                }
+
+                close_account_permanently(vending_machine_buffer, admin)
+            }
+
+            EchoInstruction::CoSignedBatchEcho { data } => {
+                verbose_msg!("Instruction: CoSignedBatchEcho");
+                let pairs_len = data
+                    .len()
+                    .checked_mul(2)
+                    .ok_or(EchoError::ArithmeticOverflow)?;
+                if accounts.len() < pairs_len {
+                    return Err(ProgramError::NotEnoughAccountKeys);
+                }
+                let (pairs, chunk_checksums) = accounts.split_at(pairs_len);
+                let chunk_checksums_iter = &mut chunk_checksums.iter();
+
+                for (pair, data) in pairs.chunks_exact(2).zip(&data) {
+                    let (authorized_buffer, authority) = (&pair[0], &pair[1]);
+                    let buffer_data =
+                        load_authorized_buffer(program_id, authorized_buffer, authority)?;
+                    let chunk_checksums = if buffer_data.chunk_checksums {
+                        chunk_checksums_iter.next()
+                    } else {
+                        None
+                    };
+                    write_authorized_echo(
+                        program_id,
+                        authorized_buffer,
+                        &buffer_data,
+                        data,
+                        chunk_checksums,
+                        accounts,
+                    )?;
+                }
+                record_stats(global_stats, |stats| stats.record_writes(data.len() as u64))?;
+
+                Ok(())
+            }
+
+            EchoInstruction::AppendEcho { data } => {
+                verbose_msg!("Instruction: AppendEcho");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+
+                let mut buffer_data =
+                    load_authorized_buffer(program_id, authorized_buffer, authority)?;
+
+                let header_len = AuthorizedBufferHeader::header_len(&buffer_data.namespace);
+                let echo_len = authorized_buffer.data_len() - header_len;
+                let start = buffer_data.write_offset as usize;
+                let end = start
+                    .checked_add(data.len())
+                    .ok_or(EchoError::ArithmeticOverflow)?;
+                if end > echo_len {
+                    return Err(EchoError::BufferFull.into());
+                }
+                buffer_data.write_offset = end as u32;
+                write_authorized_buffer(
+                    program_id,
+                    authorized_buffer,
+                    &buffer_data,
+                    EchoWrite::Append {
+                        offset: start,
+                        data: &data,
+                    },
+                    accounts.get(2),
+                    accounts,
+                )?;
+                record_stats(global_stats, GlobalStats::record_write)?;
+
+                Ok(())
+            }
+
+            EchoInstruction::SetFeatures { features } => {
+                verbose_msg!("Instruction: SetFeatures");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+
+                assert_is_writable(authorized_buffer)?;
+                let mut buffer_data =
+                    load_authorized_buffer(program_id, authorized_buffer, authority)?;
+                assert_with_msg(
+                    features & !KNOWN_FEATURES == 0,
+                    ProgramError::InvalidInstructionData,
+                    "Unknown feature bits",
+                )?;
+                if buffer_data.has_feature(FEATURE_IMMUTABLE) {
+                    return Err(EchoError::BufferImmutable.into());
+                }
+                // Writes only check the bytes they touch, so the echo data must already be UTF-8
+                if features & FEATURE_UTF8 != 0 && !buffer_data.has_feature(FEATURE_UTF8) {
+                    let header_len = AuthorizedBufferHeader::header_len(&buffer_data.namespace);
+                    if std::str::from_utf8(&authorized_buffer.data.borrow()[header_len..]).is_err()
+                    {
+                        return Err(EchoError::InvalidUtf8.into());
+                    }
+                }
+
+                buffer_data.features = features;
+                buffer_data.pack_header(&mut authorized_buffer.data.borrow_mut())?;
+
+                Ok(())
+            }
+
+            EchoInstruction::EchoAtOffset { offset, data } => {
+                verbose_msg!("Instruction: EchoAtOffset");
+                let accounts_iter = &mut accounts.iter();
+                let authorized_buffer = next_account_info(accounts_iter)?;
+                let authority = next_account_info(accounts_iter)?;
+
+                let buffer_data = load_authorized_buffer(program_id, authorized_buffer, authority)?;
+
+                let header_len = AuthorizedBufferHeader::header_len(&buffer_data.namespace);
+                let echo_len = authorized_buffer.data_len() - header_len;
+                let offset = offset as usize;
+                match offset.checked_add(data.len()) {
+                    Some(end) if end <= echo_len => {}
+                    _ => return Err(EchoError::OffsetOutOfBounds.into()),
+                }
+                write_authorized_buffer(
+                    program_id,
+                    authorized_buffer,
+                    &buffer_data,
+                    EchoWrite::Patch {
+                        offset,
+                        data: &data,
+                    },
+                    accounts.get(2),
+                    accounts,
+                )?;
+                record_stats(global_stats, GlobalStats::record_write)?;
+
+                Ok(())
+            }
+
+            EchoInstruction::MultiEcho { data } => {
+                verbose_msg!("Instruction: MultiEcho");
+                let buffers_len = data
+                    .len()
+                    .checked_add(1)
+                    .ok_or(EchoError::ArithmeticOverflow)?;
+                if accounts.len() < buffers_len {
+                    return Err(ProgramError::NotEnoughAccountKeys);
+                }
+                let authority = &accounts[0];
+                let (authorized_buffers, chunk_checksums) = accounts[1..].split_at(data.len());
+                let chunk_checksums_iter = &mut chunk_checksums.iter();
+
+                for (authorized_buffer, data) in authorized_buffers.iter().zip(&data) {
+                    let buffer_data =
+                        load_authorized_buffer(program_id, authorized_buffer, authority)?;
+                    let chunk_checksums = if buffer_data.chunk_checksums {
+                        chunk_checksums_iter.next()
+                    } else {
+                        None
+                    };
+                    write_authorized_echo(
+                        program_id,
+                        authorized_buffer,
+                        &buffer_data,
+                        data,
+                        chunk_checksums,
+                        accounts,
+                    )?;
+                }
+                record_stats(global_stats, |stats| stats.record_writes(data.len() as u64))?;
+
+                Ok(())
+            }
+
+            EchoInstruction::SweepVendingMachineLamports => {
+                verbose_msg!("Instruction: SweepVendingMachineLamports");
+                let accounts_iter = &mut accounts.iter();
+                let vending_machine_buffer = next_account_info(accounts_iter)?;
+                let admin = next_account_info(accounts_iter)?;
+
+                if !admin.is_signer {
+                    return Err(EchoError::AuthorityNotSigner.into());
+                }
+                assert_is_writable(vending_machine_buffer)?;
+                assert_is_writable(admin)?;
+                if vending_machine_buffer.owner != program_id {
+                    return Err(ProgramError::IncorrectProgramId);
+                }
+
+                let vending_buffer = VendingMachineBufferHeader::try_from_slice(
+                    &vending_machine_buffer.data.borrow(),
+                )?;
+                assert_valid_vending_header(&vending_buffer)?;
+                if vending_buffer.admin != *admin.key {
+                    return Err(EchoError::InvalidAuthority.into());
+                }
+
+                release_excess_rent(vending_machine_buffer, admin)
             }
         }
         // Ok(())
@@ -0,0 +1,80 @@
+//! Program IDs of the Echo Program on each cluster, so tests and tools stop deriving addresses from ad-hoc keys.
+
+use crate::bridge::wormhole_program;
+use solana_program::pubkey::Pubkey;
+use std::str::FromStr;
+
+/// Address the test validator loads the program at. Any key works locally, a fixed one makes every run derive the
+/// same PDAs.
+pub mod localnet {
+    solana_program::declare_id!("EchoLoca1net1111111111111111111111111111111");
+}
+
+/// Address of the devnet deployment, deployed from `target/deploy/echo-devnet-keypair.json`.
+pub mod devnet {
+    solana_program::declare_id!("5zbv5jjtPyJ7thmstA1qRB6vKFyH1YB733YfDms3mfK3");
+}
+
+/// Address of the mainnet-beta deployment, deployed from `target/deploy/echo-keypair.json`, the keypair
+/// `cargo build-bpf` and `solana program deploy` default to.
+pub mod mainnet_beta {
+    solana_program::declare_id!("39kwm9zMqqMwyVbNCJX8Sc6vgphvmbSibyp3Vx5LSfZy");
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cluster {
+    Localnet,
+    Devnet,
+    MainnetBeta,
+}
+
+impl Cluster {
+    pub const ALL: [Cluster; 3] = [Cluster::Localnet, Cluster::Devnet, Cluster::MainnetBeta];
+
+    /// Cluster the crate is built for: devnet with the `devnet` feature, mainnet-beta otherwise.
+    pub const BUILD: Cluster = if cfg!(feature = "devnet") {
+        Cluster::Devnet
+    } else {
+        Cluster::MainnetBeta
+    };
+
+    /// Cluster whose program ID is `program_id`, if any.
+    pub fn of(program_id: &Pubkey) -> Option<Cluster> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|cluster| cluster.program_id() == *program_id)
+    }
+
+    /// Program ID on the cluster.
+    pub fn program_id(&self) -> Pubkey {
+        match self {
+            Cluster::Localnet => localnet::id(),
+            Cluster::Devnet => devnet::id(),
+            Cluster::MainnetBeta => mainnet_beta::id(),
+        }
+    }
+
+    /// Address of the Wormhole core bridge on the cluster, see `PostToBridge`.
+    pub fn wormhole_program_id(&self) -> Pubkey {
+        match self {
+            Cluster::Localnet => wormhole_program::localnet::id(),
+            Cluster::Devnet => wormhole_program::devnet::id(),
+            Cluster::MainnetBeta => wormhole_program::mainnet_beta::id(),
+        }
+    }
+}
+
+impl FromStr for Cluster {
+    type Err = String;
+
+    /// Parses the cluster names of the Solana CLI: `localnet`, `devnet` and `mainnet-beta`.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "localnet" => Ok(Cluster::Localnet),
+            "devnet" => Ok(Cluster::Devnet),
+            "mainnet-beta" => Ok(Cluster::MainnetBeta),
+            _ => Err(format!("unknown cluster {}", name)),
+        }
+    }
+}
@@ -1,17 +1,1164 @@
 use borsh::{BorshDeserialize, BorshSerialize};
-// use solana_program::{pubkey::Pubkey};
+use solana_program::hash::hash;
+use solana_program::pubkey::Pubkey;
+use std::io;
+
+use crate::checksum::chunk_count;
+use crate::error::EchoError;
+use crate::leaderboard::{LeaderboardEntry, LEADERBOARD_LEN};
+use crate::pricing::dutch_auction_price;
+
+/// Layout version written into the header of every buffer created by this program.
+pub const CURRENT_VERSION: u8 = 1;
+
+/// Layout version of vending machine buffers. Version 1 headers ran out of reserved bytes, version 2 grew a new
+/// reserve, see `MigrateVendingMachineBuffer`.
+pub const VENDING_MACHINE_VERSION: u8 = 2;
+
+/// Bytes kept free at the end of each header so new metadata can be added without reallocating.
+pub const RESERVED_LEN: usize = 64;
+
+/// Length of the daily sale window cycle of vending machines.
+pub const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Size of an account made of a `header_len` byte header followed by `count` items of `item_len` bytes, or `None` if
+/// it does not fit in a `usize`.
+pub fn checked_account_len(header_len: usize, count: usize, item_len: usize) -> Option<usize> {
+    count.checked_mul(item_len)?.checked_add(header_len)
+}
+
+/// Reserved header bytes. New header fields are carved out of them, so accounts written before a field
+/// existed decode with that field zeroed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reserved<const N: usize>(pub [u8; N]);
+
+impl<const N: usize> Reserved<N> {
+    pub fn is_zero(&self) -> bool {
+        self.0.iter().all(|&b| b == 0)
+    }
+}
+
+impl<const N: usize> Default for Reserved<N> {
+    fn default() -> Self {
+        Self([0; N])
+    }
+}
+
+impl<const N: usize> BorshSerialize for Reserved<N> {
+    fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.0)
+    }
+}
+
+impl<const N: usize> BorshDeserialize for Reserved<N> {
+    fn deserialize(buf: &mut &[u8]) -> io::Result<Self> {
+        if buf.len() < N {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Unexpected length of input",
+            ));
+        }
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(&buf[..N]);
+        *buf = &buf[N..];
+        Ok(Self(bytes))
+    }
+}
+
+/// Optional section at the very end of an account. Accounts created before the section existed have no bytes left
+/// for it and decode it as `None`; `None` serializes to nothing.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Trailing<T>(pub Option<T>);
+
+impl<T: BorshSerialize> BorshSerialize for Trailing<T> {
+    fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        match &self.0 {
+            Some(value) => value.serialize(writer),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T: BorshDeserialize> BorshDeserialize for Trailing<T> {
+    fn deserialize(buf: &mut &[u8]) -> io::Result<Self> {
+        if buf.is_empty() {
+            return Ok(Self(None));
+        }
+        Ok(Self(Some(T::deserialize(buf)?)))
+    }
+}
+
+/// `AppendEcho` is allowed.
+pub const FEATURE_APPEND_ALLOWED: u32 = 1 << 0;
+/// The echo data can no longer change: writes and resizes fail, and so does `SetFeatures`.
+pub const FEATURE_IMMUTABLE: u32 = 1 << 1;
+/// Writes fail unless the bytes they store are valid UTF-8.
+pub const FEATURE_UTF8: u32 = 1 << 2;
+/// Writes fail when issued through CPI. They take the instructions sysvar after their other accounts.
+pub const FEATURE_CPI_GUARD: u32 = 1 << 3;
+/// Writes log the buffer, offset and length of the bytes they store.
+pub const FEATURE_LOG_WRITES: u32 = 1 << 4;
+/// Bits of `AuthorizedBufferHeader::features` with a meaning, `SetFeatures` rejects the others.
+pub const KNOWN_FEATURES: u32 = FEATURE_APPEND_ALLOWED
+    | FEATURE_IMMUTABLE
+    | FEATURE_UTF8
+    | FEATURE_CPI_GUARD
+    | FEATURE_LOG_WRITES;
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
 pub struct AuthorizedBufferHeader {
     pub bump_seed: u8,
     pub buffer_seed: u64,
+    pub version: u8,
+    pub namespace: Option<[u8; 8]>,
+    // Last slot at which the buffer can be written, zero if it never expires
+    pub write_deadline_slot: u64,
+    // Slot after which `CloseAuthorizedBuffer` is allowed, zero if no close is scheduled
+    pub close_after_slot: u64,
+    // Number of open `BufferReference`s, the buffer cannot be closed while it is non-zero
+    pub reference_count: u32,
+    // Authority of a buffer created at a keypair address, the default pubkey for PDA buffers
+    pub authority: Pubkey,
+    // Whether writes read the buffer back and compare it with the written data, see `SetVerifyAfterWrite`
+    pub verify_after_write: bool,
+    // Whether writes keep the `chunk_checksums` PDA of the buffer up to date, see `SetChunkChecksums`
+    pub chunk_checksums: bool,
+    // Offset in the echo data at which `AppendEcho` writes, the end of the bytes written last
+    pub write_offset: u32,
+    // `FEATURE_*` bits, see `SetFeatures`
+    pub features: u32,
+    pub _reserved: Reserved<{ RESERVED_LEN - 62 }>,
     pub echo_data: Vec<u8>,
 }
 
+impl AuthorizedBufferHeader {
+    /// Number of bytes in front of the echo data: bump_seed, buffer_seed, version, namespace,
+    /// write_deadline_slot, close_after_slot, reference_count, authority, verify_after_write, chunk_checksums,
+    /// write_offset, features, the reserved bytes and the `echo_data` length prefix.
+    pub fn header_len(namespace: &Option<[u8; 8]>) -> usize {
+        1 + 8
+            + 1
+            + 1
+            + namespace.map_or(0, |n| n.len())
+            + 8
+            + 8
+            + 4
+            + 32
+            + 1
+            + 1
+            + 4
+            + 4
+            + (RESERVED_LEN - 62)
+            + 4
+    }
+
+    /// Whether `feature`, one of the `FEATURE_*` bits, is set.
+    pub fn has_feature(&self, feature: u32) -> bool {
+        self.features & feature != 0
+    }
+
+    /// Whether the buffer lives at a keypair address rather than a PDA derived from its authority.
+    pub fn is_keypair_buffer(&self) -> bool {
+        self.authority != Pubkey::default()
+    }
+
+    /// Whether writes are no longer accepted at `slot`.
+    pub fn is_expired(&self, slot: u64) -> bool {
+        self.write_deadline_slot != 0 && slot > self.write_deadline_slot
+    }
+
+    /// Decodes the header from the start of the account data without copying the echo data: `echo_data` is left
+    /// empty, the echo data starts at `header_len(&namespace)`.
+    pub fn unpack_header(data: &[u8]) -> io::Result<Self> {
+        // The namespace option tag follows bump_seed, buffer_seed and version
+        let namespace = match data.get(1 + 8 + 1) {
+            Some(&tag) if tag != 0 => Some([0; 8]),
+            _ => None,
+        };
+        let header_len = Self::header_len(&namespace);
+        if data.len() < header_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Unexpected length of input",
+            ));
+        }
+        let mut header = data[..header_len].to_vec();
+        header[header_len - 4..].fill(0);
+        Self::try_from_slice(&header)
+    }
+
+    /// Same as `unpack_header`, but also fails unless the `echo_data` length prefix matches the length of `data`.
+    /// Tells authorized buffers apart from other accounts of the program, whose first bytes may decode as well.
+    pub fn unpack_header_checked(data: &[u8]) -> io::Result<Self> {
+        let header = Self::unpack_header(data)?;
+        let header_len = Self::header_len(&header.namespace);
+        let mut length_prefix = [0; 4];
+        length_prefix.copy_from_slice(&data[header_len - 4..header_len]);
+        if u32::from_le_bytes(length_prefix) as usize != data.len() - header_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Echo data length does not match the account size",
+            ));
+        }
+        Ok(header)
+    }
+
+    /// Writes the header fields to the start of the account data, leaving the echo data and its length untouched.
+    pub fn pack_header(&self, data: &mut [u8]) -> io::Result<()> {
+        let mut header = Self {
+            echo_data: Vec::new(),
+            ..self.clone()
+        }
+        .try_to_vec()?;
+        header.truncate(header.len() - 4);
+        if data.len() < header.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Unexpected length of input",
+            ));
+        }
+        data[..header.len()].copy_from_slice(&header);
+        Ok(())
+    }
+
+    /// Decodes a whole authorized buffer, in the current layout or in the one written before headers carried a
+    /// version. Legacy buffers are normalized to the current layout with `version` zero, see
+    /// `MigrateAuthorizedBuffer`.
+    pub fn unpack(data: &[u8]) -> io::Result<Self> {
+        match Self::try_from_slice(data) {
+            Ok(header) if header.version != 0 => Ok(header),
+            _ => LegacyAuthorizedBufferHeader::try_from_slice(data).map(Self::from),
+        }
+    }
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
 pub struct VendingMachineBufferHeader {
     pub bump_seed: u8,
     pub price: u64,
+    pub version: u8,
     // pub vending_machine_mint: Pubkey,
+    // Payer of the machine, allowed to change its sale window; the default pubkey for migrated machines
+    pub admin: Pubkey,
+    // Unix timestamps bounding the sale, zero if unbounded
+    pub opens_at: i64,
+    pub closes_at: i64,
+    // Seconds since midnight UTC of the daily sale window, which wraps around midnight if it opens after it closes.
+    // Equal values mean the machine sells all day.
+    pub daily_opens_at: u32,
+    pub daily_closes_at: u32,
+    // Whether buyers must prove a minimum holding, configured in the `HoldingGate` of the machine
+    pub holding_gated: bool,
+    // Number of token accounts that wrote to the machine, counted through their `WriterMarker`
+    pub unique_writers: u32,
+    // Whether the k-th purchase of a token account costs `price * k^2`, see `SetQuadraticPricing`
+    pub quadratic_pricing: bool,
+    // Whether the price decays since the last purchase, configured in the `DutchAuction` of the machine
+    pub dutch_auction: bool,
+    // Whether every purchase mints a token of the receipt mint of the machine, see `InitializeReceiptMint`
+    pub receipts: bool,
+    pub _reserved: Reserved<RESERVED_LEN>,
+    pub echo_data: Vec<u8>,
+    // Display metadata stored after the echo data, absent for machines created without it
+    pub metadata: Trailing<VendingMachineMetadata>,
+}
+
+impl VendingMachineBufferHeader {
+    /// Number of bytes in front of the echo data: bump_seed, price, version, admin, opens_at, closes_at,
+    /// daily_opens_at, daily_closes_at, holding_gated, unique_writers, quadratic_pricing, dutch_auction, receipts,
+    /// the reserved bytes and the `echo_data` length prefix.
+    pub const HEADER_LEN: usize =
+        1 + 8 + 1 + 32 + 8 + 8 + 4 + 4 + 1 + 4 + 1 + 1 + 1 + RESERVED_LEN + 4;
+
+    /// Whether `VendingMachineEcho` is accepted at `unix_timestamp`.
+    pub fn is_on_sale(&self, unix_timestamp: i64) -> bool {
+        if (self.opens_at != 0 && unix_timestamp < self.opens_at)
+            || (self.closes_at != 0 && unix_timestamp >= self.closes_at)
+        {
+            return false;
+        }
+        let time_of_day = unix_timestamp.rem_euclid(SECONDS_PER_DAY) as u32;
+        match self.daily_opens_at.cmp(&self.daily_closes_at) {
+            std::cmp::Ordering::Equal => true,
+            std::cmp::Ordering::Less => {
+                self.daily_opens_at <= time_of_day && time_of_day < self.daily_closes_at
+            }
+            std::cmp::Ordering::Greater => {
+                time_of_day >= self.daily_opens_at || time_of_day < self.daily_closes_at
+            }
+        }
+    }
+
+    /// Decodes a whole vending machine buffer, in the current layout, in the version 1 layout or in the one written
+    /// before headers carried a version. Older buffers are normalized to the current layout, keeping their `version`
+    /// (zero for legacy buffers), see `MigrateVendingMachineBuffer`.
+    pub fn unpack(data: &[u8]) -> io::Result<Self> {
+        match Self::try_from_slice(data) {
+            Ok(header) if header.version == VENDING_MACHINE_VERSION => Ok(header),
+            _ => match VendingMachineBufferHeaderV1::try_from_slice(data) {
+                Ok(header) if header.version == 1 => Ok(Self::from(header)),
+                _ => LegacyVendingMachineBufferHeader::try_from_slice(data).map(Self::from),
+            },
+        }
+    }
+}
+
+/// Name, URI and description of a vending machine for explorers and storefronts, as zero-padded UTF-8.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct VendingMachineMetadata {
+    pub name: [u8; 32],
+    pub uri: [u8; 128],
+    pub description: [u8; 256],
+}
+
+impl VendingMachineMetadata {
+    pub const LEN: usize = 32 + 128 + 256;
+
+    /// Pads the fields, or returns `None` if one of them is too long.
+    pub fn new(name: &str, uri: &str, description: &str) -> Option<Self> {
+        Some(Self {
+            name: pad(name)?,
+            uri: pad(uri)?,
+            description: pad(description)?,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        unpad(&self.name)
+    }
+
+    pub fn uri(&self) -> &str {
+        unpad(&self.uri)
+    }
+
+    pub fn description(&self) -> &str {
+        unpad(&self.description)
+    }
+
+    /// Whether every field is valid UTF-8, so the accessors return the full field.
+    pub fn is_valid(&self) -> bool {
+        [&self.name[..], &self.uri[..], &self.description[..]]
+            .iter()
+            .all(|field| std::str::from_utf8(field).is_ok())
+    }
+}
+
+/// `field` padded with zeros to `N` bytes, `None` if it is longer.
+fn pad<const N: usize>(field: &str) -> Option<[u8; N]> {
+    let mut padded = [0; N];
+    padded
+        .get_mut(..field.len())?
+        .copy_from_slice(field.as_bytes());
+    Some(padded)
+}
+
+/// Zero-padded UTF-8 field without its padding, empty if it is not valid UTF-8.
+fn unpad(field: &[u8]) -> &str {
+    let len = field.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+    std::str::from_utf8(&field[..len]).unwrap_or_default()
+}
+
+/// Maximum number of tags of a buffer, see `BufferMetadata`.
+pub const MAX_BUFFER_TAGS: usize = 8;
+
+/// Display information about a buffer for frontends, as zero-padded UTF-8. Unused tags are all zeros.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct BufferMetadata {
+    pub title: [u8; 64],
+    pub external_url: [u8; 128],
+    pub tags: [[u8; 16]; MAX_BUFFER_TAGS],
+}
+
+impl BufferMetadata {
+    pub const LEN: usize = 64 + 128 + 16 * MAX_BUFFER_TAGS;
+
+    /// Pads the fields, or returns `None` if one of them is too long or there are too many tags.
+    pub fn new(title: &str, external_url: &str, tags: &[&str]) -> Option<Self> {
+        if tags.len() > MAX_BUFFER_TAGS {
+            return None;
+        }
+        let mut padded_tags = [[0; 16]; MAX_BUFFER_TAGS];
+        for (padded_tag, tag) in padded_tags.iter_mut().zip(tags) {
+            *padded_tag = pad(tag)?;
+        }
+        Some(Self {
+            title: pad(title)?,
+            external_url: pad(external_url)?,
+            tags: padded_tags,
+        })
+    }
+
+    pub fn title(&self) -> &str {
+        unpad(&self.title)
+    }
+
+    pub fn external_url(&self) -> &str {
+        unpad(&self.external_url)
+    }
+
+    pub fn tags(&self) -> Vec<&str> {
+        self.tags
+            .iter()
+            .map(|tag| unpad(tag))
+            .filter(|tag| !tag.is_empty())
+            .collect()
+    }
+
+    /// Whether every field is valid UTF-8, so the accessors return the full field.
+    pub fn is_valid(&self) -> bool {
+        std::iter::once(&self.title[..])
+            .chain(std::iter::once(&self.external_url[..]))
+            .chain(self.tags.iter().map(|tag| &tag[..]))
+            .all(|field| std::str::from_utf8(field).is_ok())
+    }
+}
+
+/// Companion account of a buffer holding its `BufferMetadata`, see `SetBufferMetadata`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct BufferMetadataHeader {
+    pub bump_seed: u8,
+    pub version: u8,
+    pub buffer: Pubkey,
+    // Authority of `buffer` that created the metadata, the only key allowed to close it
+    pub creator: Pubkey,
+    pub metadata: BufferMetadata,
+    pub _reserved: Reserved<RESERVED_LEN>,
+}
+
+impl BufferMetadataHeader {
+    pub const LEN: usize = 1 + 1 + 32 + 32 + BufferMetadata::LEN + RESERVED_LEN;
+}
+
+/// Location and digest of a payload stored off-chain, for payloads that do not fit in an account.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq)]
+pub struct PayloadPointer {
+    // sha256 of the URI the payload was uploaded to, the URI itself is kept off-chain
+    pub uri_hash: [u8; 32],
+    // sha256 of the payload
+    pub content_hash: [u8; 32],
+    pub len: u64,
+}
+
+impl PayloadPointer {
+    pub const LEN: usize = 32 + 32 + 8;
+
+    pub fn new(uri: &str, payload: &[u8]) -> Self {
+        Self {
+            uri_hash: hash(uri.as_bytes()).to_bytes(),
+            content_hash: hash(payload).to_bytes(),
+            len: payload.len() as u64,
+        }
+    }
+
+    /// Whether `payload`, as downloaded from the backend, is the payload the pointer was created for.
+    pub fn matches(&self, payload: &[u8]) -> bool {
+        payload.len() as u64 == self.len && hash(payload).to_bytes() == self.content_hash
+    }
+}
+
+/// Longest binary CID accepted by `SetIpfsPayloadPointer`: up to 8 bytes of version, codec, multihash code and
+/// digest length, then the 32 byte digest.
+pub const MAX_CID_LEN: usize = 40;
+
+// Multihash codes of the digests accepted in a CID
+const MULTIHASH_SHA2_256: u64 = 0x12;
+const MULTIHASH_BLAKE2B_256: u64 = 0xb220;
+
+/// Decodes the unsigned varint at the start of `bytes`, returning it with the rest of `bytes`.
+fn read_varint(bytes: &[u8]) -> Option<(u64, &[u8])> {
+    let mut value = 0u64;
+    // Multiformats varints are at most 9 bytes long
+    for (i, byte) in bytes.iter().enumerate().take(9) {
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, &bytes[i + 1..]));
+        }
+    }
+    None
+}
+
+/// Whether `cid` is a binary IPFS CID: a CIDv0, or a CIDv1 with any codec, whose multihash is a 32 byte
+/// sha2-256 or blake2b-256 digest.
+pub fn is_valid_cid(cid: &[u8]) -> bool {
+    if cid.len() > MAX_CID_LEN {
+        return false;
+    }
+    // CIDv0 is a bare sha2-256 multihash
+    if cid.len() == 34 && cid[0] == 0x12 {
+        return cid[1] == 32;
+    }
+    let multihash = match read_varint(cid) {
+        Some((1, rest)) => match read_varint(rest) {
+            Some((_codec, multihash)) => multihash,
+            None => return false,
+        },
+        _ => return false,
+    };
+    match read_varint(multihash) {
+        Some((MULTIHASH_SHA2_256 | MULTIHASH_BLAKE2B_256, digest)) => match read_varint(digest) {
+            Some((32, digest)) => digest.len() == 32,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Companion account of a buffer pointing at its off-chain payload, see `SetPayloadPointer`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct PayloadPointerHeader {
+    pub bump_seed: u8,
+    pub version: u8,
+    pub buffer: Pubkey,
+    pub pointer: PayloadPointer,
+    // Slot of the last `SetPayloadPointer`
+    pub slot: u64,
+    // Binary IPFS CID set by `SetIpfsPayloadPointer`, split into everything in front of the digest and the digest.
+    // `cid_prefix_len` is zero for other backends.
+    pub cid_prefix_len: u8,
+    pub cid_prefix: [u8; 8],
+    pub cid_digest: [u8; 32],
+    pub _reserved: Reserved<{ RESERVED_LEN - 41 }>,
+}
+
+impl PayloadPointerHeader {
+    pub const LEN: usize = 1 + 1 + 32 + PayloadPointer::LEN + 8 + RESERVED_LEN;
+
+    /// Stores `cid`, which must have passed `is_valid_cid`, or clears the CID if `cid` is empty.
+    pub fn set_cid(&mut self, cid: &[u8]) {
+        let (prefix, digest) = cid.split_at(cid.len().saturating_sub(32));
+        self.cid_prefix_len = prefix.len() as u8;
+        self.cid_prefix = [0; 8];
+        self.cid_prefix[..prefix.len()].copy_from_slice(prefix);
+        self.cid_digest = [0; 32];
+        self.cid_digest[..digest.len()].copy_from_slice(digest);
+    }
+
+    /// Binary IPFS CID of the payload, `None` for other backends.
+    pub fn cid(&self) -> Option<Vec<u8>> {
+        if self.cid_prefix_len == 0 {
+            return None;
+        }
+        let mut cid = self.cid_prefix[..self.cid_prefix_len as usize].to_vec();
+        cid.extend_from_slice(&self.cid_digest);
+        Some(cid)
+    }
+}
+
+/// Append log of `(unix_timestamp, value)` records with a fixed value length. Once `capacity` records
+/// have been written the oldest record is overwritten.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct TimeSeriesHeader {
+    pub bump_seed: u8,
+    pub buffer_seed: u64,
+    pub version: u8,
+    pub value_len: u32,
+    pub capacity: u32,
+    // Number of records currently stored
+    pub len: u32,
+    // Index of the slot the next record is written to
+    pub head: u32,
+    pub last_timestamp: i64,
+    pub _reserved: Reserved<RESERVED_LEN>,
+    pub records: Vec<u8>,
+}
+
+impl TimeSeriesHeader {
+    /// Number of bytes in front of the records, including the `records` length prefix.
+    pub const HEADER_LEN: usize = 1 + 8 + 1 + 4 + 4 + 4 + 4 + 8 + RESERVED_LEN + 4;
+
+    /// Size of one record: the `i64` timestamp followed by the value.
+    pub fn record_len(value_len: u32) -> usize {
+        8 + value_len as usize
+    }
+
+    /// Stored records as `(unix_timestamp, value)` pairs, oldest first.
+    pub fn records(&self) -> Vec<(i64, &[u8])> {
+        let record_len = Self::record_len(self.value_len);
+        let start = if self.len < self.capacity {
+            0
+        } else {
+            self.head
+        };
+        (0..self.len)
+            .map(|i| {
+                let offset = ((start + i) % self.capacity) as usize * record_len;
+                let record = &self.records[offset..offset + record_len];
+                let mut timestamp = [0u8; 8];
+                timestamp.copy_from_slice(&record[..8]);
+                (i64::from_le_bytes(timestamp), &record[8..])
+            })
+            .collect()
+    }
+}
+
+/// A single numeric value `value * 10^exponent`, constrained to `[min_value, max_value]`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct FeedHeader {
+    pub bump_seed: u8,
+    pub buffer_seed: u64,
+    pub version: u8,
+    pub exponent: i32,
+    pub min_value: i128,
+    pub max_value: i128,
+    pub value: i128,
+    pub last_update_slot: u64,
+    pub _reserved: Reserved<RESERVED_LEN>,
+}
+
+impl FeedHeader {
+    pub const LEN: usize = 1 + 8 + 1 + 4 + 16 + 16 + 16 + 8 + RESERVED_LEN;
+
+    /// Renders the value as an exact decimal string, e.g. `value: -12345, exponent: -2` is `-123.45`.
+    pub fn format_value(&self) -> String {
+        let sign = if self.value < 0 { "-" } else { "" };
+        let digits = self.value.unsigned_abs().to_string();
+        if self.exponent >= 0 {
+            return format!("{}{}{}", sign, digits, "0".repeat(self.exponent as usize));
+        }
+        let scale = self.exponent.unsigned_abs() as usize;
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - scale);
+        format!("{}{}.{}", sign, integer, fraction)
+    }
+}
+
+/// Maximum number of whitelisted writers of an aggregator.
+pub const MAX_AGGREGATOR_WRITERS: usize = 16;
+
+/// Latest submission of one whitelisted aggregator writer. `slot` is zero until the first submission.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct WriterSlot {
+    pub writer: Pubkey,
+    pub value: i128,
+    pub slot: u64,
+}
+
+impl WriterSlot {
+    pub const LEN: usize = 32 + 16 + 8;
+}
+
+/// Median of the values submitted by a fixed set of writers, recomputed by `Crank`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct AggregatorHeader {
+    pub bump_seed: u8,
+    pub buffer_seed: u64,
+    pub version: u8,
+    pub max_staleness_slots: u64,
+    pub min_submissions: u8,
+    pub median: i128,
+    pub median_slot: u64,
+    pub _reserved: Reserved<RESERVED_LEN>,
+    pub writers: Vec<WriterSlot>,
+}
+
+impl AggregatorHeader {
+    /// Number of bytes in front of the writer slots, including the `writers` length prefix.
+    pub const HEADER_LEN: usize = 1 + 8 + 1 + 8 + 1 + 16 + 8 + RESERVED_LEN + 4;
+
+    /// Median of the submissions made within `max_staleness_slots` of `current_slot`, or `None` if there are
+    /// fewer than `min_submissions` of them. An even number of submissions yields the mean of the two middle
+    /// values, rounded towards negative infinity.
+    pub fn fresh_median(&self, current_slot: u64) -> Option<i128> {
+        let mut values: Vec<i128> = self
+            .writers
+            .iter()
+            .filter(|w| {
+                w.slot > 0 && current_slot.saturating_sub(w.slot) <= self.max_staleness_slots
+            })
+            .map(|w| w.value)
+            .collect();
+        if values.is_empty() || values.len() < self.min_submissions as usize {
+            return None;
+        }
+        values.sort_unstable();
+        let mid = values.len() / 2;
+        if values.len() % 2 == 1 {
+            Some(values[mid])
+        } else {
+            let (a, b) = (values[mid - 1], values[mid]);
+            // a + (b - a) / 2 could overflow for values of opposite signs, so halve both first
+            Some(a.div_euclid(2) + b.div_euclid(2) + (a.rem_euclid(2) + b.rem_euclid(2)) / 2)
+        }
+    }
+}
+
+/// Maximum number of admins of an org.
+pub const MAX_ORG_ADMINS: usize = 8;
+
+/// Maximum number of members of a project.
+pub const MAX_PROJECT_MEMBERS: usize = 32;
+
+/// Organization whose admins create projects and manage their members, see `InitializeOrg`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct OrgHeader {
+    pub bump_seed: u8,
+    pub version: u8,
+    pub creator: Pubkey,
+    pub org_seed: u64,
+    pub _reserved: Reserved<RESERVED_LEN>,
+    pub admins: Vec<Pubkey>,
+}
+
+impl OrgHeader {
+    /// Number of bytes in front of the admins, including the `admins` length prefix.
+    pub const HEADER_LEN: usize = 1 + 1 + 32 + 8 + RESERVED_LEN + 4;
+
+    pub fn is_admin(&self, key: &Pubkey) -> bool {
+        self.admins.contains(key)
+    }
+}
+
+/// Project of an org. Its members may write to the authorized buffers derived from the project address, see
+/// `InitializeProjectBuffer`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct ProjectHeader {
+    pub bump_seed: u8,
+    pub version: u8,
+    pub org: Pubkey,
+    pub project_seed: u64,
+    pub _reserved: Reserved<RESERVED_LEN>,
+    pub members: Vec<Pubkey>,
+}
+
+impl ProjectHeader {
+    /// Number of bytes in front of the members, including the `members` length prefix.
+    pub const HEADER_LEN: usize = 1 + 1 + 32 + 8 + RESERVED_LEN + 4;
+
+    pub fn is_member(&self, key: &Pubkey) -> bool {
+        self.members.contains(key)
+    }
+}
+
+/// Budget `session_key` may burn from `user`'s token account at one vending machine without further signatures
+/// from `user`. The session PDA is the spl-token delegate of that token account.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct SessionHeader {
+    pub bump_seed: u8,
+    pub version: u8,
+    pub user: Pubkey,
+    pub vending_machine_buffer: Pubkey,
+    pub session_key: Pubkey,
+    pub max_total_burn: u64,
+    pub total_burned: u64,
+    pub _reserved: Reserved<RESERVED_LEN>,
+}
+
+impl SessionHeader {
+    pub const LEN: usize = 1 + 1 + 32 + 32 + 32 + 8 + 8 + RESERVED_LEN;
+}
+
+/// Minimum holding required from the buyers of a gated vending machine: a registration of their token account
+/// at least `min_slots` old, and at least `min_amount` tokens at the time of the purchase.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct HoldingGate {
+    pub bump_seed: u8,
+    pub version: u8,
+    pub min_amount: u64,
+    pub min_slots: u64,
+    pub _reserved: Reserved<RESERVED_LEN>,
+}
+
+impl HoldingGate {
+    pub const LEN: usize = 1 + 1 + 8 + 8 + RESERVED_LEN;
+}
+
+/// Dutch auction pricing of a vending machine, see `SetDutchAuction`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct DutchAuction {
+    pub bump_seed: u8,
+    pub version: u8,
+    pub start_price: u64,
+    pub floor_price: u64,
+    pub decay_slots: u64,
+    // Slot of the last purchase, or of the configuration of the auction before the first one
+    pub last_purchase_slot: u64,
+    pub _reserved: Reserved<RESERVED_LEN>,
+}
+
+impl DutchAuction {
+    pub const LEN: usize = 1 + 1 + 8 + 8 + 8 + 8 + RESERVED_LEN;
+
+    /// Price of a purchase at `slot`.
+    pub fn price_at(&self, slot: u64) -> u64 {
+        dutch_auction_price(
+            self.start_price,
+            self.floor_price,
+            self.decay_slots,
+            slot.saturating_sub(self.last_purchase_slot),
+        )
+    }
+}
+
+/// Records that a token account held at least the `min_amount` of a holding gate at `slot`, see `RegisterHolding`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct HoldingRegistration {
+    pub bump_seed: u8,
+    pub version: u8,
+    pub amount: u64,
+    pub slot: u64,
+    pub _reserved: Reserved<RESERVED_LEN>,
+}
+
+impl HoldingRegistration {
+    pub const LEN: usize = 1 + 1 + 8 + 8 + RESERVED_LEN;
+}
+
+/// Marks that a token account wrote to a vending machine at least once, see `VendingMachineEcho`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct WriterMarker {
+    pub bump_seed: u8,
+    pub version: u8,
+    // Slot of the first write
+    pub slot: u64,
+    // Number of purchases made with the marker, which prices them on machines with quadratic pricing
+    pub purchases: u64,
+    // Tokens burned, or lamports paid on native machines, by the purchases made with the marker since it counts them
+    pub burned: u64,
+    pub _reserved: Reserved<{ RESERVED_LEN - 16 }>,
+}
+
+impl WriterMarker {
+    pub const LEN: usize = 1 + 1 + 8 + 8 + 8 + (RESERVED_LEN - 16);
+}
+
+/// Token accounts that burned the most on a vending machine, see `VendingMachineEcho`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct Leaderboard {
+    pub bump_seed: u8,
+    pub version: u8,
+    pub _reserved: Reserved<RESERVED_LEN>,
+    // Sorted by decreasing burn, see `leaderboard::record_burn`
+    pub entries: [LeaderboardEntry; LEADERBOARD_LEN],
+}
+
+impl Leaderboard {
+    pub const LEN: usize = 1 + 1 + RESERVED_LEN + LEADERBOARD_LEN * LeaderboardEntry::LEN;
+}
+
+/// One authority rotation of a keypair buffer, see `RotateAuthorityWithAttestation`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct AuthorityRotation {
+    pub old_authority: Pubkey,
+    pub new_authority: Pubkey,
+    pub slot: u64,
+    // Hash of the off-chain attestation approving the rotation
+    pub attestation_hash: [u8; 32],
+}
+
+impl AuthorityRotation {
+    pub const LEN: usize = 32 + 32 + 8 + 32;
+}
+
+/// Append-only log of the authority rotations of a keypair buffer, oldest first.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct AuthorityRotationLog {
+    pub bump_seed: u8,
+    pub version: u8,
+    pub buffer: Pubkey,
+    pub _reserved: Reserved<RESERVED_LEN>,
+    pub rotations: Vec<AuthorityRotation>,
+}
+
+impl AuthorityRotationLog {
+    /// Number of bytes in front of the rotations, including the `rotations` length prefix.
+    pub const HEADER_LEN: usize = 1 + 1 + 32 + RESERVED_LEN + 4;
+}
+
+/// Maximum number of guardians of a keypair buffer.
+pub const MAX_RECOVERY_GUARDIANS: usize = 8;
+
+/// Guardians able to hand a keypair buffer to a new authority, see `SetRecoveryGuardians`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct RecoveryHeader {
+    pub bump_seed: u8,
+    pub version: u8,
+    pub buffer: Pubkey,
+    pub threshold: u8,
+    pub timelock_slots: u64,
+    /// Authority proposed by the pending recovery, the default pubkey when none is pending.
+    pub pending_authority: Pubkey,
+    /// Bit `i` is set once `guardians[i]` approved `pending_authority`.
+    pub approvals: u8,
+    /// First slot at which the pending recovery can be finalized, zero until `threshold` guardians approved it.
+    pub unlock_slot: u64,
+    pub _reserved: Reserved<RESERVED_LEN>,
+    pub guardians: Vec<Pubkey>,
+}
+
+impl RecoveryHeader {
+    /// Number of bytes in front of the guardians, including the `guardians` length prefix.
+    pub const HEADER_LEN: usize = 1 + 1 + 32 + 1 + 8 + 32 + 1 + 8 + RESERVED_LEN + 4;
+
+    pub fn is_pending(&self) -> bool {
+        self.pending_authority != Pubkey::default()
+    }
+
+    pub fn clear_pending(&mut self) {
+        self.pending_authority = Pubkey::default();
+        self.approvals = 0;
+        self.unlock_slot = 0;
+    }
+
+    /// Records the approval of `new` by `guardians[index]` and starts the timelock once `threshold` guardians
+    /// approved. Fails if a recovery proposing another authority is pending, which only `CancelRecovery` or
+    /// `FinalizeRecovery` clear, so that a single guardian cannot reset the approvals of the others.
+    pub fn approve(&mut self, index: usize, new: Pubkey, slot: u64) -> Result<(), EchoError> {
+        if self.is_pending() && self.pending_authority != new {
+            return Err(EchoError::RecoveryPending);
+        }
+        self.pending_authority = new;
+        self.approvals |= 1 << index;
+        if self.unlock_slot == 0 && self.approvals.count_ones() >= self.threshold as u32 {
+            self.unlock_slot = slot.saturating_add(self.timelock_slots).max(1);
+        }
+        Ok(())
+    }
+
+    /// Whether the pending recovery can be finalized at `slot`.
+    pub fn is_unlocked(&self, slot: u64) -> bool {
+        self.is_pending() && self.unlock_slot != 0 && slot >= self.unlock_slot
+    }
+}
+
+/// Content staged by the authority of a buffer, released by `ReleaseDeadManSwitch` once the authority failed to
+/// check in for `check_in_epochs` epochs, see `StageDeadManSwitch`. Account data is public, so content meant to stay
+/// confidential until then should be staged encrypted, and the key left to another channel.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct DeadManSwitchHeader {
+    pub bump_seed: u8,
+    pub version: u8,
+    pub buffer: Pubkey,
+    pub check_in_epochs: u64,
+    pub last_check_in_epoch: u64,
+    pub released: bool,
+    pub _reserved: Reserved<RESERVED_LEN>,
+    pub data: Vec<u8>,
+}
+
+impl DeadManSwitchHeader {
+    /// Number of bytes in front of the staged data, including the `data` length prefix.
+    pub const HEADER_LEN: usize = 1 + 1 + 32 + 8 + 8 + 1 + RESERVED_LEN + 4;
+
+    /// Whether the authority missed its check-in at `epoch`.
+    pub fn is_overdue(&self, epoch: u64) -> bool {
+        epoch
+            > self
+                .last_check_in_epoch
+                .saturating_add(self.check_in_epochs)
+    }
+}
+
+/// Key of the last write to an authorized buffer made with `AuthorizedEchoIdempotent`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct IdempotencyRecord {
+    pub bump_seed: u8,
+    pub version: u8,
+    pub last_key: [u8; 16],
+    pub _reserved: Reserved<RESERVED_LEN>,
+}
+
+impl IdempotencyRecord {
+    pub const LEN: usize = 1 + 1 + 16 + RESERVED_LEN;
+}
+
+/// Protocol-wide counters of the singleton PDA derived from `[b"global_stats"]`, updated by the instructions it is
+/// passed to, see `InitializeGlobalStats`. Counters saturate instead of failing the instruction they count.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct GlobalStats {
+    pub bump_seed: u8,
+    pub version: u8,
+    // Buffers of any kind created by an initialize instruction
+    pub buffers_created: u64,
+    // Successful writes to buffers of any kind
+    pub writes: u64,
+    // Tokens burned by vending machine purchases
+    pub tokens_burned: u64,
+    // Lamports paid to vending machines priced in the native mint
+    pub lamport_fees: u64,
+    pub _reserved: Reserved<RESERVED_LEN>,
+}
+
+impl GlobalStats {
+    pub const LEN: usize = 1 + 1 + 8 + 8 + 8 + 8 + RESERVED_LEN;
+
+    pub fn record_buffer_created(&mut self) {
+        self.buffers_created = self.buffers_created.saturating_add(1);
+    }
+
+    pub fn record_write(&mut self) {
+        self.record_writes(1);
+    }
+
+    pub fn record_writes(&mut self, count: u64) {
+        self.writes = self.writes.saturating_add(count);
+    }
+
+    pub fn record_burn(&mut self, amount: u64) {
+        self.tokens_burned = self.tokens_burned.saturating_add(amount);
+    }
+
+    pub fn record_fee(&mut self, lamports: u64) {
+        self.lamport_fees = self.lamport_fees.saturating_add(lamports);
+    }
+}
+
+/// CRC-32 of each `checksum::CHUNK_LEN` bytes of the echo data of an authorized buffer, kept up to date by its
+/// writes, see `SetChunkChecksums`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct ChunkChecksums {
+    pub bump_seed: u8,
+    pub version: u8,
+    pub _reserved: Reserved<RESERVED_LEN>,
+    pub checksums: Vec<u32>,
+}
+
+impl ChunkChecksums {
+    /// Number of bytes in front of the checksums, including the `checksums` length prefix.
+    pub const HEADER_LEN: usize = 1 + 1 + RESERVED_LEN + 4;
+
+    /// Size of the account holding the checksums of `echo_len` bytes of echo data.
+    pub fn len(echo_len: usize) -> usize {
+        Self::HEADER_LEN + 4 * chunk_count(echo_len)
+    }
+}
+
+/// Content-addressed buffer written once by `PublishImmutable`, at the address derived from `content_hash`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct ImmutableBufferHeader {
+    pub bump_seed: u8,
+    pub version: u8,
+    // sha256 of `data`
+    pub content_hash: [u8; 32],
+    pub data: Vec<u8>,
+}
+
+impl ImmutableBufferHeader {
+    pub const HEADER_LEN: usize = 1 + 1 + 32 + 4;
+}
+
+/// Dependence of `referrer` on an authorized buffer, registered with `AddReference`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct BufferReference {
+    pub bump_seed: u8,
+    pub buffer: Pubkey,
+    pub referrer: Pubkey,
+}
+
+impl BufferReference {
+    pub const LEN: usize = 1 + 32 + 32;
+}
+
+/// Hash of the Borsh schema that payloads written to a buffer are expected to follow.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct BufferSchema {
+    pub bump_seed: u8,
+    pub schema_hash: [u8; 32],
+}
+
+impl BufferSchema {
+    pub const LEN: usize = 1 + 32;
+}
+
+/// Authorized buffer layout written before headers carried a version and reserved bytes. Legacy buffers predate
+/// namespaces, so they are all derived without one.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct LegacyAuthorizedBufferHeader {
+    pub bump_seed: u8,
+    pub buffer_seed: u64,
+    pub echo_data: Vec<u8>,
+}
+
+/// Vending machine buffer layout written before headers carried a version and reserved bytes.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct LegacyVendingMachineBufferHeader {
+    pub bump_seed: u8,
+    pub price: u64,
+    pub echo_data: Vec<u8>,
+}
+
+/// Vending machine buffer layout of version 1, whose fields after `admin` took all of its reserved bytes.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct VendingMachineBufferHeaderV1 {
+    pub bump_seed: u8,
+    pub price: u64,
+    pub version: u8,
+    pub admin: Pubkey,
+    pub opens_at: i64,
+    pub closes_at: i64,
+    pub daily_opens_at: u32,
+    pub daily_closes_at: u32,
+    pub holding_gated: bool,
+    pub unique_writers: u32,
+    pub quadratic_pricing: bool,
+    pub dutch_auction: bool,
+    pub receipts: bool,
     pub echo_data: Vec<u8>,
+    pub metadata: Trailing<VendingMachineMetadata>,
+}
+
+impl From<LegacyAuthorizedBufferHeader> for AuthorizedBufferHeader {
+    fn from(legacy: LegacyAuthorizedBufferHeader) -> Self {
+        Self {
+            bump_seed: legacy.bump_seed,
+            buffer_seed: legacy.buffer_seed,
+            version: 0,
+            namespace: None,
+            write_deadline_slot: 0,
+            close_after_slot: 0,
+            reference_count: 0,
+            authority: Pubkey::default(),
+            verify_after_write: false,
+            chunk_checksums: false,
+            write_offset: 0,
+            features: 0,
+            _reserved: Reserved::default(),
+            echo_data: legacy.echo_data,
+        }
+    }
+}
+
+impl From<LegacyVendingMachineBufferHeader> for VendingMachineBufferHeader {
+    fn from(legacy: LegacyVendingMachineBufferHeader) -> Self {
+        Self {
+            bump_seed: legacy.bump_seed,
+            price: legacy.price,
+            version: 0,
+            admin: Pubkey::default(),
+            opens_at: 0,
+            closes_at: 0,
+            daily_opens_at: 0,
+            daily_closes_at: 0,
+            holding_gated: false,
+            unique_writers: 0,
+            quadratic_pricing: false,
+            dutch_auction: false,
+            receipts: false,
+            _reserved: Reserved::default(),
+            echo_data: legacy.echo_data,
+            metadata: Trailing(None),
+        }
+    }
+}
+
+impl From<VendingMachineBufferHeaderV1> for VendingMachineBufferHeader {
+    fn from(v1: VendingMachineBufferHeaderV1) -> Self {
+        Self {
+            bump_seed: v1.bump_seed,
+            price: v1.price,
+            version: v1.version,
+            admin: v1.admin,
+            opens_at: v1.opens_at,
+            closes_at: v1.closes_at,
+            daily_opens_at: v1.daily_opens_at,
+            daily_closes_at: v1.daily_closes_at,
+            holding_gated: v1.holding_gated,
+            unique_writers: v1.unique_writers,
+            quadratic_pricing: v1.quadratic_pricing,
+            dutch_auction: v1.dutch_auction,
+            receipts: v1.receipts,
+            _reserved: Reserved::default(),
+            echo_data: v1.echo_data,
+            metadata: v1.metadata,
+        }
+    }
 }
@@ -0,0 +1,43 @@
+//! Helpers for tests that run the Echo Program on a validator, shared with downstream crates.
+
+use solana_client::client_error::Result as ClientResult;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+use crate::state::AuthorizedBufferHeader;
+
+/// Airdrops `lamports` to `to` and waits until the airdrop reaches the commitment of `rpc_client`.
+pub fn airdrop_and_confirm(
+    rpc_client: &RpcClient,
+    to: &Pubkey,
+    lamports: u64,
+) -> ClientResult<Signature> {
+    let signature = rpc_client.request_airdrop(to, lamports)?;
+    rpc_client.poll_for_signature(&signature)?;
+    Ok(signature)
+}
+
+/// Asserts that the authorized buffer `pda` holds `expected` followed by zero padding.
+pub fn assert_buffer_contains(rpc_client: &RpcClient, pda: &Pubkey, expected: &[u8]) {
+    let account = rpc_client
+        .get_account(pda)
+        .unwrap_or_else(|e| panic!("cannot fetch buffer {}: {}", pda, e));
+    let header = AuthorizedBufferHeader::unpack(&account.data)
+        .unwrap_or_else(|e| panic!("{} is not an authorized buffer: {}", pda, e));
+    assert!(
+        header.echo_data.len() >= expected.len(),
+        "buffer {} holds {} bytes, expected at least {}",
+        pda,
+        header.echo_data.len(),
+        expected.len()
+    );
+    let (data, padding) = header.echo_data.split_at(expected.len());
+    assert_eq!(data, expected, "unexpected data in buffer {}", pda);
+    assert!(
+        padding.iter().all(|&byte| byte == 0),
+        "buffer {} has data past the expected {} bytes",
+        pda,
+        expected.len()
+    );
+}
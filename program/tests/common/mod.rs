@@ -0,0 +1,283 @@
+//! Fixtures shared by the integration tests.
+
+use std::net::{Ipv4Addr, TcpListener};
+use std::ops::Deref;
+use std::sync::Arc;
+
+use assert_matches::*;
+use borsh::BorshSerialize;
+use echo::error::EchoError;
+use echo::instruction::EchoInstruction;
+use echo::state::{Trailing, VendingMachineBufferHeader};
+use solana_client::client_error::ClientError;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::account::AccountSharedData;
+use solana_sdk::instruction::{AccountMeta, Instruction, InstructionError};
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::system_program;
+use solana_sdk::transaction::{Transaction, TransactionError};
+use solana_validator::test_validator::{TestValidator, TestValidatorGenesis};
+use tempfile::TempDir;
+
+/// Genesis of a validator with the Echo Program deployed, see [`validator_genesis`].
+pub struct EchoGenesis {
+    genesis: TestValidatorGenesis,
+    ledger: Arc<TempDir>,
+}
+
+impl EchoGenesis {
+    pub fn add_account(&mut self, address: Pubkey, account: AccountSharedData) -> &mut Self {
+        self.genesis.add_account(address, account);
+        self
+    }
+
+    /// Starts the validator, which keeps the ledger directory until it is dropped.
+    pub fn start(&self) -> (EchoValidator, Keypair) {
+        let (validator, payer) = self.genesis.start();
+        (
+            EchoValidator {
+                validator,
+                _ledger: self.ledger.clone(),
+            },
+            payer,
+        )
+    }
+}
+
+/// A running validator, whose ledger directory is removed when it is dropped.
+pub struct EchoValidator {
+    validator: TestValidator,
+    // Declared after the validator so that it is only removed once the validator has shut down
+    _ledger: Arc<TempDir>,
+}
+
+impl Deref for EchoValidator {
+    type Target = TestValidator;
+
+    fn deref(&self) -> &TestValidator {
+        &self.validator
+    }
+}
+
+/// Finds a free port for the RPC service of a validator, followed by a free one for its pubsub service.
+///
+/// The ports are asked from the OS rather than counted from a fixed base, so that `cargo test` processes running at
+/// the same time do not collide.
+fn free_rpc_port() -> u16 {
+    loop {
+        let rpc_port = TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0))
+            .and_then(|listener| listener.local_addr())
+            .expect("cannot bind a free port")
+            .port();
+        if rpc_port < u16::MAX && TcpListener::bind((Ipv4Addr::UNSPECIFIED, rpc_port + 1)).is_ok() {
+            return rpc_port;
+        }
+    }
+}
+
+/// Genesis of a validator with the Echo Program deployed at `program_id`.
+///
+/// Every validator gets its own RPC ports and temporary ledger directory, so tests can boot them in parallel.
+pub fn validator_genesis(program_id: Pubkey) -> EchoGenesis {
+    let ledger = TempDir::new().expect("cannot create the ledger directory");
+
+    let mut genesis = TestValidatorGenesis::default();
+    genesis
+        .add_program("echo", program_id)
+        .rpc_port(free_rpc_port())
+        .ledger_path(ledger.path());
+    EchoGenesis {
+        genesis,
+        ledger: Arc::new(ledger),
+    }
+}
+
+/// Starts a validator with the Echo Program deployed at `program_id`, see [`validator_genesis`].
+pub fn start_validator(program_id: Pubkey) -> (EchoValidator, Keypair) {
+    validator_genesis(program_id).start()
+}
+
+/// Sends `instructions` in a transaction paid by `payer` and signed by `signers` as well, and waits for its
+/// confirmation.
+pub fn send_instructions(
+    rpc_client: &RpcClient,
+    payer: &Keypair,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<Signature, ClientError> {
+    let mut keypairs = vec![payer];
+    keypairs.extend_from_slice(signers);
+    let blockhash = rpc_client.get_latest_blockhash()?;
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&payer.pubkey()),
+        &keypairs,
+        blockhash,
+    );
+    rpc_client.send_and_confirm_transaction(&transaction)
+}
+
+pub fn initialize_authorized_echo_ix(
+    program_id: Pubkey,
+    pda: Pubkey,
+    authority: Pubkey,
+    authority_is_signer: bool,
+    buffer_seed: u64,
+    buffer_size: usize,
+    namespace: Option<[u8; 8]>,
+) -> anyhow::Result<Instruction> {
+    Ok(Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pda, false),
+            AccountMeta::new(authority, authority_is_signer),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: EchoInstruction::InitializeAuthorizedEcho {
+            buffer_seed,
+            buffer_size,
+            namespace: Trailing(Some(namespace)),
+            write_deadline_slot: Trailing(None),
+        }
+        .try_to_vec()?,
+    })
+}
+
+/// Creates a vending machine mint, funds a token account owned by `payer` with `amount` tokens and
+/// initializes the vending machine buffer for `price`. Returns `(pda, mint, user_token_account)`.
+pub fn setup_vending_machine(
+    rpc_client: &RpcClient,
+    payer: &Keypair,
+    program_id: Pubkey,
+    price: u64,
+    amount: u64,
+    freeze: bool,
+) -> anyhow::Result<(Pubkey, Pubkey, Pubkey)> {
+    let vending_machine_mint = Keypair::new();
+    let user_token_account = Keypair::new();
+    let (pda, _) = Pubkey::find_program_address(
+        &[
+            b"vending_machine",
+            vending_machine_mint.pubkey().as_ref(),
+            &price.to_le_bytes(),
+        ],
+        &program_id,
+    );
+
+    let mut instructions = vec![
+        system_instruction::create_account(
+            &payer.pubkey(),
+            &vending_machine_mint.pubkey(),
+            rpc_client.get_minimum_balance_for_rent_exemption(spl_token::state::Mint::LEN)?,
+            spl_token::state::Mint::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_mint(
+            &spl_token::id(),
+            &vending_machine_mint.pubkey(),
+            &payer.pubkey(),
+            Some(&payer.pubkey()),
+            spl_token::native_mint::DECIMALS,
+        )?,
+        system_instruction::create_account(
+            &payer.pubkey(),
+            &user_token_account.pubkey(),
+            rpc_client.get_minimum_balance_for_rent_exemption(spl_token::state::Account::LEN)?,
+            spl_token::state::Account::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_account(
+            &spl_token::id(),
+            &user_token_account.pubkey(),
+            &vending_machine_mint.pubkey(),
+            &payer.pubkey(),
+        )?,
+        spl_token::instruction::mint_to(
+            &spl_token::id(),
+            &vending_machine_mint.pubkey(),
+            &user_token_account.pubkey(),
+            &payer.pubkey(),
+            &[&payer.pubkey()],
+            amount,
+        )?,
+    ];
+    if freeze {
+        instructions.push(spl_token::instruction::freeze_account(
+            &spl_token::id(),
+            &user_token_account.pubkey(),
+            &vending_machine_mint.pubkey(),
+            &payer.pubkey(),
+            &[&payer.pubkey()],
+        )?);
+    }
+    instructions.push(Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pda, false),
+            AccountMeta::new_readonly(vending_machine_mint.pubkey(), false),
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: EchoInstruction::InitializeVendingMachineEcho {
+            price,
+            buffer_size: VendingMachineBufferHeader::HEADER_LEN + b"vending machine".len(),
+            metadata: None,
+            machine_seed: Trailing(None),
+        }
+        .try_to_vec()?,
+    });
+
+    send_instructions(
+        rpc_client,
+        payer,
+        &instructions,
+        &[&vending_machine_mint, &user_token_account],
+    )?;
+    Ok((
+        pda,
+        vending_machine_mint.pubkey(),
+        user_token_account.pubkey(),
+    ))
+}
+
+pub fn vending_machine_echo_ix(
+    program_id: Pubkey,
+    pda: Pubkey,
+    user: Pubkey,
+    user_token_account: Pubkey,
+    vending_machine_mint: Pubkey,
+    token_program: Pubkey,
+) -> anyhow::Result<Instruction> {
+    Ok(Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pda, false),
+            AccountMeta::new_readonly(user, true),
+            AccountMeta::new(user_token_account, false),
+            AccountMeta::new(vending_machine_mint, false),
+            AccountMeta::new_readonly(token_program, false),
+            // No session
+            AccountMeta::new_readonly(program_id, false),
+        ],
+        data: EchoInstruction::VendingMachineEcho {
+            data: b"vending machine".to_vec(),
+            machine_seed: Trailing(None),
+        }
+        .try_to_vec()?,
+    })
+}
+
+pub fn assert_custom_error(e: ClientError, code: u32) {
+    println!("{:?}", e);
+    assert_matches!(
+        e.get_transaction_error(),
+        Some(TransactionError::InstructionError(_, InstructionError::Custom(c))) if c == code
+    );
+}
+
+pub fn assert_echo_error(e: ClientError, err: EchoError) {
+    assert_custom_error(e, err as u32);
+}
@@ -1,41 +1,79 @@
 // #![cfg(feature = "test-bpf")]
 use anyhow::anyhow;
-use echo::state::{AuthorizedBufferHeader, VendingMachineBufferHeader};
+use echo::bridge::{
+    build_payload, post_message, wormhole_program, ConsistencyLevel, PAYLOAD_ID_ECHO,
+};
+use echo::checksum::{
+    chunk_checksums, chunk_count, covering_range, crc32, verify_chunks, CHUNK_LEN,
+};
+use echo::client::wrapped_sol_vending_machine_echo;
+use echo::error::EchoError;
+use echo::leaderboard::{record_burn, LeaderboardEntry, LEADERBOARD_LEN};
+use echo::pda::{
+    find_aggregator_address, find_authorized_buffer_address, find_buffer_companion_addresses,
+    find_buffer_metadata_address, find_chunk_checksums_address, find_dead_man_switch_address,
+    find_dutch_auction_address, find_emitter_address, find_feed_address, find_global_stats_address,
+    find_holding_gate_address, find_holding_registration_address, find_idempotency_record_address,
+    find_immutable_address, find_leaderboard_address, find_org_address,
+    find_payload_pointer_address, find_project_address, find_receipt_mint_address,
+    find_recovery_address, find_reference_address, find_rent_vault_address,
+    find_rotation_log_address, find_schema_address, find_session_address, find_time_series_address,
+    find_vending_machine_address, find_vending_machine_companion_addresses,
+    find_writer_marker_address,
+};
+use echo::pricing::{dutch_auction_price, purchase_price, simulate, PricingCurve, Simulation};
+use echo::program_ids::{devnet, localnet, mainnet_beta, Cluster};
+use echo::state::{
+    checked_account_len, is_valid_cid, AggregatorHeader, AuthorityRotationLog,
+    AuthorizedBufferHeader, BufferMetadata, BufferMetadataHeader, BufferReference, BufferSchema,
+    ChunkChecksums, DeadManSwitchHeader, DutchAuction, FeedHeader, GlobalStats,
+    HoldingRegistration, IdempotencyRecord, ImmutableBufferHeader, Leaderboard,
+    LegacyAuthorizedBufferHeader, OrgHeader, PayloadPointer, PayloadPointerHeader, ProjectHeader,
+    RecoveryHeader, Reserved, SessionHeader, TimeSeriesHeader, Trailing,
+    VendingMachineBufferHeader, VendingMachineBufferHeaderV1, VendingMachineMetadata, WriterMarker,
+    WriterSlot, CURRENT_VERSION, FEATURE_APPEND_ALLOWED, FEATURE_CPI_GUARD, FEATURE_IMMUTABLE,
+    FEATURE_LOG_WRITES, FEATURE_UTF8, MAX_BUFFER_TAGS, RESERVED_LEN, SECONDS_PER_DAY,
+    VENDING_MACHINE_VERSION,
+};
 // use solana_sdk::transaction::Transaction;
 // use std::path::{Path, PathBuf};
 
 use assert_matches::*;
 use borsh::{BorshDeserialize, BorshSerialize};
-use solana_client::client_error::{ClientError/*, ClientErrorKind*/};
-// use solana_client::rpc_client::RpcClient;
-use solana_sdk::account::ReadableAccount;
+use solana_client::client_error::{ClientError /*, ClientErrorKind*/};
+use solana_sdk::account::{AccountSharedData, ReadableAccount};
 use solana_sdk::instruction::AccountMeta;
 use solana_sdk::instruction::Instruction;
 // use solana_sdk::message::Message;
-// use solana_sdk::program_error::ProgramError;
+use solana_sdk::program_error::ProgramError;
 use solana_sdk::program_pack::Pack;
 use solana_sdk::pubkey::Pubkey;
 // use solana_sdk::rent::Rent;
 use solana_sdk::signature::Keypair;
 use solana_sdk::system_instruction;
 use solana_sdk::system_program;
+use solana_sdk::transaction::TransactionError;
 // use solana_sdk::sysvar;
 use solana_sdk::{signature::Signer, transaction::Transaction};
-use solana_validator::test_validator::*;
 // use spl_token::instruction::initialize_mint;
 
-use echo::instruction::EchoInstruction;
+use echo::instruction::{EchoInstruction, MAX_INSTRUCTION_DATA_LEN};
+use echo::processor::Processor;
+
+mod common;
+use common::{
+    assert_custom_error, assert_echo_error, initialize_authorized_echo_ix, send_instructions,
+    setup_vending_machine, start_validator, validator_genesis, vending_machine_echo_ix,
+};
 
 #[test]
 fn test_echo() -> anyhow::Result<()> {
     solana_logger::setup_with_default("solana_program_runtime=debug");
-    let program_id = Pubkey::new_unique();
+    let program_id = localnet::id();
     let echo_buffer = Keypair::new();
 
     // Set up the test validator
-    let (test_validator, payer) = TestValidatorGenesis::default()
-        .add_program("echo", program_id)
-        .start();
+    let (test_validator, payer) = start_validator(program_id);
     let rpc_client = test_validator.get_rpc_client();
 
     // let rpc_client = RpcClient::new_with_commitment("https://api.devnet.solana.com".to_string(), CommitmentLevel::confirmed());
@@ -84,13 +122,11 @@ fn test_echo() -> anyhow::Result<()> {
 #[test]
 fn test_echo_uninitialized() -> anyhow::Result<()> {
     solana_logger::setup_with_default("solana_program_runtime=debug");
-    let program_id = Pubkey::new_unique();
+    let program_id = localnet::id();
     let echo_buffer = Keypair::new();
 
     // Set up the test validator
-    let (test_validator, payer) = TestValidatorGenesis::default()
-        .add_program("echo", program_id)
-        .start();
+    let (test_validator, payer) = start_validator(program_id);
     let rpc_client = test_validator.get_rpc_client();
 
     // let rpc_client = RpcClient::new_with_commitment("https://api.devnet.solana.com".to_string(), CommitmentLevel::confirmed());
@@ -124,15 +160,36 @@ fn test_echo_uninitialized() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_instruction_data_length() {
+    let program_id = localnet::id();
+    assert_eq!(
+        Processor::process_instruction(&program_id, &[], &[]),
+        Err(EchoError::InstructionDataTooShort.into())
+    );
+    assert_eq!(
+        Processor::process_instruction(&program_id, &[], &vec![0; MAX_INSTRUCTION_DATA_LEN + 1]),
+        Err(EchoError::InstructionDataTooLong.into())
+    );
+
+    // Within bounds, malformed data is left to the decoder
+    assert_eq!(
+        Processor::process_instruction(&program_id, &[], &[0]),
+        Err(ProgramError::InvalidInstructionData)
+    );
+    assert_eq!(
+        Processor::process_instruction(&program_id, &[], &vec![0; MAX_INSTRUCTION_DATA_LEN]),
+        Err(ProgramError::InvalidInstructionData)
+    );
+}
+
 #[test]
 fn test_echo_nonzero() -> anyhow::Result<()> {
     solana_logger::setup_with_default("solana_program_runtime=debug");
-    let program_id = Pubkey::new_unique();
+    let program_id = localnet::id();
     let echo_buffer = Keypair::new();
 
-    let (test_validator, payer) = TestValidatorGenesis::default()
-        .add_program("echo", program_id)
-        .start();
+    let (test_validator, payer) = start_validator(program_id);
     let rpc_client = test_validator.get_rpc_client();
 
     // let rpc_client = RpcClient::new_with_commitment("https://api.devnet.solana.com".to_string(), CommitmentLevel::confirmed());
@@ -180,27 +237,19 @@ fn test_echo_nonzero() -> anyhow::Result<()> {
 #[test]
 fn test_authorized_echo() -> anyhow::Result<()> {
     solana_logger::setup_with_default("solana_program_runtime=debug");
-    let program_id = Pubkey::new_unique();
+    let program_id = localnet::id();
 
-    let (test_validator, payer) = TestValidatorGenesis::default()
-        .add_program("echo", program_id)
-        .start();
+    let (test_validator, payer) = start_validator(program_id);
     let rpc_client = test_validator.get_rpc_client();
 
     let buffer_seed = 1u64;
-    let (pda, _) = Pubkey::find_program_address(
-        &[
-            b"authority",
-            payer.pubkey().as_ref(),
-            &buffer_seed.to_le_bytes(),
-        ],
-        &program_id,
-    );
+    let (pda, _) = find_authorized_buffer_address(&program_id, &payer.pubkey(), buffer_seed, None);
 
     let data = b"authorized".to_vec();
 
-    let blockhash = rpc_client.get_latest_blockhash()?;
-    let mut transaction = Transaction::new_signed_with_payer(
+    send_instructions(
+        &rpc_client,
+        &payer,
         &[Instruction {
             program_id,
             accounts: vec![
@@ -210,20 +259,19 @@ fn test_authorized_echo() -> anyhow::Result<()> {
             ],
             data: EchoInstruction::InitializeAuthorizedEcho {
                 buffer_seed,
-                buffer_size: 13 + data.len(),
+                buffer_size: AuthorizedBufferHeader::header_len(&None) + data.len(),
+                namespace: Trailing(None),
+                write_deadline_slot: Trailing(None),
             }
             .try_to_vec()?,
         }],
-        Some(&payer.pubkey()),
-        &vec![&payer],
-        blockhash,
-    );
-    transaction.sign(&[&payer], blockhash);
-    rpc_client.send_and_confirm_transaction(&transaction)?;
+        &[],
+    )?;
     // let account = rpc_client.get_account(&pda)?;
 
-    let blockhash = rpc_client.get_latest_blockhash()?;
-    let mut transaction = Transaction::new_signed_with_payer(
+    send_instructions(
+        &rpc_client,
+        &payer,
         &[Instruction {
             program_id,
             accounts: vec![
@@ -232,12 +280,8 @@ fn test_authorized_echo() -> anyhow::Result<()> {
             ],
             data: EchoInstruction::AuthorizedEcho { data }.try_to_vec()?,
         }],
-        Some(&payer.pubkey()),
-        &vec![&payer],
-        blockhash,
-    );
-    transaction.sign(&[&payer], blockhash);
-    rpc_client.send_and_confirm_transaction(&transaction)?;
+        &[],
+    )?;
     let echo_data = rpc_client.get_account(&pda)?.data;
     let echo_buffer = AuthorizedBufferHeader::try_from_slice(&echo_data)?.echo_data;
     let string = std::str::from_utf8(&echo_buffer)?;
@@ -248,13 +292,11 @@ fn test_authorized_echo() -> anyhow::Result<()> {
 #[test]
 fn test_vending_machine() -> anyhow::Result<()> {
     solana_logger::setup_with("solana_runtime::message_processor=debug");
-    let program_id = Pubkey::new_unique();
+    let program_id = localnet::id();
     let vending_machine_mint = Keypair::new();
     let user_token_account = Keypair::new();
 
-    let (test_validator, payer) = TestValidatorGenesis::default()
-        .add_program("echo", program_id)
-        .start();
+    let (test_validator, payer) = start_validator(program_id);
     let rpc_client = test_validator.get_rpc_client();
 
     let price = 42u64;
@@ -267,8 +309,9 @@ fn test_vending_machine() -> anyhow::Result<()> {
         &program_id,
     );
 
-    let blockhash = rpc_client.get_latest_blockhash()?;
-    let mut transaction = Transaction::new_signed_with_payer(
+    send_instructions(
+        &rpc_client,
+        &payer,
         &[
             system_instruction::create_account(
                 &payer.pubkey(),
@@ -316,20 +359,15 @@ fn test_vending_machine() -> anyhow::Result<()> {
                 ],
                 data: EchoInstruction::InitializeVendingMachineEcho {
                     price,
-                    buffer_size: b"vending_machine".len() + 4 + 9,
+                    buffer_size: VendingMachineBufferHeader::HEADER_LEN + b"vending_machine".len(),
+                    metadata: None,
+                    machine_seed: Trailing(None),
                 }
                 .try_to_vec()?,
             },
         ],
-        Some(&payer.pubkey()),
-        &vec![&payer, &vending_machine_mint, &user_token_account],
-        blockhash,
-    );
-    transaction.sign(
-        &[&payer, &vending_machine_mint, &user_token_account],
-        blockhash,
-    );
-    rpc_client.send_and_confirm_transaction(&transaction)?;
+        &[&vending_machine_mint, &user_token_account],
+    )?;
     let ta_initial_amount = spl_token::state::Account::unpack(
         rpc_client.get_account(&user_token_account.pubkey())?.data(),
     )?
@@ -338,8 +376,9 @@ fn test_vending_machine() -> anyhow::Result<()> {
     let vending_machine_buffer = rpc_client.get_account(&pda)?;
     println!("{:?}", vending_machine_buffer.data);
 
-    let blockhash = rpc_client.get_latest_blockhash()?;
-    let mut transaction = Transaction::new_signed_with_payer(
+    send_instructions(
+        &rpc_client,
+        &payer,
         &[Instruction {
             program_id,
             accounts: vec![
@@ -351,15 +390,12 @@ fn test_vending_machine() -> anyhow::Result<()> {
             ],
             data: EchoInstruction::VendingMachineEcho {
                 data: b"vending machine".to_vec(),
+                machine_seed: Trailing(None),
             }
             .try_to_vec()?,
         }],
-        Some(&payer.pubkey()),
-        &vec![&payer],
-        blockhash,
-    );
-    transaction.sign(&[&payer], blockhash);
-    rpc_client.send_and_confirm_transaction(&transaction)?;
+        &[],
+    )?;
     let ta_final_amount = spl_token::state::Account::unpack(
         rpc_client.get_account(&user_token_account.pubkey())?.data(),
     )?
@@ -371,4 +407,5271 @@ fn test_vending_machine() -> anyhow::Result<()> {
     assert_matches!(string, "vending machine");
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_echo_wrong_owner() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let echo_buffer = Keypair::new();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let data: Vec<u8> = b"echo".to_vec();
+    let e = send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            // Buffer is owned by the system program, so the echo program may not write to it
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &echo_buffer.pubkey(),
+                rpc_client.get_minimum_balance_for_rent_exemption(data.len())?,
+                data.len() as u64,
+                &system_program::id(),
+            ),
+            Instruction {
+                program_id,
+                accounts: vec![AccountMeta::new(echo_buffer.pubkey(), false)],
+                data: EchoInstruction::Echo { data }.try_to_vec()?,
+            },
+        ],
+        &[&echo_buffer],
+    )
+    .unwrap_err();
+    println!("{:?}", e);
+    assert_matches!(
+        e.get_transaction_error(),
+        Some(TransactionError::InstructionError(1, _))
+    );
+    Ok(())
+}
+
+#[test]
+fn test_initialize_authorized_echo_wrong_pda() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    // PDA is derived from a different buffer_seed than the one in the instruction
+    let (pda, _) = find_authorized_buffer_address(&program_id, &payer.pubkey(), 2u64, None);
+
+    let e = send_instructions(
+        &rpc_client,
+        &payer,
+        &[initialize_authorized_echo_ix(
+            program_id,
+            pda,
+            payer.pubkey(),
+            true,
+            1,
+            32,
+            None,
+        )?],
+        &[],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::InvalidAuthorizedBuffer);
+    Ok(())
+}
+
+#[test]
+fn test_initialize_authorized_echo_authority_not_signer() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let authority = Keypair::new();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let (pda, _) =
+        find_authorized_buffer_address(&program_id, &authority.pubkey(), buffer_seed, None);
+
+    let e = send_instructions(
+        &rpc_client,
+        &payer,
+        &[initialize_authorized_echo_ix(
+            program_id,
+            pda,
+            authority.pubkey(),
+            false,
+            buffer_seed,
+            32,
+            None,
+        )?],
+        &[],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::AuthorityNotSigner);
+    Ok(())
+}
+
+#[test]
+fn test_initialize_authorized_echo_undersized_buffer() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let (pda, _) = find_authorized_buffer_address(&program_id, &payer.pubkey(), buffer_seed, None);
+
+    // Smaller than the bump_seed + buffer_seed + echo_data length prefix
+    let e = send_instructions(
+        &rpc_client,
+        &payer,
+        &[initialize_authorized_echo_ix(
+            program_id,
+            pda,
+            payer.pubkey(),
+            true,
+            buffer_seed,
+            8,
+            None,
+        )?],
+        &[],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::ArithmeticOverflow);
+    assert!(rpc_client.get_account(&pda).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_initialize_authorized_echo_seed_reuse() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let (pda, _) = find_authorized_buffer_address(&program_id, &payer.pubkey(), buffer_seed, None);
+    let initialize = |buffer_size: usize| -> Result<(), ClientError> {
+        send_instructions(
+            &rpc_client,
+            &payer,
+            &[initialize_authorized_echo_ix(
+                program_id,
+                pda,
+                payer.pubkey(),
+                true,
+                buffer_seed,
+                buffer_size,
+                None,
+            )
+            .unwrap()],
+            &[],
+        )?;
+        Ok(())
+    };
+
+    initialize(AuthorizedBufferHeader::header_len(&None) + 8)?;
+    let e = initialize(AuthorizedBufferHeader::header_len(&None) + 16).unwrap_err();
+    assert_echo_error(e, EchoError::BufferAlreadyExists);
+    assert_eq!(
+        rpc_client.get_account_data(&pda)?.len(),
+        AuthorizedBufferHeader::header_len(&None) + 8
+    );
+    Ok(())
+}
+
+#[test]
+fn test_authorized_echo_authority_not_signer() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let authority = Keypair::new();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let (pda, _) =
+        find_authorized_buffer_address(&program_id, &authority.pubkey(), buffer_seed, None);
+    let data = b"authorized".to_vec();
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            system_instruction::transfer(&payer.pubkey(), &authority.pubkey(), 1_000_000_000),
+            initialize_authorized_echo_ix(
+                program_id,
+                pda,
+                authority.pubkey(),
+                true,
+                buffer_seed,
+                AuthorizedBufferHeader::header_len(&None) + data.len(),
+                None,
+            )?,
+        ],
+        &[&authority],
+    )?;
+
+    let e = send_instructions(
+        &rpc_client,
+        &payer,
+        &[Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new_readonly(authority.pubkey(), false),
+            ],
+            data: EchoInstruction::AuthorizedEcho { data }.try_to_vec()?,
+        }],
+        &[],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::AuthorityNotSigner);
+    Ok(())
+}
+
+#[test]
+fn test_authorized_echo_wrong_authority() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let authority = Keypair::new();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let (pda, _) =
+        find_authorized_buffer_address(&program_id, &authority.pubkey(), buffer_seed, None);
+    let data = b"authorized".to_vec();
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            system_instruction::transfer(&payer.pubkey(), &authority.pubkey(), 1_000_000_000),
+            initialize_authorized_echo_ix(
+                program_id,
+                pda,
+                authority.pubkey(),
+                true,
+                buffer_seed,
+                AuthorizedBufferHeader::header_len(&None) + data.len(),
+                None,
+            )?,
+        ],
+        &[&authority],
+    )?;
+
+    // payer signs, but is not the authority the buffer was derived from
+    let result = send_instructions(
+        &rpc_client,
+        &payer,
+        &[Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+            ],
+            data: EchoInstruction::AuthorizedEcho { data }.try_to_vec()?,
+        }],
+        &[],
+    );
+    assert!(result.is_err());
+
+    let echo_data = rpc_client.get_account(&pda)?.data;
+    let echo_buffer = AuthorizedBufferHeader::try_from_slice(&echo_data)?.echo_data;
+    assert!(echo_buffer.iter().all(|&b| b == 0));
+    Ok(())
+}
+
+#[test]
+fn test_initialize_vending_machine_wrong_pda() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let vending_machine_mint = Pubkey::new_unique();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    // PDA is derived from a different price than the one in the instruction
+    let (pda, _) = Pubkey::find_program_address(
+        &[
+            b"vending_machine",
+            vending_machine_mint.as_ref(),
+            &43u64.to_le_bytes(),
+        ],
+        &program_id,
+    );
+
+    let e = send_instructions(
+        &rpc_client,
+        &payer,
+        &[Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new_readonly(vending_machine_mint, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+            data: EchoInstruction::InitializeVendingMachineEcho {
+                price: 42,
+                buffer_size: 32,
+                metadata: None,
+                machine_seed: Trailing(None),
+            }
+            .try_to_vec()?,
+        }],
+        &[],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::InvalidVendingMachinePda);
+    Ok(())
+}
+
+#[test]
+fn test_vending_machine_machine_seed() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let price = 42u64;
+    let (pda, mint, user_token_account) =
+        setup_vending_machine(&rpc_client, &payer, program_id, price, price, false)?;
+
+    // Second machine for the same mint and price
+    let machine_seed = 7u64;
+    let (seeded_pda, _) =
+        find_vending_machine_address(&program_id, &mint, price, Some(machine_seed));
+    assert_ne!(seeded_pda, pda);
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(seeded_pda, false),
+                AccountMeta::new_readonly(mint, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+            data: EchoInstruction::InitializeVendingMachineEcho {
+                price,
+                buffer_size: VendingMachineBufferHeader::HEADER_LEN + b"vending machine".len(),
+                metadata: None,
+                machine_seed: Trailing(Some(machine_seed)),
+            }
+            .try_to_vec()?,
+        }],
+        &[],
+    )?;
+
+    // The seeded machine is not found without its seed
+    let mut ix = vending_machine_echo_ix(
+        program_id,
+        seeded_pda,
+        payer.pubkey(),
+        user_token_account,
+        mint,
+        spl_token::id(),
+    )?;
+    let e = send_instructions(&rpc_client, &payer, &[ix.clone()], &[]).unwrap_err();
+    assert_echo_error(e, EchoError::InvalidVendingMachinePda);
+
+    ix.data = EchoInstruction::VendingMachineEcho {
+        data: b"vending machine".to_vec(),
+        machine_seed: Trailing(Some(machine_seed)),
+    }
+    .try_to_vec()?;
+    send_instructions(&rpc_client, &payer, &[ix], &[])?;
+
+    let seeded =
+        VendingMachineBufferHeader::try_from_slice(&rpc_client.get_account_data(&seeded_pda)?)?;
+    assert_eq!(seeded.echo_data, b"vending machine");
+    let unseeded = VendingMachineBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert_eq!(unseeded.echo_data, vec![0; b"vending machine".len()]);
+    Ok(())
+}
+
+#[test]
+fn test_vending_machine_insufficient_funds() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let price = 42u64;
+    let (pda, mint, user_token_account) =
+        setup_vending_machine(&rpc_client, &payer, program_id, price, price - 1, false)?;
+
+    let e = send_instructions(
+        &rpc_client,
+        &payer,
+        &[vending_machine_echo_ix(
+            program_id,
+            pda,
+            payer.pubkey(),
+            user_token_account,
+            mint,
+            spl_token::id(),
+        )?],
+        &[],
+    )
+    .unwrap_err();
+    assert_custom_error(e, spl_token::error::TokenError::InsufficientFunds as u32);
+    Ok(())
+}
+
+#[test]
+fn test_vending_machine_frozen_token_account() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let price = 42u64;
+    let (pda, mint, user_token_account) =
+        setup_vending_machine(&rpc_client, &payer, program_id, price, price, true)?;
+
+    let e = send_instructions(
+        &rpc_client,
+        &payer,
+        &[vending_machine_echo_ix(
+            program_id,
+            pda,
+            payer.pubkey(),
+            user_token_account,
+            mint,
+            spl_token::id(),
+        )?],
+        &[],
+    )
+    .unwrap_err();
+    assert_custom_error(e, spl_token::error::TokenError::AccountFrozen as u32);
+    Ok(())
+}
+
+#[test]
+fn test_vending_machine_wrong_token_program() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let price = 42u64;
+    let (pda, mint, user_token_account) =
+        setup_vending_machine(&rpc_client, &payer, program_id, price, price, false)?;
+
+    let result = send_instructions(
+        &rpc_client,
+        &payer,
+        &[vending_machine_echo_ix(
+            program_id,
+            pda,
+            payer.pubkey(),
+            user_token_account,
+            mint,
+            Pubkey::new_unique(),
+        )?],
+        &[],
+    );
+    assert!(result.is_err());
+
+    let ta_amount =
+        spl_token::state::Account::unpack(rpc_client.get_account(&user_token_account)?.data())?
+            .amount;
+    assert_eq!(ta_amount, price);
+    Ok(())
+}
+
+#[test]
+fn test_authorized_echo_namespaces() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    // Same authority and buffer_seed, different namespaces
+    let buffer_seed = 1u64;
+    let namespace_a = Some(*b"app_a\0\0\0");
+    let namespace_b = Some(*b"app_b\0\0\0");
+    let (pda_a, _) =
+        find_authorized_buffer_address(&program_id, &payer.pubkey(), buffer_seed, namespace_a);
+    let (pda_b, _) =
+        find_authorized_buffer_address(&program_id, &payer.pubkey(), buffer_seed, namespace_b);
+    assert_ne!(pda_a, pda_b);
+
+    let data_a = b"from app a".to_vec();
+    let data_b = b"from app b".to_vec();
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            initialize_authorized_echo_ix(
+                program_id,
+                pda_a,
+                payer.pubkey(),
+                true,
+                buffer_seed,
+                AuthorizedBufferHeader::header_len(&namespace_a) + data_a.len(),
+                namespace_a,
+            )?,
+            initialize_authorized_echo_ix(
+                program_id,
+                pda_b,
+                payer.pubkey(),
+                true,
+                buffer_seed,
+                AuthorizedBufferHeader::header_len(&namespace_b) + data_b.len(),
+                namespace_b,
+            )?,
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pda_a, false),
+                    AccountMeta::new_readonly(payer.pubkey(), true),
+                ],
+                data: EchoInstruction::AuthorizedEcho { data: data_a }.try_to_vec()?,
+            },
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pda_b, false),
+                    AccountMeta::new_readonly(payer.pubkey(), true),
+                ],
+                data: EchoInstruction::AuthorizedEcho { data: data_b }.try_to_vec()?,
+            },
+        ],
+        &[],
+    )?;
+
+    let header_a = AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account(&pda_a)?.data)?;
+    let header_b = AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account(&pda_b)?.data)?;
+    assert_eq!(header_a.namespace, namespace_a);
+    assert_eq!(header_b.namespace, namespace_b);
+    assert_matches!(std::str::from_utf8(&header_a.echo_data)?, "from app a");
+    assert_matches!(std::str::from_utf8(&header_b.echo_data)?, "from app b");
+    Ok(())
+}
+
+#[test]
+fn test_migrate_authorized_buffer() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let authority = Keypair::new();
+
+    // Seed a buffer in the pre-version layout directly into genesis: bump_seed, buffer_seed, then the echo data
+    // with its length prefix
+    let buffer_seed = 1u64;
+    let (pda, bump_seed) =
+        find_authorized_buffer_address(&program_id, &authority.pubkey(), buffer_seed, None);
+    let mut legacy_data = vec![bump_seed];
+    legacy_data.extend_from_slice(&buffer_seed.to_le_bytes());
+    legacy_data.extend_from_slice(&6u32.to_le_bytes());
+    legacy_data.extend_from_slice(b"legacy");
+    let mut legacy_account = AccountSharedData::new(1_000_000_000, legacy_data.len(), &program_id);
+    legacy_account.set_data(legacy_data);
+
+    let (test_validator, payer) = validator_genesis(program_id)
+        .add_account(pda, legacy_account)
+        .start();
+    let rpc_client = test_validator.get_rpc_client();
+
+    let authorized_echo_ix = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pda, false),
+            AccountMeta::new_readonly(authority.pubkey(), true),
+        ],
+        data: EchoInstruction::AuthorizedEcho {
+            data: b"latest".to_vec(),
+        }
+        .try_to_vec()?,
+    };
+
+    // Writes are refused until the buffer is migrated
+    assert!(send_instructions(
+        &rpc_client,
+        &payer,
+        &[authorized_echo_ix.clone()],
+        &[&authority]
+    )
+    .is_err());
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            system_instruction::transfer(&payer.pubkey(), &authority.pubkey(), 1_000_000_000),
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pda, false),
+                    AccountMeta::new(authority.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::MigrateAuthorizedBuffer.try_to_vec()?,
+            },
+            authorized_echo_ix,
+        ],
+        &[&authority],
+    )?;
+
+    let account = rpc_client.get_account(&pda)?;
+    assert_eq!(
+        account.data.len(),
+        AuthorizedBufferHeader::header_len(&None) + b"latest".len()
+    );
+    let header = AuthorizedBufferHeader::try_from_slice(&account.data)?;
+    assert_eq!(header.version, CURRENT_VERSION);
+    assert_eq!(header.buffer_seed, buffer_seed);
+    assert!(header._reserved.is_zero());
+    assert_matches!(std::str::from_utf8(&header.echo_data)?, "latest");
+    Ok(())
+}
+
+#[test]
+fn test_authorized_echo_with_schema() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let (pda, _) = find_authorized_buffer_address(&program_id, &payer.pubkey(), buffer_seed, None);
+    let (schema, _) = find_schema_address(&program_id, &pda);
+    let schema_hash = solana_sdk::hash::hash(b"struct Status { code: u8 }").to_bytes();
+    let echo_with_schema_ix = |schema_hash: [u8; 32]| -> anyhow::Result<Instruction> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+                AccountMeta::new_readonly(schema, false),
+            ],
+            data: EchoInstruction::AuthorizedEchoWithSchema {
+                schema_hash,
+                data: vec![7],
+            }
+            .try_to_vec()?,
+        })
+    };
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            initialize_authorized_echo_ix(
+                program_id,
+                pda,
+                payer.pubkey(),
+                true,
+                buffer_seed,
+                AuthorizedBufferHeader::header_len(&None) + 8,
+                None,
+            )?,
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new_readonly(pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new(schema, false),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::RegisterSchema { schema_hash }.try_to_vec()?,
+            },
+            echo_with_schema_ix(schema_hash)?,
+        ],
+        &[],
+    )?;
+
+    let header = AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account(&pda)?.data)?;
+    assert_eq!(header.echo_data, vec![7, 0, 0, 0, 0, 0, 0, 0]);
+    let registered = BufferSchema::try_from_slice(&rpc_client.get_account(&schema)?.data)?;
+    assert_eq!(registered.schema_hash, schema_hash);
+
+    let e =
+        send_instructions(&rpc_client, &payer, &[echo_with_schema_ix([1; 32])?], &[]).unwrap_err();
+    assert_echo_error(e, EchoError::SchemaMismatch);
+    Ok(())
+}
+
+#[test]
+fn test_time_series() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let (pda, _) = find_time_series_address(&program_id, &payer.pubkey(), buffer_seed);
+    let append_ix = |value: &[u8]| -> anyhow::Result<Instruction> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+            ],
+            data: EchoInstruction::AppendTimeSeries {
+                value: value.to_vec(),
+            }
+            .try_to_vec()?,
+        })
+    };
+
+    // Capacity of two records, so the first one gets overwritten
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::InitializeTimeSeries {
+                    buffer_seed,
+                    value_len: 4,
+                    capacity: 2,
+                }
+                .try_to_vec()?,
+            },
+            append_ix(&1u32.to_le_bytes())?,
+            append_ix(&2u32.to_le_bytes())?,
+            append_ix(&3u32.to_le_bytes())?,
+        ],
+        &[],
+    )?;
+
+    let account = rpc_client.get_account(&pda)?;
+    assert_eq!(
+        account.data.len(),
+        TimeSeriesHeader::HEADER_LEN + 2 * TimeSeriesHeader::record_len(4)
+    );
+    let time_series = TimeSeriesHeader::try_from_slice(&account.data)?;
+    let records = time_series.records();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].1, 2u32.to_le_bytes());
+    assert_eq!(records[1].1, 3u32.to_le_bytes());
+    assert!(records[0].0 <= records[1].0);
+    assert_eq!(time_series.last_timestamp, records[1].0);
+
+    let e = send_instructions(&rpc_client, &payer, &[append_ix(&[1, 2, 3])?], &[]).unwrap_err();
+    assert_echo_error(e, EchoError::InvalidValueLength);
+    Ok(())
+}
+
+#[test]
+fn test_feed() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let (pda, _) = find_feed_address(&program_id, &payer.pubkey(), buffer_seed);
+    let update_ix = |value: i128| -> anyhow::Result<Instruction> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+            ],
+            data: EchoInstruction::UpdateFeed { value }.try_to_vec()?,
+        })
+    };
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::InitializeFeed {
+                    buffer_seed,
+                    exponent: -2,
+                    min_value: -10_000,
+                    max_value: 10_000,
+                }
+                .try_to_vec()?,
+            },
+            update_ix(-1_234)?,
+        ],
+        &[],
+    )?;
+
+    let feed = FeedHeader::try_from_slice(&rpc_client.get_account(&pda)?.data)?;
+    assert_eq!(feed.value, -1_234);
+    assert_eq!(feed.format_value(), "-12.34");
+
+    let e = send_instructions(&rpc_client, &payer, &[update_ix(10_001)?], &[]).unwrap_err();
+    assert_echo_error(e, EchoError::FeedValueOutOfBounds);
+    Ok(())
+}
+
+#[test]
+fn test_feed_format_value() {
+    let feed = |value: i128, exponent: i32| FeedHeader {
+        bump_seed: 0,
+        buffer_seed: 0,
+        version: CURRENT_VERSION,
+        exponent,
+        min_value: i128::MIN,
+        max_value: i128::MAX,
+        value,
+        last_update_slot: 0,
+        _reserved: Reserved::default(),
+    };
+    assert_eq!(feed(0, 0).format_value(), "0");
+    assert_eq!(feed(12_345, -2).format_value(), "123.45");
+    assert_eq!(feed(-5, -3).format_value(), "-0.005");
+    assert_eq!(feed(7, 3).format_value(), "7000");
+    assert_eq!(
+        feed(i128::MIN, 0).format_value(),
+        "-170141183460469231731687303715884105728"
+    );
+}
+
+#[test]
+fn test_aggregator() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let writers = [Keypair::new(), Keypair::new(), Keypair::new()];
+    let outsider = Keypair::new();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let (pda, _) = find_aggregator_address(&program_id, &payer.pubkey(), buffer_seed);
+    let submit_ix = |writer: &Keypair, value: i128| -> anyhow::Result<Instruction> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new_readonly(writer.pubkey(), true),
+            ],
+            data: EchoInstruction::SubmitValue { value }.try_to_vec()?,
+        })
+    };
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::InitializeAggregator {
+                    buffer_seed,
+                    writers: writers.iter().map(|w| w.pubkey()).collect(),
+                    max_staleness_slots: 1_000,
+                    min_submissions: 2,
+                }
+                .try_to_vec()?,
+            },
+            submit_ix(&writers[0], 10)?,
+            submit_ix(&writers[1], 30)?,
+            submit_ix(&writers[2], 20)?,
+            Instruction {
+                program_id,
+                accounts: vec![AccountMeta::new(pda, false)],
+                data: EchoInstruction::Crank.try_to_vec()?,
+            },
+        ],
+        &[&writers[0], &writers[1], &writers[2]],
+    )?;
+
+    let aggregator = AggregatorHeader::try_from_slice(&rpc_client.get_account(&pda)?.data)?;
+    assert_eq!(aggregator.median, 20);
+    assert!(aggregator.median_slot > 0);
+
+    let e = send_instructions(
+        &rpc_client,
+        &payer,
+        &[submit_ix(&outsider, 1_000_000)?],
+        &[&outsider],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::WriterNotWhitelisted);
+    Ok(())
+}
+
+#[test]
+fn test_aggregator_fresh_median() {
+    let aggregator = |submissions: &[(i128, u64)]| AggregatorHeader {
+        bump_seed: 0,
+        buffer_seed: 0,
+        version: CURRENT_VERSION,
+        max_staleness_slots: 10,
+        min_submissions: 2,
+        median: 0,
+        median_slot: 0,
+        _reserved: Reserved::default(),
+        writers: submissions
+            .iter()
+            .map(|&(value, slot)| WriterSlot {
+                writer: Pubkey::new_unique(),
+                value,
+                slot,
+            })
+            .collect(),
+    };
+
+    assert_eq!(
+        aggregator(&[(3, 100), (1, 100), (2, 100)]).fresh_median(100),
+        Some(2)
+    );
+    assert_eq!(aggregator(&[(1, 100), (4, 100)]).fresh_median(105), Some(2));
+    assert_eq!(
+        aggregator(&[(-3, 100), (0, 100)]).fresh_median(100),
+        Some(-2)
+    );
+    assert_eq!(
+        aggregator(&[(i128::MAX, 100), (i128::MAX, 100)]).fresh_median(100),
+        Some(i128::MAX)
+    );
+    assert_eq!(
+        aggregator(&[(i128::MIN, 100), (i128::MAX, 100)]).fresh_median(100),
+        Some(-1)
+    );
+    // Stale and never-submitted slots are ignored
+    assert_eq!(
+        aggregator(&[(1, 100), (5, 89), (9, 0)]).fresh_median(100),
+        None
+    );
+    assert_eq!(
+        aggregator(&[(1, 100), (5, 90), (9, 0)]).fresh_median(100),
+        Some(3)
+    );
+}
+
+#[test]
+fn test_authorized_echo_write_deadline() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let authority = Keypair::new();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let (pda, _) =
+        find_authorized_buffer_address(&program_id, &authority.pubkey(), buffer_seed, None);
+    let write_deadline_slot = rpc_client.get_slot()? + 20;
+    let (chunk_checksums, _) = find_chunk_checksums_address(&program_id, &pda);
+    let authorized_echo_ix = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pda, false),
+            AccountMeta::new_readonly(authority.pubkey(), true),
+        ],
+        data: EchoInstruction::AuthorizedEcho {
+            data: b"bid".to_vec(),
+        }
+        .try_to_vec()?,
+    };
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            system_instruction::transfer(&payer.pubkey(), &authority.pubkey(), 1_000_000_000),
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pda, false),
+                    AccountMeta::new(authority.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::InitializeAuthorizedEcho {
+                    buffer_seed,
+                    buffer_size: AuthorizedBufferHeader::header_len(&None) + 3,
+                    namespace: Trailing(Some(None)),
+                    write_deadline_slot: Trailing(Some(Some(write_deadline_slot))),
+                }
+                .try_to_vec()?,
+            },
+            authorized_echo_ix.clone(),
+            // A companion of the buffer
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pda, false),
+                    AccountMeta::new(authority.pubkey(), true),
+                    AccountMeta::new(chunk_checksums, false),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::SetChunkChecksums { enabled: true }.try_to_vec()?,
+            },
+        ],
+        &[&authority],
+    )?;
+
+    // Closing is refused while the buffer is still writable
+    let mut close_accounts = vec![
+        AccountMeta::new(pda, false),
+        AccountMeta::new(authority.pubkey(), false),
+    ];
+    close_accounts.extend(
+        find_buffer_companion_addresses(&program_id, &pda)
+            .iter()
+            .map(|companion| AccountMeta::new(*companion, false)),
+    );
+    let close_ix = Instruction {
+        program_id,
+        accounts: close_accounts,
+        data: EchoInstruction::CloseExpiredBuffer.try_to_vec()?,
+    };
+    let e = send_instructions(&rpc_client, &payer, &[close_ix.clone()], &[]).unwrap_err();
+    assert_echo_error(e, EchoError::WriteDeadlineNotReached);
+
+    while rpc_client.get_slot()? <= write_deadline_slot {
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+
+    let e =
+        send_instructions(&rpc_client, &payer, &[authorized_echo_ix], &[&authority]).unwrap_err();
+    assert_echo_error(e, EchoError::WriteDeadlinePassed);
+
+    // Lamports sent to the expired buffer can still be swept
+    let buffer_lamports = rpc_client.get_balance(&pda)?;
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            system_instruction::transfer(&payer.pubkey(), &pda, 5_000),
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pda, false),
+                    AccountMeta::new(authority.pubkey(), true),
+                ],
+                data: EchoInstruction::SweepExcessLamports.try_to_vec()?,
+            },
+        ],
+        &[&authority],
+    )?;
+    assert_eq!(rpc_client.get_balance(&pda)?, buffer_lamports);
+
+    // Anyone can close the expired buffer, the rent goes back to the authority along with that of its companions
+    let buffer_lamports =
+        rpc_client.get_balance(&pda)? + rpc_client.get_balance(&chunk_checksums)?;
+    let authority_lamports = rpc_client.get_balance(&authority.pubkey())?;
+    send_instructions(&rpc_client, &payer, &[close_ix], &[])?;
+    assert!(rpc_client.get_account(&pda).is_err());
+    assert!(rpc_client.get_account(&chunk_checksums).is_err());
+    assert_eq!(
+        rpc_client.get_balance(&authority.pubkey())?,
+        authority_lamports + buffer_lamports
+    );
+    Ok(())
+}
+
+#[test]
+fn test_vending_machine_session() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let kiosk = Keypair::new();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let price = 10u64;
+    let (pda, mint, user_token_account) =
+        setup_vending_machine(&rpc_client, &payer, program_id, price, 100, false)?;
+    let (session, _) = find_session_address(&program_id, &pda, &user_token_account);
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(session, false),
+                AccountMeta::new_readonly(pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new(user_token_account, false),
+                AccountMeta::new_readonly(kiosk.pubkey(), false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+            data: EchoInstruction::OpenSession { max_total_burn: 25 }.try_to_vec()?,
+        }],
+        &[],
+    )?;
+
+    // The kiosk pays from the budget without the user signing
+    let mut session_echo_ix = vending_machine_echo_ix(
+        program_id,
+        pda,
+        kiosk.pubkey(),
+        user_token_account,
+        mint,
+        spl_token::id(),
+    )?;
+    session_echo_ix.accounts[5] = AccountMeta::new(session, false);
+    for _ in 0..2 {
+        send_instructions(&rpc_client, &payer, &[session_echo_ix.clone()], &[&kiosk])?;
+    }
+
+    let token_account =
+        spl_token::state::Account::unpack(&rpc_client.get_account_data(&user_token_account)?)?;
+    assert_eq!(token_account.amount, 80);
+    let session_data = SessionHeader::try_from_slice(&rpc_client.get_account_data(&session)?)?;
+    assert_eq!(session_data.total_burned, 20);
+
+    let e = send_instructions(&rpc_client, &payer, &[session_echo_ix], &[&kiosk]).unwrap_err();
+    assert_echo_error(e, EchoError::SessionBudgetExceeded);
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(session, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new(user_token_account, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+            ],
+            data: EchoInstruction::CloseSession.try_to_vec()?,
+        }],
+        &[],
+    )?;
+
+    assert!(rpc_client.get_account(&session).is_err());
+    let token_account =
+        spl_token::state::Account::unpack(&rpc_client.get_account_data(&user_token_account)?)?;
+    assert!(token_account.delegate.is_none());
+    Ok(())
+}
+
+#[test]
+fn test_vending_machine_delegate() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let delegate = Keypair::new();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let price = 42u64;
+    let (pda, mint, user_token_account) =
+        setup_vending_machine(&rpc_client, &payer, program_id, price, 100, false)?;
+
+    let delegate_echo_ix = vending_machine_echo_ix(
+        program_id,
+        pda,
+        delegate.pubkey(),
+        user_token_account,
+        mint,
+        spl_token::id(),
+    )?;
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            spl_token::instruction::approve(
+                &spl_token::id(),
+                &user_token_account,
+                &delegate.pubkey(),
+                &payer.pubkey(),
+                &[&payer.pubkey()],
+                price,
+            )?,
+            delegate_echo_ix.clone(),
+        ],
+        &[&delegate],
+    )?;
+
+    let token_account =
+        spl_token::state::Account::unpack(&rpc_client.get_account_data(&user_token_account)?)?;
+    assert_eq!(token_account.amount, 100 - price);
+
+    // The approved amount is used up
+    let e = send_instructions(&rpc_client, &payer, &[delegate_echo_ix], &[&delegate]).unwrap_err();
+    assert_echo_error(e, EchoError::InsufficientDelegation);
+    Ok(())
+}
+
+#[test]
+fn test_vending_machine_unique_writers() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let price = 1u64;
+    let (pda, mint, user_token_account) =
+        setup_vending_machine(&rpc_client, &payer, program_id, price, 100, false)?;
+    let (writer_marker, _) = find_writer_marker_address(&program_id, &pda, &user_token_account);
+    let mut counted_echo_ix = vending_machine_echo_ix(
+        program_id,
+        pda,
+        payer.pubkey(),
+        user_token_account,
+        mint,
+        spl_token::id(),
+    )?;
+    counted_echo_ix.accounts[1].is_writable = true;
+    counted_echo_ix
+        .accounts
+        .push(AccountMeta::new(writer_marker, false));
+    counted_echo_ix
+        .accounts
+        .push(AccountMeta::new_readonly(system_program::id(), false));
+
+    // Writes without a marker are not counted, repeated writes are counted once
+    for echo_ix in [
+        vending_machine_echo_ix(
+            program_id,
+            pda,
+            payer.pubkey(),
+            user_token_account,
+            mint,
+            spl_token::id(),
+        )?,
+        counted_echo_ix.clone(),
+        counted_echo_ix,
+    ] {
+        send_instructions(&rpc_client, &payer, &[echo_ix], &[])?;
+    }
+
+    let buffer_data =
+        VendingMachineBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert_eq!(buffer_data.unique_writers, 1);
+    let marker = WriterMarker::try_from_slice(&rpc_client.get_account_data(&writer_marker)?)?;
+    assert_eq!(marker.version, CURRENT_VERSION);
+    assert!(marker.slot > 0);
+
+    // The marker must be derived from the machine and the token account
+    let mut wrong_marker_ix = vending_machine_echo_ix(
+        program_id,
+        pda,
+        payer.pubkey(),
+        user_token_account,
+        mint,
+        spl_token::id(),
+    )?;
+    wrong_marker_ix.accounts[1].is_writable = true;
+    wrong_marker_ix
+        .accounts
+        .push(AccountMeta::new(Pubkey::new_unique(), false));
+    wrong_marker_ix
+        .accounts
+        .push(AccountMeta::new_readonly(system_program::id(), false));
+    let e = send_instructions(&rpc_client, &payer, &[wrong_marker_ix], &[]).unwrap_err();
+    assert_echo_error(e, EchoError::InvalidAuthority);
+    Ok(())
+}
+
+#[test]
+fn test_purchase_price() {
+    // Flat pricing ignores the purchase count
+    assert_eq!(purchase_price(7, false, 1), Some(7));
+    assert_eq!(purchase_price(7, false, u64::MAX), Some(7));
+    assert_eq!(purchase_price(u64::MAX, false, 2), Some(u64::MAX));
+
+    assert_eq!(purchase_price(7, true, 1), Some(7));
+    assert_eq!(purchase_price(7, true, 2), Some(28));
+    assert_eq!(purchase_price(7, true, 10), Some(700));
+    assert_eq!(purchase_price(0, true, u32::MAX as u64), Some(0));
+    assert_eq!(purchase_price(u64::MAX, true, 1), Some(u64::MAX));
+
+    // k^2 overflows on its own past 2^32 - 1
+    let max_k = u32::MAX as u64;
+    assert_eq!(purchase_price(1, true, max_k), Some(max_k * max_k));
+    assert_eq!(purchase_price(1, true, max_k + 1), None);
+    assert_eq!(purchase_price(0, true, max_k + 1), None);
+    assert_eq!(purchase_price(1, true, u64::MAX), None);
+
+    // price * k^2 overflows at the boundary
+    assert_eq!(
+        purchase_price(u64::MAX / 4, true, 2),
+        Some(u64::MAX / 4 * 4)
+    );
+    assert_eq!(purchase_price(u64::MAX / 4 + 1, true, 2), None);
+    assert_eq!(purchase_price(u64::MAX, true, 2), None);
+    for k in 2..=1024u64 {
+        let max_price = u64::MAX / (k * k);
+        assert_eq!(purchase_price(max_price, true, k), Some(max_price * k * k));
+        assert_eq!(purchase_price(max_price + 1, true, k), None);
+    }
+}
+
+#[test]
+fn test_checked_account_len() {
+    assert_eq!(checked_account_len(0, 0, 0), Some(0));
+    assert_eq!(checked_account_len(1, 0, usize::MAX), Some(1));
+    assert_eq!(checked_account_len(0, usize::MAX, 1), Some(usize::MAX));
+    assert_eq!(checked_account_len(0, 1, usize::MAX), Some(usize::MAX));
+    assert_eq!(checked_account_len(usize::MAX, 0, 32), Some(usize::MAX));
+    assert_eq!(checked_account_len(10, 3, 32), Some(106));
+
+    assert_eq!(checked_account_len(1, usize::MAX, 1), None);
+    assert_eq!(checked_account_len(usize::MAX, 1, 1), None);
+    assert_eq!(checked_account_len(0, usize::MAX, 2), None);
+    assert_eq!(checked_account_len(0, usize::MAX / 32 + 1, 32), None);
+    assert_eq!(
+        checked_account_len(usize::MAX % 32, usize::MAX / 32, 32),
+        Some(usize::MAX)
+    );
+    assert_eq!(
+        checked_account_len(usize::MAX % 32 + 1, usize::MAX / 32, 32),
+        None
+    );
+}
+
+#[test]
+fn test_vending_machine_quadratic_pricing() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let price = 3u64;
+    let (pda, mint, user_token_account) =
+        setup_vending_machine(&rpc_client, &payer, program_id, price, 100, false)?;
+    let (writer_marker, _) = find_writer_marker_address(&program_id, &pda, &user_token_account);
+    let plain_echo_ix = vending_machine_echo_ix(
+        program_id,
+        pda,
+        payer.pubkey(),
+        user_token_account,
+        mint,
+        spl_token::id(),
+    )?;
+    let mut counted_echo_ix = plain_echo_ix.clone();
+    counted_echo_ix.accounts[1].is_writable = true;
+    counted_echo_ix
+        .accounts
+        .push(AccountMeta::new(writer_marker, false));
+    counted_echo_ix
+        .accounts
+        .push(AccountMeta::new_readonly(system_program::id(), false));
+    let send = |instruction: Instruction| -> Result<_, ClientError> {
+        send_instructions(&rpc_client, &payer, &[instruction], &[])
+    };
+    let balance = || -> anyhow::Result<u64> {
+        Ok(
+            spl_token::state::Account::unpack(&rpc_client.get_account_data(&user_token_account)?)?
+                .amount,
+        )
+    };
+
+    send(Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pda, false),
+            AccountMeta::new_readonly(payer.pubkey(), true),
+        ],
+        data: EchoInstruction::SetQuadraticPricing { enabled: true }.try_to_vec()?,
+    })?;
+    let buffer_data =
+        VendingMachineBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert!(buffer_data.quadratic_pricing);
+
+    // The purchase count lives in the writer marker, which is now required
+    let e = send(plain_echo_ix).unwrap_err();
+    assert_matches!(
+        e.get_transaction_error(),
+        Some(TransactionError::InstructionError(
+            0,
+            solana_sdk::instruction::InstructionError::NotEnoughAccountKeys
+        ))
+    );
+
+    // 3 * 1^2, then 3 * 2^2, then 3 * 3^2
+    for (k, expected_balance) in [(1u64, 97u64), (2, 85), (3, 58)] {
+        send(counted_echo_ix.clone())?;
+        assert_eq!(balance()?, expected_balance);
+        let marker = WriterMarker::try_from_slice(&rpc_client.get_account_data(&writer_marker)?)?;
+        assert_eq!(marker.purchases, k);
+    }
+
+    // The off-chain model agrees with the burns
+    let simulation = simulate(
+        &PricingCurve {
+            price,
+            quadratic: true,
+        },
+        3,
+    )
+    .unwrap();
+    assert_eq!(balance()?, 100 - simulation.cumulative_burn[2]);
+    let marker = WriterMarker::try_from_slice(&rpc_client.get_account_data(&writer_marker)?)?;
+    assert_eq!(marker.burned, simulation.cumulative_burn[2]);
+
+    // Only the admin can change the pricing
+    let other = Keypair::new();
+    let e = send_instructions(
+        &rpc_client,
+        &payer,
+        &[Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new_readonly(other.pubkey(), true),
+            ],
+            data: EchoInstruction::SetQuadraticPricing { enabled: false }.try_to_vec()?,
+        }],
+        &[&other],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::InvalidAuthority);
+    Ok(())
+}
+
+#[test]
+fn test_simulate_pricing() {
+    let flat = PricingCurve {
+        price: 5,
+        quadratic: false,
+    };
+    assert_eq!(
+        simulate(&flat, 3),
+        Some(Simulation {
+            prices: vec![5, 5, 5],
+            cumulative_burn: vec![5, 10, 15],
+        })
+    );
+    assert_eq!(
+        simulate(&flat, 0),
+        Some(Simulation {
+            prices: vec![],
+            cumulative_burn: vec![],
+        })
+    );
+
+    let quadratic = PricingCurve {
+        price: 3,
+        quadratic: true,
+    };
+    let simulation = simulate(&quadratic, 4).unwrap();
+    assert_eq!(simulation.prices, vec![3, 12, 27, 48]);
+    assert_eq!(simulation.cumulative_burn, vec![3, 15, 42, 90]);
+    for (k, price) in (1..).zip(&simulation.prices) {
+        assert_eq!(purchase_price(3, true, k), Some(*price));
+    }
+
+    // Overflowing prices and totals fail like on chain
+    let expensive = PricingCurve {
+        price: u64::MAX / 2,
+        quadratic: false,
+    };
+    assert_eq!(
+        simulate(&expensive, 2).unwrap().cumulative_burn[1],
+        u64::MAX - 1
+    );
+    assert_eq!(simulate(&expensive, 3), None);
+    let steep = PricingCurve {
+        price: u64::MAX / 4,
+        quadratic: true,
+    };
+    assert_eq!(simulate(&steep, 2), None);
+}
+
+#[test]
+fn test_dutch_auction_price() {
+    assert_eq!(dutch_auction_price(100, 20, 80, 0), 100);
+    assert_eq!(dutch_auction_price(100, 20, 80, 1), 99);
+    assert_eq!(dutch_auction_price(100, 20, 80, 40), 60);
+    assert_eq!(dutch_auction_price(100, 20, 80, 79), 21);
+    assert_eq!(dutch_auction_price(100, 20, 80, 80), 20);
+    assert_eq!(dutch_auction_price(100, 20, 80, u64::MAX), 20);
+    // Prices round up to the next whole token
+    assert_eq!(dutch_auction_price(10, 0, 3, 1), 7);
+    assert_eq!(dutch_auction_price(7, 7, 10, 5), 7);
+    // Full range prices and slots do not overflow
+    assert_eq!(dutch_auction_price(u64::MAX, 0, u64::MAX, u64::MAX - 1), 1);
+    assert_eq!(dutch_auction_price(u64::MAX, 0, u64::MAX, 1), u64::MAX - 1);
+    assert_eq!(dutch_auction_price(u64::MAX, 0, 2, 1), u64::MAX / 2 + 1);
+}
+
+#[test]
+fn test_vending_machine_dutch_auction() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let (pda, mint, user_token_account) =
+        setup_vending_machine(&rpc_client, &payer, program_id, 1, 1_000_000, false)?;
+    let (dutch_auction, _) = find_dutch_auction_address(&program_id, &pda);
+    let set_dutch_auction_ix =
+        |start_price: u64, floor_price: u64, decay_slots: u64| -> anyhow::Result<Instruction> {
+            Ok(Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new(dutch_auction, false),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::SetDutchAuction {
+                    start_price,
+                    floor_price,
+                    decay_slots,
+                }
+                .try_to_vec()?,
+            })
+        };
+    let send = |instruction: Instruction| -> Result<_, ClientError> {
+        send_instructions(&rpc_client, &payer, &[instruction], &[])
+    };
+    let balance = || -> anyhow::Result<u64> {
+        Ok(
+            spl_token::state::Account::unpack(&rpc_client.get_account_data(&user_token_account)?)?
+                .amount,
+        )
+    };
+
+    let e = send(set_dutch_auction_ix(10, 11, 100)?).unwrap_err();
+    assert_matches!(
+        e.get_transaction_error(),
+        Some(TransactionError::InstructionError(0, _))
+    );
+
+    // The decay is long enough for the price to stay within a few tokens of the start
+    send(set_dutch_auction_ix(100_000, 0, 1_000_000)?)?;
+    let buffer_data =
+        VendingMachineBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert!(buffer_data.dutch_auction);
+
+    let mut echo_ix = vending_machine_echo_ix(
+        program_id,
+        pda,
+        payer.pubkey(),
+        user_token_account,
+        mint,
+        spl_token::id(),
+    )?;
+    echo_ix
+        .accounts
+        .push(AccountMeta::new(dutch_auction, false));
+    send(echo_ix.clone())?;
+    let paid = 1_000_000 - balance()?;
+    assert!(paid <= 100_000 && paid > 99_000);
+    let auction = DutchAuction::try_from_slice(&rpc_client.get_account_data(&dutch_auction)?)?;
+    assert!(auction.last_purchase_slot > 0);
+    assert_eq!(auction.price_at(auction.last_purchase_slot), 100_000);
+    assert_eq!(auction.price_at(auction.last_purchase_slot + 1_000_000), 0);
+
+    // Once the decay is over, purchases cost the floor price
+    send(set_dutch_auction_ix(100_000, 5, 1)?)?;
+    while rpc_client.get_slot()? < auction.last_purchase_slot + 2 {
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    let before = balance()?;
+    send(echo_ix)?;
+    assert_eq!(before - balance()?, 5);
+
+    // Turning the auction off restores the fixed price and its accounts
+    send(set_dutch_auction_ix(0, 0, 0)?)?;
+    let before = balance()?;
+    send(vending_machine_echo_ix(
+        program_id,
+        pda,
+        payer.pubkey(),
+        user_token_account,
+        mint,
+        spl_token::id(),
+    )?)?;
+    assert_eq!(before - balance()?, 1);
+    Ok(())
+}
+
+#[test]
+fn test_vending_machine_receipts() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let (pda, mint, user_token_account) =
+        setup_vending_machine(&rpc_client, &payer, program_id, 1, 100, false)?;
+    let (receipt_mint, _) = find_receipt_mint_address(&program_id, &pda);
+    let receipt_account = Keypair::new();
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new(receipt_mint, false),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(spl_token::id(), false),
+                    AccountMeta::new_readonly(solana_sdk::sysvar::rent::id(), false),
+                ],
+                data: EchoInstruction::InitializeReceiptMint.try_to_vec()?,
+            },
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &receipt_account.pubkey(),
+                rpc_client
+                    .get_minimum_balance_for_rent_exemption(spl_token::state::Account::LEN)?,
+                spl_token::state::Account::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_account(
+                &spl_token::id(),
+                &receipt_account.pubkey(),
+                &receipt_mint,
+                &payer.pubkey(),
+            )?,
+        ],
+        &[&receipt_account],
+    )?;
+
+    let receipt_mint_data =
+        spl_token::state::Mint::unpack(&rpc_client.get_account_data(&receipt_mint)?)?;
+    assert_eq!(
+        receipt_mint_data.mint_authority,
+        solana_sdk::program_option::COption::Some(pda)
+    );
+    assert_eq!(receipt_mint_data.decimals, 0);
+    let buffer_data =
+        VendingMachineBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert!(buffer_data.receipts);
+
+    let mut echo_ix = vending_machine_echo_ix(
+        program_id,
+        pda,
+        payer.pubkey(),
+        user_token_account,
+        mint,
+        spl_token::id(),
+    )?;
+    echo_ix.accounts.push(AccountMeta::new(receipt_mint, false));
+    echo_ix
+        .accounts
+        .push(AccountMeta::new(receipt_account.pubkey(), false));
+    for _ in 0..2 {
+        send_instructions(&rpc_client, &payer, &[echo_ix.clone()], &[])?;
+    }
+    let receipts = spl_token::state::Account::unpack(
+        &rpc_client.get_account_data(&receipt_account.pubkey())?,
+    )?;
+    assert_eq!(receipts.amount, 2);
+
+    // Purchases on machines with receipts must pass the receipt accounts
+    assert!(send_instructions(
+        &rpc_client,
+        &payer,
+        &[vending_machine_echo_ix(
+            program_id,
+            pda,
+            payer.pubkey(),
+            user_token_account,
+            mint,
+            spl_token::id(),
+        )?],
+        &[]
+    )
+    .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_vending_machine_native_mint() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let native_mint = spl_token::native_mint::id();
+    let proceeds = Keypair::new();
+    let stranger = Keypair::new();
+    let stranger_proceeds = Keypair::new();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+    let send = |instructions: &[Instruction], signers: Vec<&Keypair>| -> Result<(), ClientError> {
+        let blockhash = rpc_client.get_latest_blockhash()?;
+        let mut transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&payer.pubkey()),
+            &signers,
+            blockhash,
+        );
+        transaction.sign(&signers, blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)?;
+        Ok(())
+    };
+
+    let price = 1_000_000u64;
+    let (pda, _) = find_vending_machine_address(&program_id, &native_mint, price, None);
+    let token_account_rent =
+        rpc_client.get_minimum_balance_for_rent_exemption(spl_token::state::Account::LEN)?;
+    let create_wrapped_sol_account_ixs =
+        |account: &Keypair, owner: &Pubkey| -> anyhow::Result<Vec<Instruction>> {
+            Ok(vec![
+                system_instruction::create_account(
+                    &payer.pubkey(),
+                    &account.pubkey(),
+                    token_account_rent,
+                    spl_token::state::Account::LEN as u64,
+                    &spl_token::id(),
+                ),
+                spl_token::instruction::initialize_account(
+                    &spl_token::id(),
+                    &account.pubkey(),
+                    &native_mint,
+                    owner,
+                )?,
+            ])
+        };
+    let mut setup_ixs = vec![Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pda, false),
+            AccountMeta::new_readonly(native_mint, false),
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: EchoInstruction::InitializeVendingMachineEcho {
+            price,
+            buffer_size: VendingMachineBufferHeader::HEADER_LEN + b"vending machine".len(),
+            metadata: None,
+            machine_seed: Trailing(None),
+        }
+        .try_to_vec()?,
+    }];
+    setup_ixs.extend(create_wrapped_sol_account_ixs(&proceeds, &payer.pubkey())?);
+    setup_ixs.extend(create_wrapped_sol_account_ixs(
+        &stranger_proceeds,
+        &stranger.pubkey(),
+    )?);
+    send(&setup_ixs, vec![&payer, &proceeds, &stranger_proceeds])?;
+    let buffer_lamports = rpc_client.get_balance(&pda)?;
+
+    // The SOL of the buyer is wrapped into a temporary account, paid to the admin and the rest unwrapped
+    let buy_ixs = |proceeds: Pubkey, wrapped_sol_account: &Keypair| -> anyhow::Result<_> {
+        let mut echo_ix = vending_machine_echo_ix(
+            program_id,
+            pda,
+            payer.pubkey(),
+            wrapped_sol_account.pubkey(),
+            native_mint,
+            spl_token::id(),
+        )?;
+        // The mint supply is left alone
+        echo_ix.accounts[3] = AccountMeta::new_readonly(native_mint, false);
+        echo_ix.accounts.push(AccountMeta::new(proceeds, false));
+        Ok(wrapped_sol_vending_machine_echo(
+            &payer.pubkey(),
+            &wrapped_sol_account.pubkey(),
+            price,
+            token_account_rent,
+            echo_ix,
+        )?)
+    };
+    let wrapped_sol_account = Keypair::new();
+    let e = send(
+        &buy_ixs(stranger_proceeds.pubkey(), &wrapped_sol_account)?,
+        vec![&payer, &wrapped_sol_account],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::InvalidAuthority);
+
+    send(
+        &buy_ixs(proceeds.pubkey(), &wrapped_sol_account)?,
+        vec![&payer, &wrapped_sol_account],
+    )?;
+    let proceeds_account =
+        spl_token::state::Account::unpack(&rpc_client.get_account_data(&proceeds.pubkey())?)?;
+    assert_eq!(proceeds_account.amount, price);
+    assert!(rpc_client
+        .get_account(&wrapped_sol_account.pubkey())
+        .is_err());
+    assert_eq!(rpc_client.get_balance(&pda)?, buffer_lamports);
+    let buffer_data =
+        VendingMachineBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert_eq!(buffer_data.echo_data, b"vending machine".to_vec());
+
+    // Only the admin collects lamports sent to the machine, and the machine stays open
+    let sweep_ix = |admin: Pubkey| -> anyhow::Result<Instruction> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![AccountMeta::new(pda, false), AccountMeta::new(admin, true)],
+            data: EchoInstruction::SweepVendingMachineLamports.try_to_vec()?,
+        })
+    };
+    send(
+        &[system_instruction::transfer(&payer.pubkey(), &pda, 5_000)],
+        vec![&payer],
+    )?;
+    let e = send(&[sweep_ix(stranger.pubkey())?], vec![&payer, &stranger]).unwrap_err();
+    assert_echo_error(e, EchoError::InvalidAuthority);
+    send(&[sweep_ix(payer.pubkey())?], vec![&payer])?;
+    assert_eq!(rpc_client.get_balance(&pda)?, buffer_lamports);
+    assert_eq!(rpc_client.get_account(&pda)?.owner, program_id);
+    Ok(())
+}
+
+#[test]
+fn test_authorized_echo_large_buffer() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let authority = Keypair::new();
+
+    // Larger than a single CPI can allocate, so the buffer is seeded directly into genesis
+    let buffer_seed = 1u64;
+    let (pda, bump_seed) =
+        find_authorized_buffer_address(&program_id, &authority.pubkey(), buffer_seed, None);
+    let buffer_data = AuthorizedBufferHeader {
+        bump_seed,
+        buffer_seed,
+        version: CURRENT_VERSION,
+        namespace: None,
+        write_deadline_slot: 0,
+        close_after_slot: 0,
+        reference_count: 0,
+        authority: Pubkey::default(),
+        verify_after_write: false,
+        chunk_checksums: false,
+        write_offset: 0,
+        features: 0,
+        _reserved: Reserved::default(),
+        echo_data: vec![1; 10_240],
+    }
+    .try_to_vec()?;
+    let mut account = AccountSharedData::new(1_000_000_000, buffer_data.len(), &program_id);
+    account.set_data(buffer_data);
+
+    let (test_validator, payer) = validator_genesis(program_id)
+        .add_account(pda, account)
+        .start();
+    let rpc_client = test_validator.get_rpc_client();
+
+    // As large a payload as fits in a transaction
+    let data = vec![42; 1_000];
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new_readonly(authority.pubkey(), true),
+            ],
+            data: EchoInstruction::AuthorizedEcho { data: data.clone() }.try_to_vec()?,
+        }],
+        &[&authority],
+    )?;
+
+    let buffer_data = AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert_eq!(buffer_data.echo_data.len(), 10_240);
+    assert_eq!(&buffer_data.echo_data[..data.len()], &data[..]);
+    assert!(buffer_data.echo_data[data.len()..].iter().all(|&b| b == 0));
+    Ok(())
+}
+
+#[test]
+fn test_authorized_echo_tampered_bump_seed() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let authority = Keypair::new();
+    let buffer_seed = 1u64;
+    let (pda, bump_seed) =
+        find_authorized_buffer_address(&program_id, &authority.pubkey(), buffer_seed, None);
+
+    // One bump seed derives another PDA, the other no PDA at all, both must fail the same way
+    let derive = |bump_seed: u8| {
+        Pubkey::create_program_address(
+            &[
+                b"authority",
+                authority.pubkey().as_ref(),
+                &buffer_seed.to_le_bytes(),
+                &[bump_seed],
+            ],
+            &program_id,
+        )
+    };
+    let other_pda_bump = (0..bump_seed).rev().find(|&b| derive(b).is_ok()).unwrap();
+    let off_curve_bump = (0..=255).find(|&b| derive(b).is_err()).unwrap();
+
+    let mut genesis = validator_genesis(program_id);
+    let buffers: Vec<Pubkey> = [other_pda_bump, off_curve_bump]
+        .iter()
+        .map(|&tampered_bump_seed| {
+            let buffer_data = AuthorizedBufferHeader {
+                bump_seed: tampered_bump_seed,
+                buffer_seed,
+                version: CURRENT_VERSION,
+                namespace: None,
+                write_deadline_slot: 0,
+                close_after_slot: 0,
+                reference_count: 0,
+                authority: Pubkey::default(),
+                verify_after_write: false,
+                chunk_checksums: false,
+                write_offset: 0,
+                features: 0,
+                _reserved: Reserved::default(),
+                echo_data: vec![0; 8],
+            }
+            .try_to_vec()
+            .unwrap();
+            let mut account = AccountSharedData::new(1_000_000_000, buffer_data.len(), &program_id);
+            account.set_data(buffer_data);
+            // Seeds that yield no PDA fail before any key comparison, so that copy can live anywhere
+            let key = if tampered_bump_seed == other_pda_bump {
+                pda
+            } else {
+                Pubkey::new_unique()
+            };
+            genesis.add_account(key, account);
+            key
+        })
+        .collect();
+    let (test_validator, payer) = genesis.start();
+    let rpc_client = test_validator.get_rpc_client();
+
+    for buffer in buffers {
+        let e = send_instructions(
+            &rpc_client,
+            &payer,
+            &[Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(buffer, false),
+                    AccountMeta::new_readonly(authority.pubkey(), true),
+                ],
+                data: EchoInstruction::AuthorizedEcho { data: vec![1] }.try_to_vec()?,
+            }],
+            &[&authority],
+        )
+        .unwrap_err();
+        assert_echo_error(e, EchoError::InvalidAuthority);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_authorized_buffer_unpack_header() -> anyhow::Result<()> {
+    for namespace in [None, Some(*b"recycled")] {
+        let header = AuthorizedBufferHeader {
+            bump_seed: 255,
+            buffer_seed: 7,
+            version: CURRENT_VERSION,
+            namespace,
+            write_deadline_slot: 99,
+            close_after_slot: 0,
+            reference_count: 0,
+            authority: Pubkey::default(),
+            verify_after_write: false,
+            chunk_checksums: false,
+            write_offset: 0,
+            features: 0,
+            _reserved: Reserved::default(),
+            echo_data: b"payload".to_vec(),
+        };
+        let data = header.try_to_vec()?;
+        let unpacked = AuthorizedBufferHeader::unpack_header(&data)?;
+        assert_eq!(unpacked.namespace, namespace);
+        assert_eq!(unpacked.buffer_seed, 7);
+        assert_eq!(unpacked.write_deadline_slot, 99);
+        assert!(unpacked.echo_data.is_empty());
+        assert_eq!(
+            &data[AuthorizedBufferHeader::header_len(&namespace)..],
+            b"payload"
+        );
+        assert!(AuthorizedBufferHeader::unpack_header_checked(&data).is_ok());
+        // Trailing bytes past the echo data are not part of an authorized buffer
+        let mut data = data;
+        data.push(0);
+        assert!(AuthorizedBufferHeader::unpack_header_checked(&data).is_err());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_top_up_rent() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let authority = Pubkey::new_unique();
+
+    // A marginally funded buffer, seeded directly into genesis
+    let buffer_seed = 1u64;
+    let (pda, bump_seed) =
+        find_authorized_buffer_address(&program_id, &authority, buffer_seed, None);
+    let buffer_data = AuthorizedBufferHeader {
+        bump_seed,
+        buffer_seed,
+        version: CURRENT_VERSION,
+        namespace: None,
+        write_deadline_slot: 0,
+        close_after_slot: 0,
+        reference_count: 0,
+        authority: Pubkey::default(),
+        verify_after_write: false,
+        chunk_checksums: false,
+        write_offset: 0,
+        features: 0,
+        _reserved: Reserved::default(),
+        echo_data: vec![0; 32],
+    }
+    .try_to_vec()?;
+    let buffer_len = buffer_data.len();
+    let mut account = AccountSharedData::new(1_000, buffer_len, &program_id);
+    account.set_data(buffer_data);
+
+    let (test_validator, payer) = validator_genesis(program_id)
+        .add_account(pda, account)
+        .start();
+    let rpc_client = test_validator.get_rpc_client();
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+            data: EchoInstruction::TopUpRent.try_to_vec()?,
+        }],
+        &[],
+    )?;
+
+    assert_eq!(
+        rpc_client.get_balance(&pda)?,
+        rpc_client.get_minimum_balance_for_rent_exemption(buffer_len)?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_sweep_excess_lamports() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let authority = Keypair::new();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let buffer_size = AuthorizedBufferHeader::header_len(&None) + 32;
+    let (pda, _) =
+        find_authorized_buffer_address(&program_id, &authority.pubkey(), buffer_seed, None);
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            system_instruction::transfer(&payer.pubkey(), &authority.pubkey(), 1_000_000_000),
+            initialize_authorized_echo_ix(
+                program_id,
+                pda,
+                authority.pubkey(),
+                true,
+                buffer_seed,
+                buffer_size,
+                None,
+            )?,
+            // A stray transfer into the buffer
+            system_instruction::transfer(&payer.pubkey(), &pda, 5_000),
+        ],
+        &[&authority],
+    )?;
+
+    let authority_lamports = rpc_client.get_balance(&authority.pubkey())?;
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new(authority.pubkey(), true),
+            ],
+            data: EchoInstruction::SweepExcessLamports.try_to_vec()?,
+        }],
+        &[&authority],
+    )?;
+
+    assert_eq!(
+        rpc_client.get_balance(&pda)?,
+        rpc_client.get_minimum_balance_for_rent_exemption(buffer_size)?
+    );
+    assert_eq!(
+        rpc_client.get_balance(&authority.pubkey())?,
+        authority_lamports + 5_000
+    );
+    Ok(())
+}
+
+#[test]
+fn test_authorized_buffer_pack_header() -> anyhow::Result<()> {
+    let header = AuthorizedBufferHeader {
+        bump_seed: 255,
+        buffer_seed: 7,
+        version: CURRENT_VERSION,
+        namespace: Some(*b"recycled"),
+        write_deadline_slot: 0,
+        close_after_slot: 0,
+        reference_count: 0,
+        authority: Pubkey::default(),
+        verify_after_write: false,
+        chunk_checksums: false,
+        write_offset: 0,
+        features: 0,
+        _reserved: Reserved::default(),
+        echo_data: b"payload".to_vec(),
+    };
+    let mut data = header.try_to_vec()?;
+
+    let mut unpacked = AuthorizedBufferHeader::unpack_header(&data)?;
+    unpacked.close_after_slot = 42;
+    unpacked.pack_header(&mut data)?;
+
+    let repacked = AuthorizedBufferHeader::try_from_slice(&data)?;
+    assert_eq!(repacked.close_after_slot, 42);
+    assert_eq!(repacked.namespace, header.namespace);
+    assert_eq!(repacked.echo_data, header.echo_data);
+    Ok(())
+}
+
+#[test]
+fn test_scheduled_close() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let authority = Keypair::new();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let (pda, _) =
+        find_authorized_buffer_address(&program_id, &authority.pubkey(), buffer_seed, None);
+    let (chunk_checksums, _) = find_chunk_checksums_address(&program_id, &pda);
+    let send_with =
+        |instruction: EchoInstruction, companions: &[Pubkey]| -> Result<(), ClientError> {
+            let mut accounts = vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new(authority.pubkey(), true),
+            ];
+            accounts.extend(
+                companions
+                    .iter()
+                    .map(|companion| AccountMeta::new(*companion, false)),
+            );
+            send_instructions(
+                &rpc_client,
+                &payer,
+                &[Instruction {
+                    program_id,
+                    accounts,
+                    data: instruction.try_to_vec().unwrap(),
+                }],
+                &[&authority],
+            )?;
+            Ok(())
+        };
+    let companions = find_buffer_companion_addresses(&program_id, &pda);
+    let send = |instruction: EchoInstruction| match instruction {
+        EchoInstruction::CloseAuthorizedBuffer => send_with(instruction, &companions),
+        _ => send_with(instruction, &[]),
+    };
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            system_instruction::transfer(&payer.pubkey(), &authority.pubkey(), 1_000_000_000),
+            initialize_authorized_echo_ix(
+                program_id,
+                pda,
+                authority.pubkey(),
+                true,
+                buffer_seed,
+                AuthorizedBufferHeader::header_len(&None) + 32,
+                None,
+            )?,
+            // A companion of the buffer
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pda, false),
+                    AccountMeta::new(authority.pubkey(), true),
+                    AccountMeta::new(chunk_checksums, false),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::SetChunkChecksums { enabled: true }.try_to_vec()?,
+            },
+        ],
+        &[&authority],
+    )?;
+
+    let e = send(EchoInstruction::CloseAuthorizedBuffer).unwrap_err();
+    assert_echo_error(e, EchoError::CloseNotScheduled);
+
+    // Scheduled, then cancelled
+    let after_slot = rpc_client.get_slot()? + 1_000;
+    send(EchoInstruction::ScheduleClose { after_slot })?;
+    let buffer_data = AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert_eq!(buffer_data.close_after_slot, after_slot);
+    let e = send(EchoInstruction::CloseAuthorizedBuffer).unwrap_err();
+    assert_echo_error(e, EchoError::CloseGracePeriodActive);
+    send(EchoInstruction::CancelClose)?;
+    let e = send(EchoInstruction::CloseAuthorizedBuffer).unwrap_err();
+    assert_echo_error(e, EchoError::CloseNotScheduled);
+
+    let after_slot = rpc_client.get_slot()? + 10;
+    send(EchoInstruction::ScheduleClose { after_slot })?;
+    while rpc_client.get_slot()? <= after_slot {
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    // Every companion must be passed, so none is left for a buffer created again at the same address
+    let e = send_with(EchoInstruction::CloseAuthorizedBuffer, &companions[..7]).unwrap_err();
+    assert_matches!(
+        e.get_transaction_error(),
+        Some(TransactionError::InstructionError(
+            0,
+            solana_sdk::instruction::InstructionError::NotEnoughAccountKeys
+        ))
+    );
+    send(EchoInstruction::CloseAuthorizedBuffer)?;
+    assert!(rpc_client.get_account(&pda).is_err());
+    assert!(rpc_client.get_account(&chunk_checksums).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_buffer_references() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let authority = Keypair::new();
+    let referrer = Keypair::new();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let (pda, _) =
+        find_authorized_buffer_address(&program_id, &authority.pubkey(), buffer_seed, None);
+    let (reference, _) = find_reference_address(&program_id, &pda, &referrer.pubkey());
+    let after_slot = rpc_client.get_slot()? + 10;
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            system_instruction::transfer(&payer.pubkey(), &authority.pubkey(), 1_000_000_000),
+            initialize_authorized_echo_ix(
+                program_id,
+                pda,
+                authority.pubkey(),
+                true,
+                buffer_seed,
+                AuthorizedBufferHeader::header_len(&None) + 32,
+                None,
+            )?,
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pda, false),
+                    AccountMeta::new_readonly(authority.pubkey(), true),
+                ],
+                data: EchoInstruction::ScheduleClose { after_slot }.try_to_vec()?,
+            },
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pda, false),
+                    AccountMeta::new_readonly(authority.pubkey(), true),
+                    AccountMeta::new(reference, false),
+                    AccountMeta::new_readonly(referrer.pubkey(), true),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::AddReference.try_to_vec()?,
+            },
+        ],
+        &[&authority, &referrer],
+    )?;
+
+    let buffer_data = AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert_eq!(buffer_data.reference_count, 1);
+
+    // without the authority co-signing, a reference cannot pin the buffer
+    let squatter = Keypair::new();
+    let (squatter_reference, _) = find_reference_address(&program_id, &pda, &squatter.pubkey());
+    let e = send_instructions(
+        &rpc_client,
+        &payer,
+        &[Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new_readonly(authority.pubkey(), false),
+                AccountMeta::new(squatter_reference, false),
+                AccountMeta::new_readonly(squatter.pubkey(), true),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+            data: EchoInstruction::AddReference.try_to_vec()?,
+        }],
+        &[&squatter],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::AuthorityNotSigner);
+
+    while rpc_client.get_slot()? <= after_slot {
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    let mut close_accounts = vec![
+        AccountMeta::new(pda, false),
+        AccountMeta::new(authority.pubkey(), true),
+    ];
+    close_accounts.extend(
+        find_buffer_companion_addresses(&program_id, &pda)
+            .iter()
+            .map(|companion| AccountMeta::new(*companion, false)),
+    );
+    let close_ix = Instruction {
+        program_id,
+        accounts: close_accounts,
+        data: EchoInstruction::CloseAuthorizedBuffer.try_to_vec()?,
+    };
+    let e = send_instructions(&rpc_client, &payer, &[close_ix.clone()], &[&authority]).unwrap_err();
+    assert_echo_error(e, EchoError::BufferReferenced);
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pda, false),
+                    AccountMeta::new(reference, false),
+                    AccountMeta::new_readonly(referrer.pubkey(), true),
+                    AccountMeta::new(payer.pubkey(), false),
+                ],
+                data: EchoInstruction::RemoveReference.try_to_vec()?,
+            },
+            close_ix,
+        ],
+        &[&authority, &referrer],
+    )?;
+
+    assert!(rpc_client.get_account(&reference).is_err());
+    assert!(rpc_client.get_account(&pda).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_keypair_authorized_echo() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let authority = Keypair::new();
+    let impostor = Keypair::new();
+    let buffer = Keypair::new();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let authorized_echo_ix = |authority: Pubkey| -> anyhow::Result<Instruction> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(buffer.pubkey(), false),
+                AccountMeta::new_readonly(authority, true),
+            ],
+            data: EchoInstruction::AuthorizedEcho {
+                data: b"vanity".to_vec(),
+            }
+            .try_to_vec()?,
+        })
+    };
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            system_instruction::transfer(&payer.pubkey(), &authority.pubkey(), 1_000_000_000),
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(buffer.pubkey(), true),
+                    AccountMeta::new(authority.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::InitializeKeypairAuthorizedEcho {
+                    buffer_size: AuthorizedBufferHeader::header_len(&None) + 6,
+                    write_deadline_slot: None,
+                }
+                .try_to_vec()?,
+            },
+            authorized_echo_ix(authority.pubkey())?,
+        ],
+        &[&authority, &buffer],
+    )?;
+
+    let buffer_data =
+        AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&buffer.pubkey())?)?;
+    assert_eq!(buffer_data.authority, authority.pubkey());
+    assert_eq!(buffer_data.echo_data, b"vanity".to_vec());
+
+    let e = send_instructions(
+        &rpc_client,
+        &payer,
+        &[authorized_echo_ix(impostor.pubkey())?],
+        &[&impostor],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::InvalidAuthority);
+    Ok(())
+}
+
+#[test]
+fn test_publish_immutable() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let data = b"content addressed".to_vec();
+    let content_hash = solana_sdk::hash::hash(&data).to_bytes();
+    let (pda, _) = find_immutable_address(&program_id, &content_hash);
+    let publish_ix = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pda, false),
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: EchoInstruction::PublishImmutable { data: data.clone() }.try_to_vec()?,
+    };
+
+    // Publishing the same content twice is a no-op
+    send_instructions(&rpc_client, &payer, &[publish_ix.clone(), publish_ix], &[])?;
+
+    let buffer_data = ImmutableBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert_eq!(buffer_data.content_hash, content_hash);
+    assert_eq!(buffer_data.data, data);
+
+    // Other content can't be published at that address
+    let e = send_instructions(
+        &rpc_client,
+        &payer,
+        &[Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+            data: EchoInstruction::PublishImmutable {
+                data: b"tampered".to_vec(),
+            }
+            .try_to_vec()?,
+        }],
+        &[],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::InvalidAuthorizedBuffer);
+    Ok(())
+}
+
+#[test]
+fn test_vending_machine_is_on_sale() {
+    let machine = |opens_at: i64, closes_at: i64, daily_opens_at: u32, daily_closes_at: u32| {
+        VendingMachineBufferHeader {
+            bump_seed: 0,
+            price: 0,
+            version: CURRENT_VERSION,
+            admin: Pubkey::default(),
+            opens_at,
+            closes_at,
+            daily_opens_at,
+            daily_closes_at,
+            holding_gated: false,
+            unique_writers: 0,
+            quadratic_pricing: false,
+            dutch_auction: false,
+            receipts: false,
+            _reserved: Reserved::default(),
+            echo_data: Vec::new(),
+            metadata: Trailing(None),
+        }
+    };
+    let day = SECONDS_PER_DAY;
+    assert!(machine(0, 0, 0, 0).is_on_sale(0));
+    assert!(!machine(100, 0, 0, 0).is_on_sale(99));
+    assert!(machine(100, 200, 0, 0).is_on_sale(100));
+    assert!(!machine(100, 200, 0, 0).is_on_sale(200));
+    // 09:00 to 17:00 UTC
+    assert!(machine(0, 0, 9 * 3600, 17 * 3600).is_on_sale(10 * day + 12 * 3600));
+    assert!(!machine(0, 0, 9 * 3600, 17 * 3600).is_on_sale(10 * day + 17 * 3600));
+    // 22:00 to 02:00 UTC spans midnight
+    assert!(machine(0, 0, 22 * 3600, 2 * 3600).is_on_sale(10 * day + 23 * 3600));
+    assert!(machine(0, 0, 22 * 3600, 2 * 3600).is_on_sale(10 * day + 3600));
+    assert!(!machine(0, 0, 22 * 3600, 2 * 3600).is_on_sale(10 * day + 12 * 3600));
+}
+
+#[test]
+fn test_vending_machine_sale_window() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let stranger = Keypair::new();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let price = 42u64;
+    let (pda, mint, user_token_account) =
+        setup_vending_machine(&rpc_client, &payer, program_id, price, 100, false)?;
+    let set_sale_window_ix =
+        |admin: Pubkey, closes_at: Option<i64>| -> anyhow::Result<Instruction> {
+            Ok(Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pda, false),
+                    AccountMeta::new_readonly(admin, true),
+                ],
+                data: EchoInstruction::SetSaleWindow {
+                    opens_at: None,
+                    closes_at,
+                    daily_window: None,
+                }
+                .try_to_vec()?,
+            })
+        };
+    let echo_ix = vending_machine_echo_ix(
+        program_id,
+        pda,
+        payer.pubkey(),
+        user_token_account,
+        mint,
+        spl_token::id(),
+    )?;
+
+    // The sale ended long ago
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[set_sale_window_ix(payer.pubkey(), Some(1))?],
+        &[],
+    )?;
+    let buffer_data =
+        VendingMachineBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert_eq!(buffer_data.admin, payer.pubkey());
+    assert_eq!(buffer_data.closes_at, 1);
+
+    let e = send_instructions(&rpc_client, &payer, &[echo_ix.clone()], &[]).unwrap_err();
+    assert_echo_error(e, EchoError::SaleWindowClosed);
+
+    // Only the admin can reopen the machine
+    let e = send_instructions(
+        &rpc_client,
+        &payer,
+        &[set_sale_window_ix(stranger.pubkey(), None)?],
+        &[&stranger],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::InvalidAuthority);
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[set_sale_window_ix(payer.pubkey(), None)?, echo_ix],
+        &[],
+    )?;
+    let buffer_data =
+        VendingMachineBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert_eq!(buffer_data.echo_data, b"vending machine".to_vec());
+    Ok(())
+}
+
+#[test]
+fn test_vending_machine_holding_gate() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let price = 42u64;
+    let (pda, mint, user_token_account) =
+        setup_vending_machine(&rpc_client, &payer, program_id, price, 100, false)?;
+    let (holding_gate, _) = find_holding_gate_address(&program_id, &pda);
+    let (holding_registration, _) =
+        find_holding_registration_address(&program_id, &pda, &user_token_account);
+    let set_holding_gate_ix = |min_slots: u64| -> anyhow::Result<Instruction> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new(holding_gate, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+            data: EchoInstruction::SetHoldingGate {
+                min_amount: 50,
+                min_slots,
+            }
+            .try_to_vec()?,
+        })
+    };
+    let mut echo_ix = vending_machine_echo_ix(
+        program_id,
+        pda,
+        payer.pubkey(),
+        user_token_account,
+        mint,
+        spl_token::id(),
+    )?;
+    echo_ix.accounts.extend([
+        AccountMeta::new_readonly(holding_gate, false),
+        AccountMeta::new_readonly(holding_registration, false),
+    ]);
+
+    // Not registered
+    let e = send_instructions(
+        &rpc_client,
+        &payer,
+        &[set_holding_gate_ix(1_000_000)?, echo_ix.clone()],
+        &[],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::HoldingRequirementNotMet);
+
+    // Registered too recently
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            set_holding_gate_ix(1_000_000)?,
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new_readonly(pda, false),
+                    AccountMeta::new_readonly(mint, false),
+                    AccountMeta::new_readonly(holding_gate, false),
+                    AccountMeta::new(holding_registration, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(user_token_account, false),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::RegisterHolding {
+                    machine_seed: Trailing(None),
+                }
+                .try_to_vec()?,
+            },
+        ],
+        &[],
+    )?;
+    let registration =
+        HoldingRegistration::try_from_slice(&rpc_client.get_account_data(&holding_registration)?)?;
+    assert_eq!(registration.amount, 100);
+
+    let e = send_instructions(&rpc_client, &payer, &[echo_ix.clone()], &[]).unwrap_err();
+    assert_echo_error(e, EchoError::HoldingRequirementNotMet);
+
+    // Held long enough
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[set_holding_gate_ix(0)?, echo_ix],
+        &[],
+    )?;
+    let buffer_data =
+        VendingMachineBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert!(buffer_data.holding_gated);
+    assert_eq!(buffer_data.echo_data, b"vending machine".to_vec());
+    Ok(())
+}
+
+#[test]
+fn test_vending_machine_metadata() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let price = 42u64;
+    let (pda, mint, user_token_account) =
+        setup_vending_machine(&rpc_client, &payer, program_id, price, 100, false)?;
+    let buffer_data =
+        VendingMachineBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert_eq!(buffer_data.metadata, Trailing(None));
+
+    let metadata = VendingMachineMetadata::new(
+        "Echo Machine",
+        "https://example.com/machine.json",
+        "Writes a message for 42 tokens",
+    )
+    .ok_or_else(|| anyhow!("metadata too long"))?;
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::SetVendingMachineMetadata {
+                    metadata: metadata.clone(),
+                }
+                .try_to_vec()?,
+            },
+            vending_machine_echo_ix(
+                program_id,
+                pda,
+                payer.pubkey(),
+                user_token_account,
+                mint,
+                spl_token::id(),
+            )?,
+        ],
+        &[],
+    )?;
+
+    let account_data = rpc_client.get_account_data(&pda)?;
+    assert_eq!(
+        account_data.len(),
+        VendingMachineBufferHeader::HEADER_LEN
+            + b"vending machine".len()
+            + VendingMachineMetadata::LEN
+    );
+    let buffer_data = VendingMachineBufferHeader::try_from_slice(&account_data)?;
+    assert_eq!(buffer_data.echo_data, b"vending machine".to_vec());
+    let stored = buffer_data
+        .metadata
+        .0
+        .ok_or_else(|| anyhow!("metadata missing"))?;
+    assert_eq!(stored, metadata);
+    assert_eq!(stored.name(), "Echo Machine");
+    assert_eq!(stored.uri(), "https://example.com/machine.json");
+    Ok(())
+}
+
+#[test]
+fn test_vending_machine_metadata_bounds() {
+    assert!(VendingMachineMetadata::new(&"n".repeat(32), "", "").is_some());
+    assert!(VendingMachineMetadata::new(&"n".repeat(33), "", "").is_none());
+    assert!(VendingMachineMetadata::new("", &"u".repeat(129), "").is_none());
+    assert!(VendingMachineMetadata::new("", "", &"d".repeat(257)).is_none());
+}
+
+#[test]
+fn test_buffer_metadata() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let (pda, _) = find_authorized_buffer_address(&program_id, &payer.pubkey(), buffer_seed, None);
+    let (buffer_metadata, _) = find_buffer_metadata_address(&program_id, &pda);
+    let set_buffer_metadata_ix = |metadata: BufferMetadata| -> anyhow::Result<Instruction> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new(buffer_metadata, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+            data: EchoInstruction::SetBufferMetadata { metadata }.try_to_vec()?,
+        })
+    };
+
+    let metadata = BufferMetadata::new("Status", "https://example.com", &["status", "ops"])
+        .ok_or_else(|| anyhow!("metadata too long"))?;
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            initialize_authorized_echo_ix(
+                program_id,
+                pda,
+                payer.pubkey(),
+                true,
+                buffer_seed,
+                AuthorizedBufferHeader::header_len(&None) + 8,
+                None,
+            )?,
+            set_buffer_metadata_ix(metadata)?,
+        ],
+        &[],
+    )?;
+    let metadata_data =
+        BufferMetadataHeader::try_from_slice(&rpc_client.get_account_data(&buffer_metadata)?)?;
+    assert_eq!(metadata_data.buffer, pda);
+    assert_eq!(metadata_data.creator, payer.pubkey());
+    assert_eq!(metadata_data.metadata.title(), "Status");
+    assert_eq!(metadata_data.metadata.tags(), vec!["status", "ops"]);
+
+    // Update in place
+    let metadata = BufferMetadata::new("Status v2", "https://example.com", &[])
+        .ok_or_else(|| anyhow!("metadata too long"))?;
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[set_buffer_metadata_ix(metadata)?],
+        &[],
+    )?;
+    let metadata_data =
+        BufferMetadataHeader::try_from_slice(&rpc_client.get_account_data(&buffer_metadata)?)?;
+    assert_eq!(metadata_data.metadata.title(), "Status v2");
+    assert!(metadata_data.metadata.tags().is_empty());
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(buffer_metadata, false),
+                AccountMeta::new(payer.pubkey(), true),
+            ],
+            data: EchoInstruction::CloseBufferMetadata.try_to_vec()?,
+        }],
+        &[],
+    )?;
+    assert!(rpc_client.get_account(&buffer_metadata).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_buffer_metadata_bounds() {
+    let tags = ["tag"; MAX_BUFFER_TAGS + 1];
+    assert!(BufferMetadata::new("", "", &tags[..MAX_BUFFER_TAGS]).is_some());
+    assert!(BufferMetadata::new("", "", &tags).is_none());
+    assert!(BufferMetadata::new("", "", &["a tag longer than 16"]).is_none());
+    assert!(BufferMetadata::new(&"t".repeat(65), "", &[]).is_none());
+}
+
+#[test]
+fn test_org_project_buffer() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let member = Keypair::new();
+    let (org, _) = find_org_address(&program_id, &payer.pubkey(), 1);
+    let (project, _) = find_project_address(&program_id, &org, 2);
+    let buffer_seed = 3u64;
+    let (pda, _) = find_authorized_buffer_address(&program_id, &project, buffer_seed, None);
+    let set_project_member_ix = |is_member: bool| -> anyhow::Result<Instruction> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(org, false),
+                AccountMeta::new(project, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+            data: EchoInstruction::SetProjectMember {
+                member: member.pubkey(),
+                is_member,
+            }
+            .try_to_vec()?,
+        })
+    };
+    let project_echo_ix = |data: Vec<u8>| -> anyhow::Result<Instruction> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new_readonly(project, false),
+                AccountMeta::new_readonly(member.pubkey(), true),
+            ],
+            data: EchoInstruction::ProjectEcho { data }.try_to_vec()?,
+        })
+    };
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            system_instruction::transfer(&payer.pubkey(), &member.pubkey(), 1_000_000_000),
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(org, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::InitializeOrg { org_seed: 1 }.try_to_vec()?,
+            },
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new_readonly(org, false),
+                    AccountMeta::new(project, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::InitializeProject { project_seed: 2 }.try_to_vec()?,
+            },
+            set_project_member_ix(true)?,
+        ],
+        &[],
+    )?;
+    let org_data = OrgHeader::try_from_slice(&rpc_client.get_account_data(&org)?)?;
+    assert_eq!(org_data.admins, vec![payer.pubkey()]);
+    let project_data = ProjectHeader::try_from_slice(&rpc_client.get_account_data(&project)?)?;
+    assert_eq!(project_data.org, org);
+    assert_eq!(project_data.members, vec![member.pubkey()]);
+
+    // The member initializes a project buffer and writes to it
+    let blockhash = rpc_client.get_latest_blockhash()?;
+    let mut transaction = Transaction::new_signed_with_payer(
+        &[
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pda, false),
+                    AccountMeta::new_readonly(project, false),
+                    AccountMeta::new(member.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::InitializeProjectBuffer {
+                    buffer_seed,
+                    buffer_size: AuthorizedBufferHeader::header_len(&None) + 4,
+                }
+                .try_to_vec()?,
+            },
+            project_echo_ix(vec![1, 2, 3, 4])?,
+        ],
+        Some(&member.pubkey()),
+        &vec![&member],
+        blockhash,
+    );
+    transaction.sign(&[&member], blockhash);
+    rpc_client.send_and_confirm_transaction(&transaction)?;
+    let buffer_data = AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert_eq!(buffer_data.echo_data, vec![1, 2, 3, 4]);
+
+    // Removed members lose write access
+    send_instructions(&rpc_client, &payer, &[set_project_member_ix(false)?], &[])?;
+    let project_data = ProjectHeader::try_from_slice(&rpc_client.get_account_data(&project)?)?;
+    assert!(project_data.members.is_empty());
+
+    let blockhash = rpc_client.get_latest_blockhash()?;
+    let mut transaction = Transaction::new_signed_with_payer(
+        &[project_echo_ix(vec![5])?],
+        Some(&member.pubkey()),
+        &vec![&member],
+        blockhash,
+    );
+    transaction.sign(&[&member], blockhash);
+    let e = rpc_client
+        .send_and_confirm_transaction(&transaction)
+        .unwrap_err();
+    assert_echo_error(e, EchoError::NotAProjectMember);
+    Ok(())
+}
+
+#[test]
+fn test_rotate_authority_with_attestation() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let authority = Keypair::new();
+    let new_authority = Keypair::new();
+    let buffer = Keypair::new();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let (rotation_log, _) = find_rotation_log_address(&program_id, &buffer.pubkey());
+    let rotate_ix = |authority: Pubkey, new: Pubkey| -> anyhow::Result<Instruction> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(buffer.pubkey(), false),
+                AccountMeta::new(authority, true),
+                AccountMeta::new(rotation_log, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+            data: EchoInstruction::RotateAuthorityWithAttestation {
+                new,
+                attestation_hash: [7; 32],
+            }
+            .try_to_vec()?,
+        })
+    };
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            system_instruction::transfer(&payer.pubkey(), &authority.pubkey(), 1_000_000_000),
+            system_instruction::transfer(&payer.pubkey(), &new_authority.pubkey(), 1_000_000_000),
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(buffer.pubkey(), true),
+                    AccountMeta::new(authority.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::InitializeKeypairAuthorizedEcho {
+                    buffer_size: AuthorizedBufferHeader::header_len(&None) + 6,
+                    write_deadline_slot: None,
+                }
+                .try_to_vec()?,
+            },
+            rotate_ix(authority.pubkey(), new_authority.pubkey())?,
+        ],
+        &[&authority, &buffer],
+    )?;
+
+    let buffer_data =
+        AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&buffer.pubkey())?)?;
+    assert_eq!(buffer_data.authority, new_authority.pubkey());
+    let log_data =
+        AuthorityRotationLog::try_from_slice(&rpc_client.get_account_data(&rotation_log)?)?;
+    assert_eq!(log_data.buffer, buffer.pubkey());
+    assert_eq!(log_data.rotations.len(), 1);
+    assert_eq!(log_data.rotations[0].old_authority, authority.pubkey());
+    assert_eq!(log_data.rotations[0].new_authority, new_authority.pubkey());
+    assert_eq!(log_data.rotations[0].attestation_hash, [7; 32]);
+
+    // The old authority can no longer rotate, the new one appends to the log
+    let e = send_instructions(
+        &rpc_client,
+        &payer,
+        &[rotate_ix(authority.pubkey(), authority.pubkey())?],
+        &[&authority],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::InvalidAuthority);
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[rotate_ix(new_authority.pubkey(), authority.pubkey())?],
+        &[&new_authority],
+    )?;
+    let log_data =
+        AuthorityRotationLog::try_from_slice(&rpc_client.get_account_data(&rotation_log)?)?;
+    assert_eq!(log_data.rotations.len(), 2);
+    assert_eq!(log_data.rotations[1].old_authority, new_authority.pubkey());
+    assert!(log_data.rotations[0].slot <= log_data.rotations[1].slot);
+    Ok(())
+}
+
+#[test]
+fn test_recovery_approvals() {
+    let new = Pubkey::new_unique();
+    let mut recovery = RecoveryHeader {
+        bump_seed: 0,
+        version: CURRENT_VERSION,
+        buffer: Pubkey::new_unique(),
+        threshold: 2,
+        timelock_slots: 100,
+        pending_authority: Pubkey::default(),
+        approvals: 0,
+        unlock_slot: 0,
+        _reserved: Reserved::default(),
+        guardians: (0..3).map(|_| Pubkey::new_unique()).collect(),
+    };
+    assert!(!recovery.is_pending());
+
+    recovery.approve(0, new, 10).unwrap();
+    recovery.approve(0, new, 11).unwrap();
+    assert!(recovery.is_pending());
+    assert_eq!(recovery.unlock_slot, 0);
+    assert!(!recovery.is_unlocked(u64::MAX));
+
+    // The timelock starts with the approval reaching the threshold, later approvals do not restart it
+    recovery.approve(2, new, 20).unwrap();
+    assert_eq!(recovery.unlock_slot, 120);
+    recovery.approve(1, new, 30).unwrap();
+    assert_eq!(recovery.unlock_slot, 120);
+    assert!(!recovery.is_unlocked(119));
+    assert!(recovery.is_unlocked(120));
+
+    // A guardian proposing another authority cannot reset the pending recovery
+    let other = Pubkey::new_unique();
+    assert_eq!(
+        recovery.approve(1, other, 130),
+        Err(EchoError::RecoveryPending)
+    );
+    assert_eq!(recovery.pending_authority, new);
+    assert_eq!(recovery.approvals, 0b111);
+    assert!(recovery.is_unlocked(130));
+
+    recovery.clear_pending();
+    assert!(!recovery.is_pending());
+    assert_eq!(recovery.approvals, 0);
+    recovery.approve(1, other, 140).unwrap();
+    assert_eq!(recovery.pending_authority, other);
+    assert_eq!(recovery.approvals, 0b010);
+}
+
+#[test]
+fn test_recover_authority() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let authority = Keypair::new();
+    let new_authority = Pubkey::new_unique();
+    let buffer = Keypair::new();
+    let guardians: Vec<Keypair> = (0..3).map(|_| Keypair::new()).collect();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let (recovery, _) = find_recovery_address(&program_id, &buffer.pubkey());
+    let (rotation_log, _) = find_rotation_log_address(&program_id, &buffer.pubkey());
+    let recover_ix = |guardian: Pubkey| -> anyhow::Result<Instruction> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(buffer.pubkey(), false),
+                AccountMeta::new(recovery, false),
+                AccountMeta::new_readonly(guardian, true),
+            ],
+            data: EchoInstruction::RecoverAuthority { new: new_authority }.try_to_vec()?,
+        })
+    };
+    let finalize_ix = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(buffer.pubkey(), false),
+            AccountMeta::new(recovery, false),
+            AccountMeta::new(rotation_log, false),
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: EchoInstruction::FinalizeRecovery.try_to_vec()?,
+    };
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            system_instruction::transfer(&payer.pubkey(), &authority.pubkey(), 1_000_000_000),
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(buffer.pubkey(), true),
+                    AccountMeta::new(authority.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::InitializeKeypairAuthorizedEcho {
+                    buffer_size: AuthorizedBufferHeader::header_len(&None) + 6,
+                    write_deadline_slot: None,
+                }
+                .try_to_vec()?,
+            },
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new_readonly(buffer.pubkey(), false),
+                    AccountMeta::new(authority.pubkey(), true),
+                    AccountMeta::new(recovery, false),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::SetRecoveryGuardians {
+                    guardians: guardians.iter().map(|g| g.pubkey()).collect(),
+                    threshold: 2,
+                    timelock_slots: 0,
+                }
+                .try_to_vec()?,
+            },
+            recover_ix(guardians[0].pubkey())?,
+        ],
+        &[&authority, &buffer, &guardians[0]],
+    )?;
+
+    let recovery_data = RecoveryHeader::try_from_slice(&rpc_client.get_account_data(&recovery)?)?;
+    assert_eq!(recovery_data.buffer, buffer.pubkey());
+    assert_eq!(recovery_data.guardians.len(), 3);
+    assert_eq!(recovery_data.pending_authority, new_authority);
+    assert_eq!(recovery_data.approvals, 0b001);
+
+    // One approval is below the threshold
+    let e = send_instructions(&rpc_client, &payer, &[finalize_ix.clone()], &[]).unwrap_err();
+    assert_echo_error(e, EchoError::RecoveryTimelockActive);
+
+    // Strangers cannot approve
+    let stranger = Keypair::new();
+    let e = send_instructions(
+        &rpc_client,
+        &payer,
+        &[recover_ix(stranger.pubkey())?],
+        &[&stranger],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::NotAGuardian);
+
+    // Another guardian cannot replace the pending recovery with its own proposal
+    let e = send_instructions(
+        &rpc_client,
+        &payer,
+        &[Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(buffer.pubkey(), false),
+                AccountMeta::new(recovery, false),
+                AccountMeta::new_readonly(guardians[1].pubkey(), true),
+            ],
+            data: EchoInstruction::RecoverAuthority {
+                new: Pubkey::new_unique(),
+            }
+            .try_to_vec()?,
+        }],
+        &[&guardians[1]],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::RecoveryPending);
+    let recovery_data = RecoveryHeader::try_from_slice(&rpc_client.get_account_data(&recovery)?)?;
+    assert_eq!(recovery_data.pending_authority, new_authority);
+    assert_eq!(recovery_data.approvals, 0b001);
+
+    // The authority cancels, so the next recovery needs two fresh approvals
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new_readonly(buffer.pubkey(), false),
+                    AccountMeta::new_readonly(authority.pubkey(), true),
+                    AccountMeta::new(recovery, false),
+                ],
+                data: EchoInstruction::CancelRecovery.try_to_vec()?,
+            },
+            recover_ix(guardians[1].pubkey())?,
+            recover_ix(guardians[2].pubkey())?,
+            finalize_ix.clone(),
+        ],
+        &[&authority, &guardians[1], &guardians[2]],
+    )?;
+
+    let buffer_data =
+        AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&buffer.pubkey())?)?;
+    assert_eq!(buffer_data.authority, new_authority);
+    let log_data =
+        AuthorityRotationLog::try_from_slice(&rpc_client.get_account_data(&rotation_log)?)?;
+    assert_eq!(log_data.rotations.len(), 1);
+    assert_eq!(log_data.rotations[0].old_authority, authority.pubkey());
+    assert_eq!(log_data.rotations[0].new_authority, new_authority);
+    assert_eq!(log_data.rotations[0].attestation_hash, [0; 32]);
+    let recovery_data = RecoveryHeader::try_from_slice(&rpc_client.get_account_data(&recovery)?)?;
+    assert!(!recovery_data.is_pending());
+    Ok(())
+}
+
+#[test]
+fn test_dead_man_switch_is_overdue() {
+    let switch = DeadManSwitchHeader {
+        bump_seed: 0,
+        version: CURRENT_VERSION,
+        buffer: Pubkey::new_unique(),
+        check_in_epochs: 3,
+        last_check_in_epoch: 10,
+        released: false,
+        _reserved: Reserved::default(),
+        data: vec![],
+    };
+    assert!(!switch.is_overdue(10));
+    assert!(!switch.is_overdue(13));
+    assert!(switch.is_overdue(14));
+
+    let switch = DeadManSwitchHeader {
+        last_check_in_epoch: u64::MAX,
+        ..switch
+    };
+    assert!(!switch.is_overdue(u64::MAX));
+}
+
+#[test]
+fn test_dead_man_switch() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let authority = Keypair::new();
+    let buffer = Keypair::new();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let (dead_man_switch, _) = find_dead_man_switch_address(&program_id, &buffer.pubkey());
+    let stage_ix = |data: Vec<u8>| -> anyhow::Result<Instruction> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(buffer.pubkey(), false),
+                AccountMeta::new(authority.pubkey(), true),
+                AccountMeta::new(dead_man_switch, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+            data: EchoInstruction::StageDeadManSwitch {
+                data,
+                check_in_epochs: 2,
+            }
+            .try_to_vec()?,
+        })
+    };
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            system_instruction::transfer(&payer.pubkey(), &authority.pubkey(), 1_000_000_000),
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(buffer.pubkey(), true),
+                    AccountMeta::new(authority.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::InitializeKeypairAuthorizedEcho {
+                    buffer_size: AuthorizedBufferHeader::header_len(&None) + 6,
+                    write_deadline_slot: None,
+                }
+                .try_to_vec()?,
+            },
+            stage_ix(b"to be published".to_vec())?,
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new_readonly(buffer.pubkey(), false),
+                    AccountMeta::new_readonly(authority.pubkey(), true),
+                    AccountMeta::new(dead_man_switch, false),
+                ],
+                data: EchoInstruction::CheckIn.try_to_vec()?,
+            },
+        ],
+        &[&authority, &buffer],
+    )?;
+
+    let switch_data =
+        DeadManSwitchHeader::try_from_slice(&rpc_client.get_account_data(&dead_man_switch)?)?;
+    assert_eq!(switch_data.buffer, buffer.pubkey());
+    assert_eq!(switch_data.check_in_epochs, 2);
+    assert_eq!(switch_data.data, b"to be published");
+    assert!(!switch_data.released);
+
+    // The authority just checked in, so nobody can release yet
+    let e = send_instructions(
+        &rpc_client,
+        &payer,
+        &[Instruction {
+            program_id,
+            accounts: vec![AccountMeta::new(dead_man_switch, false)],
+            data: EchoInstruction::ReleaseDeadManSwitch.try_to_vec()?,
+        }],
+        &[],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::CheckInNotOverdue);
+
+    // Restaging shrinks the switch to the new data
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[stage_ix(b"shorter".to_vec())?],
+        &[&authority],
+    )?;
+    let account_data = rpc_client.get_account_data(&dead_man_switch)?;
+    assert_eq!(account_data.len(), DeadManSwitchHeader::HEADER_LEN + 7);
+    assert_eq!(
+        DeadManSwitchHeader::try_from_slice(&account_data)?.data,
+        b"shorter"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_rent_vault() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let authority = Keypair::new();
+    let buffer_seed = 7;
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    // The authority wallet holds no SOL, the rent is drawn from its vault
+    let (rent_vault, _) = find_rent_vault_address(&program_id, &authority.pubkey());
+    let (pda, _) =
+        find_authorized_buffer_address(&program_id, &authority.pubkey(), buffer_seed, None);
+    let buffer_size = AuthorizedBufferHeader::header_len(&None) + 32;
+    let mut initialize_ix = initialize_authorized_echo_ix(
+        program_id,
+        pda,
+        authority.pubkey(),
+        true,
+        buffer_seed,
+        buffer_size,
+        None,
+    )?;
+    initialize_ix.accounts[1] = AccountMeta::new_readonly(authority.pubkey(), true);
+    initialize_ix
+        .accounts
+        .push(AccountMeta::new(rent_vault, false));
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            system_instruction::transfer(&payer.pubkey(), &rent_vault, 1_000_000_000),
+            initialize_ix,
+        ],
+        &[&authority],
+    )?;
+
+    let rent = rpc_client.get_minimum_balance_for_rent_exemption(buffer_size)?;
+    assert_eq!(rpc_client.get_balance(&pda)?, rent);
+    assert_eq!(rpc_client.get_balance(&rent_vault)?, 1_000_000_000 - rent);
+    assert_eq!(rpc_client.get_balance(&authority.pubkey())?, 0);
+
+    // a buffer paid by the vault can be referenced like any other
+    let referrer = Keypair::new();
+    let (reference, bump_seed) = find_reference_address(&program_id, &pda, &referrer.pubkey());
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new_readonly(authority.pubkey(), true),
+                AccountMeta::new(reference, false),
+                AccountMeta::new_readonly(referrer.pubkey(), true),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+            data: EchoInstruction::AddReference.try_to_vec()?,
+        }],
+        &[&authority, &referrer],
+    )?;
+
+    let reference_account = rpc_client.get_account(&reference)?;
+    assert_eq!(reference_account.owner, program_id);
+    let reference_data = BufferReference::try_from_slice(&reference_account.data)?;
+    assert_eq!(reference_data.bump_seed, bump_seed);
+    assert_eq!(reference_data.buffer, pda);
+    assert_eq!(reference_data.referrer, referrer.pubkey());
+    let buffer_data = AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert_eq!(buffer_data.reference_count, 1);
+    assert_eq!(rpc_client.get_balance(&rent_vault)?, 1_000_000_000 - rent);
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(rent_vault, false),
+                AccountMeta::new(authority.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+            data: EchoInstruction::WithdrawRentVault {
+                lamports: 1_000_000_000 - rent,
+            }
+            .try_to_vec()?,
+        }],
+        &[&authority],
+    )?;
+    assert_eq!(rpc_client.get_balance(&rent_vault)?, 0);
+    assert_eq!(
+        rpc_client.get_balance(&authority.pubkey())?,
+        1_000_000_000 - rent
+    );
+    Ok(())
+}
+
+#[test]
+fn test_payload_pointer_matches() {
+    let payload = vec![42u8; 50_000];
+    let pointer = PayloadPointer::new("ar://bNbA3TEQVL60xlgCcqdz4ZPHFZ711cZ3hmkpGttDt_U", &payload);
+    assert_eq!(pointer.len, 50_000);
+    assert!(pointer.matches(&payload));
+    assert!(!pointer.matches(&payload[1..]));
+    let mut tampered = payload;
+    tampered[0] = 0;
+    assert!(!pointer.matches(&tampered));
+}
+
+#[test]
+fn test_set_payload_pointer() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let (pda, _) = find_authorized_buffer_address(&program_id, &payer.pubkey(), buffer_seed, None);
+    let (payload_pointer, _) = find_payload_pointer_address(&program_id, &pda);
+    let set_payload_pointer_ix = |pointer: PayloadPointer| -> anyhow::Result<Instruction> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new(payload_pointer, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+            data: EchoInstruction::SetPayloadPointer { pointer }.try_to_vec()?,
+        })
+    };
+
+    let pointer = PayloadPointer::new("s3://echo/payload-v1", &[1; 20_000]);
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            initialize_authorized_echo_ix(
+                program_id,
+                pda,
+                payer.pubkey(),
+                true,
+                buffer_seed,
+                AuthorizedBufferHeader::header_len(&None),
+                None,
+            )?,
+            set_payload_pointer_ix(pointer)?,
+        ],
+        &[],
+    )?;
+    let pointer_data =
+        PayloadPointerHeader::try_from_slice(&rpc_client.get_account_data(&payload_pointer)?)?;
+    assert_eq!(pointer_data.buffer, pda);
+    assert_eq!(pointer_data.pointer, pointer);
+    assert!(pointer_data.slot > 0);
+
+    // Replaced in place by the next upload
+    let pointer = PayloadPointer::new("s3://echo/payload-v2", &[2; 30_000]);
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[set_payload_pointer_ix(pointer)?],
+        &[],
+    )?;
+    let pointer_data =
+        PayloadPointerHeader::try_from_slice(&rpc_client.get_account_data(&payload_pointer)?)?;
+    assert_eq!(pointer_data.pointer, pointer);
+    assert!(pointer_data.pointer.matches(&[2; 30_000]));
+    assert_eq!(pointer_data.cid(), None);
+
+    let cid =
+        solana_sdk::bs58::decode("QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG").into_vec()?;
+    let pointer = PayloadPointer::new(
+        "ipfs://QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG",
+        &[3; 10],
+    );
+    let set_ipfs_payload_pointer_ix = |cid: Vec<u8>| -> anyhow::Result<Instruction> {
+        let mut instruction = set_payload_pointer_ix(pointer)?;
+        instruction.data = EchoInstruction::SetIpfsPayloadPointer { pointer, cid }.try_to_vec()?;
+        Ok(instruction)
+    };
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[set_ipfs_payload_pointer_ix(cid.clone())?],
+        &[],
+    )?;
+    let pointer_data =
+        PayloadPointerHeader::try_from_slice(&rpc_client.get_account_data(&payload_pointer)?)?;
+    assert_eq!(pointer_data.pointer, pointer);
+    assert_eq!(pointer_data.cid(), Some(cid.clone()));
+
+    // Garbage CIDs are rejected
+    let e = send_instructions(
+        &rpc_client,
+        &payer,
+        &[set_ipfs_payload_pointer_ix(cid[..20].to_vec())?],
+        &[],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::InvalidContentIdentifier);
+    Ok(())
+}
+
+#[test]
+fn test_is_valid_cid() {
+    let digest = [7u8; 32];
+    let with_digest = |prefix: &[u8]| [prefix, &digest[..]].concat();
+
+    // CIDv0, CIDv1 raw and dag-json with sha2-256, CIDv1 raw with blake2b-256
+    assert!(is_valid_cid(&with_digest(&[0x12, 0x20])));
+    assert!(is_valid_cid(&with_digest(&[0x01, 0x55, 0x12, 0x20])));
+    assert!(is_valid_cid(&with_digest(&[0x01, 0xa9, 0x02, 0x12, 0x20])));
+    assert!(is_valid_cid(&with_digest(&[
+        0x01, 0x55, 0xa0, 0xe4, 0x02, 0x20
+    ])));
+
+    // Identity multihash, unknown CID version, wrong digest length, truncated and oversized CIDs
+    assert!(!is_valid_cid(&with_digest(&[0x01, 0x55, 0x00, 0x20])));
+    assert!(!is_valid_cid(&with_digest(&[0x02, 0x55, 0x12, 0x20])));
+    assert!(!is_valid_cid(&with_digest(&[0x01, 0x55, 0x12, 0x21])));
+    assert!(!is_valid_cid(&with_digest(&[0x01, 0x55, 0x12, 0x20])[..35]));
+    assert!(!is_valid_cid(&[0x01, 0x55, 0x12, 0x20, 0x00].repeat(10)));
+    assert!(!is_valid_cid(&[]));
+}
+
+#[test]
+fn test_bridge_payload() {
+    let program_id = localnet::id();
+    let buffer = Pubkey::new_unique();
+    let echo_data = b"hello bridge".to_vec();
+
+    let payload = build_payload(&buffer, 0x0102, &echo_data);
+    assert_eq!(payload.len(), 77 + echo_data.len());
+    assert_eq!(payload[0], PAYLOAD_ID_ECHO);
+    assert_eq!(&payload[1..33], buffer.as_ref());
+    assert_eq!(&payload[33..41], &[0, 0, 0, 0, 0, 0, 1, 2]);
+    assert_eq!(
+        &payload[41..73],
+        solana_sdk::hash::hash(&echo_data).as_ref()
+    );
+    assert_eq!(&payload[73..77], &[0, 0, 0, 12]);
+    assert_eq!(&payload[77..], &echo_data[..]);
+
+    let (emitter, _) = find_emitter_address(&program_id);
+    let message = Pubkey::new_unique();
+    let ix = post_message(
+        &Cluster::Localnet.wormhole_program_id(),
+        &Pubkey::new_unique(),
+        &message,
+        &emitter,
+        &Pubkey::new_unique(),
+        &Pubkey::new_unique(),
+        &Pubkey::new_unique(),
+        7,
+        &payload,
+        ConsistencyLevel::Finalized,
+    );
+    assert_eq!(ix.program_id, wormhole_program::localnet::id());
+    assert_eq!(ix.accounts.len(), 9);
+    assert!(ix.accounts[1].is_signer && ix.accounts[1].pubkey == message);
+    assert!(ix.accounts[2].is_signer && !ix.accounts[2].is_writable);
+    assert_eq!(ix.accounts[2].pubkey, emitter);
+    // PostMessage tag, nonce, length prefixed payload, consistency level
+    assert_eq!(&ix.data[..5], &[1, 7, 0, 0, 0]);
+    assert_eq!(&ix.data[5..9], &(payload.len() as u32).to_le_bytes());
+    assert_eq!(&ix.data[9..ix.data.len() - 1], &payload[..]);
+    assert_eq!(ix.data[ix.data.len() - 1], 1);
+}
+
+#[test]
+fn test_verify_after_write() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let authority = Keypair::new();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let (pda, _) =
+        find_authorized_buffer_address(&program_id, &authority.pubkey(), buffer_seed, None);
+    let send = |instruction: EchoInstruction| -> Result<(), ClientError> {
+        send_instructions(
+            &rpc_client,
+            &payer,
+            &[Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pda, false),
+                    AccountMeta::new(authority.pubkey(), true),
+                ],
+                data: instruction.try_to_vec().unwrap(),
+            }],
+            &[&authority],
+        )?;
+        Ok(())
+    };
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            system_instruction::transfer(&payer.pubkey(), &authority.pubkey(), 1_000_000_000),
+            initialize_authorized_echo_ix(
+                program_id,
+                pda,
+                authority.pubkey(),
+                true,
+                buffer_seed,
+                AuthorizedBufferHeader::header_len(&None) + 8,
+                None,
+            )?,
+        ],
+        &[&authority],
+    )?;
+    let buffer_data = AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert!(!buffer_data.verify_after_write);
+
+    send(EchoInstruction::SetVerifyAfterWrite { enabled: true })?;
+    let buffer_data = AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert!(buffer_data.verify_after_write);
+
+    // Verified writes are truncated and zero padded like any other
+    send(EchoInstruction::AuthorizedEcho {
+        data: b"verified writes".to_vec(),
+    })?;
+    let buffer_data = AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert_eq!(buffer_data.echo_data, b"verified");
+    send(EchoInstruction::AuthorizedEcho {
+        data: b"echo".to_vec(),
+    })?;
+    let buffer_data = AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert_eq!(buffer_data.echo_data, b"echo\0\0\0\0");
+    assert!(buffer_data.verify_after_write);
+
+    send(EchoInstruction::SetVerifyAfterWrite { enabled: false })?;
+    let buffer_data = AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert!(!buffer_data.verify_after_write);
+    assert_eq!(buffer_data.echo_data, b"echo\0\0\0\0");
+    Ok(())
+}
+
+#[test]
+fn test_program_ids() {
+    assert_eq!("localnet".parse(), Ok(Cluster::Localnet));
+    assert_eq!("devnet".parse(), Ok(Cluster::Devnet));
+    assert_eq!("mainnet-beta".parse(), Ok(Cluster::MainnetBeta));
+    assert!("testnet".parse::<Cluster>().is_err());
+
+    assert_eq!(Cluster::Localnet.program_id(), localnet::id());
+    assert_eq!(
+        localnet::id().to_string(),
+        "EchoLoca1net1111111111111111111111111111111"
+    );
+    assert_eq!(Cluster::Devnet.program_id(), devnet::id());
+    assert_eq!(
+        devnet::id().to_string(),
+        "5zbv5jjtPyJ7thmstA1qRB6vKFyH1YB733YfDms3mfK3"
+    );
+    assert_eq!(Cluster::MainnetBeta.program_id(), mainnet_beta::id());
+    assert_eq!(
+        mainnet_beta::id().to_string(),
+        "39kwm9zMqqMwyVbNCJX8Sc6vgphvmbSibyp3Vx5LSfZy"
+    );
+
+    assert_eq!(
+        Cluster::MainnetBeta.wormhole_program_id().to_string(),
+        "worm2ZoG2kUd4vFXhvjh93UUH596ayRfgQ2MgjNMTth"
+    );
+    assert_eq!(
+        Cluster::Devnet.wormhole_program_id(),
+        wormhole_program::devnet::id()
+    );
+
+    #[cfg(feature = "devnet")]
+    assert_eq!(Cluster::BUILD, Cluster::Devnet);
+    #[cfg(not(feature = "devnet"))]
+    assert_eq!(Cluster::BUILD, Cluster::MainnetBeta);
+}
+
+#[test]
+fn test_cluster_of_program_id() {
+    for cluster in Cluster::ALL {
+        assert_eq!(Cluster::of(&cluster.program_id()), Some(cluster));
+    }
+    assert_eq!(Cluster::of(&Pubkey::new_unique()), None);
+}
+
+#[test]
+fn test_deployed_program_id() -> anyhow::Result<()> {
+    // The entrypoint compares against the declared IDs, so the built program embeds them
+    let out_dir = std::env::var("BPF_OUT_DIR").unwrap_or_else(|_| "target/deploy".to_string());
+    let program = std::fs::read(std::path::Path::new(&out_dir).join("echo.so"))?;
+    let program_ids: Vec<Pubkey> = Cluster::ALL.iter().map(Cluster::program_id).collect();
+    assert!(program_ids.contains(&echo::id()));
+    for program_id in program_ids {
+        assert!(program
+            .windows(32)
+            .any(|bytes| bytes == program_id.as_ref()));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_undeclared_program_id() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = Pubkey::new_unique();
+    let echo_buffer = Keypair::new();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let e = send_instructions(
+        &rpc_client,
+        &payer,
+        &[Instruction {
+            program_id,
+            accounts: vec![AccountMeta::new(echo_buffer.pubkey(), false)],
+            data: EchoInstruction::Echo {
+                data: b"echo".to_vec(),
+            }
+            .try_to_vec()?,
+        }],
+        &[],
+    )
+    .unwrap_err();
+    assert_matches!(
+        e.get_transaction_error(),
+        Some(TransactionError::InstructionError(
+            0,
+            solana_sdk::instruction::InstructionError::IncorrectProgramId
+        ))
+    );
+    Ok(())
+}
+
+#[test]
+fn test_authorized_echo_idempotent() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let authority = Keypair::new();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let (pda, _) =
+        find_authorized_buffer_address(&program_id, &authority.pubkey(), buffer_seed, None);
+    let (record, _) = find_idempotency_record_address(&program_id, &pda);
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[
+            system_instruction::transfer(&payer.pubkey(), &authority.pubkey(), 1_000_000_000),
+            initialize_authorized_echo_ix(
+                program_id,
+                pda,
+                authority.pubkey(),
+                true,
+                buffer_seed,
+                AuthorizedBufferHeader::header_len(&None) + 4,
+                None,
+            )?,
+        ],
+        &[&authority],
+    )?;
+
+    let write = |data: &[u8], idempotency_key: [u8; 16]| -> Result<(), ClientError> {
+        send_instructions(
+            &rpc_client,
+            &payer,
+            &[Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pda, false),
+                    AccountMeta::new(authority.pubkey(), true),
+                    AccountMeta::new(record, false),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+                data: EchoInstruction::AuthorizedEchoIdempotent {
+                    data: data.to_vec(),
+                    idempotency_key,
+                }
+                .try_to_vec()
+                .unwrap(),
+            }],
+            &[&authority],
+        )?;
+        Ok(())
+    };
+    let echo_data = || -> anyhow::Result<Vec<u8>> {
+        Ok(AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?.echo_data)
+    };
+
+    write(b"one", [1; 16])?;
+    assert_eq!(echo_data()?, b"one\0");
+    let record_data = IdempotencyRecord::try_from_slice(&rpc_client.get_account_data(&record)?)?;
+    assert_eq!(record_data.last_key, [1; 16]);
+
+    // A resubmission with the same key succeeds without writing
+    write(b"two", [1; 16])?;
+    assert_eq!(echo_data()?, b"one\0");
+
+    write(b"two", [2; 16])?;
+    assert_eq!(echo_data()?, b"two\0");
+    let record_data = IdempotencyRecord::try_from_slice(&rpc_client.get_account_data(&record)?)?;
+    assert_eq!(record_data.last_key, [2; 16]);
+
+    // Only the last key is remembered
+    write(b"one", [1; 16])?;
+    assert_eq!(echo_data()?, b"one\0");
+    Ok(())
+}
+
+#[test]
+fn test_unpack_legacy_layouts() -> anyhow::Result<()> {
+    // Legacy buffers hold bump_seed, buffer_seed or price, then the echo data with its length prefix
+    let legacy_bytes = |bump_seed: u8, seed: u64, echo_data: &[u8]| {
+        let mut data = vec![bump_seed];
+        data.extend_from_slice(&seed.to_le_bytes());
+        data.extend_from_slice(&(echo_data.len() as u32).to_le_bytes());
+        data.extend_from_slice(echo_data);
+        data
+    };
+
+    let buffer_data = AuthorizedBufferHeader::unpack(&legacy_bytes(254, 7, b"legacy"))?;
+    assert_eq!(buffer_data.version, 0);
+    assert_eq!(buffer_data.bump_seed, 254);
+    assert_eq!(buffer_data.buffer_seed, 7);
+    assert_eq!(buffer_data.namespace, None);
+    assert!(!buffer_data.is_keypair_buffer());
+    assert_eq!(buffer_data.echo_data, b"legacy");
+
+    // The first byte of the length prefix, where the current layout has its version, is not always zero
+    let long_echo_data = vec![b'x'; 300];
+    let long_buffer_data = AuthorizedBufferHeader::unpack(&legacy_bytes(254, 7, &long_echo_data))?;
+    assert_eq!(long_buffer_data.version, 0);
+    assert_eq!(long_buffer_data.echo_data, long_echo_data);
+
+    let current = AuthorizedBufferHeader {
+        version: CURRENT_VERSION,
+        reference_count: 3,
+        ..buffer_data
+    };
+    let buffer_data = AuthorizedBufferHeader::unpack(&current.try_to_vec()?)?;
+    assert_eq!(buffer_data.version, CURRENT_VERSION);
+    assert_eq!(buffer_data.reference_count, 3);
+    assert_eq!(buffer_data.echo_data, b"legacy");
+
+    let vending_buffer = VendingMachineBufferHeader::unpack(&legacy_bytes(253, 100, b"legacy"))?;
+    assert_eq!(vending_buffer.version, 0);
+    assert_eq!(vending_buffer.price, 100);
+    assert_eq!(vending_buffer.echo_data, b"legacy");
+    assert!(vending_buffer.metadata.0.is_none());
+
+    let current = VendingMachineBufferHeader {
+        version: VENDING_MACHINE_VERSION,
+        unique_writers: 2,
+        ..vending_buffer
+    };
+    let vending_buffer = VendingMachineBufferHeader::unpack(&current.try_to_vec()?)?;
+    assert_eq!(vending_buffer.version, VENDING_MACHINE_VERSION);
+    assert_eq!(vending_buffer.unique_writers, 2);
+
+    // Version 1 has no reserved bytes between `receipts` and the echo data
+    let v1 = VendingMachineBufferHeaderV1 {
+        bump_seed: 253,
+        price: 100,
+        version: 1,
+        admin: Pubkey::new_unique(),
+        opens_at: 10,
+        closes_at: 20,
+        daily_opens_at: 0,
+        daily_closes_at: 0,
+        holding_gated: true,
+        unique_writers: 5,
+        quadratic_pricing: false,
+        dutch_auction: false,
+        receipts: true,
+        echo_data: b"v1".to_vec(),
+        metadata: Trailing(None),
+    };
+    let v1_bytes = v1.try_to_vec()?;
+    assert_eq!(
+        v1_bytes.len() + RESERVED_LEN,
+        VendingMachineBufferHeader::HEADER_LEN + 2
+    );
+    let vending_buffer = VendingMachineBufferHeader::unpack(&v1_bytes)?;
+    assert_eq!(vending_buffer.version, 1);
+    assert_eq!(vending_buffer.admin, v1.admin);
+    assert_eq!(vending_buffer.closes_at, 20);
+    assert!(vending_buffer.holding_gated);
+    assert_eq!(vending_buffer.unique_writers, 5);
+    assert!(vending_buffer.receipts);
+    assert!(vending_buffer._reserved.is_zero());
+    assert_eq!(vending_buffer.echo_data, b"v1");
+
+    assert!(AuthorizedBufferHeader::unpack(&[1, 2, 3]).is_err());
+    assert!(VendingMachineBufferHeader::unpack(&[]).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_global_stats_saturate() {
+    let mut stats = GlobalStats {
+        bump_seed: 0,
+        version: CURRENT_VERSION,
+        buffers_created: 0,
+        writes: u64::MAX - 1,
+        tokens_burned: u64::MAX - 1,
+        lamport_fees: 1,
+        _reserved: Reserved::default(),
+    };
+    stats.record_buffer_created();
+    stats.record_write();
+    stats.record_write();
+    stats.record_burn(5);
+    stats.record_fee(5);
+    assert_eq!(stats.buffers_created, 1);
+    assert_eq!(stats.writes, u64::MAX);
+    assert_eq!(stats.tokens_burned, u64::MAX);
+    assert_eq!(stats.lamport_fees, 6);
+}
+
+#[test]
+fn test_global_stats() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let (global_stats, _) = find_global_stats_address(&program_id);
+    let initialize_global_stats_ix = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(global_stats, false),
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: EchoInstruction::InitializeGlobalStats.try_to_vec()?,
+    };
+    let send = |instruction: Instruction| -> Result<(), ClientError> {
+        send_instructions(&rpc_client, &payer, &[instruction], &[])?;
+        Ok(())
+    };
+    let stats = || -> anyhow::Result<GlobalStats> {
+        Ok(GlobalStats::try_from_slice(
+            &rpc_client.get_account_data(&global_stats)?,
+        )?)
+    };
+    let with_stats = |mut instruction: Instruction| {
+        instruction
+            .accounts
+            .push(AccountMeta::new(global_stats, false));
+        instruction
+    };
+
+    send(initialize_global_stats_ix.clone())?;
+    assert_eq!(stats()?.buffers_created, 0);
+    let e = send(initialize_global_stats_ix).unwrap_err();
+    assert_echo_error(e, EchoError::BufferAlreadyExists);
+
+    let buffer_seed = 1u64;
+    let (pda, _) = find_authorized_buffer_address(&program_id, &payer.pubkey(), buffer_seed, None);
+    send(with_stats(initialize_authorized_echo_ix(
+        program_id,
+        pda,
+        payer.pubkey(),
+        true,
+        buffer_seed,
+        AuthorizedBufferHeader::header_len(&None) + 4,
+        None,
+    )?))?;
+    let authorized_echo_ix = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pda, false),
+            AccountMeta::new_readonly(payer.pubkey(), true),
+        ],
+        data: EchoInstruction::AuthorizedEcho {
+            data: b"echo".to_vec(),
+        }
+        .try_to_vec()?,
+    };
+    send(with_stats(authorized_echo_ix.clone()))?;
+    // Instructions sent without the stats account are not counted
+    send(authorized_echo_ix)?;
+    let counters = stats()?;
+    assert_eq!(counters.buffers_created, 1);
+    assert_eq!(counters.writes, 1);
+
+    let price = 42u64;
+    let (vending_pda, mint, user_token_account) =
+        setup_vending_machine(&rpc_client, &payer, program_id, price, price, false)?;
+    send(with_stats(vending_machine_echo_ix(
+        program_id,
+        vending_pda,
+        payer.pubkey(),
+        user_token_account,
+        mint,
+        spl_token::id(),
+    )?))?;
+    let counters = stats()?;
+    assert_eq!(counters.writes, 2);
+    assert_eq!(counters.tokens_burned, price);
+    assert_eq!(counters.lamport_fees, 0);
+    Ok(())
+}
+
+#[test]
+fn test_leaderboard_record_burn() {
+    let buyer = |n: u8| Pubkey::new_from_array([n; 32]);
+    let board = |entries: &[LeaderboardEntry; LEADERBOARD_LEN]| -> Vec<(Pubkey, u64)> {
+        entries
+            .iter()
+            .filter(|entry| entry.burned > 0)
+            .map(|entry| (entry.buyer, entry.burned))
+            .collect()
+    };
+    let mut entries = [LeaderboardEntry::default(); LEADERBOARD_LEN];
+
+    assert!(!record_burn(&mut entries, buyer(1), 0));
+    assert!(record_burn(&mut entries, buyer(1), 5));
+    assert!(record_burn(&mut entries, buyer(2), 7));
+    assert_eq!(board(&entries), vec![(buyer(2), 7), (buyer(1), 5)]);
+
+    // Totals only grow
+    assert!(!record_burn(&mut entries, buyer(1), 3));
+    // Ties keep the buyer that reached the total first in front
+    assert!(record_burn(&mut entries, buyer(1), 7));
+    assert_eq!(board(&entries), vec![(buyer(2), 7), (buyer(1), 7)]);
+    assert!(record_burn(&mut entries, buyer(1), 9));
+    assert_eq!(board(&entries), vec![(buyer(1), 9), (buyer(2), 7)]);
+
+    // A full board only takes buyers that burned more than its last entry, which they evict
+    let mut entries = [LeaderboardEntry::default(); LEADERBOARD_LEN];
+    for n in 0..LEADERBOARD_LEN as u8 {
+        assert!(record_burn(&mut entries, buyer(n), 10 + n as u64));
+    }
+    assert_eq!(
+        entries[0],
+        LeaderboardEntry {
+            buyer: buyer(9),
+            burned: 19
+        }
+    );
+    assert_eq!(
+        entries[9],
+        LeaderboardEntry {
+            buyer: buyer(0),
+            burned: 10
+        }
+    );
+    assert!(!record_burn(&mut entries, buyer(20), 10));
+    assert!(record_burn(&mut entries, buyer(20), 11));
+    assert_eq!(
+        entries[8],
+        LeaderboardEntry {
+            buyer: buyer(1),
+            burned: 11
+        }
+    );
+    assert_eq!(
+        entries[9],
+        LeaderboardEntry {
+            buyer: buyer(20),
+            burned: 11
+        }
+    );
+    assert!(!board(&entries).contains(&(buyer(0), 10)));
+
+    // Moving up within the board evicts nobody
+    assert!(record_burn(&mut entries, buyer(20), 30));
+    assert_eq!(
+        entries[0],
+        LeaderboardEntry {
+            buyer: buyer(20),
+            burned: 30
+        }
+    );
+    assert_eq!(
+        entries[9],
+        LeaderboardEntry {
+            buyer: buyer(1),
+            burned: 11
+        }
+    );
+    assert_eq!(board(&entries).len(), LEADERBOARD_LEN);
+}
+
+#[test]
+fn test_vending_machine_leaderboard() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let price = 3u64;
+    let (pda, mint, user_token_account) =
+        setup_vending_machine(&rpc_client, &payer, program_id, price, 100, false)?;
+    let (writer_marker, _) = find_writer_marker_address(&program_id, &pda, &user_token_account);
+    let (leaderboard, _) = find_leaderboard_address(&program_id, &pda);
+    let mut counted_echo_ix = vending_machine_echo_ix(
+        program_id,
+        pda,
+        payer.pubkey(),
+        user_token_account,
+        mint,
+        spl_token::id(),
+    )?;
+    counted_echo_ix.accounts[1].is_writable = true;
+    counted_echo_ix
+        .accounts
+        .push(AccountMeta::new(writer_marker, false));
+    counted_echo_ix
+        .accounts
+        .push(AccountMeta::new_readonly(system_program::id(), false));
+    let mut ranked_echo_ix = counted_echo_ix.clone();
+    ranked_echo_ix
+        .accounts
+        .push(AccountMeta::new(leaderboard, false));
+    let send = |instruction: Instruction| -> Result<_, ClientError> {
+        send_instructions(&rpc_client, &payer, &[instruction], &[])
+    };
+
+    // The marker adds up purchases made without the leaderboard, which enter it with the next ranked one
+    send(counted_echo_ix)?;
+    assert!(rpc_client.get_account_data(&leaderboard).is_err());
+    send(ranked_echo_ix)?;
+    let marker = WriterMarker::try_from_slice(&rpc_client.get_account_data(&writer_marker)?)?;
+    assert_eq!(marker.purchases, 2);
+    assert_eq!(marker.burned, 2 * price);
+    let board = Leaderboard::try_from_slice(&rpc_client.get_account_data(&leaderboard)?)?;
+    assert_eq!(board.version, CURRENT_VERSION);
+    assert_eq!(
+        board.entries[0],
+        LeaderboardEntry {
+            buyer: user_token_account,
+            burned: 2 * price
+        }
+    );
+    assert_eq!(board.entries[1], LeaderboardEntry::default());
+    Ok(())
+}
+
+#[test]
+fn test_resize_authorized_buffer() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let header_len = AuthorizedBufferHeader::header_len(&None);
+    let (pda, _) = find_authorized_buffer_address(&program_id, &payer.pubkey(), buffer_seed, None);
+    let send = |instruction: Instruction| -> Result<(), ClientError> {
+        send_instructions(&rpc_client, &payer, &[instruction], &[])?;
+        Ok(())
+    };
+    let resize_ix = |buffer_size: usize| -> anyhow::Result<Instruction> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+            data: EchoInstruction::ResizeAuthorizedBuffer { buffer_size }.try_to_vec()?,
+        })
+    };
+    let assert_rent_exempt = || -> anyhow::Result<()> {
+        let account = rpc_client.get_account(&pda)?;
+        assert_eq!(
+            account.lamports,
+            rpc_client.get_minimum_balance_for_rent_exemption(account.data.len())?
+        );
+        Ok(())
+    };
+
+    send(initialize_authorized_echo_ix(
+        program_id,
+        pda,
+        payer.pubkey(),
+        true,
+        buffer_seed,
+        header_len + 4,
+        None,
+    )?)?;
+    send(Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pda, false),
+            AccountMeta::new_readonly(payer.pubkey(), true),
+        ],
+        data: EchoInstruction::AuthorizedEcho {
+            data: b"echo".to_vec(),
+        }
+        .try_to_vec()?,
+    })?;
+
+    send(resize_ix(header_len + 8)?)?;
+    let buffer_data = AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert_eq!(buffer_data.echo_data, b"echo\0\0\0\0");
+    assert_rent_exempt()?;
+
+    send(resize_ix(header_len + 2)?)?;
+    let buffer_data = AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert_eq!(buffer_data.echo_data, b"ec");
+    assert_rent_exempt()?;
+
+    let e = send(resize_ix(header_len - 1)?).unwrap_err();
+    assert_echo_error(e, EchoError::ArithmeticOverflow);
+    Ok(())
+}
+
+#[test]
+fn test_chunk_checksum_ranges() {
+    // Check value of the CRC-32 used by zlib
+    assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    assert_eq!(crc32(b""), 0);
+
+    assert_eq!(chunk_count(0), 0);
+    assert_eq!(chunk_count(1), 1);
+    assert_eq!(chunk_count(CHUNK_LEN), 1);
+    assert_eq!(chunk_count(CHUNK_LEN + 1), 2);
+    assert_eq!(
+        ChunkChecksums::len(2 * CHUNK_LEN + 1),
+        ChunkChecksums::HEADER_LEN + 12
+    );
+
+    let echo_data: Vec<u8> = (0..10_000).map(|i| (i % 251) as u8).collect();
+    let checksums = chunk_checksums(&echo_data);
+    assert_eq!(checksums.len(), 3);
+    assert_eq!(checksums[2], crc32(&echo_data[2 * CHUNK_LEN..]));
+
+    // Ranges are widened to whole chunks, the last one ending with the echo data
+    assert_eq!(covering_range(10..20, echo_data.len()), (0, 0..CHUNK_LEN));
+    assert_eq!(
+        covering_range(CHUNK_LEN - 1..CHUNK_LEN + 1, echo_data.len()),
+        (0, 0..2 * CHUNK_LEN)
+    );
+    let (first_chunk, range) = covering_range(9_000..9_500, echo_data.len());
+    assert_eq!((first_chunk, range.clone()), (2, 2 * CHUNK_LEN..10_000));
+    assert!(verify_chunks(
+        first_chunk,
+        &echo_data[range.clone()],
+        &checksums
+    ));
+
+    let mut corrupted = echo_data[range].to_vec();
+    corrupted[42] ^= 1;
+    assert!(!verify_chunks(first_chunk, &corrupted, &checksums));
+    // Bytes past the last checksum are not covered
+    assert!(!verify_chunks(3, &echo_data[..1], &checksums));
+}
+
+#[test]
+fn test_chunk_checksums() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let header_len = AuthorizedBufferHeader::header_len(&None);
+    let (pda, _) = find_authorized_buffer_address(&program_id, &payer.pubkey(), buffer_seed, None);
+    let (checksums_pda, _) = find_chunk_checksums_address(&program_id, &pda);
+    let send = |instruction: Instruction| -> Result<(), ClientError> {
+        send_instructions(&rpc_client, &payer, &[instruction], &[])?;
+        Ok(())
+    };
+    let set_chunk_checksums_ix = |enabled: bool| -> anyhow::Result<Instruction> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new(checksums_pda, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+            data: EchoInstruction::SetChunkChecksums { enabled }.try_to_vec()?,
+        })
+    };
+    let echo_ix = |data: Vec<u8>, with_checksums: bool| -> anyhow::Result<Instruction> {
+        let mut accounts = vec![
+            AccountMeta::new(pda, false),
+            AccountMeta::new_readonly(payer.pubkey(), true),
+        ];
+        if with_checksums {
+            accounts.push(AccountMeta::new(checksums_pda, false));
+        }
+        Ok(Instruction {
+            program_id,
+            accounts,
+            data: EchoInstruction::AuthorizedEcho { data }.try_to_vec()?,
+        })
+    };
+    let checksums = || -> anyhow::Result<Vec<u32>> {
+        let checksums_data =
+            ChunkChecksums::try_from_slice(&rpc_client.get_account_data(&checksums_pda)?)?;
+        Ok(checksums_data.checksums)
+    };
+
+    send(initialize_authorized_echo_ix(
+        program_id,
+        pda,
+        payer.pubkey(),
+        true,
+        buffer_seed,
+        header_len + 10_000,
+        None,
+    )?)?;
+    send(set_chunk_checksums_ix(true)?)?;
+    let buffer_data = AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert!(buffer_data.chunk_checksums);
+    assert_eq!(checksums()?, chunk_checksums(&[0; 10_000]));
+
+    // Writes must keep the checksums up to date
+    let data: Vec<u8> = (0..9_000).map(|i| (i % 251) as u8).collect();
+    let e = send(echo_ix(data.clone(), false)?).unwrap_err();
+    assert_matches!(
+        e.get_transaction_error(),
+        Some(TransactionError::InstructionError(
+            0,
+            solana_sdk::instruction::InstructionError::NotEnoughAccountKeys
+        ))
+    );
+    send(echo_ix(data, true)?)?;
+    let account_data = rpc_client.get_account_data(&pda)?;
+    assert_eq!(checksums()?, chunk_checksums(&account_data[header_len..]));
+
+    // A range read checks against the checksums without the rest of the echo data
+    let (first_chunk, range) = covering_range(5_000..5_100, 10_000);
+    let slice = &account_data[header_len + range.start..header_len + range.end];
+    assert!(verify_chunks(first_chunk, slice, &checksums()?));
+
+    // Resizing resizes the checksums, and needs them as well
+    let resize_ix = |buffer_size: usize, with_checksums: bool| -> anyhow::Result<Instruction> {
+        let mut accounts = vec![
+            AccountMeta::new(pda, false),
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ];
+        if with_checksums {
+            accounts.push(AccountMeta::new(checksums_pda, false));
+        }
+        Ok(Instruction {
+            program_id,
+            accounts,
+            data: EchoInstruction::ResizeAuthorizedBuffer { buffer_size }.try_to_vec()?,
+        })
+    };
+    assert!(send(resize_ix(header_len + 3_000, false)?).is_err());
+    send(resize_ix(header_len + 3_000, true)?)?;
+    let account_data = rpc_client.get_account_data(&pda)?;
+    assert_eq!(checksums()?, chunk_checksums(&account_data[header_len..]));
+    assert_eq!(checksums()?.len(), 1);
+    send(resize_ix(header_len + 12_000, true)?)?;
+    let account_data = rpc_client.get_account_data(&pda)?;
+    assert_eq!(checksums()?, chunk_checksums(&account_data[header_len..]));
+    assert_eq!(checksums()?.len(), 3);
+    let account = rpc_client.get_account(&checksums_pda)?;
+    assert_eq!(
+        account.lamports,
+        rpc_client.get_minimum_balance_for_rent_exemption(account.data.len())?
+    );
+
+    // Disabling closes the checksums, and writes no longer need them
+    send(set_chunk_checksums_ix(false)?)?;
+    assert!(rpc_client.get_account(&checksums_pda).is_err());
+    send(echo_ix(b"echo".to_vec(), false)?)?;
+    let buffer_data = AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert!(!buffer_data.chunk_checksums);
+    Ok(())
+}
+
+#[test]
+fn test_close_vending_machine() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let stranger = Keypair::new();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let price = 42u64;
+    let (pda, mint, user_token_account) =
+        setup_vending_machine(&rpc_client, &payer, program_id, price, 100, false)?;
+    let (writer_marker, _) = find_writer_marker_address(&program_id, &pda, &user_token_account);
+    let (leaderboard, _) = find_leaderboard_address(&program_id, &pda);
+    let (session, _) = find_session_address(&program_id, &pda, &user_token_account);
+    let close_ix = |admin: Pubkey, companions: &[AccountMeta]| -> anyhow::Result<Instruction> {
+        let mut accounts = vec![AccountMeta::new(pda, false), AccountMeta::new(admin, true)];
+        accounts.extend(
+            find_vending_machine_companion_addresses(&program_id, &pda)
+                .iter()
+                .map(|companion| AccountMeta::new(*companion, false)),
+        );
+        accounts.extend_from_slice(companions);
+        Ok(Instruction {
+            program_id,
+            accounts,
+            data: EchoInstruction::CloseVendingMachine.try_to_vec()?,
+        })
+    };
+    let send = |instructions: &[Instruction], signers: Vec<&Keypair>| -> Result<(), ClientError> {
+        let blockhash = rpc_client.get_latest_blockhash()?;
+        let mut transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&payer.pubkey()),
+            &signers,
+            blockhash,
+        );
+        transaction.sign(&signers, blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)?;
+        Ok(())
+    };
+
+    // A purchase counted in a writer marker and on the leaderboard, and a session, leave companion accounts to close
+    // with the machine
+    let mut counted_echo_ix = vending_machine_echo_ix(
+        program_id,
+        pda,
+        payer.pubkey(),
+        user_token_account,
+        mint,
+        spl_token::id(),
+    )?;
+    counted_echo_ix.accounts[1].is_writable = true;
+    counted_echo_ix
+        .accounts
+        .push(AccountMeta::new(writer_marker, false));
+    counted_echo_ix
+        .accounts
+        .push(AccountMeta::new_readonly(system_program::id(), false));
+    counted_echo_ix
+        .accounts
+        .push(AccountMeta::new(leaderboard, false));
+    let open_session_ix = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(session, false),
+            AccountMeta::new_readonly(pda, false),
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new(user_token_account, false),
+            AccountMeta::new_readonly(stranger.pubkey(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: EchoInstruction::OpenSession { max_total_burn: 10 }.try_to_vec()?,
+    };
+    send(&[counted_echo_ix, open_session_ix], vec![&payer])?;
+    assert!(rpc_client.get_account(&writer_marker).is_ok());
+    assert!(rpc_client.get_account(&leaderboard).is_ok());
+
+    // The holding gate, Dutch auction and leaderboard slots cannot be left out
+    let e = send(
+        &[Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+            ],
+            data: EchoInstruction::CloseVendingMachine.try_to_vec()?,
+        }],
+        vec![&payer],
+    )
+    .unwrap_err();
+    assert_matches!(
+        e.get_transaction_error(),
+        Some(TransactionError::InstructionError(
+            0,
+            solana_sdk::instruction::InstructionError::NotEnoughAccountKeys
+        ))
+    );
+
+    // Only the payer that initialized the machine can close it
+    let e = send(
+        &[close_ix(stranger.pubkey(), &[])?],
+        vec![&payer, &stranger],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::InvalidAuthority);
+
+    // Companions must be derived from the machine and the token account following them
+    let e = send(
+        &[close_ix(
+            payer.pubkey(),
+            &[
+                AccountMeta::new(writer_marker, false),
+                AccountMeta::new_readonly(Pubkey::new_unique(), false),
+            ],
+        )?],
+        vec![&payer],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::InvalidAuthority);
+
+    // Lamports sent back to the machine in the same transaction leave an empty system account
+    let rent = rpc_client.get_account(&pda)?.lamports;
+    send(
+        &[
+            close_ix(
+                payer.pubkey(),
+                &[
+                    AccountMeta::new(writer_marker, false),
+                    AccountMeta::new_readonly(user_token_account, false),
+                    AccountMeta::new(session, false),
+                    AccountMeta::new_readonly(user_token_account, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                ],
+            )?,
+            system_instruction::transfer(&payer.pubkey(), &pda, rent),
+        ],
+        vec![&payer],
+    )?;
+    let account = rpc_client.get_account(&pda)?;
+    assert_eq!(account.owner, system_program::id());
+    assert!(account.data.is_empty());
+    assert_eq!(account.lamports, rent);
+    assert!(rpc_client.get_account(&writer_marker).is_err());
+    assert!(rpc_client.get_account(&leaderboard).is_err());
+    assert!(rpc_client.get_account(&session).is_err());
+
+    let echo_ix = vending_machine_echo_ix(
+        program_id,
+        pda,
+        payer.pubkey(),
+        user_token_account,
+        mint,
+        spl_token::id(),
+    )?;
+    assert!(send(&[echo_ix], vec![&payer]).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_co_signed_batch_echo() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let alice = Keypair::new();
+    let bob = Keypair::new();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let buffer_size = AuthorizedBufferHeader::header_len(&None) + 8;
+    let (alice_pda, _) =
+        find_authorized_buffer_address(&program_id, &alice.pubkey(), buffer_seed, None);
+    let (bob_pda, _) =
+        find_authorized_buffer_address(&program_id, &bob.pubkey(), buffer_seed, None);
+    let send = |instruction: Instruction, signers: Vec<&Keypair>| -> Result<(), ClientError> {
+        let blockhash = rpc_client.get_latest_blockhash()?;
+        let mut transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&payer.pubkey()),
+            &signers,
+            blockhash,
+        );
+        transaction.sign(&signers, blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)?;
+        Ok(())
+    };
+    let batch_ix =
+        |pairs: &[(Pubkey, Pubkey, bool)], data: &[&[u8]]| -> anyhow::Result<Instruction> {
+            let mut accounts = vec![];
+            for &(pda, authority, is_signer) in pairs {
+                accounts.push(AccountMeta::new(pda, false));
+                accounts.push(AccountMeta::new_readonly(authority, is_signer));
+            }
+            Ok(Instruction {
+                program_id,
+                accounts,
+                data: EchoInstruction::CoSignedBatchEcho {
+                    data: data.iter().map(|data| data.to_vec()).collect(),
+                }
+                .try_to_vec()?,
+            })
+        };
+    let echo_data = |pda: &Pubkey| -> anyhow::Result<Vec<u8>> {
+        let account_data = rpc_client.get_account_data(pda)?;
+        Ok(AuthorizedBufferHeader::try_from_slice(&account_data)?.echo_data)
+    };
+
+    for (authority, pda) in [(&alice, alice_pda), (&bob, bob_pda)] {
+        send_instructions(
+            &rpc_client,
+            &payer,
+            &[
+                system_instruction::transfer(&payer.pubkey(), &authority.pubkey(), 1_000_000_000),
+                initialize_authorized_echo_ix(
+                    program_id,
+                    pda,
+                    authority.pubkey(),
+                    true,
+                    buffer_seed,
+                    buffer_size,
+                    None,
+                )?,
+            ],
+            &[authority],
+        )?;
+    }
+
+    // The account order maps each buffer to its authority
+    let pairs = [
+        (alice_pda, alice.pubkey(), true),
+        (bob_pda, bob.pubkey(), true),
+    ];
+    send(
+        batch_ix(&pairs, &[b"alice", b"bob"])?,
+        vec![&payer, &alice, &bob],
+    )?;
+    assert_eq!(echo_data(&alice_pda)?, b"alice\0\0\0");
+    assert_eq!(echo_data(&bob_pda)?, b"bob\0\0\0\0\0");
+
+    // A missing signature fails the whole batch
+    let unsigned_pairs = [
+        (alice_pda, alice.pubkey(), true),
+        (bob_pda, bob.pubkey(), false),
+    ];
+    let e = send(
+        batch_ix(&unsigned_pairs, &[b"echo", b"echo"])?,
+        vec![&payer, &alice],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::AuthorityNotSigner);
+    assert_eq!(echo_data(&alice_pda)?, b"alice\0\0\0");
+
+    let swapped_pairs = [
+        (alice_pda, bob.pubkey(), true),
+        (bob_pda, alice.pubkey(), true),
+    ];
+    let e = send(
+        batch_ix(&swapped_pairs, &[b"echo", b"echo"])?,
+        vec![&payer, &alice, &bob],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::InvalidAuthority);
+
+    let e = send(
+        batch_ix(&pairs[..1], &[b"echo", b"echo"])?,
+        vec![&payer, &alice],
+    )
+    .unwrap_err();
+    assert_matches!(
+        e.get_transaction_error(),
+        Some(TransactionError::InstructionError(
+            0,
+            solana_sdk::instruction::InstructionError::NotEnoughAccountKeys
+        ))
+    );
+    Ok(())
+}
+
+#[test]
+fn test_append_echo() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let header_len = AuthorizedBufferHeader::header_len(&None);
+    let (pda, _) = find_authorized_buffer_address(&program_id, &payer.pubkey(), buffer_seed, None);
+    let send = |instruction: EchoInstruction| -> Result<(), ClientError> {
+        let mut accounts = vec![
+            AccountMeta::new(pda, false),
+            AccountMeta::new(payer.pubkey(), true),
+        ];
+        if let EchoInstruction::ResizeAuthorizedBuffer { .. } = instruction {
+            accounts.push(AccountMeta::new_readonly(system_program::id(), false));
+        }
+        send_instructions(
+            &rpc_client,
+            &payer,
+            &[Instruction {
+                program_id,
+                accounts,
+                data: instruction.try_to_vec().unwrap(),
+            }],
+            &[],
+        )?;
+        Ok(())
+    };
+    let buffer_data = || -> anyhow::Result<AuthorizedBufferHeader> {
+        Ok(AuthorizedBufferHeader::try_from_slice(
+            &rpc_client.get_account_data(&pda)?,
+        )?)
+    };
+    let append = |data: &[u8]| {
+        send(EchoInstruction::AppendEcho {
+            data: data.to_vec(),
+        })
+    };
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[initialize_authorized_echo_ix(
+            program_id,
+            pda,
+            payer.pubkey(),
+            true,
+            buffer_seed,
+            header_len + 8,
+            None,
+        )?],
+        &[],
+    )?;
+    assert_eq!(buffer_data()?.write_offset, 0);
+
+    let e = append(b"ab").unwrap_err();
+    assert_echo_error(e, EchoError::AppendNotAllowed);
+    send(EchoInstruction::SetFeatures {
+        features: FEATURE_APPEND_ALLOWED,
+    })?;
+
+    // Appends start where the last overwrite ended
+    send(EchoInstruction::AuthorizedEcho {
+        data: b"ab".to_vec(),
+    })?;
+    assert_eq!(buffer_data()?.write_offset, 2);
+    append(b"cd")?;
+    let data = buffer_data()?;
+    assert_eq!(data.echo_data, b"abcd\0\0\0\0");
+    assert_eq!(data.write_offset, 4);
+
+    // Appends are written whole or not at all
+    let e = append(b"efghi").unwrap_err();
+    assert_echo_error(e, EchoError::BufferFull);
+    assert_eq!(buffer_data()?.echo_data, b"abcd\0\0\0\0");
+
+    send(EchoInstruction::SetVerifyAfterWrite { enabled: true })?;
+    append(b"efgh")?;
+    let data = buffer_data()?;
+    assert_eq!(data.echo_data, b"abcdefgh");
+    assert_eq!(data.write_offset, 8);
+    let e = append(b"i").unwrap_err();
+    assert_echo_error(e, EchoError::BufferFull);
+
+    // Shrinking pulls the offset back, overwriting moves it to the end of the new data
+    send(EchoInstruction::ResizeAuthorizedBuffer {
+        buffer_size: header_len + 4,
+    })?;
+    assert_eq!(buffer_data()?.write_offset, 4);
+    send(EchoInstruction::AuthorizedEcho {
+        data: b"x".to_vec(),
+    })?;
+    append(b"yz")?;
+    let data = buffer_data()?;
+    assert_eq!(data.echo_data, b"xyz\0");
+    assert_eq!(data.write_offset, 3);
+    Ok(())
+}
+
+#[test]
+fn test_set_features() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let header_len = AuthorizedBufferHeader::header_len(&None);
+    let (pda, _) = find_authorized_buffer_address(&program_id, &payer.pubkey(), buffer_seed, None);
+    let send_with =
+        |instruction: EchoInstruction, extra: &[AccountMeta]| -> Result<(), ClientError> {
+            let mut accounts = vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+            ];
+            if let EchoInstruction::ResizeAuthorizedBuffer { .. } = instruction {
+                accounts.push(AccountMeta::new_readonly(system_program::id(), false));
+            }
+            accounts.extend_from_slice(extra);
+            send_instructions(
+                &rpc_client,
+                &payer,
+                &[Instruction {
+                    program_id,
+                    accounts,
+                    data: instruction.try_to_vec().unwrap(),
+                }],
+                &[],
+            )?;
+            Ok(())
+        };
+    let send = |instruction: EchoInstruction| send_with(instruction, &[]);
+    let echo = |data: &[u8]| {
+        send(EchoInstruction::AuthorizedEcho {
+            data: data.to_vec(),
+        })
+    };
+    let set_features = |features: u32| send(EchoInstruction::SetFeatures { features });
+
+    send_instructions(
+        &rpc_client,
+        &payer,
+        &[initialize_authorized_echo_ix(
+            program_id,
+            pda,
+            payer.pubkey(),
+            true,
+            buffer_seed,
+            header_len + 8,
+            None,
+        )?],
+        &[],
+    )?;
+
+    let e = set_features(1 << 31).unwrap_err();
+    assert_matches!(
+        e.get_transaction_error(),
+        Some(TransactionError::InstructionError(
+            0,
+            solana_sdk::instruction::InstructionError::InvalidInstructionData
+        ))
+    );
+
+    // The echo data must already be UTF-8 when the feature is turned on
+    echo(&[0xff])?;
+    assert_echo_error(
+        set_features(FEATURE_UTF8).unwrap_err(),
+        EchoError::InvalidUtf8,
+    );
+    echo(b"")?;
+
+    // Only the bytes that fit are checked, so a character cut in half fails too
+    set_features(FEATURE_UTF8 | FEATURE_LOG_WRITES)?;
+    echo("échos".as_bytes())?;
+    assert_echo_error(echo(&[0xff]).unwrap_err(), EchoError::InvalidUtf8);
+    assert_echo_error(
+        echo("echo ééé".as_bytes()).unwrap_err(),
+        EchoError::InvalidUtf8,
+    );
+    let buffer_data = AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert_eq!(buffer_data.features, FEATURE_UTF8 | FEATURE_LOG_WRITES);
+    assert_eq!(buffer_data.echo_data, "échos\0\0".as_bytes());
+
+    // Writes at an offset are checked along with the chars they land in, and may leave one cut for the next write
+    let echo_at_offset = |offset: u32, data: &[u8]| {
+        send(EchoInstruction::EchoAtOffset {
+            offset,
+            data: data.to_vec(),
+        })
+    };
+    assert_echo_error(echo_at_offset(1, b"a").unwrap_err(), EchoError::InvalidUtf8);
+    assert_echo_error(echo_at_offset(0, b"a").unwrap_err(), EchoError::InvalidUtf8);
+    echo_at_offset(6, &[0xc3])?;
+    echo_at_offset(7, &[0xa9])?;
+    let buffer_data = AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert_eq!(buffer_data.echo_data, "échosé".as_bytes());
+    // Before the write offset, the char cut by a patch would stay cut
+    assert_echo_error(
+        echo_at_offset(2, &[0xc3]).unwrap_err(),
+        EchoError::InvalidUtf8,
+    );
+
+    // Guarded writes need the instructions sysvar to tell a top-level write from a CPI
+    set_features(FEATURE_CPI_GUARD)?;
+    let e = echo(b"echo").unwrap_err();
+    assert_matches!(
+        e.get_transaction_error(),
+        Some(TransactionError::InstructionError(
+            0,
+            solana_sdk::instruction::InstructionError::NotEnoughAccountKeys
+        ))
+    );
+    send_with(
+        EchoInstruction::AuthorizedEcho {
+            data: b"echo".to_vec(),
+        },
+        &[AccountMeta::new_readonly(
+            solana_sdk::sysvar::instructions::id(),
+            false,
+        )],
+    )?;
+
+    // Immutable buffers can no longer change, nor lose the feature
+    set_features(FEATURE_IMMUTABLE)?;
+    assert_echo_error(echo(b"echo").unwrap_err(), EchoError::BufferImmutable);
+    assert_echo_error(set_features(0).unwrap_err(), EchoError::BufferImmutable);
+    let e = send(EchoInstruction::ResizeAuthorizedBuffer {
+        buffer_size: header_len + 4,
+    })
+    .unwrap_err();
+    assert_echo_error(e, EchoError::BufferImmutable);
+    let buffer_data = AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert_eq!(buffer_data.echo_data, b"echo\0\0\0\0");
+    Ok(())
+}
+
+#[test]
+fn test_echo_at_offset() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_seed = 1u64;
+    let echo_len = 3_000;
+    let header_len = AuthorizedBufferHeader::header_len(&None);
+    let (pda, _) = find_authorized_buffer_address(&program_id, &payer.pubkey(), buffer_seed, None);
+    let send = |instruction: Instruction| -> Result<(), ClientError> {
+        send_instructions(&rpc_client, &payer, &[instruction], &[])?;
+        Ok(())
+    };
+    let echo_at_offset_ix = |offset: u32, data: &[u8]| -> anyhow::Result<Instruction> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pda, false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+            ],
+            data: EchoInstruction::EchoAtOffset {
+                offset,
+                data: data.to_vec(),
+            }
+            .try_to_vec()?,
+        })
+    };
+
+    send(initialize_authorized_echo_ix(
+        program_id,
+        pda,
+        payer.pubkey(),
+        true,
+        buffer_seed,
+        header_len + echo_len,
+        None,
+    )?)?;
+
+    // Fill the buffer in chunks too large to fit in a single transaction together
+    let content: Vec<u8> = (0..echo_len).map(|i| (i % 251) as u8).collect();
+    for (i, chunk) in content.chunks(1_000).enumerate() {
+        send(echo_at_offset_ix(i as u32 * 1_000, chunk)?)?;
+    }
+    let buffer_data = AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert_eq!(buffer_data.echo_data, content);
+    assert_eq!(buffer_data.write_offset, 0);
+
+    // Writes past the end are rejected whole
+    let e = send(echo_at_offset_ix(2_500, &[1; 1_000])?).unwrap_err();
+    assert_echo_error(e, EchoError::OffsetOutOfBounds);
+    let e = send(echo_at_offset_ix(u32::MAX, b"echo")?).unwrap_err();
+    assert_echo_error(e, EchoError::OffsetOutOfBounds);
+    send(echo_at_offset_ix(echo_len as u32 - 4, b"echo")?)?;
+    let buffer_data = AuthorizedBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert_eq!(
+        &buffer_data.echo_data[..echo_len - 4],
+        &content[..echo_len - 4]
+    );
+    assert_eq!(&buffer_data.echo_data[echo_len - 4..], b"echo");
+    Ok(())
+}
+
+#[test]
+fn test_multi_echo() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+    let authority = Keypair::new();
+    let other = Keypair::new();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let buffer_size = AuthorizedBufferHeader::header_len(&None) + 8;
+    let pdas: Vec<Pubkey> = (1..=3u64)
+        .map(|buffer_seed| {
+            find_authorized_buffer_address(&program_id, &authority.pubkey(), buffer_seed, None).0
+        })
+        .collect();
+    let (other_pda, _) = find_authorized_buffer_address(&program_id, &other.pubkey(), 1, None);
+    let send = |instruction: Instruction, signers: Vec<&Keypair>| -> Result<(), ClientError> {
+        let blockhash = rpc_client.get_latest_blockhash()?;
+        let mut transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&payer.pubkey()),
+            &signers,
+            blockhash,
+        );
+        transaction.sign(&signers, blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)?;
+        Ok(())
+    };
+    let multi_ix =
+        |authority: Pubkey, pdas: &[Pubkey], data: &[&[u8]]| -> anyhow::Result<Instruction> {
+            let mut accounts = vec![AccountMeta::new_readonly(authority, true)];
+            accounts.extend(pdas.iter().map(|pda| AccountMeta::new(*pda, false)));
+            Ok(Instruction {
+                program_id,
+                accounts,
+                data: EchoInstruction::MultiEcho {
+                    data: data.iter().map(|data| data.to_vec()).collect(),
+                }
+                .try_to_vec()?,
+            })
+        };
+    let echo_data = |pda: &Pubkey| -> anyhow::Result<Vec<u8>> {
+        let account_data = rpc_client.get_account_data(pda)?;
+        Ok(AuthorizedBufferHeader::try_from_slice(&account_data)?.echo_data)
+    };
+
+    let buffers = pdas
+        .iter()
+        .zip(1..)
+        .map(|(pda, buffer_seed)| (&authority, *pda, buffer_seed))
+        .chain([(&other, other_pda, 1)]);
+    for (authority, pda, buffer_seed) in buffers {
+        send_instructions(
+            &rpc_client,
+            &payer,
+            &[
+                system_instruction::transfer(&payer.pubkey(), &authority.pubkey(), 1_000_000_000),
+                initialize_authorized_echo_ix(
+                    program_id,
+                    pda,
+                    authority.pubkey(),
+                    true,
+                    buffer_seed,
+                    buffer_size,
+                    None,
+                )?,
+            ],
+            &[authority],
+        )?;
+    }
+
+    send(
+        multi_ix(authority.pubkey(), &pdas, &[b"up", b"up", b"degraded"])?,
+        vec![&payer, &authority],
+    )?;
+    assert_eq!(echo_data(&pdas[0])?, b"up\0\0\0\0\0\0");
+    assert_eq!(echo_data(&pdas[1])?, b"up\0\0\0\0\0\0");
+    assert_eq!(echo_data(&pdas[2])?, b"degraded");
+
+    // A buffer of another authority fails the whole instruction
+    let e = send(
+        multi_ix(
+            authority.pubkey(),
+            &[pdas[0], other_pda],
+            &[b"down", b"down"],
+        )?,
+        vec![&payer, &authority],
+    )
+    .unwrap_err();
+    assert_echo_error(e, EchoError::InvalidAuthority);
+    assert_eq!(echo_data(&pdas[0])?, b"up\0\0\0\0\0\0");
+
+    let e = send(
+        multi_ix(authority.pubkey(), &pdas[..1], &[b"down", b"down"])?,
+        vec![&payer, &authority],
+    )
+    .unwrap_err();
+    assert_matches!(
+        e.get_transaction_error(),
+        Some(TransactionError::InstructionError(
+            0,
+            solana_sdk::instruction::InstructionError::NotEnoughAccountKeys
+        ))
+    );
+    Ok(())
+}
+
+#[test]
+fn test_legacy_authorized_buffer_layout() -> anyhow::Result<()> {
+    // Bytes of a buffer written before headers carried a version: bump_seed, buffer_seed, then the echo data with
+    // its length prefix
+    let mut data = vec![254];
+    data.extend_from_slice(&7u64.to_le_bytes());
+    data.extend_from_slice(&6u32.to_le_bytes());
+    data.extend_from_slice(b"legacy");
+
+    let legacy = LegacyAuthorizedBufferHeader::try_from_slice(&data)?;
+    assert_eq!(legacy.bump_seed, 254);
+    assert_eq!(legacy.buffer_seed, 7);
+    assert_eq!(legacy.echo_data, b"legacy");
+    assert_eq!(legacy.try_to_vec()?, data);
+    Ok(())
+}
+
+#[test]
+fn test_initialize_authorized_echo_payloads() -> anyhow::Result<()> {
+    // Payload of clients predating namespaces: the variant index, buffer_seed and buffer_size
+    let mut data = vec![1];
+    data.extend_from_slice(&7u64.to_le_bytes());
+    data.extend_from_slice(&100u64.to_le_bytes());
+    assert_eq!(data.len(), 17);
+    assert_matches!(
+        EchoInstruction::try_from_slice(&data)?,
+        EchoInstruction::InitializeAuthorizedEcho {
+            buffer_seed: 7,
+            buffer_size: 100,
+            namespace: Trailing(None),
+            write_deadline_slot: Trailing(None),
+        }
+    );
+
+    // A write deadline follows a namespace, be it none
+    data.extend_from_slice(&[0, 1]);
+    data.extend_from_slice(&42u64.to_le_bytes());
+    assert_matches!(
+        EchoInstruction::try_from_slice(&data)?,
+        EchoInstruction::InitializeAuthorizedEcho {
+            namespace: Trailing(Some(None)),
+            write_deadline_slot: Trailing(Some(Some(42))),
+            ..
+        }
+    );
+    Ok(())
+}
+
+#[test]
+fn test_vending_machine_short_data() -> anyhow::Result<()> {
+    solana_logger::setup_with_default("solana_program_runtime=debug");
+    let program_id = localnet::id();
+
+    let (test_validator, payer) = start_validator(program_id);
+    let rpc_client = test_validator.get_rpc_client();
+
+    let (pda, mint, user_token_account) =
+        setup_vending_machine(&rpc_client, &payer, program_id, 1, 100, false)?;
+    let mut echo_ix = vending_machine_echo_ix(
+        program_id,
+        pda,
+        payer.pubkey(),
+        user_token_account,
+        mint,
+        spl_token::id(),
+    )?;
+    echo_ix.data = EchoInstruction::VendingMachineEcho {
+        data: b"short".to_vec(),
+        machine_seed: Trailing(None),
+    }
+    .try_to_vec()?;
+
+    send_instructions(&rpc_client, &payer, &[echo_ix], &[])?;
+
+    // Data shorter than the buffer is zero-padded
+    let buffer_data =
+        VendingMachineBufferHeader::try_from_slice(&rpc_client.get_account_data(&pda)?)?;
+    assert_eq!(buffer_data.echo_data, b"short\0\0\0\0\0\0\0\0\0\0");
+    Ok(())
+}
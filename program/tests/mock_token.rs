@@ -0,0 +1,128 @@
+#![cfg(feature = "mock-token")]
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use echo::instruction::EchoInstruction;
+use echo::pda::find_vending_machine_address;
+use echo::processor::Processor;
+use echo::program_ids::localnet;
+use echo::state::{Trailing, VendingMachineBufferHeader};
+use solana_program::program_option::COption;
+use solana_program::program_pack::Pack;
+use solana_program_test::{processor, tokio, ProgramTest};
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::rent::Rent;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::system_program;
+use solana_sdk::transaction::Transaction;
+use spl_token::state::{Account, AccountState, Mint};
+
+#[test]
+fn test_vending_machine_mock_token() {
+    let program_id = localnet::id();
+    let mint = Pubkey::new_unique();
+    let user = Keypair::new();
+    let user_token_account = Pubkey::new_unique();
+    let price = 42u64;
+
+    let mut program_test = ProgramTest::new(
+        "echo",
+        program_id,
+        processor!(Processor::process_instruction),
+    );
+    program_test.add_program(
+        "spl_token",
+        spl_token::id(),
+        processor!(echo::mock_token::process_instruction),
+    );
+    program_test.add_packable_account(
+        mint,
+        Rent::default().minimum_balance(Mint::LEN),
+        &Mint {
+            mint_authority: COption::None,
+            supply: 100,
+            decimals: 0,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        },
+        &spl_token::id(),
+    );
+    program_test.add_packable_account(
+        user_token_account,
+        Rent::default().minimum_balance(Account::LEN),
+        &Account {
+            mint,
+            owner: user.pubkey(),
+            amount: 100,
+            state: AccountState::Initialized,
+            ..Account::default()
+        },
+        &spl_token::id(),
+    );
+
+    let (pda, _) = find_vending_machine_address(&program_id, &mint, price, None);
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+        let transaction = Transaction::new_signed_with_payer(
+            &[
+                Instruction {
+                    program_id,
+                    accounts: vec![
+                        AccountMeta::new(pda, false),
+                        AccountMeta::new_readonly(mint, false),
+                        AccountMeta::new(payer.pubkey(), true),
+                        AccountMeta::new_readonly(system_program::id(), false),
+                    ],
+                    data: EchoInstruction::InitializeVendingMachineEcho {
+                        price,
+                        buffer_size: VendingMachineBufferHeader::HEADER_LEN
+                            + b"vending machine".len(),
+                        metadata: None,
+                        machine_seed: Trailing(None),
+                    }
+                    .try_to_vec()
+                    .unwrap(),
+                },
+                Instruction {
+                    program_id,
+                    accounts: vec![
+                        AccountMeta::new(pda, false),
+                        AccountMeta::new_readonly(user.pubkey(), true),
+                        AccountMeta::new(user_token_account, false),
+                        AccountMeta::new(mint, false),
+                        AccountMeta::new_readonly(spl_token::id(), false),
+                    ],
+                    data: EchoInstruction::VendingMachineEcho {
+                        data: b"vending machine".to_vec(),
+                        machine_seed: Trailing(None),
+                    }
+                    .try_to_vec()
+                    .unwrap(),
+                },
+            ],
+            Some(&payer.pubkey()),
+            &[&payer, &user],
+            recent_blockhash,
+        );
+        banks_client.process_transaction(transaction).await.unwrap();
+
+        let token_account = banks_client
+            .get_account(user_token_account)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            Account::unpack(&token_account.data).unwrap().amount,
+            100 - price
+        );
+        let mint_account = banks_client.get_account(mint).await.unwrap().unwrap();
+        assert_eq!(
+            Mint::unpack(&mint_account.data).unwrap().supply,
+            100 - price
+        );
+        let buffer = banks_client.get_account(pda).await.unwrap().unwrap();
+        let buffer_data = VendingMachineBufferHeader::try_from_slice(&buffer.data).unwrap();
+        assert_eq!(buffer_data.echo_data, b"vending machine".to_vec());
+    });
+}
@@ -1,14 +1,15 @@
 #![cfg(feature = "test-bpf")]
 
 use {
-    solana_program::{pubkey::Pubkey, system_program},
+    echo::program_ids::localnet,
+    solana_program::system_program,
     solana_program_test::ProgramTest,
     solana_sdk::signature::{Keypair, Signer},
 };
 
 #[test]
 fn test_echo() {
-    let program_id = Pubkey::new_unique();
+    let program_id = localnet::id();
     let mut program_test = ProgramTest::default();
     program_test.add_program("echo", program_id, None);
 